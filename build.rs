@@ -1,9 +1,76 @@
 // This software may be used and distributed according to the terms of the
 // GNU General Public License version 2.
 
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Return the version of the package `name` locked in Cargo.lock (the one the scheduler is
+// actually built against).
+fn locked_version(name: &str) -> Option<String> {
+    let lock = fs::read_to_string("Cargo.lock").ok()?;
+    let mut lines = lock.lines();
+
+    lines.find(|line| *line == format!("name = \"{}\"", name))?;
+    lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+        .map(String::from)
+}
+
+// Return the first line of the output of `cmd --version`.
+fn tool_version(cmd: &str) -> Option<String> {
+    let output = Command::new(cmd).arg("--version").output().ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+
+    stdout.lines().next().map(|line| line.trim().to_string())
+}
+
+// Return a fingerprint of the generated BPF skeleton (FNV-1a hash of its source), to tell
+// apart builds of the same versions with a different BPF component.
+fn skel_hash(path: &Path) -> Option<String> {
+    let skel = fs::read(path).ok()?;
+    let hash = skel.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    Some(format!("{:016x}", hash))
+}
+
 fn main() {
     scx_rustland_core::RustLandBuilder::new()
         .unwrap()
         .build()
         .unwrap();
+
+    // Build information reported by --version.
+    let unknown = || "unknown".to_string();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let rustc = env::var("RUSTC").unwrap_or("rustc".into());
+    let clang = env::var("BPF_CLANG").unwrap_or("clang".into());
+
+    println!(
+        "cargo:rustc-env=SCX_CORE_VERSION={}",
+        locked_version("scx_rustland_core").unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=SCX_UTILS_VERSION={}",
+        locked_version("scx_utils").unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=SCX_BPF_SKEL_HASH={}",
+        skel_hash(&Path::new(&out_dir).join("bpf_skel.rs")).unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=SCX_BPF_CLANG={}",
+        tool_version(&clang).unwrap_or_else(unknown)
+    );
+    println!(
+        "cargo:rustc-env=SCX_RUSTC_VERSION={}",
+        tool_version(&rustc).unwrap_or_else(unknown)
+    );
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=BPF_CLANG");
 }
@@ -0,0 +1,13 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// Feed random scheduling rounds to the policy, on the mock backend (see src/fuzz.rs).
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    scx_rust_scheduler::fuzz::run(data);
+});
@@ -0,0 +1,199 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+// CPU time (in nanoseconds) that a task can use beyond its assigned time slice without being
+// reported: the kernel only checks the time slice at every tick, so a task can exceed it by up
+// to one tick (4ms with HZ=250).
+const ACCOUNTING_SLACK_NS: u64 = 4_000_000;
+
+// Maximum amount of discrepancies logged per interval (the others are only counted).
+const ACCOUNTING_MAX_LOGGED: u64 = 5;
+
+// Minimum amount of tasks that used their entire time slice in an interval to compare the CPU
+// time they used against the assigned time slices.
+const DIVERGENCE_MIN_SAMPLES: u64 = 10;
+
+// Consecutive intervals in which the CPU time used diverges from the assigned time slices
+// before reporting that the time slices are not taking effect.
+const DIVERGENCE_INTERVALS: u64 = 3;
+
+// Time slice assigned to a dispatched task.
+struct Assignment {
+    slice_ns: u64, // Assigned time slice
+    runtime: u64,  // Total CPU time of the task when it has been dispatched
+    nvcsw: u64,    // Voluntary context switches of the task when it has been dispatched
+}
+
+/// Reconciliation of the time slices assigned by the policy against the CPU time actually used
+/// by the tasks (see --debug-accounting).
+///
+/// Every time a dispatched task is received again, the CPU time it used since the dispatch
+/// (sum_exec_runtime delta) is compared with the time slice it has been assigned: a task that
+/// used more than its time slice (plus ACCOUNTING_SLACK_NS) is reported as a discrepancy, that
+/// usually indicates an accounting bug in the policy (e.g., a wrong time slice unit or a task
+/// dispatched twice).
+///
+/// The tasks that ran without any voluntary context switch since the dispatch (i.e., they were
+/// re-enqueued because their time slice expired, not because they blocked) are also expected to
+/// use about their entire time slice: if the CPU time they used diverges from the assigned time
+/// slices beyond the tolerance (at most one tick more per task, see ACCOUNTING_SLACK_NS) for
+/// DIVERGENCE_INTERVALS consecutive intervals, the time slices are likely not taking effect (e.g.,
+/// the BPF component overrides them) and a warning is printed.
+pub struct Accounting {
+    assigned: HashMap<i32, Assignment>, // Tasks dispatched and not received again yet
+    assigned_ns: u64,                   // Time slices reconciled in the current interval
+    used_ns: u64,                       // CPU time reconciled in the current interval
+    nr_discrepancies: u64,              // Discrepancies detected in the current interval
+    nr_total: u64,                      // Discrepancies detected since the beginning
+    tolerance_pct: u64,                 // Tolerated divergence of the expired time slices
+    expired_assigned_ns: u64,           // Expired time slices in the current interval
+    expired_used_ns: u64,               // CPU time used with the expired time slices
+    nr_expired: u64,                    // Expired time slices in the current interval
+    usage_pct: Option<u64>,             // CPU time used per expired time slice (last interval)
+    nr_diverging: u64,                  // Consecutive intervals with a divergence
+}
+
+impl Accounting {
+    pub fn new(tolerance_pct: u64) -> Self {
+        Self {
+            assigned: HashMap::new(),
+            assigned_ns: 0,
+            used_ns: 0,
+            nr_discrepancies: 0,
+            nr_total: 0,
+            tolerance_pct,
+            expired_assigned_ns: 0,
+            expired_used_ns: 0,
+            nr_expired: 0,
+            usage_pct: None,
+            nr_diverging: 0,
+        }
+    }
+
+    /// Account a task dispatched with a time slice of `slice_ns`, with a total CPU time of
+    /// `runtime` and `nvcsw` voluntary context switches.
+    pub fn record_dispatch(&mut self, pid: i32, runtime: u64, nvcsw: u64, slice_ns: u64) {
+        self.assigned.insert(
+            pid,
+            Assignment {
+                slice_ns,
+                runtime,
+                nvcsw,
+            },
+        );
+    }
+
+    /// Reconcile the time slice assigned to a task, received again with a total CPU time of
+    /// `runtime` and `nvcsw` voluntary context switches.
+    pub fn record_enqueue(&mut self, pid: i32, runtime: u64, nvcsw: u64) {
+        let Some(assignment) = self.assigned.remove(&pid) else {
+            return;
+        };
+        let used_ns = runtime.saturating_sub(assignment.runtime);
+
+        self.assigned_ns = self.assigned_ns.saturating_add(assignment.slice_ns);
+        self.used_ns = self.used_ns.saturating_add(used_ns);
+        if nvcsw == assignment.nvcsw {
+            self.expired_assigned_ns = self.expired_assigned_ns.saturating_add(assignment.slice_ns);
+            self.expired_used_ns = self.expired_used_ns.saturating_add(used_ns);
+            self.nr_expired += 1;
+        }
+        if used_ns > assignment.slice_ns.saturating_add(ACCOUNTING_SLACK_NS) {
+            if self.nr_discrepancies < ACCOUNTING_MAX_LOGGED {
+                println!(
+                    "accounting: pid {} used {}us with a time slice of {}us",
+                    pid,
+                    used_ns / 1000,
+                    assignment.slice_ns / 1000
+                );
+            }
+            self.nr_discrepancies += 1;
+            self.nr_total += 1;
+        }
+    }
+
+    /// Drop the assignments of the tasks that don't satisfy `alive` (e.g., exited tasks).
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.assigned.retain(|&pid, _| alive(pid));
+    }
+
+    /// Drop the assignment of a task that is not tracked anymore (see --max-tracked-pids).
+    pub fn forget(&mut self, pid: i32) {
+        self.assigned.remove(&pid);
+    }
+
+    /// Return the amount of discrepancies detected since the beginning.
+    pub fn total_discrepancies(&self) -> u64 {
+        self.nr_total
+    }
+
+    /// Return true if the CPU time used by the tasks has been diverging from their expired time
+    /// slices for DIVERGENCE_INTERVALS consecutive intervals (or more).
+    pub fn diverging(&self) -> bool {
+        self.nr_diverging >= DIVERGENCE_INTERVALS
+    }
+
+    // Compare the CPU time used with the expired time slices of the current interval against
+    // the tolerance (intervals with too few samples are not evaluated).
+    fn evaluate(&mut self) {
+        self.usage_pct = None;
+        if self.nr_expired < DIVERGENCE_MIN_SAMPLES {
+            return;
+        }
+        let (assigned_ns, used_ns) = (self.expired_assigned_ns, self.expired_used_ns);
+        self.usage_pct = used_ns.saturating_mul(100).checked_div(assigned_ns);
+
+        let max_ns = (assigned_ns as u128 * (100 + self.tolerance_pct) as u128 / 100) as u64;
+        let max_ns = max_ns.saturating_add(self.nr_expired.saturating_mul(ACCOUNTING_SLACK_NS));
+        let min_ns = (assigned_ns as u128 * (100 - self.tolerance_pct) as u128 / 100) as u64;
+        if used_ns >= min_ns && used_ns <= max_ns {
+            if self.diverging() {
+                println!("accounting: the time slices are taking effect again");
+            }
+            self.nr_diverging = 0;
+            return;
+        }
+        self.nr_diverging += 1;
+        if self.nr_diverging == DIVERGENCE_INTERVALS {
+            println!(
+                "WARNING: tasks with an expired time slice used {}% of it for {} seconds \
+                 (tolerance {}%), the slices may be ignored or overridden by the BPF component",
+                self.usage_pct.unwrap_or(0),
+                DIVERGENCE_INTERVALS,
+                self.tolerance_pct
+            );
+        }
+    }
+
+    /// Print the reconciliation summary of the current interval and start a new interval.
+    pub fn report(&mut self) {
+        self.evaluate();
+        let pct = self
+            .used_ns
+            .saturating_mul(100)
+            .checked_div(self.assigned_ns)
+            .unwrap_or(0);
+
+        println!(
+            "accounting: assigned {}ms | used {}ms ({}%) | discrepancies: {} | expired slices: \
+             {} used {}",
+            self.assigned_ns / 1_000_000,
+            self.used_ns / 1_000_000,
+            pct,
+            self.nr_discrepancies,
+            self.nr_expired,
+            self.usage_pct
+                .map_or("-".to_string(), |pct| format!("{}%", pct))
+        );
+        self.assigned_ns = 0;
+        self.used_ns = 0;
+        self.nr_discrepancies = 0;
+        self.expired_assigned_ns = 0;
+        self.expired_used_ns = 0;
+        self.nr_expired = 0;
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Switch between the FIFO fast path and the configured policy according to the contention
+/// (see --policy-activation-threshold).
+///
+/// With a light load, ordering the queues by virtual runtime or deadline only adds overhead,
+/// since all the waiting tasks are dispatched in the same round anyway. The configured policy is
+/// engaged as soon as the amount of tasks waiting in the user-space queues reaches `thresh`, and
+/// it is disengaged (falling back to FIFO) only when it drops to half of the threshold, so that
+/// a load oscillating around the threshold doesn't keep switching between the two.
+pub struct PolicyActivation {
+    thresh: u64,         // Waiting tasks that engage the configured policy
+    active: bool,        // Configured policy engaged
+    nr_rounds: u64,      // Rounds evaluated (since the last report)
+    nr_fast_rounds: u64, // Rounds that used the FIFO fast path (since the last report)
+    nr_switches: u64,    // Policy engaged or disengaged (since the last report)
+}
+
+impl PolicyActivation {
+    pub fn new(thresh: u64) -> Self {
+        Self {
+            thresh,
+            active: false,
+            nr_rounds: 0,
+            nr_fast_rounds: 0,
+            nr_switches: 0,
+        }
+    }
+
+    /// Evaluate a round with `nr_waiting` tasks waiting to be dispatched and return true if the
+    /// configured policy needs to be applied.
+    pub fn update(&mut self, nr_waiting: u64) -> bool {
+        let active = if self.active {
+            nr_waiting > self.thresh / 2
+        } else {
+            nr_waiting >= self.thresh
+        };
+        if active != self.active {
+            self.active = active;
+            self.nr_switches += 1;
+        }
+        self.nr_rounds += 1;
+        if !active {
+            self.nr_fast_rounds += 1;
+        }
+
+        active
+    }
+
+    /// Print the share of rounds that used the FIFO fast path and the amount of switches,
+    /// resetting them.
+    pub fn report(&mut self) {
+        println!(
+            "policy activation: fast path {}/{} rounds | switches: {} | policy {}",
+            self.nr_fast_rounds,
+            self.nr_rounds,
+            self.nr_switches,
+            if self.active { "engaged" } else { "idle" }
+        );
+        self.nr_rounds = 0;
+        self.nr_fast_rounds = 0;
+        self.nr_switches = 0;
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+
+// Amount of time (in nanoseconds) used to profile the workload before selecting the first
+// profile.
+const AUTO_WARMUP_NS: u64 = 5_000_000_000;
+
+// Amount of time (in nanoseconds) between two consecutive evaluations of the workload.
+const AUTO_EVAL_NS: u64 = 10_000_000_000;
+
+// Minimum percentage of dispatches of interactive tasks to switch to the interactive profile.
+const AUTO_INTERACTIVE_PCT: u64 = 30;
+
+// Maximum percentage of dispatches of interactive tasks to switch to the batch profile.
+//
+// Workloads between AUTO_BATCH_PCT and AUTO_INTERACTIVE_PCT keep the current profile
+// (hysteresis), to avoid flapping between the two profiles.
+const AUTO_BATCH_PCT: u64 = 10;
+
+/// Scheduling profile selected by --mode auto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Interactive, // Fair policy with --compute-boost
+    Batch,       // FIFO policy with large time slices (--compute-max-slice-us)
+}
+
+/// Workload statistics collected during an evaluation window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkloadSample {
+    pub interactive: u64, // Dispatches of interactive tasks
+    pub batch: u64,       // Dispatches of batch tasks
+    pub max_queued: u64,  // Maximum amount of tasks waiting in the user-space queues
+}
+
+impl WorkloadSample {
+    /// Return the percentage of dispatches of interactive tasks (None if nothing has been
+    /// dispatched).
+    pub fn interactive_pct(&self) -> Option<u64> {
+        (self.interactive * 100).checked_div(self.interactive + self.batch)
+    }
+}
+
+/// Decide the profile that fits best the workload described by `sample`, given the current
+/// profile (None if no profile has been selected yet).
+///
+/// Return the new profile along with the reason of the switch, or None if the current profile
+/// needs to be kept.
+pub fn decide(sample: &WorkloadSample, current: Option<Profile>) -> Option<(Profile, String)> {
+    let interactive_pct = sample.interactive_pct()?;
+
+    let profile = if interactive_pct >= AUTO_INTERACTIVE_PCT {
+        Profile::Interactive
+    } else if interactive_pct < AUTO_BATCH_PCT || current.is_none() {
+        Profile::Batch
+    } else {
+        return None;
+    };
+    if current == Some(profile) {
+        return None;
+    }
+    let reason = format!(
+        "{}% interactive dispatches, up to {} queued tasks",
+        interactive_pct, sample.max_queued
+    );
+
+    Some((profile, reason))
+}
+
+/// Workload profiler used by --mode auto.
+///
+/// Profile switches are filtered by `hysteresis`, driven by the percentage of interactive
+/// dispatches, so that the profile is not changed too often.
+pub struct AutoMode {
+    profile: Option<Profile>, // Current profile (None = still warming up)
+    window_ts: u64,           // Beginning of the current evaluation window
+    sample: WorkloadSample,   // Statistics of the current evaluation window
+    hysteresis: Hysteresis,   // Anti-flapping filter of the profile switches
+}
+
+impl AutoMode {
+    pub fn new(now: u64, hysteresis: Hysteresis) -> Self {
+        Self {
+            profile: None,
+            window_ts: now,
+            sample: WorkloadSample::default(),
+            hysteresis,
+        }
+    }
+
+    /// Account a dispatched task.
+    pub fn record_dispatch(&mut self, interactive: bool, nr_queued: u64) {
+        if interactive {
+            self.sample.interactive += 1;
+        } else {
+            self.sample.batch += 1;
+        }
+        self.sample.max_queued = self.sample.max_queued.max(nr_queued);
+    }
+
+    /// Evaluate the workload at the end of each window and return the new profile (and the
+    /// reason of the switch) if the current profile needs to be changed.
+    pub fn evaluate(&mut self, now: u64) -> Option<(Profile, String)> {
+        let window_ns = if self.profile.is_none() {
+            AUTO_WARMUP_NS
+        } else {
+            AUTO_EVAL_NS
+        };
+        if now.saturating_sub(self.window_ts) < window_ns {
+            return None;
+        }
+        let decision = decide(&self.sample, self.profile).filter(|_| {
+            let interactive_pct = self.sample.interactive_pct().unwrap_or(0);
+            self.hysteresis.allow(now, interactive_pct)
+        });
+        if let Some((profile, _)) = &decision {
+            self.profile = Some(*profile);
+        }
+        self.window_ts = now;
+        self.sample = WorkloadSample::default();
+
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Return a sample with `interactive_pct` percent of interactive dispatches.
+    fn sample(interactive_pct: u64) -> WorkloadSample {
+        WorkloadSample {
+            interactive: interactive_pct,
+            batch: 100 - interactive_pct,
+            max_queued: 4,
+        }
+    }
+
+    #[test]
+    fn decide_thresholds() {
+        let interactive = Some(Profile::Interactive);
+        let batch = Some(Profile::Batch);
+        for (pct, current, expected) in [
+            (0, None, Some(Profile::Batch)),
+            (9, interactive, Some(Profile::Batch)),
+            (10, None, Some(Profile::Batch)),
+            (29, None, Some(Profile::Batch)),
+            (30, None, Some(Profile::Interactive)),
+            (30, batch, Some(Profile::Interactive)),
+            (100, batch, Some(Profile::Interactive)),
+            (9, batch, None),
+            (30, interactive, None),
+        ] {
+            let profile = decide(&sample(pct), current).map(|(profile, _)| profile);
+            assert_eq!(
+                profile, expected,
+                "{}% interactive, current {:?}",
+                pct, current
+            );
+        }
+    }
+
+    #[test]
+    fn dead_band_keeps_the_current_profile() {
+        for pct in AUTO_BATCH_PCT..AUTO_INTERACTIVE_PCT {
+            for current in [Profile::Interactive, Profile::Batch] {
+                assert_eq!(decide(&sample(pct), Some(current)), None, "{}%", pct);
+            }
+        }
+    }
+
+    #[test]
+    fn reason_and_empty_sample() {
+        let decision = decide(&sample(50), Some(Profile::Batch));
+        assert_eq!(
+            decision,
+            Some((
+                Profile::Interactive,
+                "50% interactive dispatches, up to 4 queued tasks".to_string()
+            ))
+        );
+        assert_eq!(decide(&WorkloadSample::default(), None), None);
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use libbpf_rs::OpenObject;
+
+use scx_utils::UserExitInfo;
+
+use crate::bpf::*;
+use crate::cpulist;
+use crate::isolation;
+use crate::proc_cache::ProcCache;
+use crate::slice_override;
+use crate::timetable;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Per-process flag of the kernel threads (see include/linux/sched.h).
+const PF_KTHREAD: u64 = 0x00200000;
+
+// Nice value of the scheduler when it is starved (the highest priority, see boost_self()).
+const SELF_BOOST_NICE: libc::c_int = -20;
+
+// Maximum time to wait for the information about a task that is needed as soon as the task is
+// received the first time (see ProcCache::get_wait()).
+const PROC_READ_WAIT: Duration = Duration::from_micros(500);
+
+/// Task received from the backend (see QueuedTask).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub pid: i32,              // pid that uniquely identifies a task
+    pub cpu: i32,              // CPU previously used by the task
+    pub flags: u64,            // task enqueue flags
+    pub sum_exec_runtime: u64, // Total cpu time in nanoseconds
+    pub nvcsw: u64,            // Total amount of voluntary context switches
+    pub weight: u64,           // Task priority in the range [1..10000] (default is 100)
+    pub slice: u64,            // Remaining time slice budget
+    pub vtime: u64,            // Current task vruntime / deadline
+}
+
+/// Task dispatched to the backend (see DispatchedTask).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dispatch {
+    pub pid: i32,      // pid that uniquely identifies a task
+    pub cpu: i32,      // target CPU selected by the scheduler
+    pub flags: u64,    // special dispatch flags
+    pub slice_ns: u64, // time slice in nanoseconds assigned to the task (0 = BPF default)
+    pub vtime: u64,    // task's vruntime or deadline
+}
+
+/// Error returned when a task can't be dispatched.
+#[derive(Debug)]
+pub enum DispatchError {
+    /// The backend can't accept the task right now (e.g., the dispatch queue is full), the
+    /// dispatch can be retried later.
+    Busy,
+    /// Unrecoverable error.
+    Fatal(anyhow::Error),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DispatchError::Busy => write!(f, "dispatch queue full"),
+            DispatchError::Fatal(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+/// Interface between the scheduling policy and the sched_ext BPF component.
+///
+/// The scheduler only interacts with the BPF component via this trait, so that the same policy
+/// can also be driven by a mock backend (see mock.rs), e.g., to verify it without attaching to
+/// the kernel.
+pub trait SchedBackend {
+    /// Consume a task that wants to run.
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32>;
+
+    /// Select an idle CPU for a task (negative if no idle CPU can be found).
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, flags: u64) -> i32;
+
+    /// Dispatch a task.
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError>;
+
+    /// Give control to the BPF component and report the number of tasks that are still pending.
+    fn notify_complete(&mut self, nr_pending: u64);
+
+    /// Return true if the scheduler needs to exit.
+    fn exited(&mut self) -> bool;
+
+    /// Return true if a task exists.
+    fn exists(&mut self, pid: i32) -> bool;
+
+    /// Return the thread group id (process id) of a task (None if the task doesn't exist).
+    ///
+    /// NOTE: the information about the tasks (from here to comm()) may not be known yet when a
+    /// task is looked up, it is then reported as None, as if the task didn't exist, and it can
+    /// be looked up again later (see proc_cache.rs).
+    fn tgid(&mut self, pid: i32) -> Option<i32>;
+
+    /// Return the CPUs that a task is allowed to use (None if the task doesn't exist).
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>>;
+
+    /// Return the CPU where the parent of a task last ran, i.e., the waker of a brand-new task
+    /// (None if the task or its parent don't exist).
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32>;
+
+    /// Return true if a task is a kernel thread (None if the task doesn't exist).
+    fn is_kthread(&mut self, pid: i32) -> Option<bool>;
+
+    /// Return the value of the scheduling hint `name` set by a task in its environment (None if
+    /// the task doesn't exist or didn't set the hint, see slice_override.rs).
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64>;
+
+    /// Return the name (comm) of a task (None if the task doesn't exist).
+    fn comm(&mut self, pid: i32) -> Option<String>;
+
+    /// Return the time (CLOCK_MONOTONIC, in nanoseconds) when the task `pid` started running on a
+    /// CPU after its last dispatch (None if the backend doesn't know it, see run_latency.rs).
+    ///
+    /// NOTE: scx_rustland_core doesn't report when the dispatched tasks start running yet, so
+    /// the BPF backend always returns None and the dispatch-to-run latency is estimated.
+    fn run_start_ns(&mut self, _pid: i32) -> Option<u64> {
+        None
+    }
+
+    /// Return the task (pid) that woke up the task `pid` the last time (None if unknown, see
+    /// wake_affine.rs).
+    ///
+    /// NOTE: scx_rustland_core doesn't report the waker of the tasks yet, so the BPF backend
+    /// always returns None and the waker is approximated by the threads of the same process.
+    fn waker(&mut self, _pid: i32) -> Option<i32> {
+        None
+    }
+
+    /// Raise the priority of the scheduler itself (see --loop-gap-ms), return true on success.
+    fn boost_self(&mut self) -> bool {
+        false
+    }
+
+    /// Return the CPUs that are currently online (None if they can't be determined).
+    fn online_cpus(&mut self) -> Option<Vec<usize>>;
+
+    /// Return the CPUs isolated on the kernel command line (None if they can't be determined),
+    /// see isolation::parse_cmdline().
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>>;
+
+    /// Return the time of the day of the wall clock (minutes since midnight, local time), that
+    /// selects the profile of --profile-at (see timetable.rs).
+    fn local_minute(&mut self) -> u32;
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64;
+    fn nr_queued_mut(&mut self) -> &mut u64;
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64;
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64;
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64;
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64;
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64;
+
+    /// Return the current timestamp in nanoseconds.
+    ///
+    /// NOTE: all the internal timing is based on CLOCK_MONOTONIC, rather than the wall clock
+    /// (SystemTime), since the wall clock can jump (NTP steps, manual clock changes), corrupting
+    /// the time deltas used by the scheduler (stats intervals, waiting times, task statistics).
+    fn now_ns(&self) -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+
+        ts.tv_sec as u64 * NSEC_PER_SEC + ts.tv_nsec as u64
+    }
+
+    /// Return the CPU time (in nanoseconds) consumed so far by the scheduler process itself.
+    fn self_cpu_ns(&self) -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) };
+
+        ts.tv_sec as u64 * NSEC_PER_SEC + ts.tv_nsec as u64
+    }
+
+    /// Return the current time of the wall clock (CLOCK_REALTIME) in nanoseconds.
+    ///
+    /// NOTE: only used to measure the age of the state saved across restarts (see snapshot.rs),
+    /// the only timestamps that must be compared across different runs of the scheduler.
+    fn realtime_ns(&self) -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts) };
+
+        ts.tv_sec as u64 * NSEC_PER_SEC + ts.tv_nsec as u64
+    }
+
+    /// Return the identifier of the current boot of the system (None if unknown).
+    fn boot_id(&self) -> Option<String> {
+        let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+
+        Some(boot_id.trim().to_string())
+    }
+}
+
+/// Backend connected to the sched_ext BPF component (via scx_rustland_core).
+///
+/// The information about the tasks is read from /proc by side threads and only looked up by
+/// the scheduler (see proc_cache.rs), except for the existence of the tasks, that doesn't
+/// depend on the state of the tasks.
+pub struct BpfBackend<'a> {
+    bpf: BpfScheduler<'a>,                // Connector to the sched_ext BPF backend
+    queued: HashMap<i32, QueuedTask>,     // Tasks received and not dispatched yet
+    tgids: ProcCache<i32, i32>,           // Process of the tasks
+    cpusets: ProcCache<i32, Vec<usize>>,  // CPUs that the tasks are allowed to use
+    parent_cpus: ProcCache<i32, i32>,     // CPU where the parent of the tasks last ran
+    kthreads: ProcCache<i32, bool>,       // Kernel thread flag of the tasks
+    hints: ProcCache<(i32, String), u64>, // Scheduling hints of the tasks
+    comms: ProcCache<i32, String>,        // Names of the tasks
+}
+
+impl<'a> BpfBackend<'a> {
+    pub fn init(
+        open_object: &'a mut MaybeUninit<OpenObject>,
+        exit_dump_len: u32,
+        partial: bool,
+        debug: bool,
+    ) -> Result<Self> {
+        let bpf = BpfScheduler::init(open_object, exit_dump_len, partial, debug)?;
+
+        Ok(Self {
+            bpf,
+            queued: HashMap::new(),
+            tgids: ProcCache::new(|&pid| read_tgid(pid)),
+            cpusets: ProcCache::new(|&pid| read_allowed_cpus(pid)),
+            parent_cpus: ProcCache::new(|&pid| read_parent_cpu(pid)),
+            kthreads: ProcCache::new(|&pid| read_is_kthread(pid)),
+            hints: ProcCache::new(|(pid, name): &(i32, String)| slice_override::read(*pid, name)),
+            comms: ProcCache::new(|&pid| read_comm(pid)),
+        })
+    }
+
+    pub fn shutdown_and_report(&mut self) -> Result<UserExitInfo> {
+        self.bpf.shutdown_and_report()
+    }
+}
+
+impl SchedBackend for BpfBackend<'_> {
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32> {
+        let Some(queued) = self.bpf.dequeue_task()? else {
+            return Ok(None);
+        };
+        let task = Task {
+            pid: queued.pid,
+            cpu: queued.cpu,
+            flags: queued.flags,
+            sum_exec_runtime: queued.sum_exec_runtime,
+            nvcsw: queued.nvcsw,
+            weight: queued.weight,
+            slice: queued.slice,
+            vtime: queued.vtime,
+        };
+        // Keep the original task around: a DispatchedTask can only be created from the
+        // QueuedTask it originates from.
+        self.queued.insert(queued.pid, queued);
+
+        Ok(Some(task))
+    }
+
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, flags: u64) -> i32 {
+        self.bpf.select_cpu(pid, prev_cpu, flags)
+    }
+
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError> {
+        let Some(queued) = self.queued.get(&task.pid) else {
+            return Err(DispatchError::Fatal(anyhow!(
+                "pid {} dispatched without being queued",
+                task.pid
+            )));
+        };
+
+        let mut dispatched_task = DispatchedTask::new(queued);
+        dispatched_task.cpu = task.cpu;
+        dispatched_task.flags = task.flags;
+        dispatched_task.slice_ns = task.slice_ns;
+        dispatched_task.vtime = task.vtime;
+
+        // NOTE: dispatch_task() can only fail when a slot can't be reserved in the user ring
+        // buffer used to send the dispatched tasks to the BPF component; this is transient if the
+        // ring buffer is full (ENOSPC), that libbpf-rs only reports via the error message.
+        match self.bpf.dispatch_task(&dispatched_task) {
+            Ok(()) => {
+                self.queued.remove(&task.pid);
+                Ok(())
+            }
+            Err(err) if err.to_string().contains("not enough space") => Err(DispatchError::Busy),
+            Err(err) => Err(DispatchError::Fatal(err.into())),
+        }
+    }
+
+    fn notify_complete(&mut self, nr_pending: u64) {
+        self.bpf.notify_complete(nr_pending);
+    }
+
+    fn exited(&mut self) -> bool {
+        self.bpf.exited()
+    }
+
+    // Only a lookup of the pid in /proc, that never reads the state of the task.
+    fn exists(&mut self, pid: i32) -> bool {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    }
+
+    fn tgid(&mut self, pid: i32) -> Option<i32> {
+        self.tgids.get(&pid)
+    }
+
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>> {
+        self.cpusets.get(&pid)
+    }
+
+    // Needed as soon as a brand-new task is received (see --initial-cpu).
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32> {
+        self.parent_cpus.get_wait(&pid, PROC_READ_WAIT)
+    }
+
+    // Needed as soon as a task is received the first time (see --skip-kthreads).
+    fn is_kthread(&mut self, pid: i32) -> Option<bool> {
+        self.kthreads.get_wait(&pid, PROC_READ_WAIT)
+    }
+
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64> {
+        self.hints.get(&(pid, name.to_string()))
+    }
+
+    fn comm(&mut self, pid: i32) -> Option<String> {
+        self.comms.get(&pid)
+    }
+
+    // The nice value is per-thread on Linux: only the main loop of the scheduler (the calling
+    // thread) is boosted.
+    fn boost_self(&mut self) -> bool {
+        unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, SELF_BOOST_NICE) == 0 }
+    }
+
+    fn online_cpus(&mut self) -> Option<Vec<usize>> {
+        let online = fs::read_to_string("/sys/devices/system/cpu/online").ok()?;
+
+        cpulist::parse(online.trim()).ok().map(|cpus| cpus.0)
+    }
+
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>> {
+        let cmdline = fs::read_to_string(isolation::CMDLINE_PATH).ok()?;
+
+        Some(isolation::parse_cmdline(&cmdline))
+    }
+
+    fn local_minute(&mut self) -> u32 {
+        timetable::local_minute()
+    }
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_online_cpus_mut()
+    }
+
+    fn nr_queued_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_queued_mut()
+    }
+
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_user_dispatches_mut()
+    }
+
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_kernel_dispatches_mut()
+    }
+
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_bounce_dispatches_mut()
+    }
+
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_cancel_dispatches_mut()
+    }
+
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64 {
+        self.bpf.nr_failed_dispatches_mut()
+    }
+}
+
+// Read the thread group id (process id) of a task from /proc.
+fn read_tgid(pid: i32) -> Option<i32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))
+        .and_then(|tgid| tgid.trim().parse().ok())
+}
+
+// Read the CPUs that a task is allowed to use from /proc.
+fn read_allowed_cpus(pid: i32) -> Option<Vec<usize>> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+        .and_then(|cpus| cpulist::parse(cpus).ok())
+        .map(|cpus| cpus.0)
+}
+
+// Read the CPU where the parent of a task last ran from /proc.
+fn read_parent_cpu(pid: i32) -> Option<i32> {
+    let ppid = proc_stat(pid)?.get(1)?.parse().ok()?;
+
+    proc_stat(ppid)?.get(36)?.parse().ok()
+}
+
+// Read the kernel thread flag of a task from /proc.
+fn read_is_kthread(pid: i32) -> Option<bool> {
+    let flags: u64 = proc_stat(pid)?.get(6)?.parse().ok()?;
+
+    Some(flags & PF_KTHREAD != 0)
+}
+
+// Read the name (comm) of a task from /proc.
+fn read_comm(pid: i32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+// Return the fields of /proc/<pid>/stat that follow the comm of a task (the comm can contain
+// spaces and parentheses), starting from the state (field 3, see proc(5)).
+fn proc_stat(pid: i32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, fields) = stat.rsplit_once(')')?;
+
+    Some(fields.split_whitespace().map(String::from).collect())
+}
@@ -0,0 +1,102 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Maximum factor applied to the time slices (and divisor of the tasks dispatched per round)
+// while relieving the pressure on the BPF component.
+const BACKPRESSURE_MAX_SCALE: u64 = 8;
+
+/// Response to the dispatches failed by the BPF component (see --failed-dispatch-thresh).
+///
+/// A failed dispatch is a task that the BPF component couldn't dispatch at all, unlike a bounced
+/// dispatch (redirected to the shared DSQ) or a cancelled one (the task changed while being
+/// dispatched), that still complete: rising failures mean that the scheduler dispatches faster
+/// than the BPF component can consume the tasks.
+///
+/// When the failed dispatches exceed `thresh` per second in a one-second interval, a warning is
+/// printed and the pressure on the BPF component is relieved: the amount of tasks dispatched per
+/// round is halved and the time slices are doubled (up to BACKPRESSURE_MAX_SCALE times), so that
+/// fewer dispatches are needed; both are restored, one step at a time, after an interval without
+/// failures. Scale changes are filtered by `hysteresis`, driven by the failure rate.
+pub struct Backpressure {
+    thresh: u64,            // Failed dispatches per second that trigger the response
+    hysteresis: Hysteresis, // Anti-flapping filter of the scale
+    interval_ts: u64,       // Beginning of the current interval
+    interval_failed: u64,   // Failed dispatches at the beginning of the interval
+    last_rate: u64,         // Failed dispatches per second in the last completed interval
+    scale: u64,             // Divisor of the tasks per round, factor of the time slices
+}
+
+impl Backpressure {
+    pub fn new(thresh: u64, hysteresis: Hysteresis, now: u64, nr_failed: u64) -> Self {
+        Self {
+            thresh,
+            hysteresis,
+            interval_ts: now,
+            interval_failed: nr_failed,
+            last_rate: 0,
+            scale: 1,
+        }
+    }
+
+    /// Return the divisor of the amount of tasks dispatched per round and the factor applied to
+    /// the time slices (1 while the BPF component keeps up).
+    pub fn scale(&self) -> u64 {
+        self.scale
+    }
+
+    /// Evaluate the current interval (once per second), given the amount of failed dispatches
+    /// reported so far by the BPF component (`nr_failed`).
+    pub fn evaluate(&mut self, now: u64, nr_failed: u64) {
+        let elapsed = now.saturating_sub(self.interval_ts);
+        if elapsed < NSEC_PER_SEC {
+            return;
+        }
+        let delta = nr_failed.saturating_sub(self.interval_failed);
+        let rate = (delta as u128 * NSEC_PER_SEC as u128 / elapsed as u128) as u64;
+
+        if rate > self.thresh
+            && self.scale < BACKPRESSURE_MAX_SCALE
+            && self.hysteresis.allow(now, rate)
+        {
+            self.scale *= 2;
+            println!(
+                "WARNING: {} failed dispatches/s above {}, tasks per round divided by {} and \
+                 time slices scaled by {}x",
+                rate, self.thresh, self.scale, self.scale
+            );
+        } else if rate == 0 && self.scale > 1 && self.hysteresis.allow(now, rate) {
+            self.scale /= 2;
+            println!(
+                "backpressure: no failed dispatches, tasks per round divided by {} and time \
+                 slices scaled by {}x",
+                self.scale, self.scale
+            );
+        }
+
+        self.interval_ts = now;
+        self.interval_failed = nr_failed;
+        self.last_rate = rate;
+    }
+
+    /// Print the failure rate of the last completed interval and the state of the response.
+    pub fn report(&self) {
+        let state = match self.scale {
+            1 => "enabled, inactive".to_string(),
+            scale => format!(
+                "active, 1/{} tasks per round, {}x time slices",
+                scale, scale
+            ),
+        };
+
+        println!(
+            "failed dispatches/s: {} | auto-response: {}",
+            self.last_rate, state
+        );
+    }
+}
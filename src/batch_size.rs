@@ -0,0 +1,123 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// Upper bounds of the batch size histogram buckets, batches above the last bound are accounted
+// in an additional overflow bucket.
+const BATCH_BUCKETS: [u64; 6] = [1, 2, 4, 8, 16, 32];
+const BATCH_BUCKETS_LABEL: &str = "0/1/2-3/4-7/8-15/16-31/>=32";
+const NR_BATCH_BUCKETS: usize = BATCH_BUCKETS.len() + 1;
+
+// Batch sizes of the rounds of an interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct BatchSummary {
+    nr_rounds: u64,                // Rounds completed
+    nr_dispatches: u64,            // Tasks dispatched in all the rounds
+    min: u64,                      // Smallest batch
+    max: u64,                      // Largest batch
+    hist: [u64; NR_BATCH_BUCKETS], // Rounds per batch size bucket
+}
+
+/// Distribution of the amount of tasks dispatched in each scheduling round (call of
+/// schedule()), sampled at every stats interval.
+///
+/// Small batches suggest a light load (the scheduler is woken up for one task at a time), large
+/// batches a bursty arrival of the tasks (many tasks queued while the scheduler was sleeping),
+/// that helps to tune the limits of the rounds (e.g., --max-dequeue-per-round and
+/// --round-budget-us). The rounds without dispatches are accounted as empty batches.
+pub struct BatchSizes {
+    batch: u64,            // Tasks dispatched in the current round
+    current: BatchSummary, // Rounds of the current interval
+    last: BatchSummary,    // Rounds of the last interval
+}
+
+impl BatchSizes {
+    pub fn new() -> Self {
+        Self {
+            batch: 0,
+            current: BatchSummary::default(),
+            last: BatchSummary::default(),
+        }
+    }
+
+    /// Account a task dispatched in the current round.
+    pub fn record_dispatch(&mut self) {
+        self.batch += 1;
+    }
+
+    /// Complete the current round, accounting its batch in the current interval, and return the
+    /// tasks dispatched in the round.
+    pub fn end_round(&mut self) -> u64 {
+        let batch = std::mem::take(&mut self.batch);
+        let summary = &mut self.current;
+        summary.min = if summary.nr_rounds == 0 {
+            batch
+        } else {
+            summary.min.min(batch)
+        };
+        summary.max = summary.max.max(batch);
+        summary.nr_rounds += 1;
+        summary.nr_dispatches += batch;
+        let bucket = BATCH_BUCKETS
+            .iter()
+            .position(|&bound| batch < bound)
+            .unwrap_or(NR_BATCH_BUCKETS - 1);
+        summary.hist[bucket] += 1;
+
+        batch
+    }
+
+    /// Complete the current interval and start a new one.
+    pub fn sample(&mut self) {
+        self.last = std::mem::take(&mut self.current);
+    }
+
+    /// Return the smallest, mean and largest batch of the last interval, None without rounds.
+    pub fn summary(&self) -> Option<(u64, f64, u64)> {
+        let last = &self.last;
+
+        (last.nr_rounds > 0).then(|| {
+            let mean = last.nr_dispatches as f64 / last.nr_rounds as f64;
+            (last.min, mean, last.max)
+        })
+    }
+
+    /// Return the rounds of the last interval per batch size bucket (see BATCH_BUCKETS).
+    pub fn histogram(&self) -> [u64; NR_BATCH_BUCKETS] {
+        self.last.hist
+    }
+
+    /// Print the distribution of the batch sizes of the last interval.
+    pub fn report(&self) {
+        let Some((min, mean, max)) = self.summary() else {
+            return;
+        };
+        let counts: Vec<String> = self.last.hist.iter().map(|n| n.to_string()).collect();
+        println!(
+            "dispatch batch -> rounds: {} | min: {} | mean: {:.1} | max: {} ({}: {})",
+            self.last.nr_rounds,
+            min,
+            mean,
+            max,
+            BATCH_BUCKETS_LABEL,
+            counts.join("/")
+        );
+    }
+
+    /// Format the distribution of the last interval as a JSON object (null min, mean and max
+    /// without rounds).
+    pub fn json(&self) -> String {
+        let summary = self.summary();
+        let counts: Vec<String> = self.last.hist.iter().map(|n| n.to_string()).collect();
+
+        format!(
+            "{{\"rounds\":{},\"min\":{},\"mean\":{},\"max\":{},\"histogram\":[{}]}}",
+            self.last.nr_rounds,
+            summary.map_or("null".to_string(), |(min, _, _)| min.to_string()),
+            summary.map_or("null".to_string(), |(_, mean, _)| format!("{:.3}", mean)),
+            summary.map_or("null".to_string(), |(_, _, max)| max.to_string()),
+            counts.join(",")
+        )
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// Global budget of the wakeup credit granted to the tasks (see --boost-budget-us).
+///
+/// With the fair policy, a task that has been sleeping can get up to one time slice of virtual
+/// runtime credit with respect to the minimum virtual runtime, so that it runs ahead of the
+/// tasks that kept using the CPU (see Scheduler::update_vtime()). Individually the credit is
+/// bounded, but many tasks waking up at the same time can get it all together, crowding out the
+/// CPU-bound tasks.
+///
+/// The budget bounds the total credit granted in each one-second interval: once the budget is
+/// exhausted, further credits are reduced to what is left of it (down to none) until the next
+/// interval.
+pub struct BoostBudget {
+    budget_ns: u64,   // Total credit that can be granted in one interval
+    interval_ts: u64, // Beginning of the current interval
+    granted_ns: u64,  // Credit granted in the current interval
+    nr_capped: u64,   // Credits reduced by the budget (since the last report)
+}
+
+impl BoostBudget {
+    pub fn new(budget_ns: u64, now: u64) -> Self {
+        Self {
+            budget_ns,
+            interval_ts: now,
+            granted_ns: 0,
+            nr_capped: 0,
+        }
+    }
+
+    /// Return the part of a credit of `credit_ns` requested at time `now` that fits in the
+    /// budget of the current interval (that is consumed accordingly).
+    pub fn grant(&mut self, now: u64, credit_ns: u64) -> u64 {
+        if now.saturating_sub(self.interval_ts) >= NSEC_PER_SEC {
+            self.interval_ts = now;
+            self.granted_ns = 0;
+        }
+        let granted_ns = credit_ns.min(self.budget_ns.saturating_sub(self.granted_ns));
+        if granted_ns < credit_ns {
+            self.nr_capped += 1;
+        }
+        self.granted_ns += granted_ns;
+
+        granted_ns
+    }
+
+    /// Print the credit granted in the current interval and the amount of capped credits,
+    /// resetting the latter.
+    pub fn report(&mut self) {
+        if self.nr_capped > 0 {
+            println!(
+                "boost budget: {}us of {}us granted | capped credits: {}",
+                self.granted_ns / 1000,
+                self.budget_ns / 1000,
+                self.nr_capped
+            );
+        }
+        self.nr_capped = 0;
+    }
+}
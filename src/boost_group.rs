@@ -0,0 +1,92 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+/// Maximum boost level of a group (factor applied to the weight of its members).
+pub const BOOST_GROUP_MAX_LEVEL: u64 = 100;
+
+// Tasks boosted together at the same level.
+struct BoostGroup {
+    level: u64,         // Factor applied to the weight of the members
+    pids: HashSet<i32>, // Members of the group
+}
+
+/// Groups of tasks boosted by an external controller (see the boost control command), e.g., a
+/// game mode daemon boosting all the threads of a game, or a build system boosting the jobs of
+/// the current target.
+///
+/// The members of a group are treated as interactive tasks (dispatched ahead of the batch
+/// tasks) and their weight is multiplied by the level of the group (with the fair and wrr
+/// policies), until the group is cleared; a task that belongs to several groups gets the
+/// highest level. The tasks that exit are removed from their groups, and a group without
+/// members is dropped.
+pub struct BoostGroups {
+    groups: BTreeMap<String, BoostGroup>, // Groups by name
+}
+
+impl BoostGroups {
+    pub fn new() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Define the group `name` (replacing its previous definition) with the tasks `pids` boosted
+    /// at `level`.
+    pub fn set(&mut self, name: &str, level: u64, pids: impl IntoIterator<Item = i32>) {
+        let pids = pids.into_iter().collect();
+        self.groups
+            .insert(name.to_string(), BoostGroup { level, pids });
+    }
+
+    /// Clear the group `name`, return false if it doesn't exist.
+    pub fn clear(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Return the boost level of the task `pid` (1 if it doesn't belong to any group).
+    pub fn level(&self, pid: i32) -> u64 {
+        self.groups
+            .values()
+            .filter(|group| group.pids.contains(&pid))
+            .map(|group| group.level)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Return true if the task `pid` belongs to a group.
+    pub fn is_boosted(&self, pid: i32) -> bool {
+        self.groups.values().any(|group| group.pids.contains(&pid))
+    }
+
+    /// Return all the members of the groups.
+    pub fn pids(&self) -> HashSet<i32> {
+        self.groups
+            .values()
+            .flat_map(|group| group.pids.iter().copied())
+            .collect()
+    }
+
+    /// Remove the tasks that are not `alive` from the groups and return the names of the
+    /// groups left without members (that are dropped).
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) -> Vec<String> {
+        for group in self.groups.values_mut() {
+            group.pids.retain(|&pid| alive(pid));
+        }
+        let empty: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|(_, group)| group.pids.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &empty {
+            self.groups.remove(name);
+        }
+
+        empty
+    }
+}
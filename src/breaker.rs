@@ -0,0 +1,61 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Circuit breaker of the calls to notify_complete() (see --notify-stall-ms).
+///
+/// notify_complete() gives control back to the BPF component and can put the scheduler to
+/// sleep, until another task needs to run. If it starts taking abnormally long (e.g., because of
+/// a kernel issue or contention), the scheduler stalls without any other symptom: the duration of
+/// each call is measured and, once `max_slow` consecutive calls exceed the threshold, the breaker
+/// trips and the scheduler is restarted (a single slow call doesn't trip it).
+///
+/// Only the calls made while tasks are waiting to be scheduled must be recorded: on an idle
+/// system the scheduler can legitimately sleep for any amount of time.
+pub struct NotifyBreaker {
+    thresh_ns: u64, // Duration of a slow call
+    max_slow: u64,  // Consecutive slow calls that trip the breaker
+    nr_slow: u64,   // Current streak of slow calls
+    max_ns: u64,    // Longest call of the current streak
+    tripped: bool,  // The scheduler needs to be restarted
+}
+
+impl NotifyBreaker {
+    pub fn new(thresh_ns: u64, max_slow: u64) -> Self {
+        Self {
+            thresh_ns,
+            max_slow,
+            nr_slow: 0,
+            max_ns: 0,
+            tripped: false,
+        }
+    }
+
+    /// Account a call to notify_complete() that took `duration_ns`.
+    pub fn record(&mut self, duration_ns: u64) {
+        if duration_ns <= self.thresh_ns {
+            self.nr_slow = 0;
+            self.max_ns = 0;
+            return;
+        }
+        self.nr_slow += 1;
+        self.max_ns = self.max_ns.max(duration_ns);
+        if self.nr_slow < self.max_slow || self.tripped {
+            return;
+        }
+        self.tripped = true;
+        println!(
+            "WARNING: notify_complete() took more than {}ms {} times in a row (up to {}ms), \
+             restarting the scheduler",
+            self.thresh_ns / 1_000_000,
+            self.nr_slow,
+            self.max_ns / 1_000_000
+        );
+    }
+
+    /// Return true if the breaker has tripped (the scheduler needs to be restarted).
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+}
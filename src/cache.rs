@@ -0,0 +1,290 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Per-CPU cache miss rates, used to keep the cache-sensitive tasks away from the CPUs that are
+//! thrashing their cache (see --cache-aware).
+//!
+//! The cache misses and the retired instructions of each CPU are counted by the hardware
+//! performance counters, opened with perf_event_open() (directly through libc, like the other
+//! system calls used by the scheduler) when the scheduler is attached. The miss rate of a CPU is
+//! expressed in misses per thousand instructions (MPKI).
+//!
+//! The PMU has a limited amount of counters: when more events are requested (e.g., by other
+//! perf users), the kernel multiplexes them and each counter only counts for a fraction of the
+//! time. The counts of each interval are therefore scaled by the time the counter has been
+//! enabled over the time it has actually been counting, so that the miss rates of the CPUs
+//! remain comparable.
+//!
+//! Permissions: CPU-wide counters (pid = -1) require CAP_PERFMON (or CAP_SYS_ADMIN on kernels
+//! older than 5.8), or kernel.perf_event_paranoid set to 0 or lower. Hardware counters may also
+//! be unavailable (e.g., in virtual machines that don't expose a PMU): if no counter can be
+//! opened, a warning is printed and the scheduler runs without --cache-aware.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+
+// perf_event_attr constants (see include/uapi/linux/perf_event.h).
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+const PERF_ATTR_SIZE_VER0: u32 = 64;
+const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+// First version of struct perf_event_attr (PERF_ATTR_SIZE_VER0), that is enough to count a
+// hardware event (all the flags cleared: the counter is enabled and counts user and kernel
+// events). The counters are read along with their enabled and running times (see Count).
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+// Open a counter of the hardware event `config` on `cpu` (for all the tasks).
+fn open_counter(config: u64, cpu: usize) -> io::Result<File> {
+    let attr = PerfEventAttr {
+        type_: PERF_TYPE_HARDWARE,
+        size: PERF_ATTR_SIZE_VER0,
+        config,
+        read_format: PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+        ..Default::default()
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr as *const PerfEventAttr,
+            -1 as libc::pid_t,
+            cpu as libc::c_int,
+            -1 as libc::c_int,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+}
+
+/// Value of a counter, along with the time it has been enabled and the time it has actually
+/// been counting (less than the former when the counters are multiplexed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Count {
+    value: u64,      // Events counted
+    enabled_ns: u64, // Time the counter has been enabled
+    running_ns: u64, // Time the counter has been counting
+}
+
+impl Count {
+    /// Return the events counted since `prev`, scaled by the time the counter has been enabled
+    /// over the time it has been counting in the meantime (None if it has not been counting at
+    /// all, so that the amount of events is unknown).
+    fn since(&self, prev: &Count) -> Option<u64> {
+        let value = self.value.saturating_sub(prev.value);
+        let enabled_ns = self.enabled_ns.saturating_sub(prev.enabled_ns);
+        let running_ns = self.running_ns.saturating_sub(prev.running_ns);
+        if running_ns == 0 {
+            return None;
+        }
+
+        Some((value as u128 * enabled_ns.max(running_ns) as u128 / running_ns as u128) as u64)
+    }
+}
+
+// Read the current value of a counter (see PERF_FORMAT_TOTAL_TIME_ENABLED and
+// PERF_FORMAT_TOTAL_TIME_RUNNING).
+fn read_counter(mut file: &File) -> Option<Count> {
+    let mut buf = [0u8; 24];
+    file.read_exact(&mut buf).ok()?;
+    let field = |i: usize| u64::from_ne_bytes(buf[i * 8..(i + 1) * 8].try_into().unwrap());
+
+    Some(Count {
+        value: field(0),
+        enabled_ns: field(1),
+        running_ns: field(2),
+    })
+}
+
+/// Hardware counters of the cache misses and of the instructions of each CPU.
+pub struct PerfCounters {
+    cpus: Vec<Option<(File, File)>>, // Counters of each CPU (misses, instructions)
+}
+
+impl PerfCounters {
+    /// Open the counters of the first `nr_cpus` CPUs, failing if no CPU has them.
+    pub fn open(nr_cpus: usize) -> io::Result<Self> {
+        let mut err = None;
+        let cpus: Vec<_> = (0..nr_cpus)
+            .map(|cpu| {
+                let misses = open_counter(PERF_COUNT_HW_CACHE_MISSES, cpu);
+                let instructions = open_counter(PERF_COUNT_HW_INSTRUCTIONS, cpu);
+                match (misses, instructions) {
+                    (Ok(misses), Ok(instructions)) => Some((misses, instructions)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        err.get_or_insert(e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        if let (Some(err), true) = (err, cpus.iter().all(Option::is_none)) {
+            return Err(err);
+        }
+
+        Ok(Self { cpus })
+    }
+
+    /// Return the cache misses and the instructions counted so far on each CPU (None if the CPU
+    /// has no counters or they can't be read).
+    pub fn read(&self) -> Vec<Option<(Count, Count)>> {
+        self.cpus
+            .iter()
+            .map(|counters| {
+                let (misses, instructions) = counters.as_ref()?;
+                Some((read_counter(misses)?, read_counter(instructions)?))
+            })
+            .collect()
+    }
+}
+
+/// Classifier of the CPUs that are thrashing their cache.
+///
+/// A CPU is thrashing when its miss rate reaches `thresh_mpki` in a sampling interval and it is
+/// not thrashing anymore when its miss rate drops below half of the threshold, so that a miss
+/// rate oscillating around the threshold doesn't keep changing the placement of the tasks.
+///
+/// The cache-sensitive tasks that would run on a thrashing CPU start their idle CPU search from
+/// the CPU with the lowest miss rate instead (see pick_cpu()).
+pub struct CacheMonitor {
+    counters: Option<PerfCounters>, // Hardware counters (None = miss rates provided directly)
+    thresh_mpki: u64,               // Miss rate that marks a CPU as thrashing
+    prev: Vec<Option<(Count, Count)>>, // Counters at the previous sample
+    mpki: Vec<Option<u64>>,         // Miss rate of each CPU in the last interval
+    thrashing: Vec<bool>,           // CPUs thrashing their cache
+    nr_avoided: u64,                // Tasks moved away from a thrashing CPU (since last report)
+}
+
+impl CacheMonitor {
+    pub fn new(counters: Option<PerfCounters>, thresh_mpki: u64) -> Self {
+        let prev = counters.as_ref().map_or(Vec::new(), PerfCounters::read);
+
+        Self {
+            counters,
+            thresh_mpki,
+            prev,
+            mpki: Vec::new(),
+            thrashing: Vec::new(),
+            nr_avoided: 0,
+        }
+    }
+
+    /// Read the counters and update the miss rates of the interval since the previous sample.
+    pub fn sample(&mut self) {
+        let Some(counters) = self.counters.as_ref() else {
+            return;
+        };
+        let curr = counters.read();
+        let mpki = curr
+            .iter()
+            .zip(&self.prev)
+            .map(|(curr, prev)| {
+                let ((misses, instructions), (prev_misses, prev_instructions)) = curr.zip(*prev)?;
+                let misses = misses.since(&prev_misses)?;
+                let instructions = instructions.since(&prev_instructions)?;
+                (instructions > 0).then(|| misses.saturating_mul(1000) / instructions)
+            })
+            .collect::<Vec<_>>();
+        self.prev = curr;
+        self.update(&mpki);
+    }
+
+    /// Update the miss rates of the CPUs (None if unknown) and their thrashing state.
+    pub fn update(&mut self, mpki: &[Option<u64>]) {
+        self.thrashing.resize(mpki.len(), false);
+        for (thrashing, mpki) in self.thrashing.iter_mut().zip(mpki) {
+            *thrashing = match *mpki {
+                Some(mpki) if *thrashing => mpki >= self.thresh_mpki / 2,
+                Some(mpki) => mpki >= self.thresh_mpki,
+                None => false,
+            };
+        }
+        self.mpki = mpki.to_vec();
+    }
+
+    /// Return true if `cpu` is thrashing its cache.
+    pub fn thrashing(&self, cpu: usize) -> bool {
+        self.thrashing.get(cpu).copied().unwrap_or(false)
+    }
+
+    /// Return the CPU that a cache-sensitive task that would run on `cpu` should use instead:
+    /// the usable CPU (according to `usable`) with the lowest miss rate, if `cpu` is thrashing
+    /// and there is such a CPU that is not thrashing.
+    pub fn pick_cpu(&mut self, cpu: usize, usable: impl Fn(usize) -> bool) -> Option<usize> {
+        if !self.thrashing(cpu) {
+            return None;
+        }
+        let target = (0..self.mpki.len())
+            .filter(|&cpu| usable(cpu) && !self.thrashing(cpu))
+            .filter_map(|cpu| Some((cpu, self.mpki[cpu]?)))
+            .min_by_key(|&(_, mpki)| mpki)
+            .map(|(cpu, _)| cpu)?;
+        self.nr_avoided += 1;
+
+        Some(target)
+    }
+
+    /// Print the CPUs that are thrashing their cache and the amount of tasks moved away from
+    /// them, resetting the latter.
+    pub fn report(&mut self) {
+        let thrashing: Vec<String> = (0..self.thrashing.len())
+            .filter(|&cpu| self.thrashing(cpu))
+            .map(|cpu| cpu.to_string())
+            .collect();
+        println!(
+            "cache: thrashing cpus [{}] (>= {} MPKI) | tasks moved: {}",
+            thrashing.join(","),
+            self.thresh_mpki,
+            self.nr_avoided
+        );
+        self.nr_avoided = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(value: u64, enabled_ns: u64, running_ns: u64) -> Count {
+        Count {
+            value,
+            enabled_ns,
+            running_ns,
+        }
+    }
+
+    #[test]
+    fn multiplexed_counts_are_scaled() {
+        let prev = count(1000, 10_000, 10_000);
+
+        // Counting all the time: no scaling.
+        assert_eq!(count(1500, 20_000, 20_000).since(&prev), Some(500));
+        // Counting a quarter of the time: the events are scaled up to the whole interval.
+        assert_eq!(count(1500, 20_000, 12_500).since(&prev), Some(2000));
+        // Not counting at all: unknown.
+        assert_eq!(count(1000, 20_000, 10_000).since(&prev), None);
+    }
+}
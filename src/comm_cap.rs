@@ -0,0 +1,191 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! CPU time caps of the tasks selected by name (see --comm-cap).
+//!
+//! Each cap is a regular expression, matched against the comm (task name, see
+//! /proc/<pid>/comm) of the tasks, and an amount of CPU time per second, e.g.:
+//!
+//!   $ scx_rust_scheduler --comm-cap '^stress-ng=200000' --comm-cap '^(cc1|ld)$=1000000'
+//!
+//! A cap applies to all the tasks matching its pattern together (e.g., all the compiler
+//! processes of a build), the first matching pattern is used for each task. The regular
+//! expressions are compiled once, when the command line is parsed.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// Pattern and CPU time cap provided on the command line (PATTERN=US).
+#[derive(Debug, Clone)]
+pub struct CommRule {
+    pub pattern: Regex, // Regular expression matched against the comm of the tasks
+    pub cap_ns: u64,    // CPU time allowed per second to the matching tasks
+}
+
+/// Parse a cap in the format PATTERN=US (the pattern is everything up to the last '=').
+///
+/// The CPU time can exceed one second (1000000us), to allow the matching tasks to use more
+/// than one CPU.
+pub fn parse(text: &str) -> Result<CommRule, String> {
+    let Some((pattern, cap_us)) = text.rsplit_once('=') else {
+        return Err(format!("expected PATTERN=US, found '{}'", text));
+    };
+    let pattern = Regex::new(pattern).map_err(|err| format!("invalid pattern: {}", err))?;
+    let cap_us: u64 = cap_us
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid CPU time '{}' (microseconds)", cap_us))?;
+    if cap_us == 0 {
+        return Err("the CPU time must be at least 1us".to_string());
+    }
+
+    Ok(CommRule {
+        pattern,
+        cap_ns: cap_us.saturating_mul(1000),
+    })
+}
+
+// Time slice assigned to a dispatched task that has not been received again yet.
+struct InFlight {
+    rule: usize,   // Cap matched by the task when it has been dispatched
+    slice_ns: u64, // Assigned time slice
+    ts: u64,       // Dispatch time
+}
+
+/// Accountant of the CPU time used by the tasks matching each cap.
+///
+/// As with the per-class reservations (see Reservation), the CPU time used by the tasks
+/// (sum_exec_runtime delta, accounted when a task is received again) is accounted over
+/// one-second intervals and the time slices of the tasks that are still running (dispatched and
+/// not received again) count towards the cap. Once the CPU time of a cap reaches its limit in
+/// the current interval, the matching tasks are throttled (held back in the queues) until the
+/// next interval, and the time slices are clamped to the CPU time left, so that a single
+/// dispatch can't exceed the cap.
+pub struct CommCaps {
+    rules: Vec<CommRule>,              // Caps, in the order of the command line
+    used_ns: Vec<u64>,                 // CPU time used by each cap in the current interval
+    in_flight: HashMap<i32, InFlight>, // Tasks dispatched and not received again yet
+    in_flight_ns: Vec<u64>,            // Time slices of the tasks in flight for each cap
+    interval_ts: u64,                  // Beginning of the current interval
+    last_ns: Vec<u64>,                 // CPU time used by each cap in the last interval
+    nr_throttled: Vec<u64>,            // Tasks held back by each cap (since last report)
+}
+
+impl CommCaps {
+    pub fn new(rules: Vec<CommRule>, now: u64) -> Self {
+        let nr_rules = rules.len();
+
+        Self {
+            rules,
+            used_ns: vec![0; nr_rules],
+            in_flight: HashMap::new(),
+            in_flight_ns: vec![0; nr_rules],
+            interval_ts: now,
+            last_ns: vec![0; nr_rules],
+            nr_throttled: vec![0; nr_rules],
+        }
+    }
+
+    /// Return the cap that applies to a task named `comm` (the first matching pattern).
+    pub fn matching(&self, comm: &str) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|rule| rule.pattern.is_match(comm))
+    }
+
+    /// Account a task of cap `rule` dispatched at time `now` with a time slice of `slice_ns`.
+    pub fn record_dispatch(&mut self, pid: i32, rule: usize, slice_ns: u64, now: u64) {
+        self.land(pid);
+        self.in_flight.insert(
+            pid,
+            InFlight {
+                rule,
+                slice_ns,
+                ts: now,
+            },
+        );
+        self.in_flight_ns[rule] = self.in_flight_ns[rule].saturating_add(slice_ns);
+    }
+
+    /// Account `runtime_ns` of CPU time used by a task received again (of cap `rule`, if any).
+    pub fn record(&mut self, pid: i32, rule: Option<usize>, runtime_ns: u64) {
+        self.land(pid);
+        if let Some(rule) = rule {
+            self.used_ns[rule] = self.used_ns[rule].saturating_add(runtime_ns);
+        }
+    }
+
+    // Stop accounting the time slice of a task in flight.
+    fn land(&mut self, pid: i32) {
+        if let Some(task) = self.in_flight.remove(&pid) {
+            self.in_flight_ns[task.rule] =
+                self.in_flight_ns[task.rule].saturating_sub(task.slice_ns);
+        }
+    }
+
+    /// Return the CPU time still available to the tasks of cap `rule` in the current interval.
+    pub fn budget_ns(&self, rule: usize) -> u64 {
+        self.rules[rule]
+            .cap_ns
+            .saturating_sub(self.used_ns[rule].saturating_add(self.in_flight_ns[rule]))
+    }
+
+    /// Return true if the tasks of cap `rule` need to be held back, accounting them as
+    /// throttled.
+    pub fn throttle(&mut self, rule: usize) -> bool {
+        let throttled = self.budget_ns(rule) == 0;
+        if throttled {
+            self.nr_throttled[rule] += 1;
+        }
+
+        throttled
+    }
+
+    /// Close the current interval (once per second).
+    pub fn evaluate(&mut self, now: u64) {
+        if now.saturating_sub(self.interval_ts) < NSEC_PER_SEC {
+            return;
+        }
+        self.last_ns.copy_from_slice(&self.used_ns);
+
+        // Forget the tasks that have been in flight for a whole interval (e.g., exited tasks).
+        let interval_ts = self.interval_ts;
+        let in_flight_ns = &mut self.in_flight_ns;
+        self.in_flight.retain(|_, task| {
+            let keep = task.ts >= interval_ts;
+            if !keep {
+                in_flight_ns[task.rule] = in_flight_ns[task.rule].saturating_sub(task.slice_ns);
+            }
+            keep
+        });
+
+        self.interval_ts = now;
+        self.used_ns.iter_mut().for_each(|ns| *ns = 0);
+    }
+
+    /// Print the CPU time used by the tasks of each cap in the last completed interval and the
+    /// amount of tasks held back, resetting the latter.
+    pub fn report(&mut self) {
+        let caps: Vec<String> = self
+            .rules
+            .iter()
+            .zip(self.last_ns.iter().zip(&self.nr_throttled))
+            .map(|(rule, (used_ns, nr_throttled))| {
+                format!(
+                    "'{}' {}/{}us throttled {}",
+                    rule.pattern,
+                    used_ns / 1000,
+                    rule.cap_ns / 1000,
+                    nr_throttled
+                )
+            })
+            .collect();
+        println!("comm caps: {}", caps.join(" | "));
+        self.nr_throttled.iter_mut().for_each(|nr| *nr = 0);
+    }
+}
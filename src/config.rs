@@ -0,0 +1,349 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Configuration file of the scheduler (see validate --config): a TOML file with one key per
+//! command line option, named after the long option (`slice-us` or `slice_us` for --slice-us),
+//! with the same values accepted on the command line:
+//!
+//!   policy = "fair"
+//!   slice-us = 5000
+//!   cpus-offline = "6,7"
+//!   debug-accounting = true
+//!   comm-cap = ["^make$=200000", "^cc1$=500000"]
+//!
+//! A boolean enables (true) or leaves disabled (false) an option without a value, and an array
+//! repeats an option that can be given more than once. Only this subset of TOML is supported:
+//! one `key = value` per line, with strings, numbers, booleans or single-line arrays of them,
+//! and comments (no tables, no multi-line values).
+//!
+//! Each key is checked on its own by the same parser of the command line (ranges, policy names,
+//! CPU lists), so that the problems of all the keys are reported at once with their line.
+
+use clap::builder::Resettable;
+use clap::CommandFactory;
+use clap::Parser;
+
+use crate::Opts;
+
+// Program name that precedes the options built from the file.
+const PROG: &str = "scx_rust_scheduler";
+
+// Value of a key.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Bool(bool),         // Boolean
+    Scalar(String),     // String or number (the text passed to the option)
+    Array(Vec<String>), // Array of strings, numbers or booleans
+}
+
+// Key defined in the file.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    line: usize,  // Line of the definition (starting from 1)
+    key: String,  // Name of the key
+    value: Value, // Value of the key
+}
+
+/// Load the options defined in the configuration file `text`, or return all the problems found
+/// (syntax errors, unknown keys and invalid values, by line).
+pub fn load(text: &str) -> Result<Opts, Vec<String>> {
+    let entries = parse(text)?;
+    // Check each key without the options that it requires, they can be defined by other keys.
+    let isolated = Opts::command().mut_args(|arg| arg.requires(Resettable::Reset));
+    let mut errors = Vec::new();
+    let mut args = vec![PROG.to_string()];
+
+    for entry in &entries {
+        let entry_args = match to_args(&isolated, entry) {
+            Ok(entry_args) => entry_args,
+            Err(err) => {
+                errors.push(format!("line {}: {}: {}", entry.line, entry.key, err));
+                continue;
+            }
+        };
+        let argv = std::iter::once(PROG.to_string()).chain(entry_args.iter().cloned());
+        if let Err(err) = isolated.clone().try_get_matches_from(argv) {
+            errors.push(format!(
+                "line {}: {}: {}",
+                entry.line,
+                entry.key,
+                clap_error(&err)
+            ));
+        }
+        args.extend(entry_args);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // The keys are valid on their own, but they can still miss the options that they require.
+    Opts::try_parse_from(args).map_err(|err| vec![clap_error(&err)])
+}
+
+// Return the message of a parser error on a single line, without the "error: " prefix and the
+// usage that follows it.
+fn clap_error(err: &clap::Error) -> String {
+    let text = err.to_string();
+    let message: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.is_empty())
+        .collect();
+    let message = message.join(" ");
+
+    message
+        .strip_prefix("error: ")
+        .unwrap_or(&message)
+        .to_string()
+}
+
+// Return the command line options that correspond to `entry`.
+fn to_args(command: &clap::Command, entry: &Entry) -> Result<Vec<String>, String> {
+    let name = entry.key.replace('_', "-");
+    let Some(arg) = command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(name.as_str()))
+    else {
+        return Err("unknown option".to_string());
+    };
+    let option = format!("--{}", name);
+
+    if !arg.get_action().takes_values() {
+        return match entry.value {
+            Value::Bool(true) => Ok(vec![option]),
+            Value::Bool(false) => Ok(Vec::new()),
+            _ => Err("expected true or false".to_string()),
+        };
+    }
+    // Attach the values to the option, so that a value starting with '-' is never taken for an
+    // option.
+    let values = match &entry.value {
+        Value::Bool(value) => vec![value.to_string()],
+        Value::Scalar(value) => vec![value.clone()],
+        Value::Array(values) => values.clone(),
+    };
+    if values.len() > 1 && !matches!(arg.get_action(), clap::ArgAction::Append) {
+        return Err("expected a single value, not an array".to_string());
+    }
+
+    Ok(values
+        .iter()
+        .map(|value| format!("{}={}", option, value))
+        .collect())
+}
+
+// Parse the keys defined in `text`, or return all the syntax errors.
+fn parse(text: &str) -> Result<Vec<Entry>, Vec<String>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut errors = Vec::new();
+
+    // The same option can be named with dashes or underscores.
+    let option = |key: &str| key.replace('_', "-");
+    for (line, text) in (1..).zip(text.lines()) {
+        match parse_line(text) {
+            Ok(Some((key, value))) => {
+                match entries
+                    .iter()
+                    .find(|entry| option(&entry.key) == option(&key))
+                {
+                    Some(first) => errors.push(format!(
+                        "line {}: {} defined more than once (line {})",
+                        line, key, first.line
+                    )),
+                    None => entries.push(Entry { line, key, value }),
+                }
+            }
+            Ok(None) => {}
+            Err(err) => errors.push(format!("line {}: {}", line, err)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(entries)
+}
+
+// Parse a line of the file: return its key and value, None if it is empty or a comment.
+fn parse_line(text: &str) -> Result<Option<(String, Value)>, String> {
+    let text = text.trim_start();
+    if text.is_empty() || text.starts_with('#') {
+        return Ok(None);
+    }
+    if text.starts_with('[') {
+        return Err("tables are not supported".to_string());
+    }
+
+    let (key, rest) = if text.starts_with('"') || text.starts_with('\'') {
+        parse_string(text)?
+    } else {
+        let end = text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(text.len());
+        (text[..end].to_string(), &text[end..])
+    };
+    if key.is_empty() {
+        return Err(format!("expected a key, found '{}'", text));
+    }
+    let Some(rest) = rest.trim_start().strip_prefix('=') else {
+        return Err(format!("expected '=' after {}", key));
+    };
+
+    let rest = rest.trim_start();
+    let (value, rest) = if let Some(mut rest) = rest.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return Err(format!("unterminated array of {}", key));
+            }
+            if let Some(end) = rest.strip_prefix(']') {
+                break (Value::Array(values), end);
+            }
+            let (value, next) = parse_scalar(rest)?;
+            values.push(match value {
+                Value::Bool(value) => value.to_string(),
+                Value::Scalar(value) => value,
+                Value::Array(_) => unreachable!(),
+            });
+            rest = next.trim_start();
+            if let Some(next) = rest.strip_prefix(',') {
+                rest = next;
+            } else if !rest.starts_with(']') {
+                return Err(format!("expected ',' or ']' in the array of {}", key));
+            }
+        }
+    } else {
+        parse_scalar(rest)?
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected '{}' after the value of {}", rest, key));
+    }
+
+    Ok(Some((key, value)))
+}
+
+// Parse a string, a number or a boolean at the beginning of `text`: return it with the text
+// that follows.
+fn parse_scalar(text: &str) -> Result<(Value, &str), String> {
+    if text.starts_with('"') || text.starts_with('\'') {
+        let (value, rest) = parse_string(text)?;
+        return Ok((Value::Scalar(value), rest));
+    }
+    if text.starts_with('[') {
+        return Err("nested arrays are not supported".to_string());
+    }
+
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(text.len());
+    let (token, rest) = text.split_at(end);
+    let is_number = token.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'));
+    match token {
+        "" => Err("expected a value".to_string()),
+        "true" => Ok((Value::Bool(true), rest)),
+        "false" => Ok((Value::Bool(false), rest)),
+        // The digits can be separated by underscores (e.g., 1_000_000).
+        _ if is_number => Ok((Value::Scalar(token.replace('_', "")), rest)),
+        _ => Err(format!(
+            "invalid value '{}' (strings must be quoted)",
+            token
+        )),
+    }
+}
+
+// Parse a basic ("...", with escapes) or literal ('...') string at the beginning of `text`:
+// return it with the text that follows.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut chars = text.char_indices();
+    let quote = chars.next().map(|(_, c)| c).unwrap_or_default();
+    let mut value = String::new();
+
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            _ if c == quote => return Ok((value, &text[pos + 1..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c) => return Err(format!("unsupported escape '\\{}'", c)),
+                None => break,
+            },
+            _ => value.push(c),
+        }
+    }
+
+    Err(format!("unterminated string {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_the_supported_values() {
+        let text = "\
+            # Comment\n\
+            \n\
+            policy = \"fair\" # Trailing comment\n\
+            \"slice-us\" = 5_000\n\
+            reserve_batch_pct = 12.5\n\
+            debug-accounting = true\n\
+            comm-cap = ['^a\\d$=100', \"b\\\"=200\", ]\n";
+        let entry = |line, key: &str, value| Entry {
+            line,
+            key: key.to_string(),
+            value,
+        };
+
+        assert_eq!(
+            parse(text),
+            Ok(vec![
+                entry(3, "policy", Value::Scalar("fair".to_string())),
+                entry(4, "slice-us", Value::Scalar("5000".to_string())),
+                entry(5, "reserve_batch_pct", Value::Scalar("12.5".to_string())),
+                entry(6, "debug-accounting", Value::Bool(true)),
+                entry(
+                    7,
+                    "comm-cap",
+                    Value::Array(vec!["^a\\d$=100".to_string(), "b\"=200".to_string()])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_reports_every_invalid_line() {
+        let text = "\
+            [scheduler]\n\
+            policy fair\n\
+            policy = fair\n\
+            slice-us = \"5000\n\
+            comm-cap = [\"a=1\" \"b=2\"]\n\
+            slice-min-us = 100 200\n\
+            slice-min-us = 100\n\
+            slice_min_us = 200\n\
+            slice-min-us = 300\n";
+
+        assert_eq!(
+            parse(text),
+            Err(vec![
+                "line 1: tables are not supported".to_string(),
+                "line 2: expected '=' after policy".to_string(),
+                "line 3: invalid value 'fair' (strings must be quoted)".to_string(),
+                "line 4: unterminated string \"5000".to_string(),
+                "line 5: expected ',' or ']' in the array of comm-cap".to_string(),
+                "line 6: unexpected '200' after the value of slice-min-us".to_string(),
+                "line 8: slice_min_us defined more than once (line 7)".to_string(),
+                "line 9: slice-min-us defined more than once (line 7)".to_string(),
+            ])
+        );
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Control socket.
+//!
+//! The scheduler accepts commands on a Unix socket (see --control-socket), one command per line;
+//! multiple commands can be sent over the same connection. Supported commands:
+//!
+//!  - `get stats`: reply with a single line of `key=value` pairs,
+//!  - `get stats --binary`: reply with a binary stats frame (see StatsSnapshot::encode()),
+//!  - `dump`: reply with the human-readable state of the scheduler (queued tasks and per-CPU
+//!    assignments), terminated by an empty line,
+//!  - `dump --json`: reply with the state of the scheduler as a single line of JSON,
+//!  - `dump deadlines`: reply with the soft deadline, the weight and the waiting time of the
+//!    queued tasks, in order of deadline (only with the edf policy), terminated by an empty line,
+//!  - `dump deadlines --json`: reply with the same data as a single line of JSON,
+//!  - `pin <pid> <cpu>`: always dispatch the task `pid` to `cpu`, overriding the CPU selection
+//!    policy, until the task is unpinned or exits (reply `ok`),
+//!  - `unpin <pid>`: restore the regular CPU selection for the task `pid` (reply `ok`),
+//!  - `profile <name>`: activate the profile `name` (see --profile, reply `ok`),
+//!  - `reset`: reset the counters reported by the stats and drop everything learned about the
+//!    tasks, so that a new measurement starts clean (reply `ok`, see Scheduler::reset_stats()
+//!    for what is reset and what is not),
+//!  - `boost <name> <level> <pid>...`: boost the tasks `pid` as the group `name` (replacing its
+//!    previous members) until the group is cleared: they are treated as interactive tasks and
+//!    their weight is multiplied by `level`, between 1 and 100 (reply `ok`, see BoostGroups),
+//!  - `unboost <name>`: clear the group `name` (reply `ok`).
+//!
+//! Invalid commands get a single `error: <reason>` line as reply.
+//!
+//! Commands are received by a separate thread and handled by the scheduler between two
+//! scheduling rounds, so they never run concurrently with the scheduling activity.
+
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::boost_group::BOOST_GROUP_MAX_LEVEL;
+
+// Version of the binary stats frame, increased every time the layout changes.
+pub const STATS_FRAME_VERSION: u8 = 3;
+
+// Amount of 64-bit fields in the binary stats frame.
+const STATS_FRAME_FIELDS: usize = 12;
+
+// Size of the binary stats frame payload (version byte + fields).
+const STATS_FRAME_PAYLOAD: usize = 1 + STATS_FRAME_FIELDS * 8;
+
+// Maximum time that the control thread waits for the scheduler to handle a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Command received from the control socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Stats,                        // get stats
+    StatsBinary,                  // get stats --binary
+    Dump,                         // dump
+    DumpJson,                     // dump --json
+    Deadlines,                    // dump deadlines
+    DeadlinesJson,                // dump deadlines --json
+    Pin(i32, i32),                // pin <pid> <cpu>
+    Unpin(i32),                   // unpin <pid>
+    Profile(String),              // profile <name>
+    Reset,                        // reset
+    Boost(String, u64, Vec<i32>), // boost <name> <level> <pid>...
+    Unboost(String),              // unboost <name>
+}
+
+/// Parse a command received from the control socket.
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["get", "stats"] => Ok(Request::Stats),
+        ["get", "stats", "--binary"] => Ok(Request::StatsBinary),
+        ["dump"] => Ok(Request::Dump),
+        ["dump", "--json"] => Ok(Request::DumpJson),
+        ["dump", "deadlines"] => Ok(Request::Deadlines),
+        ["dump", "deadlines", "--json"] => Ok(Request::DeadlinesJson),
+        ["pin", pid, cpu] => Ok(Request::Pin(parse_arg(pid, "pid")?, parse_arg(cpu, "cpu")?)),
+        ["unpin", pid] => Ok(Request::Unpin(parse_arg(pid, "pid")?)),
+        ["profile", name] => Ok(Request::Profile(name.to_string())),
+        ["reset"] => Ok(Request::Reset),
+        ["boost", name, level, pids @ ..] if !pids.is_empty() => {
+            let level = match level.parse() {
+                Ok(level) if (1..=BOOST_GROUP_MAX_LEVEL).contains(&level) => level,
+                _ => return Err(format!("invalid level '{}'", level)),
+            };
+            let pids = pids
+                .iter()
+                .map(|pid| parse_arg(pid, "pid"))
+                .collect::<Result<_, _>>()?;
+            Ok(Request::Boost(name.to_string(), level, pids))
+        }
+        ["unboost", name] => Ok(Request::Unboost(name.to_string())),
+        [] => Err("empty command".to_string()),
+        _ => Err(format!("unknown command '{}'", line.trim())),
+    }
+}
+
+// Parse the numeric argument `name` of a command (pid or CPU).
+fn parse_arg(value: &str, name: &str) -> Result<i32, String> {
+    match value.parse() {
+        Ok(value) if value >= 0 => Ok(value),
+        _ => Err(format!("invalid {} '{}'", name, value)),
+    }
+}
+
+/// Snapshot of the scheduler statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub now_ns: u64,               // Snapshot timestamp (CLOCK_MONOTONIC)
+    pub nr_user_dispatches: u64,   // Tasks dispatched by the user-space scheduler
+    pub nr_kernel_dispatches: u64, // Tasks dispatched directly by the BPF component
+    pub nr_interactive: u64,       // Tasks waiting in the interactive queue
+    pub nr_batch: u64,             // Tasks waiting in the batch queue
+    pub nr_dispatch_retries: u64,  // Dispatch attempts retried (BPF component busy)
+    pub nr_dispatch_requeues: u64, // Tasks re-queued after exhausting all the retries
+    pub nr_tasks: u64,             // Tasks tracked by the scheduler
+    pub nr_bounce_dispatches: u64, // Dispatches bounced by the BPF component (invalid CPU)
+    pub nr_cancel_dispatches: u64, // Dispatches cancelled by the BPF component
+    pub nr_failed_dispatches: u64, // Dispatches failed by the BPF component
+    pub backpressure_scale: u64,   // Response to the failed dispatches (0 = disabled, 1 = idle)
+}
+
+impl StatsSnapshot {
+    fn fields(&self) -> [(&'static str, u64); STATS_FRAME_FIELDS] {
+        [
+            ("now_ns", self.now_ns),
+            ("user_dispatches", self.nr_user_dispatches),
+            ("kernel_dispatches", self.nr_kernel_dispatches),
+            ("interactive", self.nr_interactive),
+            ("batch", self.nr_batch),
+            ("dispatch_retries", self.nr_dispatch_retries),
+            ("dispatch_requeues", self.nr_dispatch_requeues),
+            ("tasks", self.nr_tasks),
+            ("bounce_dispatches", self.nr_bounce_dispatches),
+            ("cancel_dispatches", self.nr_cancel_dispatches),
+            ("failed_dispatches", self.nr_failed_dispatches),
+            ("backpressure_scale", self.backpressure_scale),
+        ]
+    }
+
+    /// Format the snapshot as a single line of `key=value` pairs.
+    pub fn text(&self) -> String {
+        let fields: Vec<String> = self
+            .fields()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+
+        fields.join(" ") + "\n"
+    }
+
+    /// Format the snapshot as a single JSON object, followed by the `extra` members (already
+    /// formatted JSON values).
+    pub fn json(&self, extra: &[(&str, String)]) -> String {
+        let fields: Vec<String> = self
+            .fields()
+            .iter()
+            .map(|(key, value)| format!("\"{}\":{}", key, value))
+            .chain(
+                extra
+                    .iter()
+                    .map(|(key, value)| format!("\"{}\":{}", key, value)),
+            )
+            .collect();
+
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Encode the snapshot as a binary frame:
+    ///
+    ///   u32 length   // payload size in bytes (little-endian)
+    ///   u8  version  // STATS_FRAME_VERSION
+    ///   u64 fields[] // StatsSnapshot fields in declaration order (little-endian)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(4 + STATS_FRAME_PAYLOAD);
+
+        frame.extend_from_slice(&(STATS_FRAME_PAYLOAD as u32).to_le_bytes());
+        frame.push(STATS_FRAME_VERSION);
+        for (_, value) in self.fields() {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+
+        frame
+    }
+
+    /// Decode a binary frame generated by encode().
+    pub fn decode(frame: &[u8]) -> Result<Self> {
+        let Some((len, payload)) = frame.split_first_chunk::<4>() else {
+            bail!("truncated stats frame");
+        };
+        let len = u32::from_le_bytes(*len) as usize;
+        if payload.len() != len || len < 1 {
+            bail!("invalid stats frame length {}", len);
+        }
+        if payload[0] != STATS_FRAME_VERSION {
+            bail!("unsupported stats frame version {}", payload[0]);
+        }
+        if len != STATS_FRAME_PAYLOAD {
+            bail!("invalid stats frame length {}", len);
+        }
+
+        let mut values = payload[1..]
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+        let mut next = || values.next().unwrap_or(0);
+
+        Ok(Self {
+            now_ns: next(),
+            nr_user_dispatches: next(),
+            nr_kernel_dispatches: next(),
+            nr_interactive: next(),
+            nr_batch: next(),
+            nr_dispatch_retries: next(),
+            nr_dispatch_requeues: next(),
+            nr_tasks: next(),
+            nr_bounce_dispatches: next(),
+            nr_cancel_dispatches: next(),
+            nr_failed_dispatches: next(),
+            backpressure_scale: next(),
+        })
+    }
+}
+
+/// Server side of the control socket.
+pub struct ControlServer {
+    requests: Receiver<(Request, Sender<Vec<u8>>)>, // Commands waiting to be handled
+}
+
+impl ControlServer {
+    /// Start accepting commands on the Unix socket `path` (a stale socket is replaced).
+    pub fn start(path: &str) -> Result<Self> {
+        let _ = fs::remove_file(path);
+        let listener =
+            UnixListener::bind(path).with_context(|| format!("Failed to bind to {}", path))?;
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                thread::spawn(move || Self::handle(stream, &tx));
+            }
+        });
+
+        Ok(Self { requests: rx })
+    }
+
+    /// Return the next command waiting to be handled (if any), along with the channel used to
+    /// send the reply back to the client.
+    pub fn poll(&self) -> Option<(Request, Sender<Vec<u8>>)> {
+        self.requests.try_recv().ok()
+    }
+
+    fn handle(stream: UnixStream, requests: &Sender<(Request, Sender<Vec<u8>>)>) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let reply = match parse_request(&line) {
+                Ok(request) => {
+                    let (tx, rx) = mpsc::channel();
+                    if requests.send((request, tx)).is_err() {
+                        break;
+                    }
+                    rx.recv_timeout(REPLY_TIMEOUT)
+                        .unwrap_or_else(|_| b"error: scheduler not responding\n".to_vec())
+                }
+                Err(err) => format!("error: {}\n", err).into_bytes(),
+            };
+            if writer.write_all(&reply).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for (line, expected) in [
+            ("get stats", Request::Stats),
+            ("get stats --binary", Request::StatsBinary),
+            (" dump\n", Request::Dump),
+            ("dump deadlines --json", Request::DeadlinesJson),
+            ("pin 10 3", Request::Pin(10, 3)),
+            ("unpin 10", Request::Unpin(10)),
+            ("profile batch", Request::Profile("batch".to_string())),
+            ("reset", Request::Reset),
+            (
+                "boost game 4 3 6",
+                Request::Boost("game".to_string(), 4, vec![3, 6]),
+            ),
+            ("unboost game", Request::Unboost("game".to_string())),
+        ] {
+            assert_eq!(parse_request(line), Ok(expected), "'{}'", line);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for (line, err) in [
+            ("", "empty command"),
+            ("get", "unknown command 'get'"),
+            ("pin 10", "unknown command 'pin 10'"),
+            ("pin x 3", "invalid pid 'x'"),
+            ("pin 10 -1", "invalid cpu '-1'"),
+            ("boost game 0 10", "invalid level '0'"),
+            ("boost game 101 10", "invalid level '101'"),
+            ("boost game 4", "unknown command 'boost game 4'"),
+            ("boost game 4 10 x", "invalid pid 'x'"),
+        ] {
+            assert_eq!(parse_request(line), Err(err.to_string()), "'{}'", line);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// List of CPU ids, e.g., parsed from "0,2,4-7".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuList(pub Vec<usize>);
+
+/// Parse a comma-separated list of CPU ids and ranges (same format used by the kernel, e.g.,
+/// /sys/devices/system/cpu/online).
+pub fn parse(text: &str) -> Result<CpuList, String> {
+    let mut cpus = Vec::new();
+
+    for item in text
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let parse_cpu = |s: &str| {
+            s.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid CPU id '{}'", s))
+        };
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_cpu(first)?, parse_cpu(last)?);
+                if first > last {
+                    return Err(format!("invalid CPU range '{}'", item));
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(parse_cpu(item)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+
+    Ok(CpuList(cpus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lists() {
+        for (text, expected) in [
+            ("", &[][..]),
+            ("3", &[3]),
+            ("0,2,4-7", &[0, 2, 4, 5, 6, 7]),
+            (" 5 , 1-2 ,1,", &[1, 2, 5]),
+            ("0-3\n", &[0, 1, 2, 3]),
+        ] {
+            assert_eq!(parse(text), Ok(CpuList(expected.to_vec())), "'{}'", text);
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for (text, err) in [
+            ("x", "invalid CPU id 'x'"),
+            ("1,-2", "invalid CPU id ''"),
+            ("4-2", "invalid CPU range '4-2'"),
+            ("0-3:1/2", "invalid CPU id '3:1/2'"),
+        ] {
+            assert_eq!(parse(text), Err(err.to_string()), "'{}'", text);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Compiled-in defaults of the command line options, selected at build time by the Cargo
+//! features, so that the scheduler can be packaged for different audiences without patching it:
+//!
+//!   $ cargo build --release --features desktop
+//!
+//! The defaults of each build are:
+//!
+//!  - no feature: FIFO policy, 5ms time slices, interactive tasks above 10 voluntary context
+//!    switches per second,
+//!  - `desktop`: fair policy, 2ms time slices (tasks are preempted more often, for a better
+//!    responsiveness), interactive tasks above 5 voluntary context switches per second,
+//!  - `server`: FIFO policy, 20ms time slices (less context switches, for a better throughput),
+//!    interactive tasks above 20 voluntary context switches per second.
+//!
+//! The command line options always override the compiled-in defaults (e.g., --policy fifo with a
+//! desktop build), and `--help` reports the defaults of the build. The features are additive: if
+//! both are enabled (e.g., by two crates of the same workspace), the desktop defaults are used,
+//! since they keep the system responsive with any workload.
+
+use crate::Policy;
+
+/// Feature that selected the compiled-in defaults (reported at startup, None without features).
+#[cfg(feature = "desktop")]
+pub const FEATURE: Option<&str> = Some("desktop");
+#[cfg(all(feature = "server", not(feature = "desktop")))]
+pub const FEATURE: Option<&str> = Some("server");
+#[cfg(not(any(feature = "desktop", feature = "server")))]
+pub const FEATURE: Option<&str> = None;
+
+/// Default policy (see --policy).
+#[cfg(feature = "desktop")]
+pub const POLICY: Policy = Policy::Fair;
+#[cfg(not(feature = "desktop"))]
+pub const POLICY: Policy = Policy::Fifo;
+
+/// Default maximum time slice, in microseconds (see --slice-us).
+#[cfg(feature = "desktop")]
+pub const SLICE_US: u64 = 2000;
+#[cfg(all(feature = "server", not(feature = "desktop")))]
+pub const SLICE_US: u64 = 20000;
+#[cfg(not(any(feature = "desktop", feature = "server")))]
+pub const SLICE_US: u64 = 5000;
+
+/// Default voluntary context switches per second of the interactive tasks (see --nvcsw-thresh).
+#[cfg(feature = "desktop")]
+pub const NVCSW_THRESH: u64 = 5;
+#[cfg(all(feature = "server", not(feature = "desktop")))]
+pub const NVCSW_THRESH: u64 = 20;
+#[cfg(not(any(feature = "desktop", feature = "server")))]
+pub const NVCSW_THRESH: u64 = 10;
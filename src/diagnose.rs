@@ -0,0 +1,116 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::Error;
+use anyhow::Result;
+
+// sysfs directory exposed by kernels built with CONFIG_SCHED_CLASS_EXT.
+const SCHED_EXT_SYSFS: &str = "/sys/kernel/sched_ext";
+
+/// Common causes of a failure to attach the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    NoSchedExt,      // Kernel built without sched_ext support
+    NotPrivileged,   // Not running with the required privileges
+    AlreadyAttached, // Another sched_ext scheduler is running
+}
+
+impl Problem {
+    /// Return a description of the problem and how to fix it.
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            Problem::NoSchedExt => {
+                "the kernel doesn't support sched_ext: use a kernel built with \
+                 CONFIG_SCHED_CLASS_EXT=y (Linux 6.12 or newer)"
+            }
+            Problem::NotPrivileged => {
+                "insufficient privileges: run the scheduler as root (CAP_SYS_ADMIN and \
+                 CAP_BPF are required to load the BPF component)"
+            }
+            Problem::AlreadyAttached => {
+                "another sched_ext scheduler is already running: stop it first (see \
+                 /sys/kernel/sched_ext/root/ops)"
+            }
+        }
+    }
+}
+
+// Return the name of the sched_ext scheduler currently attached (if any).
+fn attached_scheduler() -> Option<String> {
+    let state = fs::read_to_string(Path::new(SCHED_EXT_SYSFS).join("state")).ok()?;
+    if state.trim() == "disabled" {
+        return None;
+    }
+    let ops = fs::read_to_string(Path::new(SCHED_EXT_SYSFS).join("root/ops")).ok()?;
+
+    Some(ops.trim().to_string())
+}
+
+/// Verify the prerequisites required to attach the scheduler and return the detected problems.
+pub fn check_prerequisites() -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if !Path::new(SCHED_EXT_SYSFS).exists() {
+        problems.push(Problem::NoSchedExt);
+    }
+    if unsafe { libc::geteuid() } != 0 {
+        problems.push(Problem::NotPrivileged);
+    }
+    if attached_scheduler().is_some() {
+        problems.push(Problem::AlreadyAttached);
+    }
+
+    problems
+}
+
+/// Return the most likely cause of a scheduler initialization error.
+///
+/// The error message is inspected first (errno descriptions reported by libbpf), then the
+/// prerequisites are verified, since many failures are reported with generic errors.
+pub fn probable_cause(err: &Error) -> Option<Problem> {
+    let msg = format!("{:#}", err).to_lowercase();
+
+    if msg.contains("operation not permitted") || msg.contains("permission denied") {
+        return Some(Problem::NotPrivileged);
+    }
+    if msg.contains("file exists") || msg.contains("device or resource busy") {
+        return Some(Problem::AlreadyAttached);
+    }
+
+    check_prerequisites().first().copied()
+}
+
+/// Annotate a scheduler initialization error with actionable guidance (if a probable cause can
+/// be found).
+pub fn annotate(err: Error) -> Error {
+    match probable_cause(&err) {
+        Some(problem) => err.context(format!(
+            "failed to attach the scheduler: {}",
+            problem.guidance()
+        )),
+        None => err.context("failed to attach the scheduler"),
+    }
+}
+
+/// Implement --check: report whether the scheduler can be attached, without attaching it.
+pub fn run_check() -> Result<()> {
+    let problems = check_prerequisites();
+
+    if problems.is_empty() {
+        println!("check: OK (sched_ext available, running as root, no scheduler attached)");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("check: {}", problem.guidance());
+    }
+    if let Some(ops) = attached_scheduler() {
+        println!("check: currently attached scheduler: {}", ops);
+    }
+    bail!("{} problem(s) detected", problems.len());
+}
@@ -0,0 +1,82 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Share of the dispatches performed directly by the BPF component (kernel dispatches) over all
+/// the dispatches, sampled at every stats interval.
+///
+/// The BPF component dispatches the tasks on its own when the user-space scheduler can't be
+/// used (e.g., critical per-CPU kthreads, or tasks that can't wait for the scheduler to run): a
+/// rising kernel share means that the user-space scheduler is bypassed more and more, usually
+/// because it is too slow to keep up, so a warning is printed when the share exceeds
+/// --kernel-dispatch-warn-pct.
+pub struct DispatchRatio {
+    warn_pct: u64,  // Kernel share that triggers a warning (in percent)
+    nr_user: u64,   // User-space dispatches in the last interval
+    nr_kernel: u64, // Kernel dispatches in the last interval
+}
+
+impl DispatchRatio {
+    pub fn new(warn_pct: u64) -> Self {
+        Self {
+            warn_pct,
+            nr_user: 0,
+            nr_kernel: 0,
+        }
+    }
+
+    /// Sample the dispatches of the last interval: `nr_user` performed by the user-space
+    /// scheduler and `nr_kernel` by the BPF component.
+    pub fn sample(&mut self, nr_user: u64, nr_kernel: u64) {
+        self.nr_user = nr_user;
+        self.nr_kernel = nr_kernel;
+    }
+
+    /// Return the share of the kernel dispatches in the last interval (in percent), None if
+    /// there were no dispatches at all.
+    pub fn kernel_pct(&self) -> Option<f64> {
+        let nr_total = self.nr_user + self.nr_kernel;
+
+        (nr_total > 0).then(|| self.nr_kernel as f64 * 100.0 / nr_total as f64)
+    }
+
+    /// Return true if the share of the kernel dispatches exceeds the warning threshold.
+    pub fn is_bypassed(&self) -> bool {
+        self.kernel_pct()
+            .is_some_and(|pct| pct > self.warn_pct as f64)
+    }
+
+    /// Print the share of the user-space and kernel dispatches (and a warning if the user-space
+    /// scheduler is bypassed too often).
+    pub fn report(&self) {
+        let Some(kernel_pct) = self.kernel_pct() else {
+            return;
+        };
+        println!(
+            "dispatch ratio -> user: {:.1}% | kernel: {:.1}%",
+            100.0 - kernel_pct,
+            kernel_pct
+        );
+        if self.is_bypassed() {
+            println!(
+                "WARNING: {:.1}% of the dispatches bypassed the user-space scheduler (above \
+                 {}%): the scheduler may be too slow to keep up",
+                kernel_pct, self.warn_pct
+            );
+        }
+    }
+
+    /// Format the dispatches of the last interval as a JSON object (null share without
+    /// dispatches).
+    pub fn json(&self) -> String {
+        format!(
+            "{{\"user\":{},\"kernel\":{},\"kernel_pct\":{},\"bypassed\":{}}}",
+            self.nr_user,
+            self.nr_kernel,
+            self.kernel_pct()
+                .map_or("null".to_string(), |pct| format!("{:.1}", pct)),
+            self.is_bypassed()
+        )
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt::Write as _;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+// Set by the SIGUSR1 handler, consumed by the scheduler.
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigusr1(_sig: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Install a SIGUSR1 handler that requests a dump of the scheduler state (see requested()).
+pub fn install_signal_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            handle_sigusr1 as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// Return true if a dump has been requested via SIGUSR1 since the last call.
+pub fn requested() -> bool {
+    DUMP_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Task waiting in one of the user-space queues.
+pub struct QueuedEntry {
+    pub queue: &'static str,    // Queue that contains the task
+    pub pid: i32,               // pid of the task
+    pub weight: u64,            // Weight of the task
+    pub boost_pct: Option<u64>, // Weight boost of the task, in percent (see --io-boost)
+    pub vtime: u64,             // Virtual runtime of the task
+    pub wait_ns: u64,           // Time spent in the queue
+}
+
+/// Task waiting in one of the user-space queues, with its soft deadline (see the edf policy).
+pub struct DeadlineEntry {
+    pub queue: &'static str, // Queue that contains the task
+    pub pid: i32,            // pid of the task
+    pub weight: u64,         // Weight of the task
+    pub deadline: u64,       // Soft deadline of the task
+    pub wait_ns: u64,        // Time spent in the queue
+}
+
+/// Last task dispatched to a CPU.
+pub struct CpuEntry {
+    pub cpu: usize,  // CPU id
+    pub pid: i32,    // pid of the last task dispatched to the CPU
+    pub age_ns: u64, // Time elapsed since the dispatch
+}
+
+/// Snapshot of the internal state of the scheduler.
+pub struct StateDump {
+    pub queued: Vec<QueuedEntry>, // Tasks waiting in the user-space queues
+    pub cpus: Vec<CpuEntry>,      // Per-CPU assignments
+}
+
+impl StateDump {
+    /// Format the snapshot in a human-readable form (terminated by an empty line).
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "queued tasks: {}", self.queued.len());
+        for t in &self.queued {
+            let boost = t
+                .boost_pct
+                .map(|pct| format!(" boost=x{:.2}", pct as f64 / 100.0))
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "  {:<11} pid={:<7} weight={:<5}{} vtime={:<14} wait={}us",
+                t.queue,
+                t.pid,
+                t.weight,
+                boost,
+                t.vtime,
+                t.wait_ns / 1000
+            );
+        }
+        let _ = writeln!(out, "cpus:");
+        for c in &self.cpus {
+            let _ = writeln!(
+                out,
+                "  cpu{:<3} pid={:<7} dispatched {}us ago",
+                c.cpu,
+                c.pid,
+                c.age_ns / 1000
+            );
+        }
+        out.push('\n');
+
+        out
+    }
+
+    /// Format the snapshot as a single line of JSON.
+    pub fn json(&self) -> String {
+        let queued: Vec<String> = self
+            .queued
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{\"queue\":\"{}\",\"pid\":{},\"weight\":{},\"vtime\":{},\"wait_ns\":{}}}",
+                    t.queue, t.pid, t.weight, t.vtime, t.wait_ns
+                )
+            })
+            .collect();
+        let cpus: Vec<String> = self
+            .cpus
+            .iter()
+            .map(|c| {
+                format!(
+                    "{{\"cpu\":{},\"pid\":{},\"age_ns\":{}}}",
+                    c.cpu, c.pid, c.age_ns
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"queued\":[{}],\"cpus\":[{}]}}\n",
+            queued.join(","),
+            cpus.join(",")
+        )
+    }
+}
+
+/// Deadlines of the tasks waiting in the user-space queues, in order of deadline (the order in
+/// which the edf policy dispatches them).
+pub struct DeadlineDump {
+    pub now_ns: u64,                // Snapshot timestamp
+    pub queued: Vec<DeadlineEntry>, // Queued tasks, sorted by deadline
+}
+
+impl DeadlineDump {
+    /// Format the snapshot in a human-readable form (terminated by an empty line).
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "queued tasks by deadline: {}", self.queued.len());
+        for t in &self.queued {
+            let due = match t.deadline.checked_sub(self.now_ns) {
+                Some(delta) => format!("in {}us", delta / 1000),
+                None => format!("overdue {}us", (self.now_ns - t.deadline) / 1000),
+            };
+            let _ = writeln!(
+                out,
+                "  {:<11} pid={:<7} weight={:<5} deadline={:<14} ({}) wait={}us",
+                t.queue,
+                t.pid,
+                t.weight,
+                t.deadline,
+                due,
+                t.wait_ns / 1000
+            );
+        }
+        out.push('\n');
+
+        out
+    }
+
+    /// Format the snapshot as a single line of JSON.
+    pub fn json(&self) -> String {
+        let queued: Vec<String> = self
+            .queued
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{\"queue\":\"{}\",\"pid\":{},\"weight\":{},\"deadline\":{},\"wait_ns\":{}}}",
+                    t.queue, t.pid, t.weight, t.deadline, t.wait_ns
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"now_ns\":{},\"queued\":[{}]}}\n",
+            self.now_ns,
+            queued.join(",")
+        )
+    }
+}
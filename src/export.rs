@@ -0,0 +1,147 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Export of the scheduling decisions in CSV format (see --export-csv), e.g. as a training set
+//! for learned scheduling policies.
+//!
+//! Unlike --record, that captures all the interactions with the BPF component to reproduce a
+//! session, the export is a flat table with one row per dispatched task: the features known to
+//! the scheduler when the task has been dispatched and the decision that has been taken. The
+//! first line is the header, followed by one row per dispatch, with the following columns:
+//!
+//!   - `ts_ns`: time of the scheduling round (CLOCK_MONOTONIC, in nanoseconds)
+//!   - `pid`: pid of the task
+//!   - `weight`: priority of the task, in the range [1..10000] (default is 100)
+//!   - `nvcsw`: total amount of voluntary context switches of the task
+//!   - `avg_nvcsw`: average amount of voluntary context switches per second
+//!   - `runtime_ns`: total CPU time used by the task (in nanoseconds)
+//!   - `avg_util`: average CPU utilization of the task (in percent)
+//!   - `class`: class of the task, as determined by the classifier (interactive or batch)
+//!   - `queue_depth`: amount of tasks waiting in the user-space queues in the round
+//!   - `wait_ns`: time spent by the task in the user-space queues (in nanoseconds)
+//!   - `prev_cpu`: CPU previously used by the task
+//!   - `cpu`: CPU selected for the task (-1 = first CPU available)
+//!   - `slice_ns`: time slice assigned to the task (in nanoseconds)
+//!
+//! The rows are formatted and written by a separate thread, so that the export never blocks the
+//! scheduler: if the thread can't keep up, the rows that don't fit in the channel are dropped
+//! (and reported at the end of the session).
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::thread::JoinHandle;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// Columns of the export (see the documentation of the module).
+pub const CSV_HEADER: &str = "ts_ns,pid,weight,nvcsw,avg_nvcsw,runtime_ns,avg_util,class,\
+                              queue_depth,wait_ns,prev_cpu,cpu,slice_ns";
+
+// Maximum amount of rows waiting to be written.
+const EXPORT_QUEUE_LEN: usize = 65536;
+
+/// Features and decision of a dispatched task (a row of the export).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub ts_ns: u64,        // Time of the scheduling round
+    pub pid: i32,          // Dispatched task
+    pub weight: u64,       // Priority of the task
+    pub nvcsw: u64,        // Total amount of voluntary context switches
+    pub avg_nvcsw: u64,    // Average amount of voluntary context switches per second
+    pub runtime_ns: u64,   // Total CPU time
+    pub avg_util: u64,     // Average CPU utilization (in percent)
+    pub interactive: bool, // Class of the task
+    pub queue_depth: u64,  // Tasks waiting in the user-space queues
+    pub wait_ns: u64,      // Time spent in the user-space queues
+    pub prev_cpu: i32,     // CPU previously used by the task
+    pub cpu: i32,          // CPU selected for the task
+    pub slice_ns: u64,     // Assigned time slice
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.ts_ns,
+            self.pid,
+            self.weight,
+            self.nvcsw,
+            self.avg_nvcsw,
+            self.runtime_ns,
+            self.avg_util,
+            if self.interactive {
+                "interactive"
+            } else {
+                "batch"
+            },
+            self.queue_depth,
+            self.wait_ns,
+            self.prev_cpu,
+            self.cpu,
+            self.slice_ns
+        )
+    }
+}
+
+/// Writer of the export, running in a separate thread.
+pub struct DecisionExporter {
+    tx: SyncSender<Decision>,           // Rows to be written
+    writer: JoinHandle<io::Result<()>>, // Thread that writes the rows
+    nr_dropped: u64,                    // Rows dropped because the channel was full
+}
+
+impl DecisionExporter {
+    /// Create the export file `path` (truncating it) and start writing the rows to it.
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+
+        Ok(Self::start(BufWriter::new(file)))
+    }
+
+    /// Start writing the header and the rows to `out`.
+    pub fn start(mut out: impl Write + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Decision>(EXPORT_QUEUE_LEN);
+        let writer = thread::spawn(move || {
+            writeln!(out, "{}", CSV_HEADER)?;
+            for decision in rx {
+                writeln!(out, "{}", decision)?;
+            }
+            out.flush()
+        });
+
+        Self {
+            tx,
+            writer,
+            nr_dropped: 0,
+        }
+    }
+
+    /// Queue a row, without waiting for the writer (the row is dropped if the channel is full).
+    pub fn record(&mut self, decision: Decision) {
+        if self.tx.try_send(decision).is_err() {
+            self.nr_dropped += 1;
+        }
+    }
+
+    /// Write the rows still queued and return the amount of rows that have been dropped.
+    pub fn finish(self) -> Result<u64> {
+        drop(self.tx);
+        self.writer
+            .join()
+            .map_err(|_| anyhow!("the export thread panicked"))?
+            .context("Failed to write the export")?;
+
+        Ok(self.nr_dropped)
+    }
+}
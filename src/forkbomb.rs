@@ -0,0 +1,77 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Time slice (in nanoseconds) assigned to the tasks of a fork bomb (see --fork-bomb-throttle).
+pub const FORK_BOMB_SLICE_NS: u64 = 100_000;
+
+/// Detector of the surges of brand-new tasks, e.g., fork bombs (see --fork-bomb-thresh).
+///
+/// The tasks received for the first time (pids without statistics) are counted over one-second
+/// intervals: as soon as the count of the current interval exceeds `thresh`, a surge is
+/// reported and the tasks first seen from that moment on are considered part of the flood
+/// (the caller keeps track of them); the surge ends after a complete interval below `thresh`.
+pub struct ForkBombDetector {
+    thresh: u64,      // New tasks per second that trigger a surge
+    interval_ts: u64, // Beginning of the current interval
+    nr_new: u64,      // New tasks received in the current interval
+    nr_flood: u64,    // New tasks received during the current surge
+    active: bool,     // Surge in progress
+}
+
+impl ForkBombDetector {
+    pub fn new(thresh: u64, now: u64) -> Self {
+        Self {
+            thresh,
+            interval_ts: now,
+            nr_new: 0,
+            nr_flood: 0,
+            active: false,
+        }
+    }
+
+    /// Return true if a surge of new tasks is in progress.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Account a task received for the first time at `now` and return true if it is part of
+    /// a surge.
+    pub fn record_new(&mut self, now: u64) -> bool {
+        self.evaluate(now);
+        self.nr_new += 1;
+        if self.nr_new > self.thresh && !self.active {
+            println!(
+                "WARNING: more than {} new tasks in less than 1s, possible fork bomb",
+                self.thresh
+            );
+            self.active = true;
+        }
+        if self.active {
+            self.nr_flood += 1;
+        }
+
+        self.active
+    }
+
+    /// Close the current interval if it is complete, ending the surge if less than `thresh` new
+    /// tasks have been received in it (called at least once per second).
+    pub fn evaluate(&mut self, now: u64) {
+        if now.saturating_sub(self.interval_ts) < NSEC_PER_SEC {
+            return;
+        }
+        if self.active && self.nr_new <= self.thresh {
+            println!(
+                "fork bomb: surge ended ({} new tasks received during the surge)",
+                self.nr_flood
+            );
+            self.active = false;
+            self.nr_flood = 0;
+        }
+        self.interval_ts = now;
+        self.nr_new = 0;
+    }
+}
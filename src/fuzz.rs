@@ -0,0 +1,170 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Fuzzing of the scheduling policy (see fuzz/ and the `fuzz` feature): the input drives a
+//! sequence of scheduling rounds on the mock backend, each one with random tasks received from
+//! the BPF component (random pids, weights, counters and enqueue flags) and a random amount of
+//! time elapsed, with a policy configuration also drawn from the input.
+//!
+//! Every round must complete without panics (including the arithmetic overflows, the fuzz targets
+//! are built with the overflow checks) and without violating the invariants of the policy (see
+//! --debug-invariants).
+//!
+//! The weights are bounded to what the kernel can actually report, [0..10000] (0 is never
+//! reported, but it must not crash the scheduler), while the CPU time and the voluntary context
+//! switches span the whole range, like the counters of a malformed task. The pids are drawn from
+//! a small range, so that the same tasks are received again, with updated counters, or twice in
+//! the same round. A task waiting in the user-space queues is never received again in a later
+//! round (the kernel can't enqueue a task that has not been dispatched yet).
+//!
+//! The configuration also includes the features that account the CPU time of the tasks
+//! (--reserve-*-pct, --comm-cap and --debug-accounting), since the CPU time of a malformed task
+//! must never overflow their totals, and their periodic evaluation runs at random rounds.
+
+use std::collections::HashSet;
+
+use arbitrary::Arbitrary;
+use arbitrary::Result;
+use arbitrary::Unstructured;
+
+use clap::Parser;
+
+use regex::Regex;
+
+use crate::backend::Task;
+use crate::comm_cap::CommRule;
+use crate::invariants::InvariantChecker;
+use crate::mock::MockBackend;
+use crate::InvariantMode;
+use crate::Opts;
+use crate::Order;
+use crate::Policy;
+use crate::Scheduler;
+
+// Online CPUs of the mock backend.
+const NR_CPUS: u64 = 4;
+
+// Largest pid of the random tasks.
+const MAX_PID: i32 = 16;
+
+// Largest weight reported by the kernel.
+const MAX_WEIGHT: u64 = 10000;
+
+// Maximum amount of rounds, of tasks received per round and of time elapsed between two rounds.
+const MAX_ROUNDS: u32 = 256;
+const MAX_TASKS: u32 = 32;
+const MAX_ELAPSED_NS: u64 = 2_000_000_000;
+
+// Largest CPU time reservation of each class (the two together can't exceed 100%).
+const MAX_RESERVE_PCT: u32 = 50;
+
+impl<'a> Arbitrary<'a> for Task {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Task {
+            pid: u.int_in_range(1..=MAX_PID)?,
+            // Include the invalid CPUs (the previous CPU of a task that never ran is -1).
+            cpu: u.int_in_range(-1..=NR_CPUS as i32)?,
+            flags: u.arbitrary()?,
+            sum_exec_runtime: u.arbitrary()?,
+            nvcsw: u.arbitrary()?,
+            weight: u.int_in_range(0..=MAX_WEIGHT)?,
+            slice: u.arbitrary()?,
+            vtime: u.arbitrary()?,
+        })
+    }
+}
+
+/// Run the scheduling rounds described by `data`, panicking at the first failure.
+pub fn run(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let Ok(opts) = fuzz_opts(&mut u) else {
+        return;
+    };
+    // An input that ends early is simply a shorter sequence of rounds.
+    let _ = schedule_rounds(&mut u, &opts);
+}
+
+// Draw the configuration of the policy from the input.
+fn fuzz_opts(u: &mut Unstructured) -> Result<Opts> {
+    let defaults = Opts::parse_from(["scx_rust_scheduler"]);
+
+    Ok(Opts {
+        policy: *u.choose(&[Policy::Fifo, Policy::Fair, Policy::Wrr, Policy::Edf])?,
+        order: *u.choose(&[Order::Fifo, Order::Lifo])?,
+        compute_boost: u.arbitrary()?,
+        batch_quantum_mult: u.int_in_range(1..=64)?,
+        io_boost: if u.arbitrary()? {
+            Some(u.int_in_range(2..=16)?)
+        } else {
+            None
+        },
+        deadline_slice_pct: if u.arbitrary()? {
+            Some(u.int_in_range(1..=100)?)
+        } else {
+            None
+        },
+        reserve_interactive_pct: if u.arbitrary()? {
+            Some(u.int_in_range(1..=MAX_RESERVE_PCT)? as f64)
+        } else {
+            None
+        },
+        reserve_batch_pct: if u.arbitrary()? {
+            Some(u.int_in_range(1..=MAX_RESERVE_PCT)? as f64)
+        } else {
+            None
+        },
+        // A single cap matching all the tasks, from 1us per second to the largest one accepted.
+        comm_cap: if u.arbitrary()? {
+            vec![CommRule {
+                pattern: Regex::new(".").unwrap(),
+                cap_ns: u.int_in_range(1..=u64::MAX / 1000)? * 1000,
+            }]
+        } else {
+            Vec::new()
+        },
+        debug_accounting: u.arbitrary()?,
+        debug_invariants: Some(InvariantMode::Abort),
+        ..defaults
+    })
+}
+
+// Run the scheduling rounds until the input is exhausted.
+fn schedule_rounds(u: &mut Unstructured, opts: &Opts) -> Result<()> {
+    let mut sched = Scheduler::new(MockBackend::new(NR_CPUS), opts, None, None);
+    sched.invariants = Some(InvariantChecker::new());
+    let mut queued = HashSet::new();
+    for pid in 1..=MAX_PID {
+        sched.bpf.set_comm(pid, "fuzz");
+    }
+
+    for _ in 0..u.int_in_range(1..=MAX_ROUNDS)? {
+        sched.bpf.advance(u.int_in_range(0..=MAX_ELAPSED_NS)?);
+
+        let prev_queued = queued.clone();
+        for _ in 0..u.int_in_range(0..=MAX_TASKS)? {
+            let task: Task = u.arbitrary()?;
+            if prev_queued.contains(&task.pid) {
+                continue;
+            }
+            queued.insert(task.pid);
+            sched.bpf.enqueue(task);
+        }
+        if let Err(err) = sched.schedule() {
+            panic!("schedule() failed: {:#}", err);
+        }
+        for task in sched.bpf.take_dispatched() {
+            queued.remove(&task.pid);
+        }
+
+        if u.arbitrary()? {
+            sched.gc_tasks();
+        }
+        if u.arbitrary()? {
+            sched.update_overload();
+        }
+    }
+
+    Ok(())
+}
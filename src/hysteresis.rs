@@ -0,0 +1,121 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Anti-flapping filter shared by the adaptive controllers (see --hysteresis-ms and
+/// --hysteresis-delta).
+///
+/// A controller asks the filter before changing its state, passing the value of the signal that
+/// triggered the change: the change is allowed only if at least `min_interval_ns` elapsed since
+/// the last allowed change and the signal moved by at least `min_delta` with respect to the
+/// value that triggered it. The first change is always allowed.
+pub struct Hysteresis {
+    min_interval_ns: u64,     // Minimum time between two consecutive changes
+    min_delta: u64,           // Minimum change of the signal between two consecutive changes
+    last: Option<(u64, u64)>, // Time and signal value of the last allowed change
+}
+
+impl Hysteresis {
+    pub fn new(min_interval_ns: u64, min_delta: u64) -> Self {
+        Self {
+            min_interval_ns,
+            min_delta,
+            last: None,
+        }
+    }
+
+    /// Return true if a change triggered by the signal `value` at time `now` is allowed (in
+    /// that case the change is recorded).
+    pub fn allow(&mut self, now: u64, value: u64) -> bool {
+        if let Some((ts, last)) = self.last {
+            if now.saturating_sub(ts) < self.min_interval_ns
+                || value.abs_diff(last) < self.min_delta
+            {
+                return false;
+            }
+        }
+        self.force(now, value);
+
+        true
+    }
+
+    /// Record a change that can't be delayed (e.g., a safety measure), so that the next change
+    /// is filtered with respect to it.
+    pub fn force(&mut self, now: u64, value: u64) {
+        self.last = Some((now, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Filter of the tests (the defaults of --hysteresis-ms and --hysteresis-delta).
+    const MIN_INTERVAL_NS: u64 = 30_000_000_000;
+    const MIN_DELTA: u64 = 10;
+
+    // Drive a threshold controller (state changes when the signal crosses 50) with a noisy
+    // signal around 35-65 for 500s, followed by a sustained step to 90, and return the changes
+    // allowed by `hysteresis` (time and value of the signal).
+    fn threshold_controller(mut hysteresis: Hysteresis) -> Vec<(u64, u64)> {
+        let mut seed: u64 = 42;
+        let mut noise = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % 31
+        };
+        let mut state = false;
+        let mut changes = Vec::new();
+
+        for sec in 0..600 {
+            let value = if sec < 500 { 35 + noise() } else { 90 };
+            let now = sec * 1_000_000_000;
+            if (value >= 50) != state && hysteresis.allow(now, value) {
+                state = !state;
+                changes.push((now, value));
+            }
+        }
+
+        changes
+    }
+
+    #[test]
+    fn changes_are_spaced_in_time_and_value() {
+        let filtered = threshold_controller(Hysteresis::new(MIN_INTERVAL_NS, MIN_DELTA));
+
+        for pair in filtered.windows(2) {
+            let ((prev_ts, prev), (ts, value)) = (pair[0], pair[1]);
+            assert!(ts - prev_ts >= MIN_INTERVAL_NS, "{:?}", pair);
+            assert!(value.abs_diff(prev) >= MIN_DELTA, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn noise_is_filtered() {
+        let unfiltered = threshold_controller(Hysteresis::new(0, 0));
+        let filtered = threshold_controller(Hysteresis::new(MIN_INTERVAL_NS, MIN_DELTA));
+
+        assert!(filtered.len() < unfiltered.len());
+    }
+
+    #[test]
+    fn sustained_change_is_followed() {
+        let filtered = threshold_controller(Hysteresis::new(MIN_INTERVAL_NS, MIN_DELTA));
+
+        // The controller ends in the high state (the sustained step is far above the noise).
+        assert!(filtered.last().is_some_and(|&(_, value)| value >= 50));
+    }
+
+    #[test]
+    fn forced_change_restarts_the_interval() {
+        let mut hysteresis = Hysteresis::new(MIN_INTERVAL_NS, MIN_DELTA);
+
+        assert!(hysteresis.allow(0, 20));
+        hysteresis.force(MIN_INTERVAL_NS, 80);
+        assert!(!hysteresis.allow(MIN_INTERVAL_NS + 1, 20));
+        assert!(!hysteresis.allow(MIN_INTERVAL_NS * 2, 85));
+        assert!(hysteresis.allow(MIN_INTERVAL_NS * 2, 20));
+    }
+}
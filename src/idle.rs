@@ -0,0 +1,75 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// History of the idle periods of the CPUs, derived from the dispatch activity (see
+/// --spread-idle).
+///
+/// A CPU is considered busy until the time slice of the last task dispatched to it expires and
+/// idle afterwards: the CPU that has been idle for the longest time is the coldest one.
+/// Placing tasks on the coldest CPUs first spreads the heat (and wear) more evenly across the
+/// CPUs, improving the turbo headroom.
+///
+/// The per-CPU arrays are automatically extended when a task is dispatched to a CPU with a
+/// higher id (e.g., when a CPU is brought online via hotplug).
+pub struct IdleHistory {
+    busy_until: Vec<Option<u64>>, // End of the last time slice assigned to each CPU
+    placements: Vec<u64>,         // Dispatches to each CPU in the current interval
+    nr_coldest: u64,              // Dispatches to the coldest CPU in the current interval
+}
+
+impl IdleHistory {
+    pub fn new(nr_cpus: usize) -> Self {
+        Self {
+            busy_until: vec![None; nr_cpus],
+            placements: vec![0; nr_cpus],
+            nr_coldest: 0,
+        }
+    }
+
+    /// Account a task dispatched to `cpu` at time `now` with a time slice of `slice_ns`;
+    /// `coldest` is true if the CPU was the coldest one.
+    pub fn record_dispatch(&mut self, cpu: usize, now: u64, slice_ns: u64, coldest: bool) {
+        if cpu >= self.busy_until.len() {
+            self.busy_until.resize(cpu + 1, None);
+            self.placements.resize(cpu + 1, 0);
+        }
+        self.busy_until[cpu] = Some(now + slice_ns);
+        self.placements[cpu] += 1;
+        if coldest {
+            self.nr_coldest += 1;
+        }
+    }
+
+    /// Return the CPU that has been idle for the longest time at time `now` among the CPUs
+    /// that satisfy `allowed` (None if all of them are busy). CPUs that never received a task
+    /// are the coldest ones.
+    pub fn coldest(&self, now: u64, allowed: impl Fn(usize) -> bool) -> Option<usize> {
+        self.busy_until
+            .iter()
+            .enumerate()
+            .filter(|&(cpu, until)| allowed(cpu) && until.is_none_or(|ts| ts <= now))
+            .min_by_key(|&(_, until)| until.unwrap_or(0))
+            .map(|(cpu, _)| cpu)
+    }
+
+    /// Print the distribution of the dispatches across the CPUs in the current interval and
+    /// start a new interval.
+    pub fn report(&mut self) {
+        let total: u64 = self.placements.iter().sum();
+        let shares: Vec<String> = self
+            .placements
+            .iter()
+            .map(|n| (n * 100).checked_div(total).unwrap_or(0).to_string())
+            .collect();
+
+        println!(
+            "cpu placement (%): [{}] | coldest: {}%",
+            shares.join(","),
+            (self.nr_coldest * 100).checked_div(total).unwrap_or(0)
+        );
+        self.placements.iter_mut().for_each(|n| *n = 0);
+        self.nr_coldest = 0;
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Validation of the invariants of the scheduling policy (see --debug-invariants), to catch the
+//! policy bugs at the first wrong decision, instead of from their effects on the workload (e.g.,
+//! a starved task or a task running on the wrong CPU). Each dispatch is checked against:
+//!
+//!  - a task is dispatched at most once per scheduling round,
+//!  - the time slice is never 0 (the BPF component would assign its own default time slice) and
+//!    never exceeds the largest time slice that the policy can assign,
+//!  - the target CPU is RL_CPU_ANY or one of the online CPUs,
+//!  - with the fair policy, the virtual runtime of a task never goes backwards.
+//!
+//! The time slices have no lower bound other than 0: the policy scales them down legitimately
+//! below --slice-min-us (e.g., with many interactive tasks waiting, or during a fork bomb).
+//!
+//! The validation has a cost on every dispatch, so it is only available in the debug builds,
+//! or in the release builds with the `invariants` feature; otherwise the option is ignored and
+//! the checks are never executed.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::backend::Dispatch;
+use crate::bpf::RL_CPU_ANY;
+
+/// True if the validation is compiled in.
+pub const COMPILED: bool = cfg!(any(debug_assertions, feature = "invariants"));
+
+/// Bounds of the dispatches in the current round.
+pub struct Bounds {
+    pub max_slice_ns: u64, // Largest time slice that the policy can assign
+    pub nr_cpus: i32,      // Amount of online CPUs (valid targets: 0..nr_cpus)
+    pub fair: bool,        // Tasks ordered by virtual runtime (fair policy)
+}
+
+/// Validation of the dispatches of the policy.
+pub struct InvariantChecker {
+    round: u64,                // Current scheduling round
+    dispatched: HashSet<i32>,  // Tasks dispatched in the current round
+    vtimes: HashMap<i32, u64>, // Last virtual runtime dispatched for each task (fair policy)
+    nr_violations: u64,        // Invariant violations detected
+}
+
+impl InvariantChecker {
+    pub fn new() -> Self {
+        Self {
+            round: 0,
+            dispatched: HashSet::new(),
+            vtimes: HashMap::new(),
+            nr_violations: 0,
+        }
+    }
+
+    /// Start a new scheduling round.
+    pub fn begin_round(&mut self) {
+        self.round += 1;
+        self.dispatched.clear();
+    }
+
+    /// Validate a task dispatched in the current round and return the invariants that it
+    /// violates (empty if the dispatch is valid).
+    pub fn check(&mut self, task: &Dispatch, bounds: &Bounds) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if !self.dispatched.insert(task.pid) {
+            violations.push(format!("pid {} dispatched twice", task.pid));
+        }
+        if task.slice_ns == 0 || task.slice_ns > bounds.max_slice_ns {
+            violations.push(format!(
+                "pid {}: time slice {}ns out of range (1..={}ns)",
+                task.pid, task.slice_ns, bounds.max_slice_ns
+            ));
+        }
+        if task.cpu != RL_CPU_ANY && !(0..bounds.nr_cpus).contains(&task.cpu) {
+            violations.push(format!(
+                "pid {}: invalid target CPU {} ({} online CPUs)",
+                task.pid, task.cpu, bounds.nr_cpus
+            ));
+        }
+        if bounds.fair {
+            let prev = self.vtimes.insert(task.pid, task.vtime);
+            if let Some(prev) = prev.filter(|&prev| task.vtime < prev) {
+                violations.push(format!(
+                    "pid {}: virtual runtime went backwards ({} -> {})",
+                    task.pid, prev, task.vtime
+                ));
+            }
+        }
+
+        self.nr_violations += violations.len() as u64;
+
+        violations
+            .into_iter()
+            .map(|violation| format!("round {}: {}", self.round, violation))
+            .collect()
+    }
+
+    /// Drop the state of the tasks that don't satisfy `alive` (e.g., exited tasks).
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.vtimes.retain(|&pid, _| alive(pid));
+    }
+
+    /// Drop the state of a task that is not tracked anymore (see --max-tracked-pids), its
+    /// virtual runtime starts again from the current minimum.
+    pub fn forget(&mut self, pid: i32) {
+        self.vtimes.remove(&pid);
+    }
+
+    /// Return the amount of invariant violations detected.
+    pub fn nr_violations(&self) -> u64 {
+        self.nr_violations
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// Task dispatched to a specific CPU.
+#[derive(Debug, Clone, Copy)]
+struct RunningTask {
+    pid: i32,    // pid of the task
+    weight: u64, // Weight of the task
+    end_ts: u64, // Time when the task's time slice expires
+}
+
+// Example of a detected priority inversion.
+#[derive(Debug, Clone, Copy)]
+struct Inversion {
+    waiting: (i32, u64), // Waiting task (pid and weight)
+    running: (i32, u64), // Running task (pid and weight)
+    cpu: usize,          // CPU used by the running task
+}
+
+/// Detector of potential priority inversions: a high-priority task is waiting in the user-space
+/// queues while a lower-priority task is holding a CPU.
+///
+/// The detector only knows about the tasks dispatched to a specific CPU and it assumes that a
+/// task keeps its CPU until its time slice expires (tasks that release the CPU earlier may be
+/// reported as false positives), so the reported inversions are only potential ones.
+pub struct InversionDetector {
+    weight_gap: u64,                   // Minimum weight gap that counts as an inversion
+    running: Vec<Option<RunningTask>>, // Last task dispatched to each CPU
+    nr_inversions: u64,                // Inversions detected in the current interval
+    last: Option<Inversion>,           // Last inversion detected in the current interval
+}
+
+impl InversionDetector {
+    pub fn new(weight_gap: u64, nr_cpus: usize) -> Self {
+        Self {
+            weight_gap,
+            running: vec![None; nr_cpus],
+            nr_inversions: 0,
+            last: None,
+        }
+    }
+
+    /// Account a task with weight `weight` dispatched to `cpu` at time `now` with a time slice
+    /// of `slice_ns`.
+    pub fn record_dispatch(&mut self, cpu: usize, pid: i32, weight: u64, now: u64, slice_ns: u64) {
+        if cpu >= self.running.len() {
+            self.running.resize(cpu + 1, None);
+        }
+        self.running[cpu] = Some(RunningTask {
+            pid,
+            weight,
+            end_ts: now + slice_ns,
+        });
+    }
+
+    /// Check for an inversion between the waiting task with the highest weight (`waiting`, as
+    /// pid and weight) and the running task with the lowest weight.
+    pub fn check(&mut self, waiting: Option<(i32, u64)>, now: u64) {
+        let Some((pid, weight)) = waiting else {
+            return;
+        };
+        let running = self
+            .running
+            .iter()
+            .enumerate()
+            .filter_map(|(cpu, task)| task.filter(|t| t.end_ts > now).map(|t| (cpu, t)))
+            .min_by_key(|(_, t)| t.weight);
+
+        if let Some((cpu, task)) = running {
+            if weight.saturating_sub(task.weight) >= self.weight_gap {
+                self.nr_inversions += 1;
+                self.last = Some(Inversion {
+                    waiting: (pid, weight),
+                    running: (task.pid, task.weight),
+                    cpu,
+                });
+            }
+        }
+    }
+
+    /// Print the inversions detected in the current interval (if any) and start a new interval.
+    pub fn report(&mut self) {
+        if let Some(last) = self.last.take() {
+            println!(
+                "priority inversions: {} (last: pid {} weight {} waiting, pid {} weight {} running on cpu {})",
+                self.nr_inversions,
+                last.waiting.0,
+                last.waiting.1,
+                last.running.0,
+                last.running.1,
+                last.cpu,
+            );
+        }
+        self.nr_inversions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEIGHT_GAP: u64 = 1000;
+    const SLICE_NS: u64 = 5_000_000;
+
+    // Return a detector with a low-weight task running on CPU 1 and a default-weight task
+    // running on CPU 0, both dispatched at time 0.
+    fn detector() -> InversionDetector {
+        let mut inversions = InversionDetector::new(WEIGHT_GAP, 2);
+        inversions.record_dispatch(0, 1, 100, 0, SLICE_NS);
+        inversions.record_dispatch(1, 2, 1, 0, SLICE_NS);
+        inversions
+    }
+
+    #[test]
+    fn inversion_is_detected() {
+        let mut inversions = detector();
+        inversions.check(Some((3, 10000)), SLICE_NS / 2);
+
+        assert_eq!(inversions.nr_inversions, 1);
+        let last = inversions.last.expect("inversion not recorded");
+        assert_eq!(
+            (last.waiting, last.running, last.cpu),
+            ((3, 10000), (2, 1), 1)
+        );
+
+        inversions.report();
+        assert_eq!(inversions.nr_inversions, 0);
+        assert!(inversions.last.is_none());
+    }
+
+    #[test]
+    fn small_gap_is_ignored() {
+        let mut inversions = detector();
+        inversions.check(Some((3, WEIGHT_GAP)), SLICE_NS / 2);
+        inversions.check(None, SLICE_NS / 2);
+
+        assert_eq!(inversions.nr_inversions, 0);
+    }
+
+    #[test]
+    fn expired_slice_is_ignored() {
+        let mut inversions = detector();
+        inversions.check(Some((3, 10000)), SLICE_NS);
+
+        assert_eq!(inversions.nr_inversions, 0);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! CPU isolation configured on the kernel command line.
+//!
+//! The CPUs isolated with isolcpus= or nohz_full= are usually reserved to a latency-sensitive
+//! workload, pinned there by the user, so the scheduler excludes them from its dispatches, like
+//! the CPUs listed with --cpus-offline (see --ignore-isolcpus).
+
+use crate::cpulist;
+
+/// Kernel command line of the running system.
+pub const CMDLINE_PATH: &str = "/proc/cmdline";
+
+// Flags that can precede the CPU list of isolcpus= (see
+// Documentation/admin-guide/kernel-parameters.txt).
+const ISOLCPUS_FLAGS: [&str; 3] = ["nohz", "domain", "managed_irq"];
+
+/// Return the CPUs isolated by the kernel command line `cmdline`, as the union of the CPU lists
+/// of isolcpus=[flag,...,]<cpulist> and nohz_full=<cpulist> (sorted, without duplicates).
+///
+/// The parameters with an invalid CPU list (e.g., the "used/group" syntax, not supported by
+/// cpulist::parse()) are ignored.
+pub fn parse_cmdline(cmdline: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+
+    for param in cmdline.split_whitespace() {
+        let list = match param.split_once('=') {
+            Some(("isolcpus", value)) => {
+                let items: Vec<&str> = value
+                    .split(',')
+                    .skip_while(|item| ISOLCPUS_FLAGS.contains(item))
+                    .collect();
+                items.join(",")
+            }
+            Some(("nohz_full", value)) => value.to_string(),
+            _ => continue,
+        };
+        if let Ok(list) = cpulist::parse(&list) {
+            cpus.extend(list.0);
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+
+    cpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for (cmdline, expected) in [
+            ("BOOT_IMAGE=/vmlinuz root=/dev/sda1 ro quiet", &[][..]),
+            ("quiet isolcpus=2,3", &[2, 3]),
+            ("isolcpus=domain,managed_irq,1-2 nohz_full=3", &[1, 2, 3]),
+            ("nohz_full=1-3 isolcpus=nohz,2", &[1, 2, 3]),
+            ("isolcpus=1 isolcpus=3", &[1, 3]),
+            ("isolcpus= nohz_full", &[]),
+            ("xisolcpus=1 isolcpus_x=2", &[]),
+        ] {
+            assert_eq!(parse_cmdline(cmdline), expected, "'{}'", cmdline);
+        }
+    }
+
+    #[test]
+    fn unsupported_list_is_ignored() {
+        assert_eq!(parse_cmdline("isolcpus=0-7:2/4 nohz_full=5"), [5]);
+    }
+}
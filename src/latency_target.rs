@@ -0,0 +1,154 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+use crate::metrics::LatencyHistogram;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Percentile of the scheduling latency compared against the target.
+const LATENCY_TARGET_PCT: u64 = 99;
+
+// Maximum divisor applied to the time slices to meet the target.
+const LATENCY_MAX_SLICE_DIV: u64 = 8;
+
+// Maximum factor applied to the amount of tasks dispatched per round to meet the target.
+const LATENCY_MAX_BATCH_SCALE: u64 = 4;
+
+// Format a latency in nanoseconds (u64::MAX is the +Inf bucket of the histogram).
+fn us(ns: u64) -> String {
+    match ns {
+        u64::MAX => "+Inf".to_string(),
+        ns => format!("{}us", ns / 1000),
+    }
+}
+
+/// Closed-loop controller of the scheduling latency (see --latency-target-us).
+///
+/// The latency of the dispatched tasks (time spent in the user-space queues) is collected in a
+/// histogram over one-second intervals and, at the end of each interval, the 99th percentile
+/// (the upper bound of its bucket, see LatencyHistogram::percentile_ns()) is compared against
+/// the target:
+///
+///  - if the target is missed, the scheduler is tuned for latency by one step: first the time
+///    slices are halved (down to 1/LATENCY_MAX_SLICE_DIV), so that the CPUs are released more
+///    often, then the amount of tasks dispatched per round is doubled (up to
+///    LATENCY_MAX_BATCH_SCALE times), so that the queues are drained faster;
+///
+///  - if the latency drops below half of the target, the last step is reverted (the tasks per
+///    round first, then the time slices), to give the throughput back, at most once every
+///    `hysteresis` interval, so that the controller doesn't keep oscillating around the target.
+///
+/// Steps towards the target are always applied immediately.
+pub struct LatencyTarget {
+    target_ns: u64,            // Target latency (99th percentile)
+    hysteresis: Hysteresis,    // Anti-flapping filter of the relaxing steps
+    interval_ts: u64,          // Beginning of the current interval
+    samples: LatencyHistogram, // Latency of the tasks dispatched in the current interval
+    last_ns: Option<u64>,      // Latency measured in the last completed interval
+    slice_div: u64,            // Divisor applied to the time slices
+    batch_scale: u64,          // Factor applied to the amount of tasks dispatched per round
+    nr_missed: u64,            // Intervals that missed the target (since the last report)
+}
+
+impl LatencyTarget {
+    pub fn new(target_ns: u64, hysteresis: Hysteresis, now: u64) -> Self {
+        Self {
+            target_ns,
+            hysteresis,
+            interval_ts: now,
+            samples: LatencyHistogram::new(),
+            last_ns: None,
+            slice_div: 1,
+            batch_scale: 1,
+            nr_missed: 0,
+        }
+    }
+
+    /// Account the latency of a dispatched task.
+    pub fn record(&mut self, pid: i32, latency_ns: u64) {
+        self.samples.record(pid, latency_ns);
+    }
+
+    /// Return the time slice to assign instead of `slice_ns`.
+    pub fn slice_ns(&self, slice_ns: u64) -> u64 {
+        (slice_ns / self.slice_div).max(1)
+    }
+
+    /// Return the factor that needs to be applied to the amount of tasks dispatched per round.
+    pub fn batch_scale(&self) -> u64 {
+        self.batch_scale
+    }
+
+    /// Evaluate the current interval (once per second) and adjust the time slices and the tasks
+    /// per round.
+    pub fn evaluate(&mut self, now: u64) {
+        if now.saturating_sub(self.interval_ts) < NSEC_PER_SEC {
+            return;
+        }
+        let latency_ns = self.samples.percentile_ns(LATENCY_TARGET_PCT);
+        self.samples = LatencyHistogram::new();
+        self.interval_ts = now;
+        self.last_ns = latency_ns;
+
+        // Nothing has been dispatched: there is no feedback.
+        let Some(latency_ns) = latency_ns else {
+            return;
+        };
+        if latency_ns > self.target_ns {
+            self.nr_missed += 1;
+            let adjustment = if self.slice_div < LATENCY_MAX_SLICE_DIV {
+                self.slice_div *= 2;
+                format!("time slices divided by {}", self.slice_div)
+            } else if self.batch_scale < LATENCY_MAX_BATCH_SCALE {
+                self.batch_scale *= 2;
+                format!("tasks per round scaled by {}x", self.batch_scale)
+            } else {
+                return;
+            };
+            self.hysteresis.force(now, 0);
+            println!(
+                "latency target: p{} latency {} above the target {}, {}",
+                LATENCY_TARGET_PCT,
+                us(latency_ns),
+                us(self.target_ns),
+                adjustment
+            );
+        } else if latency_ns <= self.target_ns / 2
+            && (self.slice_div > 1 || self.batch_scale > 1)
+            && self.hysteresis.allow(now, 0)
+        {
+            let adjustment = if self.batch_scale > 1 {
+                self.batch_scale /= 2;
+                format!("tasks per round scaled by {}x", self.batch_scale)
+            } else {
+                self.slice_div /= 2;
+                format!("time slices divided by {}", self.slice_div)
+            };
+            println!(
+                "latency target: p{} latency {} below half of the target {}, {}",
+                LATENCY_TARGET_PCT,
+                us(latency_ns),
+                us(self.target_ns),
+                adjustment
+            );
+        }
+    }
+
+    /// Print the latency measured in the last completed interval, the current adjustments and
+    /// the amount of intervals that missed the target, resetting the latter.
+    pub fn report(&mut self) {
+        println!(
+            "latency target: p{} {} (target {}) | missed: {} | slice /{} | tasks per round {}x",
+            LATENCY_TARGET_PCT,
+            self.last_ns.map_or("-".to_string(), us),
+            us(self.target_ns),
+            self.nr_missed,
+            self.slice_div,
+            self.batch_scale
+        );
+        self.nr_missed = 0;
+    }
+}
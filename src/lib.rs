@@ -0,0 +1,4637 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! # FIFO Linux kernel scheduler that runs in user-space
+//!
+//! ## Overview
+//!
+//! This is a fully functional FIFO scheduler for the Linux kernel that operates in user-space and
+//! it is 100% implemented in Rust.
+//!
+//! Tasks are classified as interactive or batch, according to their rate of voluntary context
+//! switches, and routed to two separate FIFO queues: interactive tasks are always dispatched first
+//! (using a shorter time slice), batch tasks are dispatched when no interactive task is waiting,
+//! or when they have been waiting for too long.
+//!
+//! The scheduler is designed to serve as a simple template for developers looking to implement
+//! more advanced scheduling policies.
+//!
+//! It is based on `scx_rustland_core`, a framework that is specifically designed to simplify the
+//! creation of user-space schedulers, leveraging the Linux kernel's `sched_ext` feature (a
+//! technology that allows to implement schedulers in BPF).
+//!
+//! The `scx_rustland_core` crate offers an abstraction layer over `sched_ext`, enabling developers
+//! to write schedulers in Rust without needing to interact directly with low-level kernel or BPF
+//! internal details.
+//!
+//! ## scx_rustland_core API
+//!
+//! ### struct `BpfScheduler`
+//!
+//! The `BpfScheduler` struct is the core interface for interacting with `sched_ext` via BPF.
+//!
+//! - **Initialization**:
+//!   - `BpfScheduler::init()` registers the scheduler and initializes the BPF component.
+//!
+//! - **Task Management**:
+//!   - `dequeue_task()`: Consume a task that wants to run, returns a QueuedTask object
+//!   - `select_cpu(pid: i32, prev_cpu: i32, flags: u64)`: Select an idle CPU for a task
+//!   - `dispatch_task(task: &DispatchedTask)`: Dispatch a task
+//!
+//! - **Completion Notification**:
+//!   - `notify_complete(nr_pending: u64)` Give control to the BPF component and report the number
+//!     of tasks that are still pending (this function can sleep)
+//!
+//! Each task received from dequeue_task() contains the following:
+//!
+//! struct QueuedTask {
+//!     pub pid: i32,              // pid that uniquely identifies a task
+//!     pub cpu: i32,              // CPU previously used by the task
+//!     pub sum_exec_runtime: u64, // Total cpu time in nanoseconds
+//!     pub weight: u64,           // Task priority in the range [1..10000] (default is 100)
+//!     pub nvcsw: u64,            // Total amount of voluntary context switches
+//!     pub slice: u64,            // Remaining time slice budget
+//!     pub vtime: u64,            // Current task vruntime / deadline (set by the scheduler)
+//! }
+//!
+//! Each task dispatched using dispatch_task() contains the following:
+//!
+//! struct DispatchedTask {
+//!     pub pid: i32,      // pid that uniquely identifies a task
+//!     pub cpu: i32,      // target CPU selected by the scheduler
+//!     pub flags: u64,    // special dispatch flags (RL_CPU_ANY = dispatch on the first
+//!                        // CPU available)
+//!     pub slice_ns: u64, // time slice in nanoseconds assigned to the task
+//!                        // (0 = use default, see "Time slices" below)
+//!     pub vtime: u64,    // this value can be used to send the task's vruntime or deadline
+//!                        // directly to the underlying BPF dispatcher
+//! }
+//!
+//! Other internal statistics that can be used to implement better scheduling policies:
+//!
+//!  let n: u64 = *self.bpf.nr_online_cpus_mut();       // amount of online CPUs
+//!  let n: u64 = *self.bpf.nr_running_mut();           // amount of currently running tasks
+//!  let n: u64 = *self.bpf.nr_queued_mut();            // amount of tasks queued to be scheduled
+//!  let n: u64 = *self.bpf.nr_scheduled_mut();         // amount of tasks managed by the user-space scheduler
+//!  let n: u64 = *self.bpf.nr_user_dispatches_mut();   // amount of user-space dispatches
+//!  let n: u64 = *self.bpf.nr_kernel_dispatches_mut(); // amount of kernel dispatches
+//!  let n: u64 = *self.bpf.nr_cancel_dispatches_mut(); // amount of cancelled dispatches
+//!  let n: u64 = *self.bpf.nr_bounce_dispatches_mut(); // amount of bounced dispatches
+//!  let n: u64 = *self.bpf.nr_failed_dispatches_mut(); // amount of failed dispatches
+//!  let n: u64 = *self.bpf.nr_sched_congested_mut();   // amount of scheduler congestion events
+//!
+//! ## Scheduling backend
+//!
+//! The scheduler doesn't use `BpfScheduler` directly, but it goes through the `SchedBackend`
+//! trait (see backend.rs), implemented by `BpfBackend` (a thin wrapper around `BpfScheduler`) and
+//! by `MockBackend` (see mock.rs), that allows to run the same policy against a synthetic
+//! workload without attaching to the kernel (see `--selftest`).
+//!
+//! The connection to the BPF component also goes through a `Recorder` (see trace.rs), that can
+//! record all the interactions with the backend to a trace (see `--record`), and the trace can
+//! be replayed by `ReplayBackend` (see replay.rs) to reproduce a session offline.
+//!
+//! When the policy needs a statistic that is not exposed by `SchedBackend` yet, simply add the
+//! corresponding method to the trait and implement it in all the backends (including the
+//! recorder and the replay backend, that also need a new trace event).
+//!
+//! ## Task weights
+//!
+//! The weight of a task (derived from its nice value, or set with `sched_setattr()`, e.g., by
+//! `renice` or `chrt`) is reported by the kernel every time the task is received, so it can
+//! change at any time. The weight used by the policies is always the weight reported by the
+//! kernel in the current round, scaled by the per-task overrides, in this order:
+//!  - with --io-boost, the boost of the I/O-bound tasks, from 1x up to --io-boost times (see
+//!    Scheduler::io_boost_pct()), applied to the virtual runtime charged to the task (see
+//!    Scheduler::update_vtime(), the order of the fair policy),
+//!  - the level of the boost groups of the task, set by an external controller with the boost
+//!    control command (the highest level among its groups, see BoostGroups), applied on top of
+//!    the I/O boost to the virtual runtime, and to the dispatch credits of the wrr policy.
+//!
+//! No override replaces the weight reported by the kernel, so renicing a boosted task still takes
+//! effect.
+//!
+//! The state derived from the weight and cached across rounds (e.g., the wrr dispatch credits)
+//! is re-validated against the weight reported by the kernel every time the task is received,
+//! and dropped when the weight changes, so that the new weight takes effect in the same round
+//! (see Scheduler::refresh_weight()).
+//!
+//! ## Time slices
+//!
+//! The time slice decided by the policy for a dispatched task maps to `slice_ns` as follows:
+//!  - run for a specific amount of time (the default time slice, scaled according to the
+//!    load, or the time slice requested by the task or computed by `--slice-expr`): that amount
+//!    of nanoseconds,
+//!  - yield as soon as possible (a task that requests a time slice of 0 via SCX_SLICE_US, see
+//!    slice_override.rs): the minimum time slice (`--slice-min-us`).
+//!
+//! `slice_ns = 0` is never a yield: it means "use the default time slice of the BPF component"
+//! (SCX_SLICE_DFL, 20ms), i.e., a task meant to run for the shortest possible time would run
+//! for a full default time slice instead. The policy never dispatches a task with
+//! `slice_ns = 0`: the time slices scaled down by the queue depth never go below the minimum
+//! time slice (see Scheduler::policy_slice()), and any other time slice that rounds down to 0
+//! (e.g., clamped by --comm-cap or by the thermal throttling) is mapped to the minimum time
+//! slice (see Scheduler::dispatch_slice_ns()).
+//!
+//! ## Concurrency model
+//!
+//! The scheduler runs in a single thread (see `--workers`): each round drains all the tasks
+//! queued by the BPF component (`dequeue_task()`), dispatches some of them and then reports the
+//! amount of tasks still pending (`notify_complete()`), that also puts the scheduler to sleep
+//! until there is more work to do. Side threads (e.g., the metrics endpoint) never touch the
+//! backend, they only consume data published by the scheduler thread.
+//!
+//! A multi-threaded (sharded) dispatcher has to preserve the following rules:
+//!  - `dequeue_task()` and `notify_complete()` must stay on a single thread, since
+//!    `notify_complete()` must report the tasks pending across all the shards and the BPF
+//!    component only wakes up one user-space scheduler thread,
+//!  - each task must be routed to exactly one shard (e.g., according to the CPU reported by
+//!    `select_cpu()`), only the owner of a task dispatches it, so a task can never be dispatched
+//!    twice,
+//!  - the user ring buffer used by `dispatch_task()` has a single producer, so dispatches from
+//!    different shards must be serialized (or each shard needs its own ring buffer in the BPF
+//!    component).
+
+mod bpf_skel;
+pub use bpf_skel::*;
+pub mod bpf_intf;
+
+mod bpf;
+use bpf::*;
+
+mod accounting;
+use accounting::Accounting;
+
+mod defaults;
+
+mod auto;
+use auto::AutoMode;
+use auto::Profile;
+
+mod backend;
+use backend::BpfBackend;
+use backend::Dispatch;
+use backend::DispatchError;
+use backend::SchedBackend;
+use backend::Task;
+
+mod control;
+use control::ControlServer;
+use control::Request;
+use control::StatsSnapshot;
+
+mod diagnose;
+
+mod dump;
+use dump::CpuEntry;
+use dump::DeadlineDump;
+use dump::DeadlineEntry;
+use dump::QueuedEntry;
+use dump::StateDump;
+
+mod export;
+use export::Decision;
+use export::DecisionExporter;
+
+mod state_log;
+use state_log::StateLog;
+use state_log::STATE_LOG_TOP_TASKS;
+
+mod comm_cap;
+use comm_cap::CommCaps;
+use comm_cap::CommRule;
+
+mod cpulist;
+use cpulist::CpuList;
+
+mod isolation;
+
+mod loop_gap;
+use loop_gap::LoopGap;
+
+mod mock;
+mod selftest;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
+mod replay;
+use replay::ReplayOpts;
+
+mod profiles;
+use profiles::NamedProfile;
+use profiles::Profiles;
+
+mod timetable;
+use timetable::TimedProfile;
+use timetable::Timetable;
+
+mod shadow;
+use shadow::ShadowPolicy;
+
+mod slice_override;
+
+mod slice_expr;
+use slice_expr::SliceExpr;
+use slice_expr::SliceVars;
+
+mod sweep;
+use sweep::SweepOpts;
+
+mod systemd;
+use systemd::Notifier;
+
+mod stats;
+use stats::CpuGapStats;
+
+mod trace;
+use trace::Recorder;
+
+mod thermal;
+use thermal::Thermal;
+
+mod hysteresis;
+use hysteresis::Hysteresis;
+
+mod idle;
+use idle::IdleHistory;
+
+mod cache;
+use cache::CacheMonitor;
+use cache::PerfCounters;
+
+mod weights;
+use weights::WeightStats;
+
+mod whatif;
+
+mod weight_classes;
+use weight_classes::WeightBounds;
+use weight_classes::WeightClasses;
+
+mod inversion;
+use inversion::InversionDetector;
+
+mod latency_target;
+use latency_target::LatencyTarget;
+
+mod llc;
+use llc::LlcDomains;
+
+mod lru;
+use lru::PidLru;
+
+mod migration;
+use migration::MigrationLock;
+
+mod wake_affine;
+use wake_affine::WakeAffinity;
+
+mod numa;
+use numa::NumaAffinity;
+use numa::NumaFallback;
+
+mod overload;
+use overload::OverloadDetector;
+
+mod wakeup_gap;
+use wakeup_gap::WakeupGap;
+
+mod overhead;
+use overhead::Overhead;
+
+mod boost;
+use boost::BoostBudget;
+
+mod boost_group;
+use boost_group::BoostGroups;
+
+mod breaker;
+use breaker::NotifyBreaker;
+
+mod forkbomb;
+use forkbomb::ForkBombDetector;
+use forkbomb::FORK_BOMB_SLICE_NS;
+
+mod reservation;
+use reservation::Reservation;
+
+mod topview;
+use topview::TopView;
+
+mod activation;
+use activation::PolicyActivation;
+
+mod metrics;
+use metrics::LatencyHistogram;
+use metrics::MetricsServer;
+
+mod udp_stats;
+use udp_stats::UdpStats;
+
+#[cfg(feature = "stats")]
+mod scx_stats_server;
+#[cfg(feature = "stats")]
+use scx_stats_server::ScxStats;
+#[cfg(feature = "stats")]
+use scx_stats_server::SCX_STATS_PATH;
+
+mod run_latency;
+use run_latency::RunLatency;
+
+mod invariants;
+use invariants::Bounds;
+use invariants::InvariantChecker;
+
+mod snapshot;
+use snapshot::Snapshot;
+use snapshot::TaskState;
+
+mod queue_trend;
+use queue_trend::QueueTrend;
+
+mod dispatch_ratio;
+use dispatch_ratio::DispatchRatio;
+
+mod batch_size;
+use batch_size::BatchSizes;
+
+mod backpressure;
+use backpressure::Backpressure;
+
+mod validate;
+use validate::ValidateOpts;
+
+mod config;
+
+mod proc_cache;
+
+use libbpf_rs::OpenObject;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::IsTerminal;
+use std::mem::MaybeUninit;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use clap::Parser;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Build information reported by --version (captured by build.rs), so that bug reports include
+// the exact versions of the scheduler, of its dependencies and of the toolchain.
+const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\nscx_rustland_core: ",
+    env!("SCX_CORE_VERSION"),
+    "\nscx_utils: ",
+    env!("SCX_UTILS_VERSION"),
+    "\nbpf skeleton: ",
+    env!("SCX_BPF_SKEL_HASH"),
+    " (",
+    env!("SCX_BPF_CLANG"),
+    ")",
+    "\nrustc: ",
+    env!("SCX_RUSTC_VERSION"),
+);
+
+// Maximum time slice (in nanoseconds) assigned to interactive tasks.
+//
+// Interactive tasks usually run for a short amount of time and then voluntarily release the CPU,
+// so there is no need to give them a large time slice.
+const INTERACTIVE_SLICE_NS: u64 = 1_000_000;
+
+// Maximum time (in nanoseconds) that a batch task can wait in the queue before it is dispatched
+// ahead of the interactive tasks (prevents batch tasks from being starved by interactive ones).
+const STARVATION_NS: u64 = 100_000_000;
+
+// Maximum CPU time (in nanoseconds) charged to a task in a single update (to its virtual runtime
+// and to the CPU time accounted to its class or to its comm cap).
+//
+// A task runs for one time slice at a time before being received again, so a larger amount of
+// CPU time can only come from a corrupted counter (e.g., a task that wrapped its runtime), and it
+// must not push the task, and min_vtime with it, to the end of the virtual time.
+const MAX_CHARGE_NS: u64 = 10 * NSEC_PER_SEC;
+
+// Maximum amount of dispatches retried in each scheduling round when the BPF component can't
+// accept a task, before re-queueing the task for the next round.
+const DISPATCH_RETRIES: u32 = 3;
+
+// Amount of time (in nanoseconds) after which the scheduling hints of a task are read again
+// (see --slice-env and the edf policy).
+const HINT_REFRESH_NS: u64 = 5 * NSEC_PER_SEC;
+
+// Interval between two consecutive reads of the cpuset of a task (see --cpuset-aware).
+const CPUSET_REFRESH_NS: u64 = NSEC_PER_SEC;
+
+// Interval between two consecutive matches of the comm of a task against the patterns of
+// --comm-cap (to follow the tasks that change their name, e.g., with an exec()).
+const COMM_REFRESH_NS: u64 = NSEC_PER_SEC;
+
+// Share (in percent) of the user-space dispatches bounced or cancelled by the BPF component in
+// one second above which the likely causes are reported.
+const BAD_DISPATCH_HINT_PCT: u64 = 1;
+
+// Consecutive failed calls to dequeue_task() that trigger a restart of the scheduler (any
+// successful call, with or without a task, resets the streak).
+const DEQUEUE_ERRORS_RESTART: u64 = 100;
+
+// Tasks dequeued or dispatched between two checks of the time budget of a scheduling round (see
+// --round-budget-us): reading the clock for each task would add a significant overhead.
+const ROUND_BUDGET_CHECK: u64 = 32;
+
+// Maximum time (in seconds) without stats during a stretch of idle intervals (see
+// --no-stats-on-idle), so that the stats also act as a heartbeat of the scheduler.
+const IDLE_STATS_HEARTBEAT_SECS: u64 = 60;
+
+/// Policy used to order the tasks within each queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Policy {
+    /// Dispatch tasks in the same order they have been received.
+    Fifo,
+    /// Dispatch tasks in order of virtual runtime (CPU time used, scaled by the task's weight).
+    Fair,
+    /// Weighted round-robin: each task is dispatched up to weight / 100 consecutive times (at
+    /// least once) before moving to the next task in the queue.
+    Wrr,
+    /// Earliest deadline first: tasks are dispatched in order of soft deadline, the time when
+    /// they have been received plus the latency requested via the SCX_LATENCY_US environment
+    /// variable (in microseconds, clamped between 1ms and 1s), or the default time slice.
+    Edf,
+}
+
+/// How the scheduling policy is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Mode {
+    /// Use the policy configured on the command line.
+    Manual,
+    /// Profile the workload and periodically select the most appropriate policy: fair with
+    /// --compute-boost for interactive workloads, FIFO with large time slices for batch
+    /// workloads.
+    Auto,
+}
+
+/// Order of the tasks within each queue (with the fair and edf policies, only among the tasks
+/// with the same virtual runtime or deadline).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Order {
+    /// Dispatch the task that has been waiting for the longest time first.
+    Fifo,
+    /// Dispatch the most recently queued task first.
+    Lifo,
+}
+
+/// CPU used when no idle CPU is found for a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Fallback {
+    /// Dispatch the task to the first CPU available (RL_CPU_ANY).
+    Any,
+    /// Distribute the tasks across the NUMA nodes (and across the CPUs of each node) in a
+    /// round-robin way.
+    Numa,
+}
+
+/// CPU where the brand-new tasks start running (see --initial-cpu).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InitialCpu {
+    /// Use the generic CPU selection, as with all the other tasks.
+    Default,
+    /// Spread the new tasks across the CPUs: start from the CPU that received a dispatch least
+    /// recently (the least loaded one).
+    Spread,
+    /// Start from the CPU where the other threads of the same process last ran.
+    LocalToParent,
+    /// Start from the CPU where the parent of the task last ran (the waker of a new task).
+    FollowWaker,
+}
+
+/// How a task received twice in the same scheduling round is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum DuplicatePid {
+    /// Keep a single instance of the task in the queues, updated to the latest state.
+    Coalesce,
+    /// Log the duplicate and ignore it, keeping the first instance of the task.
+    Skip,
+}
+
+/// What happens when a policy invariant is violated (see --debug-invariants).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InvariantMode {
+    /// Log the violation and keep scheduling.
+    Log,
+    /// Log the violation and stop the scheduler with an error.
+    Abort,
+}
+
+/// Commands that don't attach the scheduler to the kernel.
+#[derive(Debug, Clone, clap::Subcommand)]
+enum Command {
+    /// Simulate the scheduling policy against the selftest workload on the mock backend for each
+    /// combination of a grid of parameters and print the resulting fairness, throughput and
+    /// latency metrics (CSV).
+    Sweep(SweepOpts),
+
+    /// Replay a trace recorded with --record against the policy, configured with the command
+    /// line options of the recorded session, and report the scheduling decisions that differ
+    /// from the recorded ones.
+    Replay(ReplayOpts),
+
+    /// Check the configuration given on the command line, or in a configuration file with
+    /// --config (the options are checked against each other and against the CPUs of the system)
+    /// and print "OK" or all the problems found, without running the scheduler.
+    Validate(ValidateOpts),
+}
+
+/// scx_rust_scheduler: a FIFO Linux kernel scheduler that runs in user-space.
+#[derive(Debug, Clone, Parser)]
+#[clap(version, long_version = LONG_VERSION)]
+struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Policy used to order the tasks within each queue (the default depends on the features of
+    /// the build, like the defaults of --slice-us and --nvcsw-thresh).
+    #[clap(long, value_enum, default_value_t = defaults::POLICY)]
+    policy: Policy,
+
+    /// Evaluate a candidate policy in shadow mode: the tasks are still dispatched according to
+    /// --policy, while the candidate policy computes the tasks that it would have dispatched in
+    /// each round, and the share of the dispatches where the two policies agree is reported in
+    /// the stats (the candidate never affects the scheduling).
+    #[clap(long, value_enum)]
+    shadow_policy: Option<Policy>,
+
+    /// Order the tasks in FIFO order (the cheap fast path) while less than this amount of tasks
+    /// is waiting in the user-space queues, engaging the policy (fair or edf) only under
+    /// contention; the policy is disengaged again when the waiting tasks drop to half of this
+    /// threshold.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    policy_activation_threshold: Option<u64>,
+
+    /// Order of the tasks within each queue: lifo dispatches the most recently queued tasks
+    /// first, that can improve the cache reuse of bursty producers, at the cost of fairness
+    /// (interactive tasks can starve while new ones keep arriving, see --starve-timeout-ms, while
+    /// the oldest batch task still runs after waiting for 100ms).
+    #[clap(long, value_enum, default_value_t = Order::Fifo)]
+    order: Order,
+
+    /// Policy selection mode (with auto, --policy and --compute-boost are only used until the
+    /// first profile is selected).
+    #[clap(long, value_enum, default_value_t = Mode::Manual)]
+    mode: Mode,
+
+    /// Define a named profile that can be activated at runtime, in the format
+    /// NAME:KEY=VALUE[,KEY=VALUE...], where KEY is policy, slice-us or compute-boost (the
+    /// settings that are not part of the profile keep the values of the command line, also
+    /// available as the profile "default"). Can be repeated: profiles are switched with the
+    /// `profile <name>` command of the control socket, or with SIGUSR2 (next profile).
+    #[clap(long, value_name = "NAME:KEY=VALUE,...", value_parser = profiles::parse)]
+    profile: Vec<NamedProfile>,
+
+    /// Activate a profile every day at a time of the day, in the format HH:MM=NAME (local time,
+    /// 24-hour clock, NAME is a profile defined with --profile or "default"). Can be repeated:
+    /// the profile of the current time slot is activated at startup and each time a new slot
+    /// begins (a profile activated manually stays active until then).
+    #[clap(long, value_name = "HH:MM=NAME", value_parser = timetable::parse, requires = "profile")]
+    profile_at: Vec<TimedProfile>,
+
+    /// Maximum time slice (in microseconds) that a task can use before it is re-enqueued.
+    #[clap(
+        long,
+        default_value_t = defaults::SLICE_US,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    slice_us: u64,
+
+    /// Minimum time slice (in microseconds): lower bound of the time slices scaled down by the
+    /// amount of waiting tasks, requested by the tasks (see --slice-env) and computed by
+    /// --slice-expr, and time slice assigned to the tasks that yield (see "Time slices" in the
+    /// documentation).
+    #[clap(long, default_value = "100", value_parser = clap::value_parser!(u64).range(1..))]
+    slice_min_us: u64,
+
+    /// Never assign more than the time slice budget left to a task by the kernel (when nonzero,
+    /// e.g., a task preempted before using all its time slice), so that the task is not given
+    /// more CPU time than the kernel accounts for. The budget is still subject to the minimum
+    /// time slice: a budget below --slice-min-us caps the time slice to --slice-min-us, so that
+    /// a task about to exhaust its budget doesn't bounce back right after being dispatched.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    cap_remaining_slice: bool,
+
+    /// Minimum average amount of voluntary context switches per second to classify a task as
+    /// interactive.
+    #[clap(long, default_value_t = defaults::NVCSW_THRESH)]
+    nvcsw_thresh: u64,
+
+    /// How a task received twice in the same scheduling round is handled (this should never
+    /// happen, but it would corrupt the per-task statistics and dispatch the task twice).
+    #[clap(long, value_enum, default_value_t = DuplicatePid::Coalesce)]
+    duplicate_pid: DuplicatePid,
+
+    /// Validate the invariants of the policy on every dispatch (a task dispatched at most once
+    /// per round, time slices within bounds, valid target CPUs, non-decreasing virtual runtime
+    /// with the fair policy) and log the violations, optionally stopping the scheduler at the
+    /// first one (--debug-invariants abort). Only available in the debug builds, or in the
+    /// release builds with the `invariants` feature.
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "log")]
+    debug_invariants: Option<InvariantMode>,
+
+    /// Print the CPU time consumed by the scheduler itself in each interval, as a percentage of
+    /// the total CPU time and of a single CPU.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    self_stats: bool,
+
+    /// Limit the overhead of the scheduler: when the CPU time consumed by the scheduler itself
+    /// exceeds this percentage of the total CPU time in a one-second interval, the time slices
+    /// and the amount of tasks dispatched per round are doubled (up to 8 times), so that the
+    /// scheduler needs to run less often; they are halved again when the overhead drops below
+    /// half of this ceiling.
+    #[clap(long, value_parser = parse_pct)]
+    self_cpu_max_pct: Option<f64>,
+
+    /// Target scheduling latency (in microseconds): when the 99th percentile of the time spent
+    /// by the tasks in the user-space queues exceeds it in a one-second interval, the time slices
+    /// are halved (down to 1/8) and then the amount of tasks dispatched per round is doubled (up
+    /// to 4 times); the adjustments are reverted one by one when the latency drops below half
+    /// of the target.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    latency_target_us: Option<u64>,
+
+    /// React to the dispatches failed by the BPF component (see the "failed dispatches" in the
+    /// stats, distinct from the bounced and cancelled ones): when more than this amount of
+    /// dispatches per second fail in a one-second interval, a warning is printed, the amount of
+    /// tasks dispatched per round is halved and the time slices are doubled (up to 8 times and
+    /// --compute-max-slice-us), to relieve the pressure on the BPF component; they are restored
+    /// one step at a time after an interval without failures.
+    #[clap(long)]
+    failed_dispatch_thresh: Option<u64>,
+
+    /// Print a summary of the weights of the tasks received in each interval (min, max, mean and
+    /// histogram), also included in the JSON stats (see --metrics-dashboard).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    weight_stats: bool,
+
+    /// Print the dispatches per second and the average time slice of the tasks of each weight
+    /// class, also included in the JSON stats (see --metrics-dashboard): low (weights below LOW),
+    /// normal (weights in [LOW, HIGH]) and high (weights above HIGH), e.g., "100,100" to compare
+    /// the niced and the prioritized tasks with the tasks at the default weight.
+    #[clap(long, value_name = "LOW,HIGH", value_parser = weight_classes::parse)]
+    weight_classes: Option<WeightBounds>,
+
+    /// Print a per-CPU histogram of the time elapsed between consecutive dispatches to the same
+    /// CPU (useful to detect starved or bursty CPUs).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    cpu_gap_stats: bool,
+
+    /// Don't print the stats of the intervals without user-space and kernel dispatches (i.e.,
+    /// when the system is idle): the output resumes as soon as there are dispatches again, and
+    /// the stats are still printed once a minute during a long idle stretch, as a heartbeat.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_stats_on_idle: bool,
+
+    /// Share of the dispatches performed directly by the BPF component (in percent of all the
+    /// dispatches in a stats interval) above which a warning is printed: a high share means
+    /// that the user-space scheduler is bypassed, usually because it is too slow to keep up.
+    #[clap(long, default_value = "50", value_parser = clap::value_parser!(u64).range(0..=100))]
+    kernel_dispatch_warn_pct: u64,
+
+    /// Show a live view of the per-CPU load (time slices assigned to each CPU in the last
+    /// interval): on a terminal, the load bars are redrawn in place at the top of the screen,
+    /// above the regular output; otherwise, the loads are printed as regular lines.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    top_view: bool,
+
+    /// CPUs that never receive dispatches from the scheduler, even if they are online (e.g.,
+    /// because they are reserved to another workload), as a list of CPU ids and ranges (e.g.,
+    /// "6,7" or "4-7").
+    #[clap(long, value_parser = cpulist::parse)]
+    cpus_offline: Option<CpuList>,
+
+    /// Reserve a CPU to the tasks dispatched directly by the kernel (see the "kernel"
+    /// dispatches in the stats): the CPU never receives user-space dispatches, like the CPUs
+    /// excluded by --cpus-offline, so that the critical kernel tasks run on a quieter CPU.
+    ///
+    /// NOTE: kernel dispatches are not steered to the reserved CPU, they can still use any CPU,
+    /// and nr_kernel_dispatches counts them on all the CPUs (a per-CPU rate isn't available), so
+    /// the kernel rate reported in the stats is an upper bound of the load of the reserved CPU.
+    /// User-space tasks can also run on it when they are bounced to the shared DSQ.
+    #[clap(long)]
+    kernel_cpu: Option<usize>,
+
+    /// Dispatch tasks also to the CPUs isolated on the kernel command line (isolcpus= and
+    /// nohz_full=), that are excluded by default, like the CPUs listed with --cpus-offline.
+    ///
+    /// The isolated CPUs are ignored (with a warning) if they would leave no CPU to the
+    /// scheduler.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    ignore_isolcpus: bool,
+
+    /// Skip select_cpu() for tasks that carry the RL_CPU_ANY bit in their enqueue flags and
+    /// dispatch them directly on the first CPU available.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    cpu_any_shortcut: bool,
+
+    /// Validate the CPUs returned by select_cpu(): a CPU beyond the amount of online CPUs, or
+    /// that is not online (e.g., a stale CPU during a hotplug event), is never used (the task
+    /// goes to the fallback, see --fallback). The online CPUs are refreshed once per second.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    strict_select_cpu: bool,
+
+    /// Assign larger time slices to compute-bound tasks (tasks that run for long periods of time
+    /// with only a few voluntary context switches), to reduce their switching overhead.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    compute_boost: bool,
+
+    /// Maximum amount of voluntary context switches per second of CPU time to consider a task
+    /// compute-bound.
+    #[clap(long, default_value = "10")]
+    compute_nvcsw_thresh: u64,
+
+    /// Minimum CPU utilization (in percent) to consider a task compute-bound.
+    #[clap(long, default_value = "90")]
+    compute_util_thresh: u64,
+
+    /// Maximum time slice (in microseconds) assigned to compute-bound tasks (and to the batch
+    /// tasks with --batch-quantum-mult).
+    #[clap(long, default_value = "20000")]
+    compute_max_slice_us: u64,
+
+    /// Multiply the time slice of the batch tasks by this factor, so that they can run several
+    /// quanta back-to-back, amortizing the cache warm-up, while the interactive tasks keep the
+    /// short time slice (the batch time slice never exceeds --compute-max-slice-us).
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..=64))]
+    batch_quantum_mult: u64,
+
+    /// Maximum time slice (in microseconds) assigned to the interactive tasks, regardless of the
+    /// global time slice and of any boost (the slices requested with --slice-env, computed with
+    /// --slice-expr or scaled up under pressure, and the minimum time slice), so that a single
+    /// interactive task never holds a CPU for long. By default the interactive tasks are capped
+    /// to 1000us, except for the requested and computed time slices.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    interactive_max_slice_us: Option<u64>,
+
+    /// With the edf policy, assign the time slices in proportion to the time left until the
+    /// deadline of the tasks (slack), measured when they are dispatched: each task gets this
+    /// percentage of its slack, between --slice-min-us and the time slice decided by the policy,
+    /// so that the tasks with a tight deadline run with short quanta and don't delay the
+    /// following deadlines. The time slices requested with --slice-env are not affected.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..=100))]
+    deadline_slice_pct: Option<u64>,
+
+    /// Boost the weight of the I/O-bound tasks (tasks that spend most of their time sleeping,
+    /// e.g. waiting for I/O, and that release the CPU voluntarily soon after getting it) up to
+    /// this factor, so that they are scheduled promptly when they become runnable (with the
+    /// fair policy). The boost is proportional to the share of the time that a task spends
+    /// sleeping (see also --io-nvcsw-thresh).
+    #[clap(long, value_parser = clap::value_parser!(u64).range(2..=16))]
+    io_boost: Option<u64>,
+
+    /// Minimum amount of voluntary context switches per second of CPU time to consider a task
+    /// I/O-bound (see --io-boost): unlike --nvcsw-thresh, the rate is normalized against the
+    /// CPU time of the task, so a CPU-bound task can't qualify just by switching often.
+    #[clap(long, default_value = "100")]
+    io_nvcsw_thresh: u64,
+
+    /// Expose the scheduler metrics in OpenMetrics format on http://<ADDR>/metrics (e.g.,
+    /// 127.0.0.1:9000).
+    ///
+    /// The metrics include the dispatch-to-run latency of the tasks: exact when the backend
+    /// reports when the tasks start running, otherwise an estimate exposed separately
+    /// (scx_rust_scheduler_run_latency_estimated_seconds, see run_latency.rs).
+    #[clap(long)]
+    metrics_addr: Option<String>,
+
+    /// Attach exemplars to the scheduling latency histogram exposed by the metrics endpoint:
+    /// each bucket reports the most recent task (pid) whose latency fell in that bucket, along
+    /// with the observed latency.
+    #[clap(long, action = clap::ArgAction::SetTrue, requires = "metrics_addr")]
+    metrics_exemplars: bool,
+
+    /// Also serve a self-contained HTML dashboard (live counters and sparklines) on
+    /// http://<ADDR>/, along with the stats in JSON format on http://<ADDR>/api/stats.
+    #[clap(long, action = clap::ArgAction::SetTrue, requires = "metrics_addr")]
+    metrics_dashboard: bool,
+
+    /// Push the stats of each interval to a collector as JSON datagrams sent to this UDP
+    /// endpoint (HOST:PORT), e.g., for a fleet-wide aggregation without scraping: the same stats
+    /// served by the dashboard (see --metrics-dashboard), along with the host name. The push is
+    /// fire-and-forget, the datagrams that can't be sent are dropped and counted.
+    #[clap(long, value_name = "HOST:PORT")]
+    stats_udp: Option<String>,
+
+    /// Accept commands (e.g., "get stats" or "get stats --binary") on this Unix socket (see
+    /// control.rs for the protocol).
+    #[clap(long)]
+    control_socket: Option<String>,
+
+    /// Record all the interactions with the BPF component (tasks received, scheduling decisions,
+    /// timestamps and statistics) to this file, so that the session can be reproduced offline
+    /// with the replay subcommand (the file is overwritten when the scheduler restarts).
+    #[clap(long)]
+    record: Option<String>,
+
+    /// Export the features and the decision of each dispatched task (weight, voluntary context
+    /// switches, CPU time, queue depth, previous CPU, selected CPU and time slice) to this file
+    /// in CSV format, e.g. as a training set (the schema is documented in export.rs, the file is
+    /// overwritten when the scheduler restarts).
+    #[clap(long)]
+    export_csv: Option<String>,
+
+    /// Periodically append a snapshot of the full state of the scheduler (all the counters, the
+    /// top CPU consumers, the per-CPU load and the queue depth) to this file, as one line of
+    /// JSON per snapshot (see state_log.rs), e.g., for the post-mortem analysis of long runs.
+    #[clap(long)]
+    state_log: Option<String>,
+
+    /// Interval between two snapshots of the state log (see --state-log), in seconds.
+    #[clap(
+        long,
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "state_log"
+    )]
+    state_log_secs: u64,
+
+    /// Maximum size of the state log (see --state-log), in MB: once exceeded, the log is rotated
+    /// to <PATH>.1 (replacing the previous one) and a new log is started.
+    #[clap(
+        long,
+        default_value = "16",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "state_log"
+    )]
+    state_log_max_mb: u64,
+
+    /// Honor the time slice requested by the tasks via the SCX_SLICE_US environment variable
+    /// (in microseconds). Requests are clamped between --slice-min-us and the default maximum
+    /// time slice, so they can't be used to get more CPU time than the other tasks (a request of
+    /// 0 is a yield, the task gets --slice-min-us).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    slice_env: bool,
+
+    /// Compute the time slice of each task (in microseconds) with an arithmetic expression over
+    /// the variables weight, nr_waiting, nr_cpus, runtime (CPU time used by the task, in
+    /// microseconds) and slice (--slice-us), e.g., "max(500, slice / (nr_waiting + 1))", see
+    /// slice_expr.rs. The result is clamped between --slice-min-us and the maximum between
+    /// --slice-us and --compute-max-slice-us.
+    #[clap(long, value_parser = slice_expr::parse)]
+    slice_expr: Option<SliceExpr>,
+
+    /// Constrain the CPU selection to the CPUs that the tasks are allowed to use (e.g., by their
+    /// cpuset cgroup), read from Cpus_allowed_list in /proc/<pid>/status and refreshed every
+    /// second. Tasks dispatched outside of their allowed CPUs are bounced by the BPF component
+    /// to the shared DSQ.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    cpuset_aware: bool,
+
+    /// Inject idle time when the temperature reported by this hwmon sensor (e.g.,
+    /// /sys/class/hwmon/hwmon0/temp1_input) exceeds --thermal-max-temp, by dispatching fewer
+    /// tasks with shorter time slices. The sensor is read once per second.
+    #[clap(long)]
+    thermal_sensor: Option<String>,
+
+    /// Temperature (in Celsius) that triggers the idle injection.
+    #[clap(long, default_value = "85")]
+    thermal_max_temp: i64,
+
+    /// Temperature (in Celsius) below which the idle injection is stopped.
+    #[clap(long, default_value = "75")]
+    thermal_resume_temp: i64,
+
+    /// Percentage of the dispatch capacity (tasks dispatched per round and time slices) removed
+    /// while injecting idle time.
+    #[clap(long, default_value = "50", value_parser = clap::value_parser!(u64).range(0..100))]
+    thermal_idle_pct: u64,
+
+    /// Minimum time (in milliseconds) between two consecutive changes of an adaptive controller
+    /// (profile switches of --mode auto, end of the idle injection of --thermal-sensor, relaxing
+    /// steps of --latency-target-us).
+    #[clap(long, default_value = "30000")]
+    hysteresis_ms: u64,
+
+    /// Minimum change (in percentage points) of the signal that drives an adaptive controller
+    /// between two consecutive changes (percentage of interactive dispatches for --mode auto).
+    #[clap(long, default_value = "10")]
+    hysteresis_delta: u64,
+
+    /// Report when the task arrival rate exceeds the dispatch rate by at least this percentage
+    /// for a few consecutive seconds (the scheduler can't keep up with the tasks that want to
+    /// run).
+    #[clap(long, default_value = "20")]
+    overload_thresh_pct: u64,
+
+    /// Shed load when the scheduler can't keep up (see --overload-thresh-pct), by increasing the
+    /// time slices (up to --compute-max-slice-us), to reduce the amount of context switches.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    overload_shed: bool,
+
+    /// Prefer larger time slices when the scheduler itself lags behind: when the average gap (in
+    /// microseconds) between two consecutive wakeups of the scheduler, with tasks still waiting
+    /// to be dispatched, exceeds this threshold in a one-second interval (e.g., the scheduler is
+    /// preempted or overloaded), the time slices are doubled (up to 8 times and
+    /// --compute-max-slice-us), so that the scheduler needs to wake up less often.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    wakeup_gap_high_us: Option<u64>,
+
+    /// Average gap (in microseconds) between two consecutive wakeups of the scheduler below
+    /// which the time slices scaled by --wakeup-gap-high-us are halved again (default: half of
+    /// --wakeup-gap-high-us).
+    #[clap(long)]
+    wakeup_gap_low_us: Option<u64>,
+
+    /// Restart the scheduler when notify_complete() (that gives control back to the BPF
+    /// component and can put the scheduler to sleep) takes longer than this threshold (in
+    /// milliseconds) --notify-stall-count times in a row, instead of stalling silently (e.g.,
+    /// because of a kernel issue). Only the calls made while tasks are waiting (in the user-space
+    /// queues or in the BPF component) are accounted, so the scheduler can sleep for as long as
+    /// needed on an idle system.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    notify_stall_ms: Option<u64>,
+
+    /// Amount of consecutive calls to notify_complete() longer than --notify-stall-ms that
+    /// trigger a restart of the scheduler.
+    #[clap(long, default_value = "3", value_parser = clap::value_parser!(u64).range(1..))]
+    notify_stall_count: u64,
+
+    /// Maximum amount of tasks dequeued from the BPF component in a single scheduling round:
+    /// when a round exceeds it without draining the queue (e.g., a bug keeps dequeue_task()
+    /// returning tasks), the scheduler stops dequeuing, dispatches the tasks received so far and
+    /// gives control back to the BPF component (notify_complete()), instead of hanging.
+    #[clap(long, default_value = "100000", value_parser = clap::value_parser!(u64).range(1..))]
+    max_dequeue_per_round: u64,
+
+    /// Maximum wall-clock time (in microseconds) of a single scheduling round: once exceeded
+    /// (e.g., a huge queue combined with an expensive policy), the scheduler stops dequeuing and
+    /// dispatching tasks, gives control back to the BPF component reporting the tasks still
+    /// waiting, and continues in the next round. The time is checked every ROUND_BUDGET_CHECK
+    /// tasks, so the budget can be exceeded by the time needed to process them (the rounds cut
+    /// are reported in the stats).
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    round_budget_us: Option<u64>,
+
+    /// Detect when the scheduler itself doesn't get to run for too long: when an iteration of
+    /// the main loop, excluding the time slept waiting for new tasks, takes longer than this
+    /// threshold (in milliseconds), the scheduler is starved (e.g., preempted by higher priority
+    /// tasks) and all the tasks waiting in user space stall with it. The gaps are reported and,
+    /// the first time, the scheduler raises its own priority (nice -20).
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    loop_gap_ms: Option<u64>,
+
+    /// Use the rounds that dispatch no task and leave no task waiting (e.g., the scheduler woken
+    /// up with nothing to run, but not a congested BPF component) for the housekeeping of the
+    /// scheduler, at most once every this many milliseconds: the state of the tasks that exited
+    /// is dropped and the online CPUs are refreshed during the idle windows, instead of waiting
+    /// for the next stats interval (once per second). The housekeeping passes are reported in
+    /// the stats.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_maintenance_ms: Option<u64>,
+
+    /// Bound the wakeup credit granted with the fair policy: tasks that have been sleeping can
+    /// get up to one time slice of virtual runtime credit each, while the total credit granted
+    /// to all the tasks in a one-second interval is limited to this budget (in microseconds),
+    /// so that many tasks waking up together can't crowd out the CPU-bound ones.
+    #[clap(long)]
+    boost_budget_us: Option<u64>,
+
+    /// Report a possible fork bomb when more than this amount of new tasks (never seen before)
+    /// is received in less than one second.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    fork_bomb_thresh: Option<u64>,
+
+    /// Throttle the tasks first seen during a fork bomb (see --fork-bomb-thresh): until the surge
+    /// ends, they are dispatched with a minimal time slice (100us) and only after the tasks that
+    /// were already known, that are not affected by the flood.
+    #[clap(long, action = clap::ArgAction::SetTrue, requires = "fork_bomb_thresh")]
+    fork_bomb_throttle: bool,
+
+    /// Reserve this percentage of the total CPU capacity to the interactive tasks: while they
+    /// are active and below their reservation in a one-second interval, the batch tasks can't
+    /// use more than the rest of the capacity (they are held back in the queues).
+    #[clap(long, value_parser = parse_pct)]
+    reserve_interactive_pct: Option<f64>,
+
+    /// Reserve this percentage of the total CPU capacity to the batch tasks: while they are
+    /// active and below their reservation in a one-second interval, the interactive tasks can't
+    /// use more than the rest of the capacity (they are held back in the queues).
+    #[clap(long, value_parser = parse_pct)]
+    reserve_batch_pct: Option<f64>,
+
+    /// Cap the CPU time of the tasks whose name (comm) matches a regular expression, in the
+    /// format PATTERN=US: all the matching tasks together can't use more than US microseconds
+    /// of CPU time per second (they are held back in the queues once they reach the cap). Can
+    /// be repeated, the first matching pattern applies to each task.
+    #[clap(long, value_name = "PATTERN=US", value_parser = comm_cap::parse)]
+    comm_cap: Vec<CommRule>,
+
+    /// Dispatch any task that has been waiting in the user-space queues for longer than this
+    /// timeout (in milliseconds) before all the other tasks, regardless of the policy and the
+    /// weights, as a hard guarantee against starvation.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    starve_timeout_ms: Option<u64>,
+
+    /// Detect and report potential priority inversions: a task is waiting in the user-space
+    /// queues while a task with a weight lower by at least this amount is running (e.g., 100).
+    #[clap(long)]
+    inversion_weight_gap: Option<u64>,
+
+    /// Confine all the threads of a process to the same last-level cache (LLC) domain, to
+    /// maximize the shared cache hits, spreading different processes across the domains.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    llc_group: bool,
+
+    /// Bias the CPU selection towards the CPUs that have been idle for the longest time
+    /// (according to the recent dispatch activity), to spread the heat and wear more evenly
+    /// across the CPUs.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    spread_idle: bool,
+
+    /// CPU used when no idle CPU is found for a task: the first CPU available (any), or the
+    /// next CPU in a round-robin across the NUMA nodes, to balance the load across them (numa).
+    #[clap(long, value_enum, default_value_t = Fallback::Any)]
+    fallback: Fallback,
+
+    /// Placement of the brand-new tasks (never dispatched before, or without a previously used
+    /// CPU): the idle CPU search starts from the CPU selected by this policy, instead of the
+    /// previously used CPU, e.g., to reduce the initial imbalance of fork-heavy workloads.
+    #[clap(long, value_enum, default_value_t = InitialCpu::Default)]
+    initial_cpu: InitialCpu,
+
+    /// Dispatch the kernel threads (tasks with the PF_KTHREAD flag in /proc/<pid>/stat, e.g.,
+    /// kworker, ksoftirqd) as soon as they are received, on their previously used CPU with the
+    /// default time slice, bypassing the user-space queues and the scheduling policy, that only
+    /// applies to the user tasks.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    skip_kthreads: bool,
+
+    /// Keep the tasks on the NUMA node hosting their memory, approximated by the node where each
+    /// task used most of its CPU time: when a task has been moved to a different node, the idle
+    /// CPU search starts from a CPU of its home node, instead of its previously used CPU (the
+    /// task can still run on a different node if no CPU of the home node is idle, see the
+    /// cross-node dispatches in the stats).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    numa_affinity: bool,
+
+    /// Damp the migrations of the tasks (to reduce the cache misses of the tasks bouncing
+    /// between CPUs): once a task migrates to a different CPU, it stays on that CPU for this
+    /// amount of microseconds, unless the CPU is badly overloaded (the migrations cancelled are
+    /// reported in the stats).
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    migration_lock_us: Option<u64>,
+
+    /// Keep the woken tasks close to their waker: the idle CPU search of a woken task starts
+    /// from the CPU where its waker recently ran (so that the task runs on the same CPU, or in
+    /// the same LLC domain, sharing the cache of the waker), instead of its previously used CPU.
+    /// When the waker is unknown, it is approximated by the last thread of the same process
+    /// dispatched recently (the hit rate is reported in the stats).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    wake_affine: bool,
+
+    /// Grace period (in seconds) before all the state of a task that is not received anymore
+    /// (statistics, virtual runtime, process LLC domain, time slice accounting) is discarded:
+    /// a longer period preserves the state of the tasks that sleep for a long time, at the cost
+    /// of keeping the state of the tasks that exited for longer.
+    #[clap(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    pid_gc_secs: u64,
+
+    /// Maximum amount of tasks whose state is tracked (see --pid-gc-secs): when a new task
+    /// exceeds it, the state of the least recently seen task is evicted, so that the memory used
+    /// by the scheduler stays bounded on systems with huge numbers of short-lived tasks (an
+    /// evicted task that is received again is tracked as a new task).
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_tracked_pids: Option<u64>,
+
+    /// Save the state learned by the policy for each task (statistics, virtual runtime, CPU
+    /// pins) to this file when the scheduler stops, and restore it when the scheduler starts
+    /// again, so that the fairness between the tasks survives the restarts.
+    #[clap(long)]
+    state_file: Option<String>,
+
+    /// Maximum age (in seconds) of the state saved to --state-file for it to be restored: an
+    /// older state no longer reflects the behavior of the tasks and it is ignored (as well as a
+    /// state saved before a reboot, regardless of its age).
+    #[clap(long, default_value = "10")]
+    state_max_age_secs: u64,
+
+    /// Sample the cache miss rate of each CPU with the hardware performance counters and keep
+    /// the compute-bound tasks away from the CPUs that are thrashing their cache (requires
+    /// CAP_PERFMON or kernel.perf_event_paranoid <= 0, otherwise it is disabled, see cache.rs).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    cache_aware: bool,
+
+    /// Cache miss rate (in misses per thousand instructions) that marks a CPU as thrashing its
+    /// cache with --cache-aware (until the miss rate drops below half of it).
+    #[clap(long, default_value = "20", value_parser = clap::value_parser!(u64).range(1..))]
+    cache_miss_thresh: u64,
+
+    /// Reconcile the time slices assigned to the tasks against the CPU time they actually used
+    /// and log the tasks that used more than their time slice (useful to catch accounting bugs
+    /// when extending the scheduling policy), warning if the tasks that ran until their time
+    /// slice expired systematically used much more or much less than it (the time slices are
+    /// not taking effect).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    debug_accounting: bool,
+
+    /// Divergence (in percent) between the CPU time used by the tasks whose time slice expired
+    /// and their time slices, above which --debug-accounting reports that the time slices are
+    /// not taking effect.
+    #[clap(long, default_value = "50", value_parser = clap::value_parser!(u64).range(1..100))]
+    accounting_tolerance_pct: u64,
+
+    /// Number of dispatch worker threads (see the concurrency model in the documentation). Only
+    /// the single-threaded dispatcher is currently implemented, so only 1 is accepted.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..=1))]
+    workers: u64,
+
+    /// Verify the prerequisites required to attach the scheduler (sched_ext support, privileges,
+    /// no other scheduler attached) and exit.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    check: bool,
+
+    /// Run the scheduling policy against a mock backend with a synthetic workload, verify its
+    /// invariants (no starvation, valid time slices and CPUs) and exit, without attaching to the
+    /// kernel (exit code is nonzero on failure).
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    selftest: bool,
+}
+
+// Class of a task, determined by the classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TaskClass {
+    Interactive, // Latency-sensitive task (serviced first, shorter time slice)
+    Batch,       // CPU-intensive task (serviced when no interactive task is waiting)
+}
+
+// Per-task statistics used to classify tasks.
+struct TaskInfo {
+    nvcsw: u64,        // Voluntary context switches at the beginning of the current window
+    nvcsw_ts: u64,     // Timestamp of the beginning of the current window
+    avg_nvcsw: u64,    // Average amount of voluntary context switches per second
+    runtime: u64,      // Total CPU time at the beginning of the current window
+    avg_util: u64,     // Average CPU utilization (in percent)
+    avg_nvcsw_rt: u64, // Average amount of voluntary context switches per second of CPU time
+    avg_io: u64,       // Average share of the time spent sleeping while I/O-bound (in percent)
+    last_runtime: u64, // Total CPU time observed the last time the task has been received
+    vtime: u64,        // Virtual runtime (used by the fair policy)
+    last_seen: u64,    // Last time the task has been received from the BPF component
+    slice_req: Option<u64>, // Time slice requested by the task (see --slice-env)
+    latency_req: Option<u64>, // Latency requested by the task (see the edf policy)
+    hints_ts: Option<u64>, // Last time the scheduling hints have been read
+    weight: u64,       // Weight reported by the kernel the last time the task has been received
+    wrr_credits: u64,  // Consecutive dispatches left (used by the wrr policy)
+    tgid: Option<i32>, // Process of the task (see --llc-group)
+    cpuset: Option<Vec<usize>>, // CPUs the task is allowed to use (see --cpuset-aware)
+    cpuset_ts: Option<u64>, // Last time the allowed CPUs have been read
+    flood: bool,       // First seen during a fork bomb (see --fork-bomb-thresh)
+    comm_cap: Option<usize>, // CPU time cap matched by the comm of the task (see --comm-cap)
+    comm_ts: Option<u64>, // Last time the comm has been matched against the caps
+    dispatched: bool,  // Dispatched at least once (see --initial-cpu)
+}
+
+// Task waiting in one of the user-space queues.
+struct PendingTask {
+    task: Task,    // Task received from the BPF component
+    enq_ts: u64,   // Time when the task has been received
+    vtime: u64,    // Virtual runtime of the task when it has been received
+    deadline: u64, // Soft deadline of the task (used by the edf policy)
+}
+
+// Counters of the BPF component at the last reset (see the reset command): the counters can't be
+// reset in the kernel, so the stats report them relative to these values.
+#[derive(Debug, Clone, Copy, Default)]
+struct CountersBase {
+    nr_user_dispatches: u64,
+    nr_kernel_dispatches: u64,
+    nr_bounce_dispatches: u64,
+    nr_cancel_dispatches: u64,
+    nr_failed_dispatches: u64,
+}
+
+struct Scheduler<'a, B: SchedBackend> {
+    bpf: B,                                // Connector to the sched_ext BPF backend (or a mock)
+    opts: &'a Opts,                        // Command line options
+    policy: Policy,                        // Current policy
+    activation: Option<PolicyActivation>,  // FIFO fast path (see --policy-activation-threshold)
+    policy_active: bool,                   // Current policy applied to the ordering of the tasks
+    compute_boost: bool,                   // Current --compute-boost setting
+    slice_ns: u64,                         // Current maximum time slice
+    auto: Option<AutoMode>,                // Workload profiler (see --mode auto)
+    profiles: Option<Profiles>,            // Named profiles (see --profile)
+    timetable: Option<Timetable>,          // Profiles by time of the day (see --profile-at)
+    metrics: Option<&'a MetricsServer>,    // Metrics endpoint
+    control: Option<&'a ControlServer>,    // Control socket
+    notifier: Option<&'a Notifier>,        // Service state notifications to systemd
+    tasks: HashMap<i32, TaskInfo>,         // Per-task statistics (used by the classifier)
+    lru: Option<PidLru>,                   // Tracked tasks by last time seen (--max-tracked-pids)
+    nr_evicted_pids: u64,                  // Tasks evicted by --max-tracked-pids
+    interactive: VecDeque<PendingTask>,    // Queue of interactive tasks
+    batch: VecDeque<PendingTask>,          // Queue of batch tasks
+    cpu_gaps: CpuGapStats,                 // Per-CPU inter-dispatch gap statistics
+    min_vtime: u64,                        // Current minimum virtual runtime (see update_vtime())
+    boost: Option<BoostBudget>,            // Wakeup credit budget (see --boost-budget-us)
+    fork_bomb: Option<ForkBombDetector>,   // Surges of new tasks (see --fork-bomb-thresh)
+    reservation: Option<Reservation>,      // Per-class CPU reservation (see --reserve-*-pct)
+    comm_caps: Option<CommCaps>,           // CPU time caps by task name (see --comm-cap)
+    top_view: Option<TopView<io::Stdout>>, // Per-CPU load bars (see --top-view)
+    latency: LatencyHistogram,             // Time spent by the tasks in the user-space queues
+    run_latency: Option<RunLatency>,       // Time between the dispatch and the run (metrics)
+    queue_trend: QueueTrend,               // Growth rate of the queued tasks (stats)
+    dispatch_ratio: DispatchRatio,         // Share of the kernel dispatches (stats)
+    batch_sizes: BatchSizes,               // Tasks dispatched per round (stats)
+    invariants: Option<InvariantChecker>,  // Policy invariants (see --debug-invariants)
+    thermal: Option<Thermal>,              // Temperature monitor (see --thermal-sensor)
+    nr_dispatch_retries: u64,              // Dispatch attempts retried (BPF component busy)
+    nr_dispatch_requeues: u64,             // Tasks re-queued after exhausting all the retries
+    nr_starve_timeouts: u64,               // Tasks dispatched first by --starve-timeout-ms
+    nr_duplicate_pids: u64,                // Tasks received twice in the same round
+    nr_cpuset_remaps: u64,                 // Tasks moved to one of their allowed CPUs
+    nr_dequeue_errors: u64,                // Failed calls to dequeue_task()
+    nr_dequeue_errors_streak: u64,         // Consecutive failed calls to dequeue_task()
+    online_cpus: Option<Vec<bool>>,        // Online CPUs (see --strict-select-cpu)
+    nr_invalid_cpus: u64,                  // Unusable CPUs returned by select_cpu()
+    nr_dequeue_breaks: u64,                // Rounds cut by --max-dequeue-per-round
+    nr_budget_breaks: u64,                 // Rounds cut by --round-budget-us
+    nr_idle_passes: u64,                   // Housekeeping passes in idle rounds
+    idle_pass_ts: Option<u64>,             // Last housekeeping pass in an idle round
+    loop_gap: Option<LoopGap>,             // Scheduler starvation (see --loop-gap-ms)
+    nr_kthread_dispatches: u64,            // Kernel threads dispatched by --skip-kthreads
+    prev_bounce_dispatches: u64,           // Bounced dispatches at the previous stats interval
+    prev_cancel_dispatches: u64,           // Cancelled dispatches at the previous stats interval
+    prev_failed_dispatches: u64,           // Failed dispatches at the previous stats interval
+    counters_base: CountersBase,           // BPF counters at the last reset (see reset_stats())
+    last_stats_ts: u64,                    // Last time the stats have been printed (in seconds)
+    round_pids: HashSet<i32>,              // Tasks received in the current round
+    nr_round_retries: u32,                 // Dispatches retried in the current round
+    excluded_cpus: Vec<bool>,              // CPUs excluded from dispatch (see --cpus-offline)
+    next_cpu: usize,                       // Next CPU used for round-robin placement
+    pins: HashMap<i32, i32>,               // CPU of the pinned tasks (see the pin command)
+    boost_groups: BoostGroups,             // Tasks boosted via the boost command
+    inversions: Option<InversionDetector>, // Priority inversion detector
+    cpu_tasks: Vec<Option<(i32, u64)>>,    // Last task dispatched to each CPU (pid, time)
+    process_cpus: HashMap<i32, i32>,       // Last CPU of each process (see --initial-cpu)
+    kthreads: HashMap<i32, (bool, u64)>,   // Kernel threads and last time seen (--skip-kthreads)
+    llc: Option<LlcDomains>,               // Assignment of the processes to the LLC domains
+    accounting: Option<Accounting>,        // Time slice reconciliation (see --debug-accounting)
+    idle: Option<IdleHistory>,             // Idle history of the CPUs (see --spread-idle)
+    cache: Option<CacheMonitor>,           // Cache miss rates of the CPUs (see --cache-aware)
+    numa: Option<NumaFallback>,            // NUMA-interleaved fallback (see --fallback numa)
+    numa_affinity: Option<NumaAffinity>,   // NUMA home node of the tasks (see --numa-affinity)
+    migration: Option<MigrationLock>,      // Migration damping (see --migration-lock-us)
+    wake_affinity: Option<WakeAffinity>,   // Waker-wakee locality (see --wake-affine)
+    overload: OverloadDetector,            // Arrival rate vs dispatch rate detector
+    wakeup_gap: Option<WakeupGap>,         // Scheduler turnaround (see --wakeup-gap-high-us)
+    breaker: Option<NotifyBreaker>,        // notify_complete() stalls (see --notify-stall-ms)
+    overhead: Option<Overhead>,            // Scheduler CPU usage (see --self-cpu-max-pct)
+    latency_target: Option<LatencyTarget>, // Latency controller (see --latency-target-us)
+    backpressure: Option<Backpressure>,    // Failed dispatches (see --failed-dispatch-thresh)
+    weights: Option<WeightStats>,          // Weight distribution (see --weight-stats)
+    weight_classes: Option<WeightClasses>, // Service per weight class (see --weight-classes)
+    shadow: Option<ShadowPolicy>,          // Candidate policy (see --shadow-policy)
+    export: Option<DecisionExporter>,      // Decisions exported to CSV (see --export-csv)
+    state_log: Option<StateLog>,           // Periodic state snapshots (see --state-log)
+    udp_stats: Option<UdpStats>,           // Stats collector (see --stats-udp)
+    #[cfg(feature = "stats")]
+    scx_stats: Option<&'a ScxStats>, // scx_stats server (see --features stats)
+}
+
+impl<'a> Scheduler<'a, Recorder<BpfBackend<'a>, BufWriter<File>>> {
+    fn init(
+        opts: &'a Opts,
+        metrics: Option<&'a MetricsServer>,
+        control: Option<&'a ControlServer>,
+        notifier: Option<&'a Notifier>,
+        open_object: &'a mut MaybeUninit<OpenObject>,
+    ) -> Result<Self> {
+        let bpf = BpfBackend::init(
+            open_object,
+            0,     // exit_dump_len (buffer size of exit info, 0 = default)
+            false, // partial (false = include all tasks)
+            false, // debug (false = debug mode off)
+        )?;
+        let trace = opts.record.as_deref().map(trace::create).transpose()?;
+        let mut sched = Self::new(Recorder::new(bpf, trace), opts, metrics, control);
+        sched.notifier = notifier;
+        sched.export = opts
+            .export_csv
+            .as_deref()
+            .map(DecisionExporter::create)
+            .transpose()?;
+        sched.udp_stats = opts.stats_udp.as_deref().map(UdpStats::open).transpose()?;
+        if let Some(path) = opts.state_log.as_deref() {
+            let max_size = opts.state_log_max_mb << 20;
+            let now = sched.now_ns();
+            sched.state_log = Some(StateLog::create(path, max_size, opts.state_log_secs, now)?);
+        }
+        if opts.llc_group {
+            sched.set_llc_domains(llc::topology_domains()?);
+        }
+        if opts.fallback == Fallback::Numa {
+            let nr_cpus = *sched.bpf.nr_online_cpus_mut() as usize;
+            sched.numa = Some(NumaFallback::new(numa::topology_nodes(nr_cpus)?));
+        }
+        if opts.numa_affinity {
+            let nr_cpus = *sched.bpf.nr_online_cpus_mut() as usize;
+            sched.numa_affinity = Some(NumaAffinity::new(numa::topology_nodes(nr_cpus)?));
+        }
+        if opts.cache_aware {
+            let nr_cpus = *sched.bpf.nr_online_cpus_mut() as usize;
+            match PerfCounters::open(nr_cpus) {
+                Ok(counters) => {
+                    sched.cache = Some(CacheMonitor::new(Some(counters), opts.cache_miss_thresh))
+                }
+                Err(err) => println!(
+                    "WARNING: failed to open the cache miss counters ({}), --cache-aware disabled",
+                    err
+                ),
+            }
+        }
+        if let Some(path) = opts.state_file.as_deref() {
+            sched.load_state(path);
+        }
+        if let Some(notifier) = notifier {
+            notifier.ready(sched.now_ns());
+        }
+
+        Ok(sched)
+    }
+
+    /// Scheduler main loop, returning true if the scheduler needs to be restarted (requested by
+    /// the BPF component, a stall of notify_complete(), see --notify-stall-ms, or persistent
+    /// errors of dequeue_task(), see DEQUEUE_ERRORS_RESTART).
+    fn run(&mut self) -> Result<bool> {
+        println!("Rust scheduler is enabled (CTRL+c to exit)");
+        if let Some(feature) = defaults::FEATURE {
+            println!(
+                "compiled-in defaults: {} (policy {}, slice {}us, nvcsw-thresh {})",
+                feature,
+                format!("{:?}", defaults::POLICY).to_lowercase(),
+                defaults::SLICE_US,
+                defaults::NVCSW_THRESH
+            );
+        }
+        self.run_loop()?;
+        println!("Rust scheduler is disabled");
+
+        if let Some(path) = self.opts.state_file.as_deref() {
+            if let Err(err) = snapshot::save(path, &self.snapshot()) {
+                println!("WARNING: failed to save the state ({:#})", err);
+            }
+        }
+
+        self.bpf.finish().context("Failed to write the trace")?;
+        self.finish_export()?;
+        self.finish_state_log()?;
+        let exit = self.bpf.inner_mut().shutdown_and_report()?;
+
+        Ok(exit.should_restart() || self.needs_restart())
+    }
+}
+
+impl<'a, B: SchedBackend> Scheduler<'a, B> {
+    /// Schedule the tasks until the backend exits (also used to replay a trace, see replay.rs).
+    fn run_loop(&mut self) -> Result<()> {
+        let mut prev_ts = self.now();
+        let mut prev_user_dispatches = 0;
+        let mut prev_kernel_dispatches = 0;
+
+        self.refresh_timetable();
+
+        while !self.bpf.exited() && !self.needs_restart() {
+            let curr_ts = self.now();
+
+            self.check_loop_gap();
+
+            self.schedule()?;
+            self.handle_control_requests();
+
+            if let Some(notifier) = self.notifier {
+                notifier.heartbeat(self.now_ns());
+            }
+
+            // Dump the internal state to stderr on SIGUSR1 (with the edf policy, followed by the
+            // deadlines of the queued tasks).
+            if dump::requested() {
+                eprint!("{}", self.state_dump().text());
+                if let Ok(deadlines) = self.deadline_dump() {
+                    eprint!("{}", deadlines.text());
+                }
+            }
+
+            // Switch to the next profile on SIGUSR2.
+            if profiles::switch_requested() {
+                if let Some(next) = self.profiles.as_ref().map(Profiles::next) {
+                    let _ = self.switch_profile(&next);
+                }
+            }
+
+            if curr_ts > prev_ts {
+                // The counters of an idle interval don't change, so the previous values are
+                // still valid when the stats are skipped.
+                if self.stats_due(curr_ts, prev_user_dispatches, prev_kernel_dispatches) {
+                    let (new_user_dispatches, new_kernel_dispatches) =
+                        self.print_stats(prev_user_dispatches, prev_kernel_dispatches);
+
+                    prev_user_dispatches = new_user_dispatches;
+                    prev_kernel_dispatches = new_kernel_dispatches;
+                }
+
+                prev_ts = curr_ts;
+
+                self.update_metrics();
+                self.push_udp_stats();
+                self.log_state();
+                #[cfg(feature = "stats")]
+                self.update_scx_stats();
+                self.update_mode();
+                self.update_overload();
+                self.update_overhead();
+                self.update_latency_target();
+                self.update_backpressure();
+                self.refresh_timetable();
+                self.refresh_online_cpus();
+                self.gc_tasks();
+
+                let now = self.now_ns();
+                if let Some(thermal) = self.thermal.as_mut() {
+                    thermal.update(now);
+                }
+                if let Some(cache) = self.cache.as_mut() {
+                    cache.sample();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn new(
+        mut bpf: B,
+        opts: &'a Opts,
+        metrics: Option<&'a MetricsServer>,
+        control: Option<&'a ControlServer>,
+    ) -> Self {
+        let nr_cpus = *bpf.nr_online_cpus_mut() as usize;
+        let online_cpus = opts
+            .strict_select_cpu
+            .then(|| online_mask(bpf.online_cpus(), nr_cpus));
+
+        let mut excluded_cpus = Vec::new();
+        let kernel_cpu = opts.kernel_cpu.filter(|&cpu| {
+            if cpu >= nr_cpus {
+                println!(
+                    "WARNING: --kernel-cpu {} is not an online CPU, ignoring it",
+                    cpu
+                );
+            }
+            cpu < nr_cpus
+        });
+        let offline = opts.cpus_offline.iter().flat_map(|CpuList(cpus)| cpus);
+        for &cpu in offline.chain(kernel_cpu.as_ref()) {
+            if cpu >= excluded_cpus.len() {
+                excluded_cpus.resize(cpu + 1, false);
+            }
+            excluded_cpus[cpu] = true;
+        }
+        if !excluded_cpus.is_empty()
+            && (0..nr_cpus).all(|cpu| excluded_cpus.get(cpu).copied().unwrap_or(false))
+        {
+            println!(
+                "WARNING: --cpus-offline and --kernel-cpu exclude all the CPUs, ignoring them"
+            );
+            excluded_cpus.clear();
+        }
+        let isolated_cpus: Vec<usize> = if opts.ignore_isolcpus {
+            Vec::new()
+        } else {
+            let cpus = bpf.isolated_cpus().unwrap_or_default();
+            cpus.into_iter().filter(|&cpu| cpu < nr_cpus).collect()
+        };
+        if !isolated_cpus.is_empty() {
+            let list: Vec<String> = isolated_cpus.iter().map(|cpu| cpu.to_string()).collect();
+            if (0..nr_cpus).all(|cpu| {
+                excluded_cpus.get(cpu).copied().unwrap_or(false) || isolated_cpus.contains(&cpu)
+            }) {
+                println!(
+                    "WARNING: the CPUs isolated on the kernel command line ({}) leave no CPU to \
+                     the scheduler, ignoring them",
+                    list.join(",")
+                );
+            } else {
+                println!(
+                    "excluding the CPUs isolated on the kernel command line: {} \
+                     (see --ignore-isolcpus)",
+                    list.join(",")
+                );
+                excluded_cpus.resize(excluded_cpus.len().max(nr_cpus), false);
+                for cpu in isolated_cpus {
+                    excluded_cpus[cpu] = true;
+                }
+            }
+        }
+
+        let loop_gap = opts
+            .loop_gap_ms
+            .map(|ms| LoopGap::new(ms.saturating_mul(1_000_000), bpf.now_ns()));
+        let hysteresis = || {
+            Hysteresis::new(
+                opts.hysteresis_ms.saturating_mul(1_000_000),
+                opts.hysteresis_delta,
+            )
+        };
+        let auto = (opts.mode == Mode::Auto).then(|| AutoMode::new(bpf.now_ns(), hysteresis()));
+        let overload = OverloadDetector::new(
+            opts.overload_thresh_pct,
+            opts.overload_shed,
+            hysteresis(),
+            bpf.now_ns(),
+        );
+        let wakeup_gap = opts.wakeup_gap_high_us.map(|high_us| {
+            let low_us = opts.wakeup_gap_low_us.unwrap_or(high_us / 2);
+            WakeupGap::new(
+                high_us.saturating_mul(1000),
+                low_us.saturating_mul(1000),
+                hysteresis(),
+                bpf.now_ns(),
+            )
+        });
+        let boost = opts
+            .boost_budget_us
+            .map(|budget_us| BoostBudget::new(budget_us.saturating_mul(1000), bpf.now_ns()));
+        let fork_bomb = opts
+            .fork_bomb_thresh
+            .map(|thresh| ForkBombDetector::new(thresh, bpf.now_ns()));
+        let top_view = opts.top_view.then(|| {
+            TopView::new(
+                io::stdout(),
+                io::stdout().is_terminal(),
+                nr_cpus,
+                bpf.now_ns(),
+            )
+        });
+        let reservation = (opts.reserve_interactive_pct.is_some()
+            || opts.reserve_batch_pct.is_some())
+        .then(|| {
+            let ppm = |pct: Option<f64>| pct.map_or(0, |pct| (pct * 10_000.0) as u64);
+            Reservation::new(
+                ppm(opts.reserve_interactive_pct),
+                ppm(opts.reserve_batch_pct),
+                bpf.now_ns(),
+            )
+        });
+        let latency_target = opts.latency_target_us.map(|target_us| {
+            let hysteresis = Hysteresis::new(opts.hysteresis_ms.saturating_mul(1_000_000), 0);
+            LatencyTarget::new(target_us.saturating_mul(1000), hysteresis, bpf.now_ns())
+        });
+        let backpressure = opts.failed_dispatch_thresh.map(|thresh| {
+            let hysteresis = Hysteresis::new(opts.hysteresis_ms.saturating_mul(1_000_000), 0);
+            let nr_failed = *bpf.nr_failed_dispatches_mut();
+            Backpressure::new(thresh, hysteresis, bpf.now_ns(), nr_failed)
+        });
+        let comm_caps =
+            (!opts.comm_cap.is_empty()).then(|| CommCaps::new(opts.comm_cap.clone(), bpf.now_ns()));
+        let overhead = (opts.self_stats || opts.self_cpu_max_pct.is_some()).then(|| {
+            let max_ppm = opts.self_cpu_max_pct.map(|pct| (pct * 10_000.0) as u64);
+            Overhead::new(max_ppm, hysteresis(), bpf.now_ns(), bpf.self_cpu_ns())
+        });
+
+        Self {
+            bpf,
+            opts,
+            policy: opts.policy,
+            activation: opts.policy_activation_threshold.map(PolicyActivation::new),
+            policy_active: opts.policy_activation_threshold.is_none(),
+            compute_boost: opts.compute_boost,
+            slice_ns: opts.slice_us.saturating_mul(1000),
+            auto,
+            profiles: (!opts.profile.is_empty()).then(|| Profiles::new(&opts.profile)),
+            timetable: (!opts.profile_at.is_empty()).then(|| Timetable::new(&opts.profile_at)),
+            metrics,
+            control,
+            notifier: None,
+            tasks: HashMap::new(),
+            lru: opts.max_tracked_pids.map(|_| PidLru::new()),
+            nr_evicted_pids: 0,
+            interactive: VecDeque::new(),
+            batch: VecDeque::new(),
+            cpu_gaps: CpuGapStats::new(nr_cpus),
+            min_vtime: 0,
+            boost,
+            fork_bomb,
+            reservation,
+            comm_caps,
+            top_view,
+            latency: LatencyHistogram::new(),
+            run_latency: metrics.is_some().then(RunLatency::new),
+            queue_trend: QueueTrend::new(),
+            dispatch_ratio: DispatchRatio::new(opts.kernel_dispatch_warn_pct),
+            batch_sizes: BatchSizes::new(),
+            invariants: opts
+                .debug_invariants
+                .filter(|_| invariants::COMPILED)
+                .map(|_| InvariantChecker::new()),
+            thermal: opts.thermal_sensor.as_deref().map(|path| {
+                Thermal::new(
+                    path,
+                    opts.thermal_max_temp,
+                    opts.thermal_resume_temp,
+                    Hysteresis::new(opts.hysteresis_ms.saturating_mul(1_000_000), 0),
+                )
+            }),
+            nr_dispatch_retries: 0,
+            nr_dispatch_requeues: 0,
+            nr_starve_timeouts: 0,
+            nr_duplicate_pids: 0,
+            nr_cpuset_remaps: 0,
+            nr_dequeue_errors: 0,
+            nr_dequeue_errors_streak: 0,
+            online_cpus,
+            nr_invalid_cpus: 0,
+            nr_dequeue_breaks: 0,
+            nr_budget_breaks: 0,
+            nr_idle_passes: 0,
+            idle_pass_ts: None,
+            loop_gap,
+            nr_kthread_dispatches: 0,
+            prev_bounce_dispatches: 0,
+            prev_cancel_dispatches: 0,
+            prev_failed_dispatches: 0,
+            counters_base: CountersBase::default(),
+            last_stats_ts: 0,
+            round_pids: HashSet::new(),
+            nr_round_retries: 0,
+            excluded_cpus,
+            next_cpu: 0,
+            pins: HashMap::new(),
+            boost_groups: BoostGroups::new(),
+            inversions: opts
+                .inversion_weight_gap
+                .map(|gap| InversionDetector::new(gap, nr_cpus)),
+            cpu_tasks: vec![None; nr_cpus],
+            process_cpus: HashMap::new(),
+            kthreads: HashMap::new(),
+            llc: None,
+            accounting: opts
+                .debug_accounting
+                .then(|| Accounting::new(opts.accounting_tolerance_pct)),
+            idle: opts.spread_idle.then(|| IdleHistory::new(nr_cpus)),
+            cache: None,
+            numa: None,
+            numa_affinity: None,
+            migration: opts
+                .migration_lock_us
+                .map(|lock_us| MigrationLock::new(lock_us.saturating_mul(1000))),
+            wake_affinity: opts.wake_affine.then(WakeAffinity::new),
+            overload,
+            wakeup_gap,
+            breaker: opts.notify_stall_ms.map(|ms| {
+                NotifyBreaker::new(ms.saturating_mul(1_000_000), opts.notify_stall_count)
+            }),
+            overhead,
+            latency_target,
+            backpressure,
+            weights: opts.weight_stats.then(WeightStats::new),
+            weight_classes: opts.weight_classes.map(WeightClasses::new),
+            shadow: opts.shadow_policy.map(ShadowPolicy::new),
+            export: None,
+            state_log: None,
+            udp_stats: None,
+            #[cfg(feature = "stats")]
+            scx_stats: None,
+        }
+    }
+
+    /// Confine the processes to the given LLC domains (see --llc-group), ignoring the CPUs that
+    /// are offline or excluded by --cpus-offline.
+    fn set_llc_domains(&mut self, domains: Vec<Vec<usize>>) {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let domains = domains
+            .into_iter()
+            .map(|cpus| {
+                cpus.into_iter()
+                    .filter(|&cpu| cpu < nr_cpus && !self.is_cpu_excluded(cpu as i32))
+                    .collect()
+            })
+            .collect();
+
+        self.llc = Some(LlcDomains::new(domains));
+    }
+
+    /// Update the statistics of a task and return its class.
+    ///
+    /// Tasks that perform many voluntary context switches per second (e.g., tasks that are often
+    /// waiting for user input or I/O) are classified as interactive, all the others are
+    /// classified as batch.
+    fn classify(&mut self, task: &Task, now: u64) -> TaskClass {
+        let min_vtime = self.min_vtime;
+        let info = self.tasks.entry(task.pid).or_insert(TaskInfo {
+            nvcsw: task.nvcsw,
+            nvcsw_ts: now,
+            avg_nvcsw: 0,
+            runtime: task.sum_exec_runtime,
+            avg_util: 0,
+            avg_nvcsw_rt: 0,
+            avg_io: 0,
+            last_runtime: task.sum_exec_runtime,
+            vtime: min_vtime,
+            last_seen: now,
+            slice_req: None,
+            latency_req: None,
+            hints_ts: None,
+            weight: task.weight,
+            wrr_credits: 0,
+            tgid: None,
+            cpuset: None,
+            cpuset_ts: None,
+            flood: false,
+            comm_cap: None,
+            comm_ts: None,
+            dispatched: false,
+        });
+        info.last_seen = now;
+
+        // Refresh the average rate of voluntary context switches and the CPU utilization once
+        // per second.
+        let delta_t = now.saturating_sub(info.nvcsw_ts);
+        if delta_t >= NSEC_PER_SEC {
+            let delta_nvcsw = task.nvcsw.saturating_sub(info.nvcsw);
+            let rate = delta_nvcsw.saturating_mul(NSEC_PER_SEC) / delta_t;
+
+            info.avg_nvcsw = ewma(info.avg_nvcsw, rate);
+            info.nvcsw = task.nvcsw;
+            info.nvcsw_ts = now;
+
+            let delta_runtime = task.sum_exec_runtime.saturating_sub(info.runtime);
+            let util = (delta_runtime.saturating_mul(100) / delta_t).min(100);
+
+            info.avg_util = ewma(info.avg_util, util);
+            let rate_rt = delta_nvcsw
+                .saturating_mul(NSEC_PER_SEC)
+                .checked_div(delta_runtime);
+            if let Some(rate_rt) = rate_rt {
+                info.avg_nvcsw_rt = ewma(info.avg_nvcsw_rt, rate_rt);
+            }
+
+            // The time spent sleeping only counts as I/O wait if the task releases the CPU
+            // voluntarily often for the CPU time it uses (see --io-nvcsw-thresh).
+            let io = match info.avg_nvcsw_rt >= self.opts.io_nvcsw_thresh {
+                true => 100 - util,
+                false => 0,
+            };
+            info.avg_io = ewma(info.avg_io, io);
+            info.runtime = task.sum_exec_runtime;
+        }
+
+        if info.avg_nvcsw >= self.opts.nvcsw_thresh {
+            TaskClass::Interactive
+        } else {
+            TaskClass::Batch
+        }
+    }
+
+    /// Update the virtual runtime of a task and return it.
+    ///
+    /// The virtual runtime is advanced by the CPU time used since the last time the task has been
+    /// received, scaled inversely to its weight (at most MAX_CHARGE_NS per update, and never
+    /// beyond u64::MAX).
+    ///
+    /// Newly seen tasks are placed at the current minimum virtual runtime (min_vtime), so that
+    /// they compete fairly with the other tasks, rather than starting from 0 (which would allow
+    /// them to monopolize the CPUs). min_vtime only moves forward, following the virtual runtime
+    /// of the dispatched tasks, and tasks that have been sleeping for a long time can't
+    /// accumulate more than one time slice of credit with respect to it.
+    ///
+    /// With --boost-budget-us, the credit is also limited by the global budget (see
+    /// BoostBudget), with --io-boost the weight of the I/O-bound tasks is boosted (see
+    /// io_boost_pct()), as well as the weight of the tasks boosted via the boost command.
+    fn update_vtime(&mut self, task: &Task, now: u64) -> u64 {
+        let min_vtime = self.min_vtime;
+        let weight = task.weight.saturating_mul(self.io_boost_pct(task.pid)) / 100;
+        let weight = weight.saturating_mul(self.boost_groups.level(task.pid));
+        let Some(info) = self.tasks.get_mut(&task.pid) else {
+            return min_vtime;
+        };
+
+        let delta_runtime = task.sum_exec_runtime.saturating_sub(info.last_runtime);
+        info.last_runtime = task.sum_exec_runtime;
+
+        let charge = delta_runtime.min(MAX_CHARGE_NS) * 100 / weight.max(1);
+        info.vtime = info.vtime.saturating_add(charge);
+        info.vtime = info.vtime.max(min_vtime.saturating_sub(self.slice_ns));
+
+        let credit = min_vtime.saturating_sub(info.vtime);
+        if let Some(boost) = self.boost.as_mut().filter(|_| credit > 0) {
+            info.vtime = min_vtime - boost.grant(now, credit);
+        }
+
+        info.vtime
+    }
+
+    /// Return the factor (in percent) applied to the weight of a task by --io-boost: from 100
+    /// (no boost) for a task that never sleeps or that isn't I/O-bound, up to --io-boost times
+    /// for a task that sleeps all the time.
+    fn io_boost_pct(&self, pid: i32) -> u64 {
+        let (Some(factor), Some(info)) = (self.opts.io_boost, self.tasks.get(&pid)) else {
+            return 100;
+        };
+
+        (factor - 1).saturating_mul(info.avg_io).saturating_add(100)
+    }
+
+    /// Re-validate the state derived from the weight of a task against the weight reported by
+    /// the kernel, dropping it if the weight changed (e.g., the task has been reniced).
+    fn refresh_weight(&mut self, task: &Task) {
+        let Some(info) = self.tasks.get_mut(&task.pid) else {
+            return;
+        };
+        if info.weight != task.weight {
+            info.weight = task.weight;
+            info.wrr_credits = 0;
+        }
+    }
+
+    /// Return true if the tasks of `class` need to be held back to preserve the CPU capacity
+    /// reserved to the other class (see --reserve-interactive-pct and --reserve-batch-pct).
+    fn is_held(&mut self, class: TaskClass, now: u64) -> bool {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut();
+
+        self.reservation
+            .as_ref()
+            .is_some_and(|r| r.throttled(class, now, nr_cpus))
+    }
+
+    /// Return the amount of tasks waiting in the user-space queues.
+    fn nr_pending(&self) -> u64 {
+        (self.interactive.len() + self.batch.len()) as u64
+    }
+
+    /// Pick the next task to be dispatched.
+    ///
+    /// Interactive tasks are always serviced first, unless the oldest task of the batch queue has
+    /// been waiting for more than STARVATION_NS: that task is picked first, wherever it is in the
+    /// queue (e.g., at the tail with --order lifo, where the newest tasks are at the head).
+    ///
+    /// With --starve-timeout-ms, the task that has been waiting for the longest time is always
+    /// picked first if it exceeded the timeout.
+    ///
+    /// With --reserve-interactive-pct or --reserve-batch-pct, the tasks of a class that exceeded
+    /// its cap are not picked (see Reservation), except by the starvation timeout.
+    fn pick_task(&mut self, now: u64) -> Option<(PendingTask, TaskClass)> {
+        if let Some(timeout_ms) = self.opts.starve_timeout_ms {
+            if let Some(starved) = self.pick_starved(now, timeout_ms.saturating_mul(1_000_000)) {
+                self.nr_starve_timeouts += 1;
+                return Some(starved);
+            }
+        }
+        let hold_interactive =
+            !self.interactive.is_empty() && self.is_held(TaskClass::Interactive, now);
+        let hold_batch = !self.batch.is_empty() && self.is_held(TaskClass::Batch, now);
+        if let Some(reservation) = self
+            .reservation
+            .as_mut()
+            .filter(|_| hold_interactive || hold_batch)
+        {
+            reservation.record_throttled();
+        }
+        let oldest = self
+            .batch
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, t)| t.enq_ts)
+            .map(|(idx, t)| (idx, t.enq_ts))
+            .filter(|_| !hold_batch);
+        if let Some((idx, enq_ts)) = oldest {
+            if now.saturating_sub(enq_ts) >= STARVATION_NS {
+                return self.batch.remove(idx).map(|t| (t, TaskClass::Batch));
+            }
+            if self.interactive.is_empty() || hold_interactive {
+                return self.batch.pop_front().map(|t| (t, TaskClass::Batch));
+            }
+        }
+        if hold_interactive {
+            return None;
+        }
+        self.interactive
+            .pop_front()
+            .map(|t| (t, TaskClass::Interactive))
+    }
+
+    /// Remove and return the task that has been waiting for the longest time, if it has been
+    /// waiting for at least `timeout_ns`.
+    fn pick_starved(&mut self, now: u64, timeout_ns: u64) -> Option<(PendingTask, TaskClass)> {
+        let (class, idx, _) = [
+            (TaskClass::Interactive, &self.interactive),
+            (TaskClass::Batch, &self.batch),
+        ]
+        .into_iter()
+        .flat_map(|(class, queue)| {
+            queue
+                .iter()
+                .enumerate()
+                .map(move |(idx, t)| (class, idx, t.enq_ts))
+        })
+        .filter(|&(_, _, enq_ts)| now.saturating_sub(enq_ts) >= timeout_ns)
+        .min_by_key(|&(_, _, enq_ts)| enq_ts)?;
+
+        let queue = match class {
+            TaskClass::Interactive => &mut self.interactive,
+            TaskClass::Batch => &mut self.batch,
+        };
+        queue.remove(idx).map(|t| (t, class))
+    }
+
+    /// Return the target CPU of a task.
+    ///
+    /// A call to select_cpu() will return the most suitable idle CPU for the task, prioritizing
+    /// its previously used CPU (task.cpu).
+    ///
+    /// If we can't find any idle CPU, run on the first CPU available (RL_CPU_ANY).
+    ///
+    /// With --cpu-any-shortcut, tasks that carry the RL_CPU_ANY bit (1 << 20) in their enqueue
+    /// flags are dispatched directly to RL_CPU_ANY, without calling select_cpu(). No other flag
+    /// triggers the shortcut; note that the kernel never sets this bit on its own (the enqueue
+    /// flags are the SCX_ENQ_* flags), so it only applies when the BPF component is extended to
+    /// request any-CPU placement for a task.
+    ///
+    /// Tasks without a previously used CPU (task.cpu < 0, e.g., freshly forked tasks) are spread
+    /// across the CPUs in a round-robin way: the next CPU in the sequence is used as the starting
+    /// point of the idle CPU search, instead of an invalid previous CPU.
+    ///
+    /// With --llc-group, the idle CPU search starts from a CPU in the LLC domain of the task's
+    /// process (see LlcDomains::pick_cpu()) and, if select_cpu() returns a CPU outside of the
+    /// domain (or no CPU at all), the task is dispatched to that CPU anyway, to keep it in the
+    /// domain. Tasks that hit the --cpu-any-shortcut are not confined.
+    ///
+    /// Otherwise, with --spread-idle, the idle CPU search starts from the coldest CPU
+    /// (`coldest`), so that select_cpu() picks it if it is really idle.
+    ///
+    /// With --cache-aware, if the idle CPU search of a compute-bound task would start from a CPU
+    /// that is thrashing its cache, it starts from the CPU with the lowest miss rate instead
+    /// (see cache_cpu()).
+    ///
+    /// With --cpus-offline, RL_CPU_ANY is never used (since the first CPU available may be an
+    /// excluded one) and excluded CPUs returned by select_cpu() are remapped, see fallback_cpu().
+    ///
+    /// With --cpuset-aware, the selected CPU is finally constrained to the CPUs that the task is
+    /// allowed to use, see cpuset_cpu() (the cpuset takes precedence over the LLC domain).
+    ///
+    /// Tasks pinned via the control socket (see the pin command) are always dispatched to their
+    /// pinned CPU, bypassing all of the above.
+    fn pick_cpu(&mut self, task: &Task, coldest: Option<i32>) -> i32 {
+        if let Some(&cpu) = self.pins.get(&task.pid) {
+            return cpu;
+        }
+        let cpu = if self.opts.cpu_any_shortcut && task.flags & RL_CPU_ANY as u64 != 0 {
+            RL_CPU_ANY
+        } else {
+            let prev_cpu = match self.initial_cpu(task) {
+                Some(cpu) => cpu,
+                None if task.cpu < 0 => self.next_allowed_cpu().unwrap_or(0),
+                None => task.cpu,
+            };
+            let prev_cpu = self.numa_cpu(task, prev_cpu);
+            let prev_cpu = self.wake_cpu(task, prev_cpu);
+            let llc_cpu = self.llc_cpu(task);
+            let start_cpu = match llc_cpu {
+                Some(llc_cpu) => llc_cpu,
+                None => self.cache_cpu(task, coldest.unwrap_or(prev_cpu)),
+            };
+            let cpu = self.bpf.select_cpu(task.pid, start_cpu, task.flags);
+            let cpu = self.checked_cpu(cpu);
+            match (llc_cpu, &self.llc) {
+                (Some(llc_cpu), Some(llc)) if llc.cpu_domain(cpu) != llc.cpu_domain(llc_cpu) => {
+                    llc_cpu
+                }
+                _ if cpu >= 0 => cpu,
+                _ => RL_CPU_ANY,
+            }
+        };
+
+        let cpu = if cpu == RL_CPU_ANY || self.is_cpu_excluded(cpu) {
+            self.fallback_cpu(task)
+        } else {
+            cpu
+        };
+        let cpu = self.locked_cpu(task, cpu);
+
+        self.cpuset_cpu(task, cpu)
+    }
+
+    /// Return the CPU where a task needs to run, instead of `cpu`, to keep a task that recently
+    /// migrated on its current CPU (see --migration-lock-us).
+    fn locked_cpu(&mut self, task: &Task, cpu: i32) -> i32 {
+        if self.migration.is_none() {
+            return cpu;
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let prev_cpu = match task.cpu {
+            prev_cpu if prev_cpu >= 0 && (prev_cpu as usize) < nr_cpus => prev_cpu,
+            _ => -1,
+        };
+        let prev_cpu = if self.is_cpu_excluded(prev_cpu) {
+            -1
+        } else {
+            prev_cpu
+        };
+        let now = self.now_ns();
+
+        self.migration.as_mut().map_or(cpu, |migration| {
+            migration.pick_cpu(task.pid, prev_cpu, cpu, now)
+        })
+    }
+
+    /// Return the CPU `cpu` returned by select_cpu() if it can be used, or -ENODEV if it is out
+    /// of range or offline, so that the task goes to the fallback (see --strict-select-cpu).
+    fn checked_cpu(&mut self, cpu: i32) -> i32 {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let Some(online) = self.online_cpus.as_ref() else {
+            return cpu;
+        };
+        if cpu < 0 || ((cpu as usize) < nr_cpus && online.get(cpu as usize) == Some(&true)) {
+            return cpu;
+        }
+        self.nr_invalid_cpus += 1;
+
+        -libc::ENODEV
+    }
+
+    /// Refresh the online CPUs used to validate the CPUs returned by select_cpu() (see
+    /// --strict-select-cpu).
+    fn refresh_online_cpus(&mut self) {
+        if self.online_cpus.is_none() {
+            return;
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        self.online_cpus = Some(online_mask(self.bpf.online_cpus(), nr_cpus));
+    }
+
+    /// Return a CPU that the task is allowed to use (see --cpuset-aware).
+    ///
+    /// `cpu` is kept if it is in the task's cpuset (or if the cpuset is unknown), otherwise use
+    /// the previously used CPU of the task, if it is in the cpuset, or distribute the tasks
+    /// across the CPUs of the cpuset in a round-robin way, skipping the CPUs that are offline or
+    /// excluded by --cpus-offline.
+    ///
+    /// RL_CPU_ANY is always kept: the task is dispatched to the shared DSQ, that is only consumed
+    /// by the CPUs where the task can run.
+    fn cpuset_cpu(&mut self, task: &Task, cpu: i32) -> i32 {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let Some(cpuset) = self
+            .tasks
+            .get(&task.pid)
+            .and_then(|info| info.cpuset.as_ref())
+        else {
+            return cpu;
+        };
+        if cpu == RL_CPU_ANY || cpuset.contains(&(cpu as usize)) {
+            return cpu;
+        }
+        let usable: Vec<usize> = cpuset
+            .iter()
+            .copied()
+            .filter(|&cpu| cpu < nr_cpus && !self.is_cpu_excluded(cpu as i32))
+            .collect();
+        if usable.is_empty() {
+            return RL_CPU_ANY;
+        }
+        self.nr_cpuset_remaps += 1;
+
+        if task.cpu >= 0 && usable.contains(&(task.cpu as usize)) {
+            return task.cpu;
+        }
+        let cpu = usable[self.next_cpu % usable.len()];
+        self.next_cpu += 1;
+
+        cpu as i32
+    }
+
+    /// Return the CPU where the idle CPU search of a brand-new task (never dispatched before, or
+    /// without a previously used CPU) needs to start, according to --initial-cpu, None to use
+    /// the generic CPU selection.
+    fn initial_cpu(&mut self, task: &Task) -> Option<i32> {
+        let is_new = task.cpu < 0 || self.tasks.get(&task.pid).is_some_and(|t| !t.dispatched);
+        if !is_new {
+            return None;
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let cpu = match self.opts.initial_cpu {
+            InitialCpu::Default => None,
+            InitialCpu::Spread => (0..nr_cpus)
+                .filter(|&cpu| !self.is_cpu_excluded(cpu as i32))
+                .min_by_key(|&cpu| self.cpu_tasks.get(cpu).copied().flatten().map(|(_, ts)| ts))
+                .map(|cpu| cpu as i32),
+            InitialCpu::LocalToParent => self
+                .process_of(task.pid)
+                .and_then(|tgid| self.process_cpus.get(&tgid).copied()),
+            InitialCpu::FollowWaker => self.bpf.parent_cpu(task.pid),
+        }?;
+
+        (cpu >= 0 && (cpu as usize) < nr_cpus && !self.is_cpu_excluded(cpu)).then_some(cpu)
+    }
+
+    /// Return the process (tgid) of a task, None if unknown.
+    fn process_of(&mut self, pid: i32) -> Option<i32> {
+        let info = self.tasks.get_mut(&pid)?;
+        if info.tgid.is_none() {
+            info.tgid = self.bpf.tgid(pid);
+        }
+
+        info.tgid
+    }
+
+    /// Return the CPU in the LLC domain of the task's process where the task should run (see
+    /// --llc-group).
+    fn llc_cpu(&mut self, task: &Task) -> Option<i32> {
+        self.llc.as_ref()?;
+
+        let info = self.tasks.get_mut(&task.pid)?;
+        if info.tgid.is_none() {
+            info.tgid = self.bpf.tgid(task.pid);
+        }
+        let tgid = info.tgid.unwrap_or(task.pid);
+
+        self.llc.as_mut()?.pick_cpu(tgid, task.cpu)
+    }
+
+    /// Return the CPU where the idle CPU search of a task needs to start, instead of `cpu`, to
+    /// keep the task on its NUMA home node (see --numa-affinity).
+    fn numa_cpu(&mut self, task: &Task, cpu: i32) -> i32 {
+        if self.numa_affinity.is_none() {
+            return cpu;
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let excluded_cpus = &self.excluded_cpus;
+        let usable =
+            |cpu: usize| cpu < nr_cpus && !excluded_cpus.get(cpu).copied().unwrap_or(false);
+
+        self.numa_affinity
+            .as_mut()
+            .map_or(cpu, |affinity| affinity.pick_cpu(task.pid, cpu, usable))
+    }
+
+    /// Return the CPU where the idle CPU search of a task needs to start, instead of `cpu`, to
+    /// keep a woken task close to its waker (see --wake-affine).
+    fn wake_cpu(&mut self, task: &Task, cpu: i32) -> i32 {
+        if self.wake_affinity.is_none() {
+            return cpu;
+        }
+        let waker = self.bpf.waker(task.pid);
+        let tgid = self.process_of(task.pid);
+        let now = self.now_ns();
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let excluded_cpus = &self.excluded_cpus;
+        let usable =
+            |cpu: usize| cpu < nr_cpus && !excluded_cpus.get(cpu).copied().unwrap_or(false);
+
+        self.wake_affinity.as_mut().map_or(cpu, |affinity| {
+            affinity.pick_cpu(task.pid, waker, tgid, cpu, now, usable)
+        })
+    }
+
+    /// Return the CPU where the idle CPU search of a task needs to start, instead of `cpu`, to
+    /// keep the cache-sensitive (compute-bound) tasks away from the CPUs that are thrashing
+    /// their cache (see --cache-aware).
+    fn cache_cpu(&mut self, task: &Task, cpu: i32) -> i32 {
+        if self.cache.is_none() || cpu < 0 || !self.is_compute_bound(task.pid) {
+            return cpu;
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let excluded_cpus = &self.excluded_cpus;
+        let usable =
+            |cpu: usize| cpu < nr_cpus && !excluded_cpus.get(cpu).copied().unwrap_or(false);
+
+        self.cache
+            .as_mut()
+            .and_then(|cache| cache.pick_cpu(cpu as usize, usable))
+            .map_or(cpu, |cpu| cpu as i32)
+    }
+
+    /// Return the allowed CPU that has been idle for the longest time (see --spread-idle).
+    fn coldest_cpu(&mut self, now: u64) -> Option<i32> {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        let idle = self.idle.as_ref()?;
+
+        idle.coldest(now, |cpu| {
+            cpu < nr_cpus && !self.is_cpu_excluded(cpu as i32)
+        })
+        .map(|cpu| cpu as i32)
+    }
+
+    /// Return true if a CPU must not receive dispatches (see --cpus-offline, --kernel-cpu and
+    /// --ignore-isolcpus).
+    fn is_cpu_excluded(&self, cpu: i32) -> bool {
+        self.excluded_cpus
+            .get(cpu as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Return the CPU used when no suitable idle CPU has been found for a task.
+    ///
+    /// With --fallback numa, the tasks are distributed across the NUMA nodes (skipping the
+    /// excluded CPUs), see NumaFallback.
+    ///
+    /// Otherwise, without excluded CPUs this is simply RL_CPU_ANY. With excluded CPUs use the
+    /// previously used CPU of the task, if it is allowed, or distribute the tasks across the
+    /// allowed CPUs in a round-robin way.
+    ///
+    /// NOTE: if the selected CPU is not in the task's affinity mask, the BPF component bounces
+    /// the task to the shared DSQ, where it can also be consumed by an excluded CPU.
+    fn fallback_cpu(&mut self, task: &Task) -> i32 {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+        if let Some(numa) = self.numa.as_mut() {
+            let excluded_cpus = &self.excluded_cpus;
+            let usable =
+                |cpu: usize| cpu < nr_cpus && !excluded_cpus.get(cpu).copied().unwrap_or(false);
+            if let Some(cpu) = numa.pick_cpu(usable) {
+                return cpu as i32;
+            }
+        }
+        if self.excluded_cpus.is_empty() {
+            return RL_CPU_ANY;
+        }
+
+        if task.cpu >= 0 && (task.cpu as usize) < nr_cpus && !self.is_cpu_excluded(task.cpu) {
+            return task.cpu;
+        }
+
+        self.next_allowed_cpu().unwrap_or(RL_CPU_ANY)
+    }
+
+    /// Return the next online CPU that is not excluded by --cpus-offline, in round-robin order.
+    fn next_allowed_cpu(&mut self) -> Option<i32> {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as usize;
+
+        for _ in 0..nr_cpus {
+            let cpu = self.next_cpu % nr_cpus;
+            self.next_cpu = cpu + 1;
+            if !self.is_cpu_excluded(cpu as i32) {
+                return Some(cpu as i32);
+            }
+        }
+
+        None
+    }
+
+    /// Return true if a task is compute-bound: it is using most of its CPU time, rarely
+    /// releasing the CPU voluntarily.
+    fn is_compute_bound(&self, pid: i32) -> bool {
+        self.tasks.get(&pid).is_some_and(|info| {
+            info.avg_util >= self.opts.compute_util_thresh
+                && info.avg_nvcsw_rt <= self.opts.compute_nvcsw_thresh
+        })
+    }
+
+    /// Account a task seen at time `now` in the least recently seen order and, if a new task
+    /// exceeded --max-tracked-pids, evict the state of the least recently seen ones.
+    ///
+    /// The tasks waiting in the user-space queues are never evicted, since their state is still
+    /// needed to dispatch them (so the cap can be exceeded by the waiting tasks). The state of
+    /// the processes (the LLC domains) follows the evicted tasks at the next GC pass (see
+    /// gc_tasks()).
+    fn track_task(&mut self, pid: i32, now: u64) {
+        let (Some(max), Some(lru)) = (self.opts.max_tracked_pids, self.lru.as_mut()) else {
+            return;
+        };
+        lru.touch(pid, now);
+        if self.tasks.len() as u64 <= max {
+            return;
+        }
+        let queued: HashSet<i32> = self
+            .interactive
+            .iter()
+            .chain(self.batch.iter())
+            .map(|t| t.task.pid)
+            .chain([pid])
+            .collect();
+        while self.tasks.len() as u64 > max {
+            let Some(coldest) = lru.coldest().find(|pid| !queued.contains(pid)) else {
+                break;
+            };
+            lru.remove(coldest);
+            self.tasks.remove(&coldest);
+            if let Some(accounting) = self.accounting.as_mut() {
+                accounting.forget(coldest);
+            }
+            if let Some(run_latency) = self.run_latency.as_mut() {
+                run_latency.forget(coldest);
+            }
+            if let Some(invariants) = self.invariants.as_mut() {
+                invariants.forget(coldest);
+            }
+            self.nr_evicted_pids += 1;
+        }
+    }
+
+    /// Account a task received for the first time (see --fork-bomb-thresh), marking it as part
+    /// of the flood if a surge of new tasks is in progress.
+    fn record_new_task(&mut self, pid: i32, now: u64) {
+        let Some(fork_bomb) = self.fork_bomb.as_mut() else {
+            return;
+        };
+        if fork_bomb.record_new(now) {
+            if let Some(info) = self.tasks.get_mut(&pid) {
+                info.flood = true;
+            }
+        }
+    }
+
+    /// Return true if a task needs to be throttled as part of a fork bomb (see
+    /// --fork-bomb-throttle).
+    fn is_flood(&self, pid: i32) -> bool {
+        self.opts.fork_bomb_throttle
+            && self.fork_bomb.as_ref().is_some_and(|f| f.active())
+            && self.tasks.get(&pid).is_some_and(|info| info.flood)
+    }
+
+    /// Return the time slice assigned to a task: the time slice decided by the policy (see
+    /// policy_slice()), capped to the budget left to the task with --cap-remaining-slice, and to
+    /// --interactive-max-slice-us for the interactive tasks.
+    ///
+    /// The result is never 0 (that would assign the default time slice of the BPF component):
+    /// both the policy and the cap never go below the minimum time slice (only scaled down by
+    /// the latency target), except for the fixed time slice of the fork bombs.
+    fn compute_slice(&mut self, task: &Task, class: TaskClass, nr_waiting: u64) -> u64 {
+        let slice_ns = self.policy_slice(task, class, nr_waiting);
+        let slice_ns = match task.slice {
+            budget_ns if self.opts.cap_remaining_slice && budget_ns > 0 => {
+                slice_ns.min(budget_ns.max(self.opts.slice_min_us.saturating_mul(1000)))
+            }
+            _ => slice_ns,
+        };
+
+        match (class, self.opts.interactive_max_slice_us) {
+            (TaskClass::Interactive, Some(max_slice_us)) => {
+                slice_ns.min(max_slice_us.saturating_mul(1000))
+            }
+            _ => slice_ns,
+        }
+    }
+
+    /// Shorten the time slice `slice_ns` of the task `pid` in proportion to the time left until
+    /// its deadline at `now` (see --deadline-slice-pct): a task close to (or past) its deadline
+    /// gets a short time slice, down to --slice-min-us, while a task with a distant deadline
+    /// keeps the time slice decided by the policy.
+    fn deadline_slice(&self, pid: i32, deadline: u64, now: u64, slice_ns: u64) -> u64 {
+        let Some(pct) = self.opts.deadline_slice_pct else {
+            return slice_ns;
+        };
+        let slice_req = self.tasks.get(&pid).and_then(|info| info.slice_req);
+        if self.ordering() != Policy::Edf || slice_req.is_some() {
+            return slice_ns;
+        }
+        let slack_ns = deadline.saturating_sub(now);
+        let min_slice_ns = self.opts.slice_min_us.saturating_mul(1000);
+
+        (slack_ns / 100)
+            .saturating_mul(pct)
+            .max(min_slice_ns)
+            .min(slice_ns)
+    }
+
+    /// Return the time slice decided by the policy for a task.
+    ///
+    /// The time slice is scaled down according to the amount of waiting tasks (never below
+    /// --slice-min-us, even with a huge queue depth, see also --latency-target-us), interactive
+    /// tasks are also capped to INTERACTIVE_SLICE_NS (or --interactive-max-slice-us).
+    ///
+    /// With --compute-boost, compute-bound batch tasks use --compute-max-slice-us (instead of
+    /// --slice-us) as their base time slice.
+    ///
+    /// With --slice-env, tasks that requested a specific time slice always get exactly that
+    /// (already bounded) time slice.
+    ///
+    /// With --overload-shed, the base time slice is scaled up while the scheduler can't keep up
+    /// with the arrival rate, with --wakeup-gap-high-us while the scheduler lags behind and with
+    /// --self-cpu-max-pct while the scheduler uses too much CPU and with --failed-dispatch-thresh
+    /// while the BPF component fails the dispatches (up to --compute-max-slice-us).
+    ///
+    /// With --latency-target-us, the resulting time slice (and the minimum time slice) is divided
+    /// while the target latency is missed (see LatencyTarget).
+    ///
+    /// With --fork-bomb-throttle, the tasks of a fork bomb get FORK_BOMB_SLICE_NS until the
+    /// surge ends.
+    ///
+    /// With --slice-expr, all the other tasks get the time slice computed by the expression
+    /// (after the time slices requested with --slice-env).
+    fn policy_slice(&mut self, task: &Task, class: TaskClass, nr_waiting: u64) -> u64 {
+        if self.is_flood(task.pid) {
+            return FORK_BOMB_SLICE_NS;
+        }
+        if let Some(slice_ns) = self.tasks.get(&task.pid).and_then(|info| info.slice_req) {
+            return slice_ns;
+        }
+        if let Some(expr) = &self.opts.slice_expr {
+            let vars = SliceVars {
+                weight: task.weight,
+                nr_waiting,
+                nr_cpus: *self.bpf.nr_online_cpus_mut(),
+                runtime_us: task.sum_exec_runtime / 1000,
+                slice_us: self.opts.slice_us,
+            };
+            let max_slice_us = self.opts.slice_us.max(self.opts.compute_max_slice_us);
+            let min_slice_ns = self.opts.slice_min_us.saturating_mul(1000);
+            return expr.slice_ns(&vars, min_slice_ns, max_slice_us.saturating_mul(1000));
+        }
+        let compute_max_slice_ns = self.opts.compute_max_slice_us.saturating_mul(1000);
+        let gap_scale = self.wakeup_gap.as_ref().map_or(1, |gap| gap.slice_scale());
+        let scale = self
+            .overload
+            .slice_scale()
+            .saturating_mul(gap_scale)
+            .saturating_mul(self.overhead_scale())
+            .saturating_mul(self.backpressure_scale());
+        let slice_ns = match scale {
+            1 => self.slice_ns,
+            scale => self
+                .slice_ns
+                .saturating_mul(scale)
+                .min(compute_max_slice_ns)
+                .max(self.slice_ns),
+        };
+        // The batch tasks run several quanta back-to-back (see --batch-quantum-mult), still
+        // subject to the latency target.
+        let slice_ns = match class {
+            TaskClass::Batch => slice_ns
+                .saturating_mul(self.opts.batch_quantum_mult)
+                .min(compute_max_slice_ns)
+                .max(slice_ns),
+            TaskClass::Interactive => slice_ns,
+        };
+        let slice_ns = self
+            .latency_target
+            .as_ref()
+            .map_or(slice_ns, |target| target.slice_ns(slice_ns));
+
+        let nr_shares = nr_waiting.saturating_add(1);
+        let slice_ns = match class {
+            TaskClass::Interactive => {
+                let max_slice_ns = self
+                    .opts
+                    .interactive_max_slice_us
+                    .map_or(INTERACTIVE_SLICE_NS, |max_slice_us| {
+                        max_slice_us.saturating_mul(1000)
+                    });
+                (slice_ns / nr_shares).min(max_slice_ns)
+            }
+            TaskClass::Batch => {
+                let max_slice_ns = if self.compute_boost && self.is_compute_bound(task.pid) {
+                    compute_max_slice_ns.max(slice_ns)
+                } else {
+                    slice_ns
+                };
+                max_slice_ns / nr_shares
+            }
+        };
+
+        // Never let the queue depth round the time slice down to nothing (0 would assign the
+        // default time slice of the BPF component, a few nanoseconds would be pure overhead):
+        // the floor is the minimum time slice, only scaled down by the latency target.
+        let min_slice_ns = self.opts.slice_min_us.saturating_mul(1000);
+        let min_slice_ns = self
+            .latency_target
+            .as_ref()
+            .map_or(min_slice_ns, |target| target.slice_ns(min_slice_ns));
+        slice_ns.max(min_slice_ns)
+    }
+
+    /// Refresh the scheduling hints of a task: the time slice (see --slice-env) and the latency
+    /// (see the edf policy) that it requested.
+    ///
+    /// The requests are cached in the task's statistics and read again only every
+    /// HINT_REFRESH_NS (e.g., to catch pids that have been reused by a new task).
+    fn refresh_hints(&mut self, pid: i32, now: u64) {
+        let Some(info) = self.tasks.get(&pid) else {
+            return;
+        };
+        if info
+            .hints_ts
+            .is_some_and(|ts| now.saturating_sub(ts) < HINT_REFRESH_NS)
+        {
+            return;
+        }
+        let slice_req = if self.opts.slice_env {
+            self.bpf
+                .env_hint(pid, slice_override::SLICE_ENV)
+                .map(|slice_us| {
+                    let min_slice_ns = self.opts.slice_min_us.saturating_mul(1000);
+                    slice_override::slice_ns(
+                        slice_us,
+                        min_slice_ns,
+                        self.opts.slice_us.saturating_mul(1000),
+                    )
+                })
+        } else {
+            None
+        };
+        let latency_req = if self.policy == Policy::Edf {
+            self.bpf
+                .env_hint(pid, slice_override::LATENCY_ENV)
+                .map(slice_override::latency_ns)
+        } else {
+            None
+        };
+        if let Some(info) = self.tasks.get_mut(&pid) {
+            info.slice_req = slice_req;
+            info.latency_req = latency_req;
+            info.hints_ts = Some(now);
+        }
+    }
+
+    /// Return the soft deadline of a task received at `now`: the latency requested by the task
+    /// (already bounded), or the default time slice, after `now` (see the edf policy).
+    fn deadline(&self, pid: i32, now: u64) -> u64 {
+        let latency_ns = self
+            .tasks
+            .get(&pid)
+            .and_then(|info| info.latency_req)
+            .unwrap_or(self.opts.slice_us.saturating_mul(1000));
+
+        now.saturating_add(latency_ns)
+    }
+
+    /// Refresh the CPUs that a task is allowed to use (see --cpuset-aware).
+    ///
+    /// The cpuset is cached in the task's statistics and read again only every
+    /// CPUSET_REFRESH_NS, to catch the tasks that are moved to a different cpuset or whose
+    /// affinity is changed.
+    fn refresh_cpuset(&mut self, pid: i32, now: u64) {
+        let Some(info) = self.tasks.get_mut(&pid) else {
+            return;
+        };
+        if info
+            .cpuset_ts
+            .is_some_and(|ts| now.saturating_sub(ts) < CPUSET_REFRESH_NS)
+        {
+            return;
+        }
+        info.cpuset = self.bpf.allowed_cpus(pid);
+        info.cpuset_ts = Some(now);
+    }
+
+    /// Refresh the CPU time cap that applies to a task, matching its comm against the patterns
+    /// of --comm-cap.
+    ///
+    /// The matched cap is cached in the task's statistics and the comm is read again only every
+    /// COMM_REFRESH_NS, to catch the tasks that change their name (e.g., with an exec()).
+    fn refresh_comm(&mut self, pid: i32, now: u64) {
+        let (Some(comm_caps), Some(info)) = (self.comm_caps.as_ref(), self.tasks.get_mut(&pid))
+        else {
+            return;
+        };
+        if info
+            .comm_ts
+            .is_some_and(|ts| now.saturating_sub(ts) < COMM_REFRESH_NS)
+        {
+            return;
+        }
+        info.comm_cap = self
+            .bpf
+            .comm(pid)
+            .and_then(|comm| comm_caps.matching(&comm));
+        info.comm_ts = Some(now);
+    }
+
+    /// Pick the next task to be dispatched (see pick_task()), skipping the tasks whose cap is
+    /// exhausted (see --comm-cap): the skipped tasks are moved to `held`, so that they can be
+    /// put back in the queues at the end of the round.
+    fn pick_uncapped(
+        &mut self,
+        now: u64,
+        held: &mut Vec<(PendingTask, TaskClass)>,
+    ) -> Option<(PendingTask, TaskClass)> {
+        loop {
+            let (pending, class) = self.pick_task(now)?;
+            if !self.is_capped(pending.task.pid) {
+                return Some((pending, class));
+            }
+            held.push((pending, class));
+        }
+    }
+
+    /// Return true if a task needs to be held back because its cap has no CPU time left in the
+    /// current interval (see --comm-cap).
+    fn is_capped(&mut self, pid: i32) -> bool {
+        let cap = self.tasks.get(&pid).and_then(|info| info.comm_cap);
+
+        match (self.comm_caps.as_mut(), cap) {
+            (Some(comm_caps), Some(cap)) => comm_caps.throttle(cap),
+            _ => false,
+        }
+    }
+
+    /// Return the factor applied to the time slices and to the amount of tasks dispatched per
+    /// round to limit the overhead of the scheduler (see --self-cpu-max-pct).
+    fn overhead_scale(&self) -> u64 {
+        self.overhead
+            .as_ref()
+            .map_or(1, |overhead| overhead.scale())
+    }
+
+    /// Return the value of Dispatch::slice_ns for a task that needs to run for `slice_ns`.
+    ///
+    /// A time slice of 0 (e.g., a yield, or a time slice scaled down to nothing) is mapped to
+    /// the minimum time slice (--slice-min-us), since 0 would make the BPF component assign its
+    /// own default time slice (see "Time slices" in the documentation).
+    fn dispatch_slice_ns(&self, slice_ns: u64) -> u64 {
+        match slice_ns {
+            0 => self.opts.slice_min_us.saturating_mul(1000),
+            slice_ns => slice_ns,
+        }
+    }
+
+    /// Return the factor applied to the amount of tasks dispatched per round to meet the target
+    /// latency (see --latency-target-us).
+    fn batch_scale(&self) -> u64 {
+        self.latency_target
+            .as_ref()
+            .map_or(1, |target| target.batch_scale())
+    }
+
+    /// Return the divisor of the amount of tasks dispatched per round and the factor applied to
+    /// the time slices to relieve the pressure on the BPF component (see
+    /// --failed-dispatch-thresh).
+    fn backpressure_scale(&self) -> u64 {
+        self.backpressure
+            .as_ref()
+            .map_or(1, |backpressure| backpressure.scale())
+    }
+
+    /// Scale down a dispatch capacity (time slice or amount of tasks dispatched per round) while
+    /// injecting idle time (see --thermal-sensor).
+    fn throttle(&self, value: u64) -> u64 {
+        match &self.thermal {
+            Some(thermal) if thermal.throttled() => {
+                (value.saturating_mul(100 - self.opts.thermal_idle_pct) / 100).max(1)
+            }
+            _ => value,
+        }
+    }
+
+    /// Update the statistics of a task received from the BPF component and determine its class.
+    fn prepare_task(&mut self, task: Task, now: u64) -> (PendingTask, TaskClass) {
+        let first_seen = !self.tasks.contains_key(&task.pid);
+        let mut class = self.classify(&task, now);
+        if first_seen {
+            self.record_new_task(task.pid, now);
+        }
+        self.track_task(task.pid, now);
+        if self.boost_groups.is_boosted(task.pid) {
+            class = TaskClass::Interactive;
+        }
+        if self.is_flood(task.pid) {
+            class = TaskClass::Batch;
+        }
+        self.refresh_weight(&task);
+        if self.numa_affinity.is_some() {
+            let info = self.tasks.get(&task.pid);
+            let runtime_ns = info.map_or(0, |info| {
+                task.sum_exec_runtime.saturating_sub(info.last_runtime)
+            });
+            if let Some(affinity) = self.numa_affinity.as_mut() {
+                affinity.record(task.pid, task.cpu, runtime_ns);
+            }
+        }
+        if let Some(reservation) = self.reservation.as_mut() {
+            let info = self.tasks.get(&task.pid);
+            let runtime_ns = info.map_or(0, |info| {
+                task.sum_exec_runtime.saturating_sub(info.last_runtime)
+            });
+            reservation.record(task.pid, class, runtime_ns.min(MAX_CHARGE_NS));
+        }
+        if let Some(comm_caps) = self.comm_caps.as_mut() {
+            let info = self.tasks.get(&task.pid);
+            let runtime_ns = info.map_or(0, |info| {
+                task.sum_exec_runtime.saturating_sub(info.last_runtime)
+            });
+            let cap = info.and_then(|info| info.comm_cap);
+            comm_caps.record(task.pid, cap, runtime_ns.min(MAX_CHARGE_NS));
+            self.refresh_comm(task.pid, now);
+        }
+        let vtime = self.update_vtime(&task, now);
+        self.overload.record_arrival();
+        if let Some(weights) = self.weights.as_mut() {
+            weights.record(task.weight);
+        }
+        if let Some(accounting) = self.accounting.as_mut() {
+            accounting.record_enqueue(task.pid, task.sum_exec_runtime, task.nvcsw);
+        }
+        if self.run_latency.is_some() {
+            let run_start = self.bpf.run_start_ns(task.pid);
+            if let Some(run_latency) = self.run_latency.as_mut() {
+                run_latency.record_enqueue(
+                    task.pid,
+                    now,
+                    task.sum_exec_runtime,
+                    task.nvcsw,
+                    run_start,
+                );
+            }
+        }
+        if self.opts.slice_env || self.policy == Policy::Edf {
+            self.refresh_hints(task.pid, now);
+        }
+        if self.opts.cpuset_aware {
+            self.refresh_cpuset(task.pid, now);
+        }
+        let deadline = self.deadline(task.pid, now);
+        let pending = PendingTask {
+            task,
+            enq_ts: now,
+            vtime,
+            deadline,
+        };
+
+        (pending, class)
+    }
+
+    /// Process a task received from the BPF component and add it to the user-space queues.
+    fn receive_task(&mut self, task: Task, now: u64) {
+        if !self.round_pids.insert(task.pid) && self.handle_duplicate(&task) {
+            return;
+        }
+        let (pending, class) = self.prepare_task(task, now);
+        self.enqueue_task(pending, class);
+    }
+
+    /// Handle a task received twice in the same round (see --duplicate-pid) and return true if
+    /// the task must not be queued again.
+    ///
+    /// The duplicate never goes through prepare_task(), so the per-task statistics are not
+    /// updated twice.
+    fn handle_duplicate(&mut self, task: &Task) -> bool {
+        self.nr_duplicate_pids += 1;
+
+        match self.opts.duplicate_pid {
+            DuplicatePid::Coalesce => {
+                let Some(pending) = self
+                    .interactive
+                    .iter_mut()
+                    .chain(self.batch.iter_mut())
+                    .find(|t| t.task.pid == task.pid)
+                else {
+                    return false;
+                };
+                pending.task = task.clone();
+            }
+            DuplicatePid::Skip => {
+                println!(
+                    "WARNING: pid {} received twice in the same round, skipping it",
+                    task.pid
+                );
+            }
+        }
+
+        true
+    }
+
+    /// Return the policy used to order the tasks in the current round: FIFO while the policy is
+    /// not engaged (see --policy-activation-threshold).
+    fn ordering(&self) -> Policy {
+        if self.policy_active {
+            self.policy
+        } else {
+            Policy::Fifo
+        }
+    }
+
+    /// Add a task to the user-space queue of its class.
+    ///
+    /// Tasks are dispatched from the head of the queues, so they are added to the tail of the
+    /// queue (to the head with --order lifo).
+    ///
+    /// With the wrr policy, tasks that still have dispatch credits are added to the head of the
+    /// queue, so that they are dispatched again before the other tasks; otherwise their credits
+    /// are refilled according to their weight and they are queued according to the order.
+    fn enqueue_task(&mut self, pending: PendingTask, class: TaskClass) {
+        if self.policy == Policy::Wrr {
+            if let Some(info) = self.tasks.get_mut(&pending.task.pid) {
+                if info.wrr_credits > 0 {
+                    return self.requeue_task(pending, class);
+                }
+                let level = self.boost_groups.level(pending.task.pid);
+                info.wrr_credits = (pending.task.weight.saturating_mul(level) / 100).max(1);
+            }
+        }
+        let queue = match class {
+            TaskClass::Interactive => &mut self.interactive,
+            TaskClass::Batch => &mut self.batch,
+        };
+        match self.opts.order {
+            Order::Fifo => queue.push_back(pending),
+            Order::Lifo => queue.push_front(pending),
+        }
+    }
+
+    /// Add a task that couldn't be dispatched back to the head of the queue of its class.
+    fn requeue_task(&mut self, pending: PendingTask, class: TaskClass) {
+        match class {
+            TaskClass::Interactive => self.interactive.push_front(pending),
+            TaskClass::Batch => self.batch.push_front(pending),
+        }
+    }
+
+    /// Dispatch a task to the BPF component.
+    ///
+    /// If the BPF component is busy (e.g., the dispatch queue is full) and the retries of the
+    /// round are exhausted (see send_dispatch()), the task is returned to the caller, so that it
+    /// can be dispatched again in the next round.
+    fn dispatch(
+        &mut self,
+        pending: PendingTask,
+        class: TaskClass,
+        nr_waiting: u64,
+        now: u64,
+    ) -> Result<Option<PendingTask>> {
+        let task = &pending.task;
+
+        // Create a new task to be dispatched from the received enqueued task.
+        let mut dispatched_task = Dispatch {
+            pid: task.pid,
+            cpu: task.cpu,
+            flags: task.flags,
+            slice_ns: 0,
+            vtime: 0,
+        };
+
+        // With the fair policy, send the virtual runtime to the BPF dispatcher, so that tasks are
+        // also ordered by virtual runtime in the per-CPU DSQs (by deadline with the edf policy).
+        match self.ordering() {
+            Policy::Fair => dispatched_task.vtime = pending.vtime,
+            Policy::Edf => dispatched_task.vtime = pending.deadline,
+            Policy::Fifo | Policy::Wrr => {}
+        }
+
+        // Decide where the task needs to run (pick a target CPU).
+        let coldest = self.coldest_cpu(now);
+        dispatched_task.cpu = self.pick_cpu(task, coldest);
+
+        // RL_CPU_ANY is only meaningful to the user-space scheduler, never pass it back to the
+        // kernel as an enqueue flag.
+        dispatched_task.flags &= !(RL_CPU_ANY as u64);
+
+        // Assign a time slice according to the task's class.
+        let slice_ns = self.compute_slice(task, class, nr_waiting);
+        let slice_ns = self.deadline_slice(task.pid, pending.deadline, now, slice_ns);
+        dispatched_task.slice_ns = self.throttle(slice_ns);
+
+        // Never exceed the CPU time left to the task's cap (see --comm-cap).
+        let comm_cap = self.tasks.get(&task.pid).and_then(|info| info.comm_cap);
+        if let (Some(comm_caps), Some(cap)) = (self.comm_caps.as_ref(), comm_cap) {
+            dispatched_task.slice_ns = dispatched_task.slice_ns.min(comm_caps.budget_ns(cap));
+        }
+        dispatched_task.slice_ns = self.dispatch_slice_ns(dispatched_task.slice_ns);
+
+        // Dispatch the task.
+        if !self.send_dispatch(&dispatched_task)? {
+            self.nr_dispatch_requeues += 1;
+            return Ok(Some(pending));
+        }
+
+        self.check_invariants(&dispatched_task, true)?;
+
+        if let Some(weight_classes) = self.weight_classes.as_mut() {
+            weight_classes.record(task.weight, dispatched_task.slice_ns);
+        }
+
+        if self.policy == Policy::Fair {
+            self.min_vtime = self.min_vtime.max(pending.vtime);
+        }
+
+        if self.metrics.is_some() || self.latency_target.is_some() {
+            let latency_ns = self.now_ns().saturating_sub(pending.enq_ts);
+            if self.metrics.is_some() {
+                self.latency.record(task.pid, latency_ns);
+            }
+            if let Some(target) = self.latency_target.as_mut() {
+                target.record(task.pid, latency_ns);
+            }
+        }
+
+        if self.export.is_some() {
+            let info = self.tasks.get(&task.pid);
+            let decision = Decision {
+                ts_ns: now,
+                pid: task.pid,
+                weight: task.weight,
+                nvcsw: task.nvcsw,
+                avg_nvcsw: info.map_or(0, |info| info.avg_nvcsw),
+                runtime_ns: task.sum_exec_runtime,
+                avg_util: info.map_or(0, |info| info.avg_util),
+                interactive: class == TaskClass::Interactive,
+                queue_depth: nr_waiting,
+                wait_ns: self.now_ns().saturating_sub(pending.enq_ts),
+                prev_cpu: task.cpu,
+                cpu: match dispatched_task.cpu {
+                    RL_CPU_ANY => -1,
+                    cpu => cpu,
+                },
+                slice_ns: dispatched_task.slice_ns,
+            };
+            if let Some(export) = self.export.as_mut() {
+                export.record(decision);
+            }
+        }
+
+        if dispatched_task.cpu != RL_CPU_ANY {
+            let cpu = dispatched_task.cpu as usize;
+            if cpu >= self.cpu_tasks.len() {
+                self.cpu_tasks.resize(cpu + 1, None);
+            }
+            self.cpu_tasks[cpu] = Some((task.pid, now));
+            if self.opts.initial_cpu == InitialCpu::LocalToParent {
+                if let Some(tgid) = self.process_of(task.pid) {
+                    self.process_cpus.insert(tgid, dispatched_task.cpu);
+                }
+            }
+        }
+        if let Some(info) = self.tasks.get_mut(&task.pid) {
+            info.dispatched = true;
+        }
+
+        if self.policy == Policy::Wrr {
+            if let Some(info) = self.tasks.get_mut(&task.pid) {
+                info.wrr_credits = info.wrr_credits.saturating_sub(1);
+            }
+        }
+
+        self.overload.record_dispatch();
+        self.batch_sizes.record_dispatch();
+
+        if let Some(llc) = self.llc.as_mut() {
+            llc.record_dispatch(task.cpu, dispatched_task.cpu);
+        }
+
+        if let Some(affinity) = self.numa_affinity.as_mut() {
+            affinity.record_dispatch(task.pid, dispatched_task.cpu);
+        }
+
+        if let Some(migration) = self.migration.as_mut() {
+            migration.record_dispatch(dispatched_task.cpu);
+        }
+
+        if self.wake_affinity.is_some() {
+            let tgid = self.process_of(task.pid);
+            if let Some(affinity) = self.wake_affinity.as_mut() {
+                affinity.record_dispatch(task.pid, tgid, dispatched_task.cpu, now);
+            }
+        }
+
+        if let Some(idle) = self.idle.as_mut() {
+            if dispatched_task.cpu != RL_CPU_ANY {
+                idle.record_dispatch(
+                    dispatched_task.cpu as usize,
+                    now,
+                    dispatched_task.slice_ns,
+                    coldest == Some(dispatched_task.cpu),
+                );
+            }
+        }
+
+        if let Some(accounting) = self.accounting.as_mut() {
+            accounting.record_dispatch(
+                task.pid,
+                task.sum_exec_runtime,
+                task.nvcsw,
+                dispatched_task.slice_ns,
+            );
+        }
+
+        if self.run_latency.is_some() {
+            let dispatch_ts = self.now_ns();
+            if let Some(run_latency) = self.run_latency.as_mut() {
+                run_latency.record_dispatch(
+                    task.pid,
+                    dispatch_ts,
+                    task.sum_exec_runtime,
+                    task.nvcsw,
+                );
+            }
+        }
+
+        if let Some(reservation) = self.reservation.as_mut() {
+            reservation.record_dispatch(task.pid, class, dispatched_task.slice_ns, now);
+        }
+
+        if let (Some(comm_caps), Some(cap)) = (self.comm_caps.as_mut(), comm_cap) {
+            comm_caps.record_dispatch(task.pid, cap, dispatched_task.slice_ns, now);
+        }
+
+        if let Some(auto) = self.auto.as_mut() {
+            auto.record_dispatch(class == TaskClass::Interactive, nr_waiting);
+        }
+
+        if let Some(top_view) = self.top_view.as_mut() {
+            let cpu = (dispatched_task.cpu != RL_CPU_ANY).then_some(dispatched_task.cpu as usize);
+            top_view.record(cpu, dispatched_task.slice_ns);
+        }
+
+        if let Some(state_log) = self.state_log.as_mut() {
+            let cpu = (dispatched_task.cpu != RL_CPU_ANY).then_some(dispatched_task.cpu as usize);
+            state_log.record_dispatch(cpu, dispatched_task.slice_ns);
+        }
+
+        if self.opts.cpu_gap_stats && dispatched_task.cpu != RL_CPU_ANY {
+            self.cpu_gaps.record(dispatched_task.cpu as usize, now);
+        }
+
+        if let Some(inversions) = self.inversions.as_mut() {
+            if dispatched_task.cpu != RL_CPU_ANY {
+                inversions.record_dispatch(
+                    dispatched_task.cpu as usize,
+                    task.pid,
+                    task.weight,
+                    now,
+                    dispatched_task.slice_ns,
+                );
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Validate a dispatched task against the invariants of the policy (see --debug-invariants),
+    /// logging the violations: with --debug-invariants abort, the first violation stops the
+    /// scheduler with an error. The order of the virtual runtimes is only validated if `ordered`
+    /// (the dispatches that bypass the policy carry no virtual runtime).
+    fn check_invariants(&mut self, task: &Dispatch, ordered: bool) -> Result<()> {
+        if self.invariants.is_none() {
+            return Ok(());
+        }
+        let bounds = Bounds {
+            max_slice_ns: self.slice_ns.max(
+                self.opts
+                    .slice_us
+                    .max(self.opts.compute_max_slice_us)
+                    .max(self.opts.slice_min_us)
+                    .saturating_mul(1000),
+            ),
+            nr_cpus: *self.bpf.nr_online_cpus_mut() as i32,
+            fair: ordered && self.ordering() == Policy::Fair,
+        };
+        let Some(invariants) = self.invariants.as_mut() else {
+            return Ok(());
+        };
+        let violations = invariants.check(task, &bounds);
+        for violation in &violations {
+            println!("WARNING: policy invariant violated: {}", violation);
+        }
+        if let (Some(violation), Some(InvariantMode::Abort)) =
+            (violations.first(), self.opts.debug_invariants)
+        {
+            bail!("policy invariant violated: {}", violation);
+        }
+
+        Ok(())
+    }
+
+    /// Consume all tasks that are ready to run and dispatch them.
+    fn schedule(&mut self) -> Result<()> {
+        let now = self.now_ns();
+
+        self.round_pids.clear();
+        self.nr_round_retries = 0;
+        if let Some(invariants) = self.invariants.as_mut() {
+            invariants.begin_round();
+        }
+        if let Some(migration) = self.migration.as_mut() {
+            migration.begin_round();
+        }
+
+        // Measure the gap since the previous round, if it left tasks behind (see
+        // --wakeup-gap-high-us).
+        if self.wakeup_gap.is_some() {
+            let backlog = self.nr_pending() > 0 || *self.bpf.nr_queued_mut() > 0;
+            if let Some(gap) = self.wakeup_gap.as_mut() {
+                gap.record_wakeup(now, backlog);
+            }
+        }
+
+        // Fast path: if the user-space queues are empty and there is only one task waiting to be
+        // scheduled, dispatch it directly, without going through the queues.
+        //
+        // The task statistics are still updated, so the task will be dispatched exactly as if it
+        // was processed by the general path below (a task held back by --comm-cap is queued).
+        // The fast path is not taken when the picked tasks are checked or recorded (see
+        // needs_pick()).
+        if self.nr_pending() == 0 && !self.needs_pick() {
+            let Some(task) = self.dequeue_user_task(now)? else {
+                self.notify_complete(0);
+                return Ok(());
+            };
+            self.round_pids.insert(task.pid);
+            let (pending, class) = self.prepare_task(task, now);
+
+            let Some(next) = self.dequeue_user_task(now)? else {
+                if self.is_capped(pending.task.pid) {
+                    self.requeue_task(pending, class);
+                } else if let Some(pending) = self.dispatch(pending, class, 1, now)? {
+                    self.requeue_task(pending, class);
+                }
+                let nr_pending = self.nr_pending();
+                self.notify_complete(nr_pending);
+                return Ok(());
+            };
+            self.enqueue_task(pending, class);
+            self.receive_task(next, now);
+        }
+
+        // Drain the tasks queued by the BPF component and route them to the interactive or batch
+        // queue, according to their class (the tasks left behind by --max-dequeue-per-round and
+        // --round-budget-us are dequeued in the next round).
+        let mut nr_dequeued = 0;
+        while let Some(task) = self.dequeue_task() {
+            if !self.bypass_kthread(&task, now)? {
+                self.receive_task(task, now);
+            }
+            nr_dequeued += 1;
+            if self.over_budget(now, nr_dequeued) {
+                break;
+            }
+            if nr_dequeued >= self.opts.max_dequeue_per_round {
+                self.nr_dequeue_breaks += 1;
+                if self.nr_dequeue_breaks == 1 {
+                    println!(
+                        "WARNING: {} tasks dequeued in one round without draining the queue, \
+                         giving control back to the BPF component",
+                        nr_dequeued
+                    );
+                }
+                break;
+            }
+        }
+
+        // Engage the policy only if enough tasks are waiting (see --policy-activation-threshold).
+        let nr_pending = self.nr_pending();
+        if let Some(activation) = self.activation.as_mut() {
+            self.policy_active = activation.update(nr_pending);
+        }
+
+        // Order the tasks according to the candidate policy (see --shadow-policy), before they
+        // are sorted according to the current one.
+        if let Some(shadow) = self.shadow.as_mut() {
+            for (class, queue) in [
+                (TaskClass::Interactive, &self.interactive),
+                (TaskClass::Batch, &self.batch),
+            ] {
+                shadow.order(
+                    class,
+                    queue.iter().map(|t| (t.task.pid, t.vtime, t.deadline)),
+                );
+            }
+        }
+
+        // With the fair policy, tasks with the smallest virtual runtime are dispatched first,
+        // with the edf policy, tasks with the earliest deadline.
+        let ordering = self.ordering();
+        for queue in [&mut self.interactive, &mut self.batch] {
+            match ordering {
+                Policy::Fair => queue.make_contiguous().sort_by_key(|t| t.vtime),
+                Policy::Edf => queue.make_contiguous().sort_by_key(|t| t.deadline),
+                Policy::Fifo | Policy::Wrr => {}
+            }
+        }
+
+        // During a fork bomb, the tasks that were already known are dispatched before the flood
+        // and their time slices are not scaled down by it (see --fork-bomb-throttle).
+        let mut nr_waiting = self.nr_pending();
+        if self.opts.fork_bomb_throttle && self.fork_bomb.as_ref().is_some_and(|f| f.active()) {
+            let tasks = &self.tasks;
+            let is_flood = |t: &PendingTask| tasks.get(&t.task.pid).is_some_and(|info| info.flood);
+            self.batch.make_contiguous().sort_by_key(is_flood);
+            nr_waiting -= self.batch.iter().filter(|&t| is_flood(t)).count() as u64;
+        }
+
+        // Dispatch at most one task per online CPU, the remaining tasks will be dispatched in the
+        // next round, giving the interactive tasks the chance to get ahead of the batch ones
+        // (more tasks per round are dispatched with --self-cpu-max-pct, to run less often, and
+        // with --latency-target-us, to drain the queues faster).
+        let nr_cpus = *self.bpf.nr_online_cpus_mut();
+        let nr_cpus = nr_cpus
+            .saturating_mul(self.overhead_scale())
+            .saturating_mul(self.batch_scale())
+            / self.backpressure_scale();
+        let nr_cpus = self.throttle(nr_cpus).max(1);
+
+        // The budget of the round is checked again while dispatching, so that at least
+        // ROUND_BUDGET_CHECK tasks are dispatched in each round (see --round-budget-us).
+        let mut held = Vec::new();
+        let mut dispatched = Vec::new();
+        for nr_dispatched in 0..nr_cpus {
+            if self.over_budget(now, nr_dispatched) {
+                break;
+            }
+            let Some((pending, class)) = self.pick_uncapped(now, &mut held) else {
+                break;
+            };
+            let pid = pending.task.pid;
+            // Stop dispatching if the BPF component is congested, the task will be dispatched
+            // again in the next round.
+            if let Some(pending) = self.dispatch(pending, class, nr_waiting, now)? {
+                self.requeue_task(pending, class);
+                break;
+            }
+            if self.shadow.is_some() {
+                dispatched.push((pid, class));
+            }
+        }
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.record(&dispatched);
+        }
+
+        // Put the tasks held back by --comm-cap back at the head of their queues (in the same
+        // order), they will be dispatched once their cap has CPU time left.
+        for (pending, class) in held.into_iter().rev() {
+            self.requeue_task(pending, class);
+        }
+
+        // Check if the tasks left in the queues are waiting for lower-priority tasks.
+        if let Some(inversions) = self.inversions.as_mut() {
+            let waiting = self
+                .interactive
+                .iter()
+                .chain(self.batch.iter())
+                .map(|t| (t.task.pid, t.task.weight))
+                .max_by_key(|&(_, weight)| weight);
+            inversions.check(waiting, now);
+        }
+
+        // Notify the BPF component that tasks have been dispatched, reporting the amount of
+        // tasks that are still waiting in the user-space queues.
+        //
+        // This function will put the scheduler to sleep, until another task needs to run.
+        let nr_pending = self.nr_pending();
+        self.notify_complete(nr_pending);
+
+        Ok(())
+    }
+
+    /// Return true if every task must be dispatched through pick_task(), even a single task in
+    /// a round with empty queues (see the fast path of schedule()): with --reserve-*-pct the
+    /// task can be held back, and --policy-activation-threshold, --shadow-policy and
+    /// --inversion-weight-gap have to see every round.
+    fn needs_pick(&self) -> bool {
+        self.reservation.is_some()
+            || self.activation.is_some()
+            || self.shadow.is_some()
+            || self.inversions.is_some()
+    }
+
+    /// Return true if the scheduling round started at `start` has exceeded its time budget (see
+    /// --round-budget-us), given the tasks `nr_tasks` processed so far in the current loop of the
+    /// round: the clock is only read every ROUND_BUDGET_CHECK tasks.
+    fn over_budget(&mut self, start: u64, nr_tasks: u64) -> bool {
+        let Some(budget_us) = self.opts.round_budget_us else {
+            return false;
+        };
+        if nr_tasks == 0 || !nr_tasks.is_multiple_of(ROUND_BUDGET_CHECK) {
+            return false;
+        }
+        if self.now_ns().saturating_sub(start) < budget_us.saturating_mul(1000) {
+            return false;
+        }
+        self.nr_budget_breaks += 1;
+
+        true
+    }
+
+    /// Consume a task queued by the BPF component, None if there are no more tasks or if
+    /// dequeue_task() failed: errors are logged (once per streak of consecutive errors) and
+    /// counted, and a long streak restarts the scheduler (see DEQUEUE_ERRORS_RESTART).
+    fn dequeue_task(&mut self) -> Option<Task> {
+        let err = match self.bpf.dequeue_task() {
+            Ok(task) => {
+                self.nr_dequeue_errors_streak = 0;
+                return task;
+            }
+            Err(err) => io::Error::from_raw_os_error(-err),
+        };
+        self.nr_dequeue_errors += 1;
+        self.nr_dequeue_errors_streak += 1;
+        match self.nr_dequeue_errors_streak {
+            1 => println!("WARNING: dequeue_task() failed: {}", err),
+            DEQUEUE_ERRORS_RESTART => println!(
+                "WARNING: dequeue_task() failed {} times in a row ({}), restarting the scheduler",
+                DEQUEUE_ERRORS_RESTART, err
+            ),
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Dequeue the next task that needs to go through the scheduling policy, dispatching the
+    /// kernel threads received in the meantime (see --skip-kthreads).
+    ///
+    /// As with the rounds, at most --max-dequeue-per-round tasks are dequeued.
+    fn dequeue_user_task(&mut self, now: u64) -> Result<Option<Task>> {
+        for _ in 0..self.opts.max_dequeue_per_round {
+            let Some(task) = self.dequeue_task() else {
+                break;
+            };
+            if !self.bypass_kthread(&task, now)? {
+                return Ok(Some(task));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Return true if the task `pid`, received at `now`, is a kernel thread (the flag is read
+    /// once per task, see --skip-kthreads). A task whose flag is not known yet is scheduled as a
+    /// user task, and the flag is read again the next time the task is received.
+    fn is_kthread(&mut self, pid: i32, now: u64) -> bool {
+        let kthread = match self.kthreads.get(&pid) {
+            Some(&(kthread, _)) => Some(kthread),
+            None => self.bpf.is_kthread(pid),
+        };
+        let Some(kthread) = kthread else {
+            return false;
+        };
+        self.kthreads.insert(pid, (kthread, now));
+
+        kthread
+    }
+
+    /// Dispatch a kernel thread received at `now` straight away, on its previously used CPU
+    /// with the default time slice (see --skip-kthreads): return false if the task is not a
+    /// kernel thread, or if the BPF component is too busy to accept it, so that the task goes
+    /// through the regular scheduling policy.
+    ///
+    /// The CPUs that must not receive dispatches are still honored: a kernel thread that
+    /// previously ran on one of them (or on a CPU that doesn't exist anymore) is dispatched to
+    /// the fallback CPU (see fallback_cpu()), and the dispatch is validated like the others (see
+    /// --debug-invariants).
+    fn bypass_kthread(&mut self, task: &Task, now: u64) -> Result<bool> {
+        if !self.opts.skip_kthreads || !self.is_kthread(task.pid, now) {
+            return Ok(false);
+        }
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as i32;
+        let cpu = if task.cpu < 0 || task.cpu >= nr_cpus || self.is_cpu_excluded(task.cpu) {
+            self.fallback_cpu(task)
+        } else {
+            task.cpu
+        };
+        let dispatched_task = Dispatch {
+            pid: task.pid,
+            cpu,
+            flags: task.flags & !(RL_CPU_ANY as u64),
+            slice_ns: self.opts.slice_us.saturating_mul(1000),
+            vtime: 0,
+        };
+
+        if !self.send_dispatch(&dispatched_task)? {
+            return Ok(false);
+        }
+        self.check_invariants(&dispatched_task, false)?;
+        self.nr_kthread_dispatches += 1;
+        self.batch_sizes.record_dispatch();
+
+        Ok(true)
+    }
+
+    /// Send a dispatch to the BPF component, return false if the BPF component is busy.
+    ///
+    /// A rejected dispatch is retried straight away, up to DISPATCH_RETRIES times per scheduling
+    /// round (shared by all the tasks of the round): the scheduler never sleeps in the middle of
+    /// a round, once the retries are exhausted the round ends and notify_complete() gives the BPF
+    /// component the time to drain its queue.
+    fn send_dispatch(&mut self, dispatched_task: &Dispatch) -> Result<bool> {
+        loop {
+            match self.bpf.dispatch_task(dispatched_task) {
+                Ok(()) => return Ok(true),
+                Err(DispatchError::Busy) if self.nr_round_retries < DISPATCH_RETRIES => {
+                    self.nr_round_retries += 1;
+                    self.nr_dispatch_retries += 1;
+                }
+                Err(DispatchError::Busy) => return Ok(false),
+                Err(DispatchError::Fatal(err)) => return Err(err),
+            }
+        }
+    }
+
+    /// Give control to the BPF component (with --notify-stall-ms, measuring how long it takes),
+    /// completing the current scheduling round: a round that dispatched nothing and left no task
+    /// waiting is an idle window for the housekeeping, done before going to sleep (see
+    /// on_idle()); a round that dispatched nothing because the BPF component is congested is not.
+    fn notify_complete(&mut self, nr_pending: u64) {
+        // A long sleep is expected if there is nothing to do, only the calls that leave tasks
+        // behind are accounted by the breaker.
+        let backlog = nr_pending > 0 || *self.bpf.nr_queued_mut() > 0;
+        if self.batch_sizes.end_round() == 0 && !backlog {
+            self.on_idle();
+        }
+
+        let start_ts = self.now_ns();
+        self.bpf.notify_complete(nr_pending);
+        let duration_ns = self.now_ns().saturating_sub(start_ts);
+
+        if let Some(breaker) = self.breaker.as_mut().filter(|_| backlog) {
+            breaker.record(duration_ns);
+        }
+        if let Some(loop_gap) = self.loop_gap.as_mut() {
+            loop_gap.sleep(duration_ns);
+        }
+    }
+
+    /// Run the housekeeping in a round that dispatched nothing, at most once every
+    /// --idle-maintenance-ms: drop the state of the tasks that exited and refresh the online
+    /// CPUs, as done at every stats interval.
+    fn on_idle(&mut self) {
+        let Some(interval_ms) = self.opts.idle_maintenance_ms else {
+            return;
+        };
+        let now = self.now_ns();
+        let interval_ns = interval_ms.saturating_mul(1_000_000);
+        if self
+            .idle_pass_ts
+            .is_some_and(|ts| now.saturating_sub(ts) < interval_ns)
+        {
+            return;
+        }
+        self.idle_pass_ts = Some(now);
+        self.nr_idle_passes += 1;
+
+        self.gc_tasks();
+        self.refresh_online_cpus();
+    }
+
+    /// Detect an iteration of the main loop that took too long (the scheduler is starved) and
+    /// raise the priority of the scheduler the first time (see --loop-gap-ms).
+    fn check_loop_gap(&mut self) {
+        let now = self.now_ns();
+        let Some(loop_gap) = self.loop_gap.as_mut() else {
+            return;
+        };
+        let Some(gap_ns) = loop_gap.sample(now) else {
+            return;
+        };
+        // Warn once per stats interval, the other gaps are reported in the stats.
+        if loop_gap.nr_gaps() == 1 {
+            println!(
+                "WARNING: the scheduler didn't run for {}ms (above --loop-gap-ms {}), it may be \
+                 starved",
+                gap_ns / 1_000_000,
+                self.opts.loop_gap_ms.unwrap_or(0)
+            );
+        }
+        if !loop_gap.needs_boost() {
+            return;
+        }
+        if self.bpf.boost_self() {
+            println!("raised the priority of the scheduler to nice -20");
+        } else {
+            println!("WARNING: failed to raise the priority of the scheduler");
+        }
+    }
+
+    /// Write the decisions still queued to the export (see --export-csv).
+    fn finish_export(&mut self) -> Result<()> {
+        let Some(export) = self.export.take() else {
+            return Ok(());
+        };
+        let nr_dropped = export.finish()?;
+        if nr_dropped > 0 {
+            println!(
+                "WARNING: {} decisions dropped from the export (the writer couldn't keep up)",
+                nr_dropped
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Write the snapshots still queued to the state log (see --state-log).
+    fn finish_state_log(&mut self) -> Result<()> {
+        let Some(state_log) = self.state_log.take() else {
+            return Ok(());
+        };
+        let nr_dropped = state_log.finish()?;
+        if nr_dropped > 0 {
+            println!(
+                "WARNING: {} snapshots dropped from the state log (the writer couldn't keep up)",
+                nr_dropped
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Return true if the scheduler needs to be restarted: it is stalled in notify_complete()
+    /// (see --notify-stall-ms), or dequeue_task() keeps failing (see DEQUEUE_ERRORS_RESTART).
+    fn needs_restart(&self) -> bool {
+        self.breaker.as_ref().is_some_and(NotifyBreaker::tripped)
+            || self.nr_dequeue_errors_streak >= DEQUEUE_ERRORS_RESTART
+    }
+
+    /// Return the snapshot of the state learned by the policy for each task (see --state-file).
+    fn snapshot(&self) -> Snapshot {
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|(&pid, info)| {
+                let state = TaskState {
+                    nvcsw: info.nvcsw,
+                    nvcsw_ts: info.nvcsw_ts,
+                    avg_nvcsw: info.avg_nvcsw,
+                    runtime: info.runtime,
+                    avg_util: info.avg_util,
+                    avg_nvcsw_rt: info.avg_nvcsw_rt,
+                    avg_io: info.avg_io,
+                    last_runtime: info.last_runtime,
+                    vtime: info.vtime,
+                    last_seen: info.last_seen,
+                    weight: info.weight,
+                    wrr_credits: info.wrr_credits,
+                };
+                (pid, state)
+            })
+            .collect();
+
+        Snapshot {
+            saved_ns: self.bpf.realtime_ns(),
+            boot_id: self.bpf.boot_id(),
+            min_vtime: self.min_vtime,
+            tasks,
+            pins: self.pins.clone(),
+        }
+    }
+
+    /// Restore the state of the tasks from a snapshot (see --state-file), skipping the tasks
+    /// that exited in the meantime, and return the amount of tasks restored, None if the
+    /// snapshot is older than --state-max-age-secs or has been saved before a reboot.
+    fn restore(&mut self, snapshot: Snapshot) -> Option<usize> {
+        let now = self.bpf.realtime_ns();
+        let boot_id = self.bpf.boot_id();
+        let max_age_ns = self.opts.state_max_age_secs.saturating_mul(NSEC_PER_SEC);
+        if !snapshot.is_fresh(now, boot_id.as_deref(), max_age_ns) {
+            return None;
+        }
+        self.min_vtime = self.min_vtime.max(snapshot.min_vtime);
+        let mut nr_tasks = 0;
+        for (pid, state) in snapshot.tasks {
+            if !self.bpf.exists(pid) {
+                continue;
+            }
+            let info = TaskInfo {
+                nvcsw: state.nvcsw,
+                nvcsw_ts: state.nvcsw_ts,
+                avg_nvcsw: state.avg_nvcsw,
+                runtime: state.runtime,
+                avg_util: state.avg_util,
+                avg_nvcsw_rt: state.avg_nvcsw_rt,
+                avg_io: state.avg_io,
+                last_runtime: state.last_runtime,
+                vtime: state.vtime,
+                last_seen: state.last_seen,
+                slice_req: None,
+                latency_req: None,
+                hints_ts: None,
+                weight: state.weight,
+                wrr_credits: state.wrr_credits,
+                tgid: None,
+                cpuset: None,
+                cpuset_ts: None,
+                flood: false,
+                comm_cap: None,
+                comm_ts: None,
+                dispatched: true,
+            };
+            if let Some(lru) = self.lru.as_mut() {
+                lru.touch(pid, info.last_seen);
+            }
+            self.tasks.insert(pid, info);
+            nr_tasks += 1;
+        }
+        for (pid, cpu) in snapshot.pins {
+            if self.bpf.exists(pid) {
+                self.pins.insert(pid, cpu);
+            }
+        }
+
+        Some(nr_tasks)
+    }
+
+    /// Restore the state saved to `path` (see --state-file), if any: a missing, stale or
+    /// invalid state is not an error, the scheduler simply starts from scratch.
+    fn load_state(&mut self, path: &str) {
+        match snapshot::load(path) {
+            Ok(Some(snapshot)) => match self.restore(snapshot) {
+                Some(nr_tasks) => {
+                    println!("restored the state of {} tasks from {}", nr_tasks, path)
+                }
+                None => println!(
+                    "WARNING: the state in {} is too old (or from a previous boot), ignoring it",
+                    path
+                ),
+            },
+            Ok(None) => {}
+            Err(err) => println!("WARNING: {:#}, ignoring the saved state", err),
+        }
+    }
+
+    /// Drop the statistics of the tasks that have not been seen for more than --pid-gc-secs (and
+    /// all the other per-task and per-process state associated to them).
+    ///
+    /// Called once per second: the per-task and per-process state kept by the other components
+    /// (the LLC domains of the processes, the time slice accounting) follows the tasks that are
+    /// still tracked, so a single grace period applies to all of it.
+    fn gc_tasks(&mut self) {
+        let now = self.now_ns();
+        let grace_ns = self.opts.pid_gc_secs.saturating_mul(NSEC_PER_SEC);
+
+        self.tasks
+            .retain(|_, info| now.saturating_sub(info.last_seen) < grace_ns);
+
+        if let Some(lru) = self.lru.as_mut() {
+            lru.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(llc) = self.llc.as_mut() {
+            let alive: HashSet<i32> = self.tasks.values().filter_map(|info| info.tgid).collect();
+            llc.retain(&alive);
+        }
+
+        self.kthreads
+            .retain(|_, &mut (_, last_seen)| now.saturating_sub(last_seen) < grace_ns);
+
+        if !self.process_cpus.is_empty() {
+            let alive: HashSet<i32> = self.tasks.values().filter_map(|info| info.tgid).collect();
+            self.process_cpus.retain(|tgid, _| alive.contains(tgid));
+        }
+
+        if let Some(accounting) = self.accounting.as_mut() {
+            accounting.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(run_latency) = self.run_latency.as_mut() {
+            run_latency.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(affinity) = self.numa_affinity.as_mut() {
+            affinity.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(migration) = self.migration.as_mut() {
+            migration.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(affinity) = self.wake_affinity.as_mut() {
+            affinity.retain(now, |pid| self.tasks.contains_key(&pid));
+        }
+
+        if let Some(invariants) = self.invariants.as_mut() {
+            invariants.retain(|pid| self.tasks.contains_key(&pid));
+        }
+
+        // Clear the pins of the tasks that exited (the pid may be reused by a different task).
+        let pinned: Vec<i32> = self.pins.keys().copied().collect();
+        for pid in pinned {
+            if !self.bpf.exists(pid) {
+                self.pins.remove(&pid);
+                println!("pin: pid {} exited, pin cleared", pid);
+            }
+        }
+
+        // Drop the tasks that exited from the boost groups.
+        let boosted = self.boost_groups.pids();
+        let exited: HashSet<i32> = boosted
+            .into_iter()
+            .filter(|&pid| !self.bpf.exists(pid))
+            .collect();
+        if !exited.is_empty() {
+            for name in self.boost_groups.retain(|pid| !exited.contains(&pid)) {
+                println!(
+                    "boost: all the tasks of group {} exited, group cleared",
+                    name
+                );
+            }
+        }
+    }
+
+    /// Refresh the metrics exposed by the metrics endpoint.
+    fn update_metrics(&mut self) {
+        let Some(server) = self.metrics else {
+            return;
+        };
+        let mut text = String::new();
+
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_user_dispatches",
+            "Tasks dispatched by the user-space scheduler.",
+            self.nr_user_dispatches(),
+        );
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_kernel_dispatches",
+            "Tasks dispatched directly by the BPF component.",
+            self.nr_kernel_dispatches(),
+        );
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_dispatch_retries",
+            "Dispatch attempts retried because the BPF component was busy.",
+            self.nr_dispatch_retries,
+        );
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_dispatch_requeues",
+            "Tasks re-queued for the next round after exhausting all the dispatch retries.",
+            self.nr_dispatch_requeues,
+        );
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_bounce_dispatches",
+            "Dispatches bounced by the BPF component to the shared DSQ (CPU not usable).",
+            self.bpf
+                .nr_bounce_dispatches_mut()
+                .saturating_sub(self.counters_base.nr_bounce_dispatches),
+        );
+        metrics::write_counter(
+            &mut text,
+            "scx_rust_scheduler_cancel_dispatches",
+            "Dispatches cancelled by the BPF component (task changed while dispatched).",
+            self.bpf
+                .nr_cancel_dispatches_mut()
+                .saturating_sub(self.counters_base.nr_cancel_dispatches),
+        );
+        self.latency.write_openmetrics(
+            &mut text,
+            "scx_rust_scheduler_latency_seconds",
+            "Time spent by the tasks in the user-space queues before being dispatched.",
+            self.opts.metrics_exemplars,
+        );
+        if let Some(run_latency) = self.run_latency.as_ref() {
+            run_latency.write_openmetrics(&mut text, self.opts.metrics_exemplars);
+        }
+
+        server.update(text);
+
+        if self.opts.metrics_dashboard {
+            let extra = self.stats_extra();
+            server.update_stats(self.stats_snapshot().json(&extra));
+        }
+    }
+
+    /// Return the members added to the JSON stats (see --metrics-dashboard and --stats-udp), as
+    /// formatted JSON values.
+    fn stats_extra(&self) -> Vec<(&'static str, String)> {
+        [
+            ("queue", self.queue_trend.json()),
+            ("dispatch_ratio", self.dispatch_ratio.json()),
+            ("batch_size", self.batch_sizes.json()),
+        ]
+        .into_iter()
+        .chain(
+            self.weights
+                .iter()
+                .map(|weights| ("weights", weights.json())),
+        )
+        .chain(
+            self.weight_classes
+                .iter()
+                .map(|classes| ("weight_classes", classes.json())),
+        )
+        .collect()
+    }
+
+    /// Push the stats to the collector (see --stats-udp).
+    fn push_udp_stats(&mut self) {
+        let Some(udp_stats) = self.udp_stats.as_ref() else {
+            return;
+        };
+        let mut extra = self.stats_extra();
+        extra.extend(udp_stats.extra());
+        let json = self.stats_snapshot().json(&extra);
+
+        if let Some(udp_stats) = self.udp_stats.as_mut() {
+            udp_stats.send(&json);
+        }
+    }
+
+    /// Refresh the counters served by the scx_stats server.
+    #[cfg(feature = "stats")]
+    fn update_scx_stats(&mut self) {
+        if let Some(server) = self.scx_stats {
+            server.update(&self.stats_snapshot());
+        }
+    }
+
+    /// Append a snapshot of the full state to the state log, every --state-log-secs (see
+    /// --state-log).
+    fn log_state(&mut self) {
+        let now = self.now_ns();
+        if !self.state_log.as_ref().is_some_and(|log| log.due(now)) {
+            return;
+        }
+        let mut top: Vec<(i32, &TaskInfo)> =
+            self.tasks.iter().map(|(&pid, info)| (pid, info)).collect();
+        top.sort_by_key(|&(pid, info)| (Reverse(info.avg_util), pid));
+        let top: Vec<String> = top
+            .iter()
+            .take(STATE_LOG_TOP_TASKS)
+            .map(|(pid, info)| {
+                format!(
+                    "{{\"pid\":{},\"util\":{},\"nvcsw\":{},\"weight\":{}}}",
+                    pid, info.avg_util, info.avg_nvcsw, info.weight
+                )
+            })
+            .collect();
+
+        let mut extra = self.stats_extra();
+        extra.push(("bpf_queued", self.bpf.nr_queued_mut().to_string()));
+        if let Some(state_log) = self.state_log.as_mut() {
+            extra.extend(state_log.take_loads(now));
+        }
+        extra.push(("top", format!("[{}]", top.join(","))));
+        let json = self.stats_snapshot().json(&extra);
+
+        if let Some(state_log) = self.state_log.as_mut() {
+            state_log.write(json);
+        }
+    }
+
+    /// Periodically re-evaluate the workload and switch profile if needed (see --mode auto).
+    fn update_mode(&mut self) {
+        let now = self.now_ns();
+        let Some((profile, reason)) = self.auto.as_mut().and_then(|auto| auto.evaluate(now)) else {
+            return;
+        };
+
+        match profile {
+            Profile::Interactive => {
+                self.policy = Policy::Fair;
+                self.compute_boost = true;
+                self.slice_ns = self.opts.slice_us.saturating_mul(1000);
+            }
+            Profile::Batch => {
+                self.policy = Policy::Fifo;
+                self.compute_boost = false;
+                self.slice_ns = self
+                    .opts
+                    .slice_us
+                    .max(self.opts.compute_max_slice_us)
+                    .saturating_mul(1000);
+            }
+        }
+        println!("mode auto: switching to {:?} profile ({})", profile, reason);
+    }
+
+    /// Activate the profile `name`, applying all its settings (see --profile).
+    fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        let Some(profiles) = self.profiles.as_mut() else {
+            return Err("no profile defined (see --profile)".to_string());
+        };
+        let settings = profiles.switch(name)?;
+
+        self.policy = settings.policy.unwrap_or(self.opts.policy);
+        self.slice_ns = settings
+            .slice_us
+            .unwrap_or(self.opts.slice_us)
+            .saturating_mul(1000);
+        self.compute_boost = settings.compute_boost.unwrap_or(self.opts.compute_boost);
+        println!("profile: switching to {} ({})", name, settings);
+
+        Ok(())
+    }
+
+    /// Evaluate --profile-at at the time of the day reported by the backend, that is recorded in
+    /// the traces, so that a replay switches profile at the same points of the session.
+    fn refresh_timetable(&mut self) {
+        if self.timetable.is_some() {
+            let minute = self.bpf.local_minute();
+            self.update_timetable(minute);
+        }
+    }
+
+    /// Activate the profile scheduled at the time of the day `minute` (minutes since midnight),
+    /// if a new slot of --profile-at has begun.
+    fn update_timetable(&mut self, minute: u32) {
+        let Some(entry) = self
+            .timetable
+            .as_mut()
+            .and_then(|t| t.update(minute))
+            .cloned()
+        else {
+            return;
+        };
+        println!(
+            "profile-at: {} slot begins, activating {}",
+            entry, entry.name
+        );
+        if let Err(err) = self.switch_profile(&entry.name) {
+            println!("profile-at: {}", err);
+        }
+    }
+
+    /// Check if the scheduler can keep up with the task arrival rate (see --overload-thresh-pct)
+    /// and if it lags behind in dispatching the tasks (see --wakeup-gap-high-us).
+    fn update_overload(&mut self) {
+        let now = self.now_ns();
+        let nr_queued = *self.bpf.nr_queued_mut();
+
+        self.overload.evaluate(now, nr_queued);
+        if let Some(gap) = self.wakeup_gap.as_mut() {
+            gap.evaluate(now);
+        }
+        if let Some(fork_bomb) = self.fork_bomb.as_mut() {
+            fork_bomb.evaluate(now);
+        }
+        if let Some(reservation) = self.reservation.as_mut() {
+            let nr_cpus = *self.bpf.nr_online_cpus_mut();
+            reservation.evaluate(now, nr_cpus);
+        }
+        if let Some(comm_caps) = self.comm_caps.as_mut() {
+            comm_caps.evaluate(now);
+        }
+    }
+
+    /// Measure the CPU time consumed by the scheduler itself (see --self-stats and
+    /// --self-cpu-max-pct).
+    fn update_overhead(&mut self) {
+        if self.overhead.is_none() {
+            return;
+        }
+        let now = self.now_ns();
+        let cpu_ns = self.bpf.self_cpu_ns();
+        let nr_cpus = *self.bpf.nr_online_cpus_mut();
+
+        if let Some(overhead) = self.overhead.as_mut() {
+            overhead.evaluate(now, cpu_ns, nr_cpus);
+        }
+    }
+
+    /// Evaluate the latency of the last interval against the target and adjust the time slices
+    /// and the tasks per round (see --latency-target-us).
+    fn update_latency_target(&mut self) {
+        let now = self.now_ns();
+
+        if let Some(target) = self.latency_target.as_mut() {
+            target.evaluate(now);
+        }
+    }
+
+    /// Evaluate the dispatches failed by the BPF component in the last interval and relieve the
+    /// pressure on it if needed (see --failed-dispatch-thresh).
+    fn update_backpressure(&mut self) {
+        if self.backpressure.is_none() {
+            return;
+        }
+        let now = self.now_ns();
+        let nr_failed = *self.bpf.nr_failed_dispatches_mut();
+
+        if let Some(backpressure) = self.backpressure.as_mut() {
+            backpressure.evaluate(now, nr_failed);
+        }
+    }
+
+    /// Return the tasks dispatched by the user-space scheduler since the last reset.
+    fn nr_user_dispatches(&mut self) -> u64 {
+        self.bpf
+            .nr_user_dispatches_mut()
+            .saturating_sub(self.counters_base.nr_user_dispatches)
+    }
+
+    /// Return the tasks dispatched directly by the BPF component since the last reset.
+    fn nr_kernel_dispatches(&mut self) -> u64 {
+        self.bpf
+            .nr_kernel_dispatches_mut()
+            .saturating_sub(self.counters_base.nr_kernel_dispatches)
+    }
+
+    /// Return a snapshot of the scheduler statistics.
+    fn stats_snapshot(&mut self) -> StatsSnapshot {
+        StatsSnapshot {
+            now_ns: self.now_ns(),
+            nr_user_dispatches: self.nr_user_dispatches(),
+            nr_kernel_dispatches: self.nr_kernel_dispatches(),
+            nr_interactive: self.interactive.len() as u64,
+            nr_batch: self.batch.len() as u64,
+            nr_dispatch_retries: self.nr_dispatch_retries,
+            nr_dispatch_requeues: self.nr_dispatch_requeues,
+            nr_tasks: self.tasks.len() as u64,
+            nr_bounce_dispatches: self
+                .bpf
+                .nr_bounce_dispatches_mut()
+                .saturating_sub(self.counters_base.nr_bounce_dispatches),
+            nr_cancel_dispatches: self
+                .bpf
+                .nr_cancel_dispatches_mut()
+                .saturating_sub(self.counters_base.nr_cancel_dispatches),
+            nr_failed_dispatches: self
+                .bpf
+                .nr_failed_dispatches_mut()
+                .saturating_sub(self.counters_base.nr_failed_dispatches),
+            backpressure_scale: self.backpressure.as_ref().map_or(0, Backpressure::scale),
+        }
+    }
+
+    /// Return a snapshot of the internal state: the tasks waiting in the user-space queues and
+    /// the last task dispatched to each CPU.
+    fn state_dump(&self) -> StateDump {
+        let now = self.now_ns();
+        let queued = [("interactive", &self.interactive), ("batch", &self.batch)]
+            .into_iter()
+            .flat_map(|(queue, tasks)| {
+                tasks.iter().map(move |t| QueuedEntry {
+                    queue,
+                    pid: t.task.pid,
+                    weight: t.task.weight,
+                    boost_pct: self.opts.io_boost.map(|_| self.io_boost_pct(t.task.pid)),
+                    vtime: t.vtime,
+                    wait_ns: now.saturating_sub(t.enq_ts),
+                })
+            })
+            .collect();
+        let cpus = self
+            .cpu_tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(cpu, entry)| {
+                entry.map(|(pid, ts)| CpuEntry {
+                    cpu,
+                    pid,
+                    age_ns: now.saturating_sub(ts),
+                })
+            })
+            .collect();
+
+        StateDump { queued, cpus }
+    }
+
+    /// Return the soft deadlines of the queued tasks, sorted by deadline (see the `dump deadlines`
+    /// command): the deadlines are computed when the tasks are received, so the snapshot only
+    /// copies and sorts them, without touching the queues.
+    fn deadline_dump(&self) -> Result<DeadlineDump, String> {
+        if self.policy != Policy::Edf {
+            return Err("deadlines are only used by the edf policy".to_string());
+        }
+        let now = self.now_ns();
+        let mut queued: Vec<DeadlineEntry> =
+            [("interactive", &self.interactive), ("batch", &self.batch)]
+                .into_iter()
+                .flat_map(|(queue, tasks)| {
+                    tasks.iter().map(move |t| DeadlineEntry {
+                        queue,
+                        pid: t.task.pid,
+                        weight: t.task.weight,
+                        deadline: t.deadline,
+                        wait_ns: now.saturating_sub(t.enq_ts),
+                    })
+                })
+                .collect();
+        queued.sort_by_key(|t| t.deadline);
+
+        Ok(DeadlineDump {
+            now_ns: now,
+            queued,
+        })
+    }
+
+    /// Handle the commands received from the control socket.
+    fn handle_control_requests(&mut self) {
+        let Some(control) = self.control else {
+            return;
+        };
+        while let Some((request, reply)) = control.poll() {
+            let stats = self.stats_snapshot();
+            let data = match request {
+                Request::Stats => stats.text().into_bytes(),
+                Request::StatsBinary => stats.encode(),
+                Request::Dump => self.state_dump().text().into_bytes(),
+                Request::DumpJson => self.state_dump().json().into_bytes(),
+                Request::Deadlines => match self.deadline_dump() {
+                    Ok(dump) => dump.text().into_bytes(),
+                    Err(err) => format!("error: {}\n", err).into_bytes(),
+                },
+                Request::DeadlinesJson => match self.deadline_dump() {
+                    Ok(dump) => dump.json().into_bytes(),
+                    Err(err) => format!("error: {}\n", err).into_bytes(),
+                },
+                Request::Pin(pid, cpu) => match self.pin(pid, cpu) {
+                    Ok(()) => b"ok\n".to_vec(),
+                    Err(err) => format!("error: {}\n", err).into_bytes(),
+                },
+                Request::Unpin(pid) => {
+                    self.unpin(pid);
+                    b"ok\n".to_vec()
+                }
+                Request::Profile(name) => match self.switch_profile(&name) {
+                    Ok(()) => b"ok\n".to_vec(),
+                    Err(err) => format!("error: {}\n", err).into_bytes(),
+                },
+                Request::Reset => {
+                    self.reset_stats();
+                    b"ok\n".to_vec()
+                }
+                Request::Boost(name, level, pids) => match self.boost_group(&name, level, &pids) {
+                    Ok(()) => b"ok\n".to_vec(),
+                    Err(err) => format!("error: {}\n", err).into_bytes(),
+                },
+                Request::Unboost(name) => {
+                    if self.boost_groups.clear(&name) {
+                        println!("boost: group {} cleared", name);
+                    }
+                    b"ok\n".to_vec()
+                }
+            };
+            let _ = reply.send(data);
+        }
+    }
+
+    /// Always dispatch the task `pid` to `cpu`, overriding the CPU selection policy (see the pin
+    /// control command).
+    fn pin(&mut self, pid: i32, cpu: i32) -> Result<(), String> {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut();
+        if cpu as u64 >= nr_cpus {
+            return Err(format!("invalid cpu {} ({} CPUs online)", cpu, nr_cpus));
+        }
+        if !self.bpf.exists(pid) {
+            return Err(format!("pid {} doesn't exist", pid));
+        }
+        self.pins.insert(pid, cpu);
+        println!("pin: pid {} pinned to CPU {}", pid, cpu);
+
+        Ok(())
+    }
+
+    /// Boost the existing tasks among `pids` as the group `name` at `level` (see the boost
+    /// control command).
+    fn boost_group(&mut self, name: &str, level: u64, pids: &[i32]) -> Result<(), String> {
+        let (alive, exited): (Vec<i32>, Vec<i32>) =
+            pids.iter().partition(|&&pid| self.bpf.exists(pid));
+        if alive.is_empty() {
+            return Err(format!("none of the pids {:?} exists", pids));
+        }
+        println!(
+            "boost: group {} at level {}: pids {:?}{}",
+            name,
+            level,
+            alive,
+            if exited.is_empty() {
+                String::new()
+            } else {
+                format!(" (skipped {:?}, they don't exist)", exited)
+            }
+        );
+        self.boost_groups.set(name, level, alive);
+
+        Ok(())
+    }
+
+    /// Restore the regular CPU selection for the task `pid` (see the unpin control command).
+    fn unpin(&mut self, pid: i32) {
+        if self.pins.remove(&pid).is_some() {
+            println!("pin: pid {} unpinned", pid);
+        }
+    }
+
+    /// Reset the statistics and the learned state of the tasks (see the reset command), so that
+    /// a new measurement starts clean, without restarting the scheduler.
+    ///
+    /// The counters reported by the stats (the control socket, the metrics endpoint and the
+    /// JSON stats) start again from 0: the counters of the BPF component can't be reset in the
+    /// kernel, so they are reported relative to their value at the reset. Everything learned
+    /// about the tasks is dropped, as if all the tasks were new: statistics and classification,
+    /// virtual runtimes, scheduling hints, placement preferences (LLC domains of the processes,
+    /// NUMA home nodes, migration locks, waker CPUs) and time slice accounting.
+    ///
+    /// Not reset: the tasks waiting in the queues (with their virtual runtime and deadline), the
+    /// global virtual runtime, the pinned and boosted tasks, the current profile and policy, and
+    /// the state of the controllers that follow the load of the system (e.g.,
+    /// --latency-target-us, --mode auto, --failed-dispatch-thresh), that adapt on their own.
+    fn reset_stats(&mut self) {
+        self.counters_base = CountersBase {
+            nr_user_dispatches: *self.bpf.nr_user_dispatches_mut(),
+            nr_kernel_dispatches: *self.bpf.nr_kernel_dispatches_mut(),
+            nr_bounce_dispatches: *self.bpf.nr_bounce_dispatches_mut(),
+            nr_cancel_dispatches: *self.bpf.nr_cancel_dispatches_mut(),
+            nr_failed_dispatches: *self.bpf.nr_failed_dispatches_mut(),
+        };
+        self.nr_dispatch_retries = 0;
+        self.nr_dispatch_requeues = 0;
+        self.nr_starve_timeouts = 0;
+        self.nr_duplicate_pids = 0;
+        self.nr_cpuset_remaps = 0;
+        self.nr_dequeue_errors = 0;
+        self.nr_invalid_cpus = 0;
+        self.nr_dequeue_breaks = 0;
+        self.nr_budget_breaks = 0;
+        self.nr_idle_passes = 0;
+        self.nr_kthread_dispatches = 0;
+        self.nr_evicted_pids = 0;
+        self.latency = LatencyHistogram::new();
+        self.cpu_gaps = CpuGapStats::new(*self.bpf.nr_online_cpus_mut() as usize);
+
+        // Drop the state of all the tasks: the GC pass drops the per-task and per-process state
+        // kept by the other components along with it.
+        self.tasks.clear();
+        self.process_cpus.clear();
+        self.kthreads.clear();
+        self.gc_tasks();
+
+        println!("reset: statistics and learned state of the tasks cleared");
+    }
+
+    /// Return true if the stats need to be printed at time `now` (in seconds), given the
+    /// counters of dispatches at the previous stats: with --no-stats-on-idle, the intervals
+    /// without user-space and kernel dispatches are skipped, unless the stats haven't been
+    /// printed for IDLE_STATS_HEARTBEAT_SECS.
+    fn stats_due(
+        &mut self,
+        now: u64,
+        prev_user_dispatches: u64,
+        prev_kernel_dispatches: u64,
+    ) -> bool {
+        let idle = *self.bpf.nr_user_dispatches_mut() == prev_user_dispatches
+            && *self.bpf.nr_kernel_dispatches_mut() == prev_kernel_dispatches;
+        if self.opts.no_stats_on_idle
+            && idle
+            && now.saturating_sub(self.last_stats_ts) < IDLE_STATS_HEARTBEAT_SECS
+        {
+            return false;
+        }
+        self.last_stats_ts = now;
+
+        true
+    }
+
+    /// Print scheduling statistics.
+    fn print_stats(
+        &mut self,
+        prev_user_dispatches: u64,
+        prev_kernel_dispatches: u64,
+    ) -> (u64, u64) {
+        let nr_user_dispatches = *self.bpf.nr_user_dispatches_mut();
+        let nr_kernel_dispatches = *self.bpf.nr_kernel_dispatches_mut();
+
+        // Calculate the deltas for user and kernel dispatches.
+        //
+        // User dispatches refer to tasks scheduled in user-space, while kernel dispatches handle
+        // critical tasks executed internally by the scx_rustland_core framework.
+        let delta_user_dispatches = nr_user_dispatches - prev_user_dispatches;
+        let delta_kernel_dispatches = nr_kernel_dispatches - prev_kernel_dispatches;
+
+        println!(
+            "task dispatches/s -> user: {:<5} | kernel: {:<5}",
+            delta_user_dispatches, delta_kernel_dispatches,
+        );
+        self.dispatch_ratio
+            .sample(delta_user_dispatches, delta_kernel_dispatches);
+        self.dispatch_ratio.report();
+        self.batch_sizes.sample();
+        self.batch_sizes.report();
+        self.report_bad_dispatches(delta_user_dispatches);
+        self.report_failed_dispatches();
+
+        // Tasks waiting to be dispatched, in the BPF component and in the user-space queues.
+        let depth = *self.bpf.nr_queued_mut() + self.nr_pending();
+        let now = self.now_ns();
+        self.queue_trend.sample(now, depth, delta_user_dispatches);
+        self.queue_trend.report();
+
+        if self.nr_dispatch_retries > 0 {
+            println!(
+                "dispatch retries: {} | requeued: {}",
+                self.nr_dispatch_retries, self.nr_dispatch_requeues,
+            );
+        }
+
+        if self.nr_duplicate_pids > 0 {
+            println!("duplicate pids: {}", self.nr_duplicate_pids);
+        }
+
+        if self.nr_cpuset_remaps > 0 {
+            println!("cpuset remaps: {}", self.nr_cpuset_remaps);
+        }
+
+        if self.nr_dequeue_errors > 0 {
+            println!("dequeue errors: {}", self.nr_dequeue_errors);
+        }
+
+        if self.nr_invalid_cpus > 0 {
+            println!("invalid CPUs from select_cpu(): {}", self.nr_invalid_cpus);
+        }
+
+        if self.nr_dequeue_breaks > 0 {
+            println!(
+                "rounds cut by --max-dequeue-per-round: {}",
+                self.nr_dequeue_breaks
+            );
+        }
+
+        if self.nr_budget_breaks > 0 {
+            println!("rounds cut by --round-budget-us: {}", self.nr_budget_breaks);
+        }
+
+        if self.nr_idle_passes > 0 {
+            println!("idle housekeeping passes: {}", self.nr_idle_passes);
+        }
+
+        if let Some(loop_gap) = self.loop_gap.as_mut() {
+            loop_gap.report();
+        }
+
+        if self.opts.skip_kthreads {
+            println!("kernel threads dispatched: {}", self.nr_kthread_dispatches);
+        }
+
+        if let Some(udp_stats) = self.udp_stats.as_ref().filter(|udp| udp.nr_errors() > 0) {
+            println!("stats datagrams not sent: {}", udp_stats.nr_errors());
+        }
+
+        if let Some(invariants) = self.invariants.as_ref().filter(|i| i.nr_violations() > 0) {
+            println!(
+                "policy invariant violations: {}",
+                invariants.nr_violations()
+            );
+        }
+
+        if self.nr_evicted_pids > 0 {
+            println!(
+                "tracked pids: {} | evicted: {}",
+                self.tasks.len(),
+                self.nr_evicted_pids
+            );
+        }
+
+        if self.nr_starve_timeouts > 0 {
+            println!("starvation timeouts: {}", self.nr_starve_timeouts);
+        }
+
+        if let Some(weights) = self.weights.as_mut() {
+            weights.report();
+        }
+
+        if let Some(weight_classes) = self.weight_classes.as_mut() {
+            weight_classes.report();
+        }
+
+        if let Some(boost) = self.boost.as_mut() {
+            boost.report();
+        }
+
+        if let Some(activation) = self.activation.as_mut() {
+            activation.report();
+        }
+        if let Some(reservation) = self.reservation.as_mut() {
+            reservation.report();
+        }
+        if let Some(comm_caps) = self.comm_caps.as_mut() {
+            comm_caps.report();
+        }
+        if let Some(profiles) = self.profiles.as_ref() {
+            println!("profile: {}", profiles.active());
+        }
+
+        if let Some(overhead) = self.overhead.as_ref().filter(|_| self.opts.self_stats) {
+            overhead.report();
+        }
+        if let Some(target) = self.latency_target.as_mut() {
+            target.report();
+        }
+
+        if self.opts.cpu_gap_stats {
+            self.cpu_gaps.report();
+        }
+
+        if let Some(inversions) = self.inversions.as_mut() {
+            inversions.report();
+        }
+
+        if self.opts.io_boost.is_some() {
+            let boosts = self
+                .tasks
+                .keys()
+                .map(|&pid| self.io_boost_pct(pid))
+                .filter(|&pct| pct > 100);
+            let (nr_boosted, max_pct) =
+                boosts.fold((0, 100), |(nr, max), pct| (nr + 1, max.max(pct)));
+            println!(
+                "io boost: {} tasks boosted (max x{:.2})",
+                nr_boosted,
+                max_pct as f64 / 100.0
+            );
+        }
+
+        if let Some(shadow) = self.shadow.as_mut() {
+            shadow.report();
+        }
+
+        if let Some(llc) = self.llc.as_mut() {
+            llc.report();
+        }
+
+        if let Some(accounting) = self.accounting.as_mut() {
+            accounting.report();
+        }
+
+        if let Some(cache) = self.cache.as_mut() {
+            cache.report();
+        }
+        if let Some(numa) = self.numa.as_mut() {
+            numa.report();
+        }
+
+        if let Some(affinity) = self.numa_affinity.as_mut() {
+            affinity.report();
+        }
+
+        if let Some(migration) = self.migration.as_mut() {
+            migration.report();
+        }
+
+        if let Some(affinity) = self.wake_affinity.as_mut() {
+            affinity.report();
+        }
+        if let Some(idle) = self.idle.as_mut() {
+            idle.report();
+        }
+
+        if let Some(top_view) = self.top_view.as_mut() {
+            // A failure to draw the load bars must not stop the scheduler.
+            let _ = top_view.draw(now);
+        }
+
+        // Return the current values to update the previous ones in the next iteration.
+        (nr_user_dispatches, nr_kernel_dispatches)
+    }
+
+    /// Report the dispatches bounced or cancelled by the BPF component since the previous stats
+    /// interval, given the amount of user-space dispatches in the same interval.
+    ///
+    /// A dispatch is bounced to the shared DSQ when the target CPU can't be used by the task
+    /// (not in its affinity mask or offline) and it is cancelled when the task is dequeued or
+    /// changes (e.g., its affinity) while being dispatched: both usually point to bad placement
+    /// decisions, so a hint is printed when they exceed BAD_DISPATCH_HINT_PCT of the dispatches.
+    fn report_bad_dispatches(&mut self, delta_user_dispatches: u64) {
+        let nr_bounce_dispatches = *self.bpf.nr_bounce_dispatches_mut();
+        let nr_cancel_dispatches = *self.bpf.nr_cancel_dispatches_mut();
+        let delta_bounce = nr_bounce_dispatches.saturating_sub(self.prev_bounce_dispatches);
+        let delta_cancel = nr_cancel_dispatches.saturating_sub(self.prev_cancel_dispatches);
+        let elevated =
+            |delta: u64| delta > 0 && delta * 100 >= delta_user_dispatches * BAD_DISPATCH_HINT_PCT;
+
+        self.prev_bounce_dispatches = nr_bounce_dispatches;
+        self.prev_cancel_dispatches = nr_cancel_dispatches;
+        if delta_bounce == 0 && delta_cancel == 0 {
+            return;
+        }
+
+        println!(
+            "WARNING: bad dispatches/s -> bounced: {:<5} | cancelled: {:<5}",
+            delta_bounce, delta_cancel,
+        );
+        if elevated(delta_bounce) {
+            println!(
+                "hint: tasks are dispatched to CPUs they can't use: check the affinity and the \
+                 cpusets of the tasks (see --cpuset-aware) and the offline CPUs (see \
+                 --cpus-offline)"
+            );
+        }
+        if elevated(delta_cancel) {
+            println!(
+                "hint: tasks change while being dispatched: check for tasks that frequently \
+                 change their affinity or that are migrated by the kernel"
+            );
+        }
+    }
+
+    /// Report the dispatches failed by the BPF component since the previous stats interval and
+    /// whether the scheduler reacts to them (see --failed-dispatch-thresh).
+    fn report_failed_dispatches(&mut self) {
+        let nr_failed_dispatches = *self.bpf.nr_failed_dispatches_mut();
+        let delta_failed = nr_failed_dispatches.saturating_sub(self.prev_failed_dispatches);
+        self.prev_failed_dispatches = nr_failed_dispatches;
+
+        match &self.backpressure {
+            Some(backpressure) => backpressure.report(),
+            None if delta_failed > 0 => println!(
+                "failed dispatches/s: {} | auto-response: disabled",
+                delta_failed
+            ),
+            None => {}
+        }
+    }
+
+    /// Return the current timestamp in nanoseconds.
+    fn now_ns(&self) -> u64 {
+        self.bpf.now_ns()
+    }
+
+    /// Return the current timestamp in seconds.
+    fn now(&self) -> u64 {
+        self.now_ns() / NSEC_PER_SEC
+    }
+}
+
+/// Return the moving average `avg` updated with `sample` (3/4 of the previous average), without
+/// overflowing with the values of a malformed task.
+fn ewma(avg: u64, sample: u64) -> u64 {
+    avg.saturating_mul(3).saturating_add(sample) / 4
+}
+
+/// Parse a percentage in (0, 100], possibly fractional (e.g., 0.5).
+fn parse_pct(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(pct) if pct > 0.0 && pct <= 100.0 => Ok(pct),
+        _ => Err(format!("'{}' is not a percentage in (0, 100]", value)),
+    }
+}
+
+/// Return the mask of the online CPUs `cpus`, or of the first `nr_cpus` CPUs if the online CPUs
+/// are unknown.
+fn online_mask(cpus: Option<Vec<usize>>, nr_cpus: usize) -> Vec<bool> {
+    let Some(cpus) = cpus else {
+        return vec![true; nr_cpus];
+    };
+    let mut mask = vec![false; nr_cpus];
+    for cpu in cpus.into_iter().filter(|&cpu| cpu < nr_cpus) {
+        mask[cpu] = true;
+    }
+
+    mask
+}
+
+/// Entry point of the scheduler (see main.rs).
+pub fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    if let Some(Command::Validate(validate)) = &opts.command {
+        return validate::run(&opts, validate);
+    }
+    if let Some(err) = validate::errors(&opts).into_iter().next() {
+        bail!(err);
+    }
+    if opts.debug_invariants.is_some() && !invariants::COMPILED {
+        println!(
+            "WARNING: --debug-invariants is not available in this build (rebuild with \
+             --features invariants), ignoring it"
+        );
+    }
+    if opts.check {
+        return diagnose::run_check();
+    }
+    if opts.selftest {
+        return selftest::run(&opts);
+    }
+    match &opts.command {
+        Some(Command::Sweep(sweep)) => return sweep::run(&opts, sweep),
+        Some(Command::Replay(replay)) => return replay::run(replay),
+        Some(Command::Validate(_)) | None => {}
+    }
+
+    // Start the metrics endpoint, the control socket and the scx_stats server only once, so
+    // that they survive scheduler restarts.
+    let metrics = opts
+        .metrics_addr
+        .as_deref()
+        .map(|addr| MetricsServer::start(addr, opts.metrics_dashboard))
+        .transpose()?;
+    let control = opts
+        .control_socket
+        .as_deref()
+        .map(ControlServer::start)
+        .transpose()?;
+    #[cfg(feature = "stats")]
+    let scx_stats = ScxStats::start(SCX_STATS_PATH)?;
+    let notifier = Notifier::detect();
+
+    dump::install_signal_handler();
+    if !opts.profile.is_empty() {
+        profiles::install_signal_handler();
+    }
+
+    let mut open_object = MaybeUninit::uninit();
+    loop {
+        let mut sched = Scheduler::init(
+            &opts,
+            metrics.as_ref(),
+            control.as_ref(),
+            notifier.as_ref(),
+            &mut open_object,
+        )
+        .map_err(diagnose::annotate)?;
+        #[cfg(feature = "stats")]
+        {
+            sched.scx_stats = Some(&scx_stats);
+        }
+        if !sched.run()? {
+            break;
+        }
+    }
+    if let Some(notifier) = &notifier {
+        notifier.stopping();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use scx_utils::Topology;
+
+/// Return the CPUs of each last-level cache (LLC) domain of the system.
+pub fn topology_domains() -> Result<Vec<Vec<usize>>> {
+    let topo = Topology::new()?;
+    let mut domains: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+    for (&id, cpu) in topo.cpus() {
+        domains.entry(cpu.llc_id()).or_default().push(id);
+    }
+
+    Ok(domains.into_values().collect())
+}
+
+/// Assignment of the processes to the last-level cache (LLC) domains (see --llc-group).
+///
+/// All the threads of a process (tasks with the same tgid) are confined to the same LLC domain,
+/// to maximize the shared cache hits, while different processes are spread across the domains:
+/// each new process is assigned to the domain with the least processes.
+pub struct LlcDomains {
+    domains: Vec<Vec<usize>>,       // CPUs of each domain
+    cpu_domain: Vec<Option<usize>>, // Domain of each CPU
+    tgids: HashMap<i32, usize>,     // Domain assigned to each process
+    nr_tgids: Vec<u64>,             // Processes assigned to each domain
+    next_cpu: Vec<usize>,           // Next CPU used for round-robin placement in each domain
+    nr_dispatches: u64,             // Dispatches in the current interval
+    nr_cross: u64,                  // Cross-domain dispatches in the current interval
+}
+
+impl LlcDomains {
+    /// Create the assignment from the CPUs of each domain (empty domains are ignored).
+    pub fn new(domains: Vec<Vec<usize>>) -> Self {
+        let domains: Vec<Vec<usize>> = domains.into_iter().filter(|d| !d.is_empty()).collect();
+        let mut cpu_domain = Vec::new();
+
+        for (domain, cpus) in domains.iter().enumerate() {
+            for &cpu in cpus {
+                if cpu >= cpu_domain.len() {
+                    cpu_domain.resize(cpu + 1, None);
+                }
+                cpu_domain[cpu] = Some(domain);
+            }
+        }
+
+        Self {
+            nr_tgids: vec![0; domains.len()],
+            next_cpu: vec![0; domains.len()],
+            domains,
+            cpu_domain,
+            tgids: HashMap::new(),
+            nr_dispatches: 0,
+            nr_cross: 0,
+        }
+    }
+
+    /// Return the domain of a CPU (None if the CPU doesn't belong to any domain).
+    pub fn cpu_domain(&self, cpu: i32) -> Option<usize> {
+        usize::try_from(cpu)
+            .ok()
+            .and_then(|cpu| self.cpu_domain.get(cpu).copied().flatten())
+    }
+
+    /// Return the domain assigned to a process, assigning one if needed.
+    pub fn domain(&mut self, tgid: i32) -> Option<usize> {
+        if let Some(&domain) = self.tgids.get(&tgid) {
+            return Some(domain);
+        }
+        let domain = (0..self.domains.len()).min_by_key(|&d| self.nr_tgids[d])?;
+        self.nr_tgids[domain] += 1;
+        self.tgids.insert(tgid, domain);
+
+        Some(domain)
+    }
+
+    /// Return the CPU where a thread of a process should run: its previously used CPU, if it
+    /// belongs to the domain of the process, otherwise the next CPU of the domain (in
+    /// round-robin order).
+    pub fn pick_cpu(&mut self, tgid: i32, prev_cpu: i32) -> Option<i32> {
+        let domain = self.domain(tgid)?;
+        if self.cpu_domain(prev_cpu) == Some(domain) {
+            return Some(prev_cpu);
+        }
+        let cpus = &self.domains[domain];
+        let cpu = cpus[self.next_cpu[domain] % cpus.len()];
+        self.next_cpu[domain] = (self.next_cpu[domain] + 1) % cpus.len();
+
+        Some(cpu as i32)
+    }
+
+    /// Account a task dispatched to `cpu`, previously running on `prev_cpu`: the dispatch is
+    /// cross-domain if the two CPUs belong to different domains.
+    pub fn record_dispatch(&mut self, prev_cpu: i32, cpu: i32) {
+        self.nr_dispatches += 1;
+        if let (Some(prev), Some(curr)) = (self.cpu_domain(prev_cpu), self.cpu_domain(cpu)) {
+            if prev != curr {
+                self.nr_cross += 1;
+            }
+        }
+    }
+
+    /// Drop the processes that are not in `alive` anymore.
+    pub fn retain(&mut self, alive: &HashSet<i32>) {
+        let nr_tgids = &mut self.nr_tgids;
+
+        self.tgids.retain(|tgid, domain| {
+            let keep = alive.contains(tgid);
+            if !keep {
+                nr_tgids[*domain] -= 1;
+            }
+            keep
+        });
+    }
+
+    /// Print the cross-domain dispatch rate of the current interval and start a new one.
+    pub fn report(&mut self) {
+        let pct = (self.nr_cross * 100)
+            .checked_div(self.nr_dispatches)
+            .unwrap_or(0);
+        let nr_tgids: Vec<String> = self.nr_tgids.iter().map(|n| n.to_string()).collect();
+
+        println!(
+            "llc domains: {} | processes: [{}] | cross-domain dispatches/s: {} ({}%)",
+            self.domains.len(),
+            nr_tgids.join(","),
+            self.nr_cross,
+            pct
+        );
+        self.nr_dispatches = 0;
+        self.nr_cross = 0;
+    }
+}
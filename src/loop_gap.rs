@@ -0,0 +1,86 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+/// Detection of the scheduler being starved (see --loop-gap-ms).
+///
+/// The scheduler needs to run to dispatch the tasks: if it doesn't get the CPU for a long time
+/// (e.g., preempted by higher priority tasks or by the kernel), all the tasks waiting in user
+/// space stall with it. The duration of each iteration of the main loop is measured with the
+/// monotonic clock, excluding the time slept in notify_complete() waiting for new tasks (an
+/// idle system isn't a starved scheduler), and an iteration longer than the threshold is
+/// reported as a gap.
+pub struct LoopGap {
+    thresh_ns: u64,  // Duration of an iteration that reveals a gap
+    start_ts: u64,   // Start of the current iteration
+    sleep_ns: u64,   // Time slept in notify_complete() in the current iteration
+    nr_gaps: u64,    // Gaps detected since the last report
+    max_gap_ns: u64, // Longest gap since the last report
+    boosted: bool,   // The priority of the scheduler has been raised
+}
+
+impl LoopGap {
+    pub fn new(thresh_ns: u64, now: u64) -> Self {
+        Self {
+            thresh_ns,
+            start_ts: now,
+            sleep_ns: 0,
+            nr_gaps: 0,
+            max_gap_ns: 0,
+            boosted: false,
+        }
+    }
+
+    /// Account `duration_ns` slept in notify_complete() in the current iteration.
+    pub fn sleep(&mut self, duration_ns: u64) {
+        self.sleep_ns += duration_ns;
+    }
+
+    /// Complete the current iteration at time `now` and start a new one: return the duration of
+    /// the iteration (excluding the sleeps) if it reveals a gap.
+    pub fn sample(&mut self, now: u64) -> Option<u64> {
+        let gap_ns = now
+            .saturating_sub(self.start_ts)
+            .saturating_sub(self.sleep_ns);
+        self.start_ts = now;
+        self.sleep_ns = 0;
+        if gap_ns <= self.thresh_ns {
+            return None;
+        }
+        self.nr_gaps += 1;
+        self.max_gap_ns = self.max_gap_ns.max(gap_ns);
+
+        Some(gap_ns)
+    }
+
+    /// Return true the first time the scheduler needs to raise its own priority (it is raised
+    /// only once).
+    pub fn needs_boost(&mut self) -> bool {
+        if self.boosted {
+            return false;
+        }
+        self.boosted = true;
+
+        true
+    }
+
+    /// Return the gaps detected since the last report.
+    pub fn nr_gaps(&self) -> u64 {
+        self.nr_gaps
+    }
+
+    /// Print the gaps detected since the last report (if any) and reset them.
+    pub fn report(&mut self) {
+        if self.nr_gaps == 0 {
+            return;
+        }
+        println!(
+            "scheduler gaps above --loop-gap-ms: {} (max: {}ms)",
+            self.nr_gaps,
+            self.max_gap_ns / 1_000_000
+        );
+        self.nr_gaps = 0;
+        self.max_gap_ns = 0;
+    }
+}
@@ -0,0 +1,54 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+/// Tracked tasks, ordered by the last time they have been seen (see --max-tracked-pids), so that
+/// the least recently seen one can be found in O(log n) when the cap is exceeded.
+pub struct PidLru {
+    order: BTreeSet<(u64, i32)>, // Tracked tasks (last time seen and pid)
+    seen: HashMap<i32, u64>,     // Last time each tracked task has been seen
+}
+
+impl PidLru {
+    pub fn new() -> Self {
+        Self {
+            order: BTreeSet::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Account the task `pid` seen at time `now` (tracking it, if it is not tracked yet).
+    pub fn touch(&mut self, pid: i32, now: u64) {
+        if let Some(ts) = self.seen.insert(pid, now) {
+            self.order.remove(&(ts, pid));
+        }
+        self.order.insert((now, pid));
+    }
+
+    /// Stop tracking the tasks for which `alive` returns false.
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.seen.retain(|&pid, _| alive(pid));
+        self.order.retain(|&(_, pid)| alive(pid));
+    }
+
+    /// Stop tracking the task `pid`.
+    pub fn remove(&mut self, pid: i32) {
+        if let Some(ts) = self.seen.remove(&pid) {
+            self.order.remove(&(ts, pid));
+        }
+    }
+
+    /// Return the tracked tasks, from the least recently seen one.
+    pub fn coldest(&self) -> impl Iterator<Item = i32> + '_ {
+        self.order.iter().map(|&(_, pid)| pid)
+    }
+
+    /// Return the amount of tracked tasks.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+}
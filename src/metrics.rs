@@ -0,0 +1,254 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt::Write as _;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+
+// Upper bounds (in nanoseconds) of the scheduling latency histogram buckets, latencies above the
+// last bound are accounted in the +Inf bucket.
+const LATENCY_BUCKETS_NS: [u64; 10] = [
+    50_000,
+    100_000,
+    250_000,
+    500_000,
+    1_000_000,
+    2_500_000,
+    5_000_000,
+    10_000_000,
+    25_000_000,
+    100_000_000,
+];
+const NR_LATENCY_BUCKETS: usize = LATENCY_BUCKETS_NS.len() + 1;
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// Maximum time that a client can take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Maximum size of a request (request line and headers), the rest is ignored.
+const MAX_REQUEST_SIZE: u64 = 16384;
+
+// Self-contained dashboard served on / (see --metrics-dashboard).
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+// Sample attached to a histogram bucket.
+#[derive(Debug, Clone, Copy)]
+struct Exemplar {
+    pid: i32,      // Task that has been observed
+    value_ns: u64, // Observed latency (in nanoseconds)
+}
+
+/// Histogram of the scheduling latency, measured as the time that tasks spend in the user-space
+/// queues before being dispatched.
+///
+/// Each bucket keeps an exemplar: the most recent sample (pid and latency) that fell in that
+/// bucket, i.e., the first bucket whose upper bound is greater than or equal to the observed
+/// latency. Exemplars allow to correlate a latency spike with a specific task.
+pub struct LatencyHistogram {
+    buckets: [u64; NR_LATENCY_BUCKETS],
+    exemplars: [Option<Exemplar>; NR_LATENCY_BUCKETS],
+    sum_ns: u64,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: [0; NR_LATENCY_BUCKETS],
+            exemplars: [None; NR_LATENCY_BUCKETS],
+            sum_ns: 0,
+            count: 0,
+        }
+    }
+
+    /// Account a latency sample (in nanoseconds) observed for task `pid`.
+    pub fn record(&mut self, pid: i32, latency_ns: u64) {
+        let bucket = LATENCY_BUCKETS_NS
+            .iter()
+            .position(|&bound| latency_ns <= bound)
+            .unwrap_or(NR_LATENCY_BUCKETS - 1);
+
+        self.buckets[bucket] += 1;
+        self.exemplars[bucket] = Some(Exemplar {
+            pid,
+            value_ns: latency_ns,
+        });
+        self.sum_ns += latency_ns;
+        self.count += 1;
+    }
+
+    /// Return the amount of samples and their sum (in nanoseconds).
+    pub fn total(&self) -> (u64, u64) {
+        (self.count, self.sum_ns)
+    }
+
+    /// Return the upper bound (in nanoseconds) of the bucket that contains the `pct`-th
+    /// percentile of the samples (u64::MAX for the +Inf bucket, None without samples).
+    pub fn percentile_ns(&self, pct: u64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count * pct).div_ceil(100).max(1);
+        let mut cumulative = 0;
+        let bucket = self.buckets.iter().position(|count| {
+            cumulative += count;
+            cumulative >= rank
+        })?;
+
+        Some(LATENCY_BUCKETS_NS.get(bucket).copied().unwrap_or(u64::MAX))
+    }
+
+    /// Append the histogram to `out` in OpenMetrics text format, optionally including the
+    /// exemplars.
+    pub fn write_openmetrics(&self, out: &mut String, name: &str, help: &str, exemplars: bool) {
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let _ = writeln!(out, "# UNIT {} seconds", name);
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+
+        let mut cumulative = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            cumulative += count;
+
+            let le = match LATENCY_BUCKETS_NS.get(i) {
+                Some(bound) => ns_to_secs(*bound),
+                None => "+Inf".to_string(),
+            };
+            let _ = write!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+            if let Some(exemplar) = self.exemplars[i].filter(|_| exemplars) {
+                let _ = write!(
+                    out,
+                    " # {{pid=\"{}\"}} {}",
+                    exemplar.pid,
+                    ns_to_secs(exemplar.value_ns)
+                );
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}_sum {}", name, ns_to_secs(self.sum_ns));
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+// Format a time in nanoseconds as seconds.
+fn ns_to_secs(ns: u64) -> String {
+    format!("{}.{:09}", ns / 1_000_000_000, ns % 1_000_000_000)
+}
+
+/// Append a counter to `out` in OpenMetrics text format.
+pub fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "{}_total {}", name, value);
+}
+
+// Data published by the scheduler.
+struct Published {
+    text: String,  // Last rendered metrics
+    stats: String, // Last stats snapshot (JSON)
+}
+
+/// Minimal HTTP server that exposes the scheduler metrics on /metrics.
+///
+/// With --metrics-dashboard, it also serves a self-contained HTML dashboard on / and the last
+/// stats snapshot in JSON format on /api/stats (used by the dashboard).
+///
+/// The metrics are periodically rendered by the scheduler (see update()) and served from a
+/// separate thread, so that scraping never interferes with the scheduling activity. Each
+/// connection is handled by its own thread and closed after the response, so that a slow (or
+/// stuck) client never delays the others.
+pub struct MetricsServer {
+    published: Arc<Mutex<Published>>, // Last data published by the scheduler
+}
+
+impl MetricsServer {
+    /// Start serving the metrics on `addr` (e.g., "127.0.0.1:9000"), optionally along with the
+    /// dashboard.
+    pub fn start(addr: &str, dashboard: bool) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Failed to bind to {}", addr))?;
+        let published = Arc::new(Mutex::new(Published {
+            text: String::from("# EOF\n"),
+            stats: String::from("{}"),
+        }));
+
+        let shared = published.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = shared.clone();
+                thread::spawn(move || Self::handle(stream, &shared, dashboard));
+            }
+        });
+
+        Ok(Self { published })
+    }
+
+    /// Replace the metrics served by the endpoint.
+    pub fn update(&self, mut text: String) {
+        text.push_str("# EOF\n");
+        self.published.lock().unwrap().text = text;
+    }
+
+    /// Replace the stats snapshot served on /api/stats.
+    pub fn update_stats(&self, json: String) {
+        self.published.lock().unwrap().stats = json;
+    }
+
+    fn handle(
+        mut stream: TcpStream,
+        published: &Mutex<Published>,
+        dashboard: bool,
+    ) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new((&stream).take(MAX_REQUEST_SIZE));
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // Consume the headers up to the blank line: closing the connection with part of the
+        // request still unread would reset it, possibly before the client reads the response.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+            header.clear();
+        }
+
+        let path = request.split_whitespace().nth(1).unwrap_or("");
+        let (content_type, body) = match path {
+            "/metrics" => (
+                OPENMETRICS_CONTENT_TYPE,
+                published.lock().unwrap().text.clone(),
+            ),
+            "/" | "/index.html" if dashboard => {
+                ("text/html; charset=utf-8", DASHBOARD_HTML.to_string())
+            }
+            "/api/stats" if dashboard => {
+                ("application/json", published.lock().unwrap().stats.clone())
+            }
+            _ => {
+                return stream.write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+            }
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        )
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+// Dispatches to the same CPU in a scheduling round from which the CPU is considered badly
+// overloaded: a locked task is allowed to migrate away from it, instead of waiting behind the
+// other tasks.
+pub const MIGRATION_OVERLOAD: u64 = 4;
+
+/// Damping of the task migrations (see --migration-lock-us).
+///
+/// Once a task migrates to a different CPU, it is locked to the new CPU for `lock_ns`: any
+/// further migration in the meantime is cancelled and the task is dispatched to its previous
+/// CPU again, unless that CPU is badly overloaded (at least MIGRATION_OVERLOAD dispatches in
+/// the current round). The tasks dispatched to the first CPU available (RL_CPU_ANY) are placed
+/// by the kernel and are never locked.
+pub struct MigrationLock {
+    lock_ns: u64,               // Time a task stays on the CPU where it migrated
+    last_ts: HashMap<i32, u64>, // Last migration of each task
+    round_dispatches: Vec<u64>, // Dispatches to each CPU in the current round
+    nr_migrations: u64,         // Migrations allowed (since the last report)
+    nr_damped: u64,             // Migrations cancelled (since the last report)
+    nr_overloaded: u64,         // Locks broken by an overloaded CPU (since the last report)
+}
+
+impl MigrationLock {
+    pub fn new(lock_ns: u64) -> Self {
+        Self {
+            lock_ns,
+            last_ts: HashMap::new(),
+            round_dispatches: Vec::new(),
+            nr_migrations: 0,
+            nr_damped: 0,
+            nr_overloaded: 0,
+        }
+    }
+
+    /// Start a new scheduling round.
+    pub fn begin_round(&mut self) {
+        self.round_dispatches.iter_mut().for_each(|nr| *nr = 0);
+    }
+
+    /// Return the CPU where a task needs to run, given its previously used CPU `prev_cpu` (a
+    /// usable CPU, or negative if unknown) and the CPU selected for it, `cpu`, at time `now`.
+    pub fn pick_cpu(&mut self, pid: i32, prev_cpu: i32, cpu: i32, now: u64) -> i32 {
+        if prev_cpu < 0 || cpu < 0 || cpu == prev_cpu {
+            return cpu;
+        }
+        let is_locked = self
+            .last_ts
+            .get(&pid)
+            .is_some_and(|&ts| now.saturating_sub(ts) < self.lock_ns);
+        if is_locked {
+            let nr_dispatches = self.round_dispatches.get(prev_cpu as usize).copied();
+            if nr_dispatches.unwrap_or(0) < MIGRATION_OVERLOAD {
+                self.nr_damped += 1;
+                return prev_cpu;
+            }
+            self.nr_overloaded += 1;
+        }
+        self.last_ts.insert(pid, now);
+        self.nr_migrations += 1;
+
+        cpu
+    }
+
+    /// Account a task dispatched to the CPU `cpu` (negative for RL_CPU_ANY).
+    pub fn record_dispatch(&mut self, cpu: i32) {
+        let Ok(cpu) = usize::try_from(cpu) else {
+            return;
+        };
+        if cpu >= self.round_dispatches.len() {
+            self.round_dispatches.resize(cpu + 1, 0);
+        }
+        self.round_dispatches[cpu] += 1;
+    }
+
+    /// Return the migrations allowed and cancelled since the last report.
+    pub fn migrations(&self) -> (u64, u64) {
+        (self.nr_migrations, self.nr_damped)
+    }
+
+    /// Drop the state of the tasks that are not tracked anymore.
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.last_ts.retain(|&pid, _| alive(pid));
+    }
+
+    /// Print the migrations and the reduction of the migration rate and reset them.
+    pub fn report(&mut self) {
+        let nr_attempts = self.nr_migrations + self.nr_damped;
+        let pct = (self.nr_damped * 100).checked_div(nr_attempts).unwrap_or(0);
+
+        println!(
+            "migration lock: migrations/s: {} | damped: {} ({}% fewer) | overloaded: {}",
+            self.nr_migrations, self.nr_damped, pct, self.nr_overloaded
+        );
+        self.nr_migrations = 0;
+        self.nr_damped = 0;
+        self.nr_overloaded = 0;
+    }
+}
@@ -0,0 +1,513 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use crate::backend::Dispatch;
+use crate::backend::DispatchError;
+use crate::backend::SchedBackend;
+use crate::backend::Task;
+use crate::NSEC_PER_SEC;
+
+/// Mock scheduling backend, used to drive the scheduling policy without attaching to the kernel.
+///
+/// Tasks are injected with enqueue() and the dispatched tasks can be collected with
+/// take_dispatched(). Time is simulated: it only moves forward via advance().
+///
+/// Congestion can be simulated with fail_dispatches(): the next dispatch attempts fail with
+/// DispatchError::Busy (or every other one, with flaky_dispatches()). Errors of the BPF
+/// component can be simulated with fail_dequeues(): the next calls to dequeue_task() fail with
+/// -EIO, without consuming any task.
+///
+/// Tasks are single-threaded processes (tgid = pid), unless they are grouped with set_tgid(),
+/// they can run on any CPU, unless they are confined with set_allowed_cpus(), and they don't set
+/// any scheduling hint, unless it is set with set_env_hint(); tasks terminated with exit_task()
+/// don't exist anymore (tgid() and allowed_cpus() return None). The start time of the tasks is
+/// unknown, like with scx_rustland_core, unless it is reported with set_run_start(), and so is
+/// their waker, unless it is reported with set_waker().
+///
+/// With closed_loop(), the mock also simulates the execution of the dispatched tasks, so that it
+/// can drive the main loop of the scheduler on its own (see Scheduler::run_loop()): each
+/// notify_complete() completes a round of `round_ns`, re-queueing the tasks dispatched in the
+/// round (as CPU hogs that used their entire time slice, see also scale_runtime()), and exited()
+/// returns true after `nr_rounds` rounds.
+///
+/// A kernel that stalls the scheduler can be simulated with stall_notify(): the next calls to
+/// notify_complete() block for a given time (the simulated clock moves forward), and an expensive
+/// scheduling round with set_call_cost(): each call to dequeue_task() and dispatch_task() moves
+/// the simulated clock forward.
+///
+/// select_cpu() returns the previously used CPU if it is still idle in the current round,
+/// otherwise the first idle CPU, or -EBUSY if all the CPUs have already been assigned. The
+/// allowed CPUs of the tasks are ignored, so that the scheduler can be verified to enforce them:
+/// tasks dispatched to a CPU outside of their allowed CPUs are accounted as bounced dispatches
+/// (see also force_select_cpu() and set_cpu_offline() to simulate a hotplug event).
+pub struct MockBackend {
+    now_ns: u64,                        // Simulated clock
+    boot_id: u64,                       // Simulated boot of the system (see reboot())
+    self_cpu_ns: u64,                   // Simulated CPU time of the scheduler
+    queued: VecDeque<Task>,             // Tasks waiting to be consumed by the scheduler
+    dispatched: Vec<Dispatch>,          // Tasks dispatched by the scheduler
+    selected: Vec<i32>,                 // Tasks passed to select_cpu() by the scheduler
+    busy: Vec<bool>,                    // CPUs assigned in the current round
+    nr_fail: u64,                       // Amount of dispatch attempts that still need to fail
+    nr_flaky: u64,                      // Dispatch attempts left that alternately fail
+    nr_drop: u64,                       // Dispatches that still need to fail in the BPF component
+    endless: Option<(Task, u64)>,       // Task returned once the queue is empty, and times left
+    nr_dequeue_fail: u64,               // Amount of dequeue_task() calls that still need to fail
+    saturated: bool,                    // No idle CPU available (see saturate())
+    tgids: HashMap<i32, i32>,           // Thread group of the tasks (see set_tgid())
+    parent_cpus: HashMap<i32, i32>,     // CPU of the parent of the tasks (see set_parent_cpu())
+    kthreads: HashSet<i32>,             // Kernel threads (see set_kthread())
+    exited_pids: HashSet<i32>,          // Tasks that exited (see exit_task())
+    allowed: HashMap<i32, Vec<usize>>,  // CPUs the tasks can use (see set_allowed_cpus())
+    hints: HashMap<(i32, String), u64>, // Scheduling hints of the tasks (see set_env_hint())
+    comms: HashMap<i32, String>,        // Names of the tasks (see set_comm())
+    run_starts: HashMap<i32, u64>,      // Start time of the tasks (see set_run_start())
+    wakers: HashMap<i32, i32>,          // Waker of the tasks (see set_waker())
+    offline: HashSet<usize>,            // CPUs unplugged (see set_cpu_offline())
+    isolated: Vec<usize>,               // CPUs isolated (see set_isolated_cpus())
+    forced_cpu: Option<i32>,            // CPU returned by select_cpu() (see force_select_cpu())
+    closed_loop: Option<(u64, u64)>,    // Round duration and rounds left (see closed_loop())
+    stall: Option<(u64, u64)>,          // Stall duration and calls left (see stall_notify())
+    call_cost_ns: u64,                  // Time spent in each call (see set_call_cost())
+    nr_self_boosts: u64,                // Calls to boost_self()
+    runtime_pct: u64,                   // CPU time used per time slice (see scale_runtime())
+    consumed: HashMap<i32, Task>,       // Tasks consumed by the scheduler (closed loop)
+    running: Vec<Task>,                 // Tasks dispatched in the current round (closed loop)
+    nr_online_cpus: u64,
+    nr_queued: u64,
+    nr_user_dispatches: u64,
+    nr_kernel_dispatches: u64,
+    nr_bounce_dispatches: u64,
+    nr_cancel_dispatches: u64,
+    nr_failed_dispatches: u64,
+}
+
+impl MockBackend {
+    pub fn new(nr_cpus: u64) -> Self {
+        Self {
+            now_ns: 0,
+            boot_id: 0,
+            self_cpu_ns: 0,
+            queued: VecDeque::new(),
+            dispatched: Vec::new(),
+            selected: Vec::new(),
+            busy: vec![false; nr_cpus as usize],
+            nr_fail: 0,
+            nr_flaky: 0,
+            nr_drop: 0,
+            endless: None,
+            nr_dequeue_fail: 0,
+            saturated: false,
+            tgids: HashMap::new(),
+            parent_cpus: HashMap::new(),
+            kthreads: HashSet::new(),
+            exited_pids: HashSet::new(),
+            allowed: HashMap::new(),
+            hints: HashMap::new(),
+            comms: HashMap::new(),
+            run_starts: HashMap::new(),
+            wakers: HashMap::new(),
+            offline: HashSet::new(),
+            isolated: Vec::new(),
+            forced_cpu: None,
+            closed_loop: None,
+            stall: None,
+            call_cost_ns: 0,
+            nr_self_boosts: 0,
+            runtime_pct: 100,
+            consumed: HashMap::new(),
+            running: Vec::new(),
+            nr_online_cpus: nr_cpus,
+            nr_queued: 0,
+            nr_user_dispatches: 0,
+            nr_kernel_dispatches: 0,
+            nr_bounce_dispatches: 0,
+            nr_cancel_dispatches: 0,
+            nr_failed_dispatches: 0,
+        }
+    }
+
+    /// Queue a task to be consumed by the scheduler.
+    pub fn enqueue(&mut self, task: Task) {
+        self.queued.push_back(task);
+    }
+
+    /// Return all the tasks dispatched since the last call.
+    pub fn take_dispatched(&mut self) -> Vec<Dispatch> {
+        std::mem::take(&mut self.dispatched)
+    }
+
+    /// Return the tasks passed to select_cpu() since the last call.
+    pub fn take_selected(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.selected)
+    }
+
+    /// Make the next `nr` dispatch attempts fail with DispatchError::Busy.
+    pub fn fail_dispatches(&mut self, nr: u64) {
+        self.nr_fail = nr;
+    }
+
+    /// Make the next `nr` dispatch attempts alternately fail with DispatchError::Busy and
+    /// succeed, starting with a failure (a BPF component that drains its queue concurrently).
+    pub fn flaky_dispatches(&mut self, nr: u64) {
+        self.nr_flaky = nr;
+    }
+
+    /// Make the next `nr` calls to dequeue_task() fail with -EIO.
+    pub fn fail_dequeues(&mut self, nr: u64) {
+        self.nr_dequeue_fail = nr;
+    }
+
+    /// Make dequeue_task() return `task` again `nr` times once the queue is empty, like a BPF
+    /// component that never drains its queue (see endless_left()).
+    pub fn endless_dequeue(&mut self, task: Task, nr: u64) {
+        self.endless = Some((task, nr));
+    }
+
+    /// Return how many times the task set by endless_dequeue() still needs to be returned.
+    pub fn endless_left(&self) -> u64 {
+        self.endless.as_ref().map_or(0, |(_, nr)| *nr)
+    }
+
+    /// Make the next `nr` dispatches fail in the BPF component: they are accepted, but the tasks
+    /// are never dispatched and they are counted as failed dispatches.
+    pub fn drop_dispatches(&mut self, nr: u64) {
+        self.nr_drop = nr;
+    }
+
+    /// Make all the CPUs busy: select_cpu() never finds an idle CPU.
+    pub fn saturate(&mut self) {
+        self.saturated = true;
+    }
+
+    /// Make `cpu` the CPU where the parent of the task `pid` last ran.
+    pub fn set_parent_cpu(&mut self, pid: i32, cpu: i32) {
+        self.parent_cpus.insert(pid, cpu);
+    }
+
+    /// Make the task `pid` a kernel thread.
+    pub fn set_kthread(&mut self, pid: i32) {
+        self.kthreads.insert(pid);
+    }
+
+    /// Make the task `pid` a thread of the process `tgid`.
+    pub fn set_tgid(&mut self, pid: i32, tgid: i32) {
+        self.tgids.insert(pid, tgid);
+    }
+
+    /// Terminate the task `pid`.
+    pub fn exit_task(&mut self, pid: i32) {
+        self.exited_pids.insert(pid);
+    }
+
+    /// Simulate a reboot of the system: the boot identifier changes, the clocks keep going.
+    pub fn reboot(&mut self) {
+        self.boot_id += 1;
+    }
+
+    /// Allow the task `pid` to run only on `cpus` (e.g., a task confined to a cpuset).
+    pub fn set_allowed_cpus(&mut self, pid: i32, cpus: Vec<usize>) {
+        self.allowed.insert(pid, cpus);
+    }
+
+    /// Set the scheduling hint `name` of the task `pid` to `value` (see slice_override.rs).
+    pub fn set_env_hint(&mut self, pid: i32, name: &str, value: u64) {
+        self.hints.insert((pid, name.to_string()), value);
+    }
+
+    /// Set the name of the task `pid` (e.g., as done by an exec()).
+    pub fn set_comm(&mut self, pid: i32, comm: &str) {
+        self.comms.insert(pid, comm.to_string());
+    }
+
+    /// Report that the task `pid` started running at `ts_ns` (see run_start_ns()).
+    pub fn set_run_start(&mut self, pid: i32, ts_ns: u64) {
+        self.run_starts.insert(pid, ts_ns);
+    }
+
+    /// Report that the task `pid` has been woken up by the task `waker` (see waker()).
+    pub fn set_waker(&mut self, pid: i32, waker: i32) {
+        self.wakers.insert(pid, waker);
+    }
+
+    /// Unplug the CPU `cpu`: it is not reported by online_cpus() anymore (the amount of online
+    /// CPUs doesn't change, like in the middle of a hotplug event).
+    pub fn set_cpu_offline(&mut self, cpu: usize) {
+        self.offline.insert(cpu);
+    }
+
+    /// Report the CPUs `cpus` as isolated on the kernel command line (none by default).
+    pub fn set_isolated_cpus(&mut self, cpus: &[usize]) {
+        self.isolated = cpus.to_vec();
+    }
+
+    /// Make select_cpu() always return `cpu`, even if it is busy, offline or out of range (e.g., a
+    /// stale CPU during a hotplug event).
+    pub fn force_select_cpu(&mut self, cpu: i32) {
+        self.forced_cpu = Some(cpu);
+    }
+
+    /// Simulate the execution of the dispatched tasks for `nr_rounds` rounds of `round_ns`.
+    pub fn closed_loop(&mut self, round_ns: u64, nr_rounds: u64) {
+        self.closed_loop = Some((round_ns, nr_rounds));
+    }
+
+    /// Make the next `nr_calls` calls to notify_complete() block for `stall_ns`.
+    pub fn stall_notify(&mut self, stall_ns: u64, nr_calls: u64) {
+        self.stall = (nr_calls > 0).then_some((stall_ns, nr_calls));
+    }
+
+    /// Make each call to dequeue_task() and dispatch_task() take `cost_ns`.
+    pub fn set_call_cost(&mut self, cost_ns: u64) {
+        self.call_cost_ns = cost_ns;
+    }
+
+    /// Return the amount of times the scheduler raised its own priority (see boost_self()).
+    pub fn nr_self_boosts(&self) -> u64 {
+        self.nr_self_boosts
+    }
+
+    /// Make the tasks executed in the closed loop use `pct` percent of their time slice (e.g.,
+    /// to simulate a kernel that ignores the time slices), instead of all of it.
+    pub fn scale_runtime(&mut self, pct: u64) {
+        self.runtime_pct = pct;
+    }
+
+    /// Account `delta_ns` of CPU time to the scheduler (see self_cpu_ns()).
+    pub fn consume_self_cpu(&mut self, delta_ns: u64) {
+        self.self_cpu_ns += delta_ns;
+    }
+
+    /// Move the simulated clock forward.
+    pub fn advance(&mut self, delta_ns: u64) {
+        self.now_ns += delta_ns;
+    }
+}
+
+impl SchedBackend for MockBackend {
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32> {
+        self.now_ns += self.call_cost_ns;
+        if self.nr_dequeue_fail > 0 {
+            self.nr_dequeue_fail -= 1;
+            return Err(-libc::EIO);
+        }
+        let mut task = self.queued.pop_front();
+        if let (None, Some((endless, nr))) = (&task, self.endless.as_mut()) {
+            if *nr > 0 {
+                *nr -= 1;
+                task = Some(endless.clone());
+            }
+        }
+        if let (Some(task), Some(_)) = (&task, self.closed_loop) {
+            self.consumed.insert(task.pid, task.clone());
+        }
+
+        Ok(task)
+    }
+
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, _flags: u64) -> i32 {
+        self.selected.push(pid);
+        if let Some(cpu) = self.forced_cpu {
+            return cpu;
+        }
+        if self.saturated {
+            return -libc::EBUSY;
+        }
+        if prev_cpu >= 0 && !self.busy.get(prev_cpu as usize).copied().unwrap_or(true) {
+            self.busy[prev_cpu as usize] = true;
+            return prev_cpu;
+        }
+        match self.busy.iter().position(|busy| !busy) {
+            Some(cpu) => {
+                self.busy[cpu] = true;
+                cpu as i32
+            }
+            None => -libc::EBUSY,
+        }
+    }
+
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError> {
+        self.now_ns += self.call_cost_ns;
+        if self.nr_fail > 0 {
+            self.nr_fail -= 1;
+            return Err(DispatchError::Busy);
+        }
+        if self.nr_flaky > 0 {
+            self.nr_flaky -= 1;
+            if self.nr_flaky % 2 == 1 {
+                return Err(DispatchError::Busy);
+            }
+        }
+        if self.nr_drop > 0 {
+            self.nr_drop -= 1;
+            self.nr_failed_dispatches += 1;
+            return Ok(());
+        }
+        if task.cpu >= 0
+            && self
+                .allowed
+                .get(&task.pid)
+                .is_some_and(|cpus| !cpus.contains(&(task.cpu as usize)))
+        {
+            self.nr_bounce_dispatches += 1;
+        }
+        if let Some(mut running) = self.consumed.remove(&task.pid) {
+            running.sum_exec_runtime += task.slice_ns * self.runtime_pct / 100;
+            if task.cpu >= 0 {
+                running.cpu = task.cpu;
+            }
+            self.running.push(running);
+        }
+        self.dispatched.push(task.clone());
+        self.nr_user_dispatches += 1;
+
+        Ok(())
+    }
+
+    fn notify_complete(&mut self, _nr_pending: u64) {
+        self.busy.iter_mut().for_each(|busy| *busy = false);
+
+        if let Some((round_ns, nr_rounds)) = self.closed_loop.as_mut() {
+            self.now_ns += *round_ns;
+            *nr_rounds = nr_rounds.saturating_sub(1);
+            self.queued.extend(self.running.drain(..));
+        }
+        if let Some((stall_ns, nr_calls)) = self.stall {
+            self.now_ns += stall_ns;
+            self.stall = (nr_calls > 1).then_some((stall_ns, nr_calls - 1));
+        }
+    }
+
+    fn exited(&mut self) -> bool {
+        self.closed_loop
+            .is_some_and(|(_, nr_rounds)| nr_rounds == 0)
+    }
+
+    fn exists(&mut self, pid: i32) -> bool {
+        !self.exited_pids.contains(&pid)
+    }
+
+    fn tgid(&mut self, pid: i32) -> Option<i32> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        Some(self.tgids.get(&pid).copied().unwrap_or(pid))
+    }
+
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        self.parent_cpus.get(&pid).copied()
+    }
+
+    fn is_kthread(&mut self, pid: i32) -> Option<bool> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        Some(self.kthreads.contains(&pid))
+    }
+
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        let all_cpus = (0..self.nr_online_cpus as usize).collect();
+
+        Some(self.allowed.get(&pid).cloned().unwrap_or(all_cpus))
+    }
+
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64> {
+        self.hints.get(&(pid, name.to_string())).copied()
+    }
+
+    fn comm(&mut self, pid: i32) -> Option<String> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        self.comms.get(&pid).cloned()
+    }
+
+    fn run_start_ns(&mut self, pid: i32) -> Option<u64> {
+        self.run_starts.get(&pid).copied()
+    }
+
+    fn waker(&mut self, pid: i32) -> Option<i32> {
+        if self.exited_pids.contains(&pid) {
+            return None;
+        }
+        self.wakers.get(&pid).copied()
+    }
+
+    fn boost_self(&mut self) -> bool {
+        self.nr_self_boosts += 1;
+
+        true
+    }
+
+    fn online_cpus(&mut self) -> Option<Vec<usize>> {
+        let cpus = (0..self.nr_online_cpus as usize).filter(|cpu| !self.offline.contains(cpu));
+
+        Some(cpus.collect())
+    }
+
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>> {
+        Some(self.isolated.clone())
+    }
+
+    // The simulated clock starts at midnight.
+    fn local_minute(&mut self) -> u32 {
+        (self.now_ns / (60 * NSEC_PER_SEC) % (24 * 60)) as u32
+    }
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64 {
+        &mut self.nr_online_cpus
+    }
+
+    fn nr_queued_mut(&mut self) -> &mut u64 {
+        self.nr_queued = self.queued.len() as u64;
+        &mut self.nr_queued
+    }
+
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.nr_user_dispatches
+    }
+
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.nr_kernel_dispatches
+    }
+
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.nr_bounce_dispatches
+    }
+
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.nr_cancel_dispatches
+    }
+
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.nr_failed_dispatches
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.now_ns
+    }
+
+    fn self_cpu_ns(&self) -> u64 {
+        self.self_cpu_ns
+    }
+
+    fn realtime_ns(&self) -> u64 {
+        self.now_ns
+    }
+
+    fn boot_id(&self) -> Option<String> {
+        Some(format!("mock-boot-{}", self.boot_id))
+    }
+}
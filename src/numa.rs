@@ -0,0 +1,261 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cpulist;
+use crate::NSEC_PER_SEC;
+
+/// Return the CPUs of each NUMA node of the system, from /sys/devices/system/node (a system
+/// without NUMA support is reported as a single node with all the CPUs).
+pub fn topology_nodes(nr_cpus: usize) -> Result<Vec<Vec<usize>>> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return Ok(vec![(0..nr_cpus).collect()]);
+    };
+    let mut nodes = Vec::new();
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let path = entry.path().join("cpulist");
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let cpus = cpulist::parse(text.trim()).map_err(anyhow::Error::msg)?;
+        nodes.push((id, cpus.0));
+    }
+    nodes.sort_unstable();
+
+    Ok(nodes.into_iter().map(|(_, cpus)| cpus).collect())
+}
+
+/// Fallback CPU selection that spreads the tasks across the NUMA nodes (see --fallback numa).
+///
+/// When no idle CPU is found for a task, instead of dispatching it to the first CPU available
+/// (RL_CPU_ANY, that can end up concentrating the load on the node that frees up a CPU first),
+/// the tasks are distributed across the nodes in a round-robin way, and across the CPUs of each
+/// node in a round-robin way.
+pub struct NumaFallback {
+    nodes: Vec<Vec<usize>>, // CPUs of each node
+    next_node: usize,       // Next node used for the fallback
+    next_cpu: Vec<usize>,   // Next CPU used in each node
+    nr_fallbacks: Vec<u64>, // Tasks assigned to each node (since the last report)
+}
+
+impl NumaFallback {
+    /// Create the fallback from the CPUs of each node (empty nodes are ignored).
+    pub fn new(nodes: Vec<Vec<usize>>) -> Self {
+        let nodes: Vec<Vec<usize>> = nodes.into_iter().filter(|n| !n.is_empty()).collect();
+        let nr_nodes = nodes.len();
+
+        Self {
+            nodes,
+            next_node: 0,
+            next_cpu: vec![0; nr_nodes],
+            nr_fallbacks: vec![0; nr_nodes],
+        }
+    }
+
+    /// Return the CPU assigned to the next task without an idle CPU, among the CPUs accepted by
+    /// `usable` (None if no node has such a CPU).
+    pub fn pick_cpu(&mut self, usable: impl Fn(usize) -> bool) -> Option<usize> {
+        let nr_nodes = self.nodes.len();
+
+        for _ in 0..nr_nodes {
+            let node = self.next_node % nr_nodes;
+            self.next_node = node + 1;
+
+            let cpus = &self.nodes[node];
+            for _ in 0..cpus.len() {
+                let cpu = cpus[self.next_cpu[node] % cpus.len()];
+                self.next_cpu[node] = (self.next_cpu[node] + 1) % cpus.len();
+                if usable(cpu) {
+                    self.nr_fallbacks[node] += 1;
+                    return Some(cpu);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Return the amount of tasks assigned to each node since the last report.
+    pub fn distribution(&self) -> &[u64] {
+        &self.nr_fallbacks
+    }
+
+    /// Print the amount of tasks assigned to each node and reset it.
+    pub fn report(&mut self) {
+        let nodes: Vec<String> = self
+            .nr_fallbacks
+            .iter()
+            .enumerate()
+            .map(|(node, nr)| format!("node{} {}", node, nr))
+            .collect();
+        println!("numa fallback: {}", nodes.join(" | "));
+        self.nr_fallbacks.iter_mut().for_each(|nr| *nr = 0);
+    }
+}
+
+// CPU time (accumulated on all the nodes) above which the history of a task is halved, so that
+// the home node of a task follows it when it keeps running on a different node.
+const AFFINITY_DECAY_NS: u64 = 10 * NSEC_PER_SEC;
+
+// Minimum CPU time used by a task before its home node is inferred.
+const AFFINITY_MIN_NS: u64 = 10_000_000;
+
+/// Soft affinity of the tasks to the NUMA node hosting their memory (see --numa-affinity).
+///
+/// The location of the pages of a task is not known, so it is approximated by the node where
+/// the task has accumulated most of its CPU time (the memory is usually allocated on the node
+/// where the task runs, see the "first touch" policy): this node becomes the home node of the
+/// task and the idle CPU search starts from a CPU of the home node, instead of the previously
+/// used CPU, whenever the task has been moved to a different node. The preference is soft: if
+/// no CPU of the home node is idle, the task can still run on a different node (a cross-node
+/// dispatch).
+pub struct NumaAffinity {
+    nodes: Vec<Vec<usize>>,             // CPUs of each node
+    cpu_node: Vec<Option<usize>>,       // Node of each CPU
+    next_cpu: Vec<usize>,               // Next CPU used in each node
+    runtime_ns: HashMap<i32, Vec<u64>>, // CPU time used by each task on each node
+    nr_dispatches: u64,                 // Dispatches of the tasks with a home node
+    nr_cross: u64,                      // Dispatches to a CPU outside the home node
+}
+
+impl NumaAffinity {
+    /// Create the affinity from the CPUs of each node (empty nodes are ignored).
+    pub fn new(nodes: Vec<Vec<usize>>) -> Self {
+        let nodes: Vec<Vec<usize>> = nodes.into_iter().filter(|n| !n.is_empty()).collect();
+        let mut cpu_node = Vec::new();
+        for (node, cpus) in nodes.iter().enumerate() {
+            for &cpu in cpus {
+                if cpu >= cpu_node.len() {
+                    cpu_node.resize(cpu + 1, None);
+                }
+                cpu_node[cpu] = Some(node);
+            }
+        }
+        let nr_nodes = nodes.len();
+
+        Self {
+            nodes,
+            cpu_node,
+            next_cpu: vec![0; nr_nodes],
+            runtime_ns: HashMap::new(),
+            nr_dispatches: 0,
+            nr_cross: 0,
+        }
+    }
+
+    // Return the node of a CPU (None for RL_CPU_ANY and the unknown CPUs).
+    fn node(&self, cpu: i32) -> Option<usize> {
+        usize::try_from(cpu)
+            .ok()
+            .and_then(|cpu| self.cpu_node.get(cpu).copied().flatten())
+    }
+
+    /// Account `runtime_ns` of CPU time used by a task on the CPU `cpu`.
+    pub fn record(&mut self, pid: i32, cpu: i32, runtime_ns: u64) {
+        let Some(node) = self.node(cpu) else {
+            return;
+        };
+        let nr_nodes = self.nodes.len();
+        let runtime = self
+            .runtime_ns
+            .entry(pid)
+            .or_insert_with(|| vec![0; nr_nodes]);
+
+        runtime[node] = runtime[node].saturating_add(runtime_ns);
+        if runtime.iter().fold(0u64, |sum, &ns| sum.saturating_add(ns)) > AFFINITY_DECAY_NS {
+            runtime.iter_mut().for_each(|ns| *ns /= 2);
+        }
+    }
+
+    /// Return the home node of a task: the node where it used most of its CPU time, None until
+    /// it used at least AFFINITY_MIN_NS.
+    pub fn home_node(&self, pid: i32) -> Option<usize> {
+        let runtime = self.runtime_ns.get(&pid)?;
+        if runtime.iter().sum::<u64>() < AFFINITY_MIN_NS {
+            return None;
+        }
+
+        (0..runtime.len()).max_by_key(|&node| (runtime[node], std::cmp::Reverse(node)))
+    }
+
+    /// Return the CPU where the idle CPU search of a task needs to start: `prev_cpu` if it is in
+    /// the home node of the task (or the task has no home node yet), otherwise the next CPU of
+    /// the home node accepted by `usable`, in a round-robin way.
+    pub fn pick_cpu(&mut self, pid: i32, prev_cpu: i32, usable: impl Fn(usize) -> bool) -> i32 {
+        let Some(home) = self.home_node(pid) else {
+            return prev_cpu;
+        };
+        if self.node(prev_cpu) == Some(home) {
+            return prev_cpu;
+        }
+
+        let cpus = &self.nodes[home];
+        for _ in 0..cpus.len() {
+            let cpu = cpus[self.next_cpu[home] % cpus.len()];
+            self.next_cpu[home] = (self.next_cpu[home] + 1) % cpus.len();
+            if usable(cpu) {
+                return cpu as i32;
+            }
+        }
+
+        prev_cpu
+    }
+
+    /// Account a task dispatched to the CPU `cpu` (RL_CPU_ANY is not accounted).
+    pub fn record_dispatch(&mut self, pid: i32, cpu: i32) {
+        let (Some(home), Some(node)) = (self.home_node(pid), self.node(cpu)) else {
+            return;
+        };
+
+        self.nr_dispatches += 1;
+        if node != home {
+            self.nr_cross += 1;
+        }
+    }
+
+    /// Return the dispatches of the tasks with a home node and the cross-node ones, since the
+    /// last report.
+    pub fn dispatches(&self) -> (u64, u64) {
+        (self.nr_dispatches, self.nr_cross)
+    }
+
+    /// Drop the history of the tasks that are not tracked anymore.
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.runtime_ns.retain(|&pid, _| alive(pid));
+    }
+
+    /// Print the rate of the cross-node dispatches and reset it.
+    pub fn report(&mut self) {
+        let pct = (self.nr_cross * 100)
+            .checked_div(self.nr_dispatches)
+            .unwrap_or(0);
+
+        println!(
+            "numa affinity: cross-node dispatches/s: {} ({}% of {}) | tasks with a home node: {}",
+            self.nr_cross,
+            pct,
+            self.nr_dispatches,
+            self.runtime_ns
+                .keys()
+                .filter(|&&pid| self.home_node(pid).is_some())
+                .count()
+        );
+        self.nr_dispatches = 0;
+        self.nr_cross = 0;
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Maximum factor applied to the time slices and to the amount of tasks dispatched per round
+// while the scheduler limits its own overhead.
+const OVERHEAD_MAX_SCALE: u64 = 8;
+
+// Format an amount of parts per million as a percentage.
+fn pct(ppm: u64) -> String {
+    format!("{}.{:04}%", ppm / 10_000, ppm % 10_000)
+}
+
+/// Estimate of the CPU time consumed by the scheduler itself (see --self-stats and
+/// --self-cpu-max-pct).
+///
+/// The overhead is the CPU time of the scheduler process in a one-second interval, relative to
+/// the total CPU time available in the same interval (elapsed time multiplied by the online
+/// CPUs), in parts per million.
+///
+/// With a ceiling (`max_ppm`), the scheduler coarsens its behavior while the overhead exceeds
+/// it: the time slices and the amount of tasks dispatched per round are doubled (up to
+/// OVERHEAD_MAX_SCALE times), so that the scheduler needs to wake up and take decisions less
+/// often; they are halved again when the overhead drops below half of the ceiling. Scale changes
+/// are filtered by `hysteresis`, driven by the overhead (in hundredths of percent).
+pub struct Overhead {
+    max_ppm: Option<u64>,   // Overhead ceiling
+    hysteresis: Hysteresis, // Anti-flapping filter of the scale
+    interval_ts: u64,       // Beginning of the current interval
+    interval_cpu_ns: u64,   // CPU time of the scheduler at the beginning of the interval
+    last_ppm: u64,          // Overhead measured in the last completed interval
+    last_rate_ns: u64,      // CPU time of the scheduler per second in the last interval
+    scale: u64,             // Factor applied to the time slices and to the tasks per round
+}
+
+impl Overhead {
+    pub fn new(max_ppm: Option<u64>, hysteresis: Hysteresis, now: u64, cpu_ns: u64) -> Self {
+        Self {
+            max_ppm,
+            hysteresis,
+            interval_ts: now,
+            interval_cpu_ns: cpu_ns,
+            last_ppm: 0,
+            last_rate_ns: 0,
+            scale: 1,
+        }
+    }
+
+    /// Return the factor that needs to be applied to the time slices and to the amount of tasks
+    /// dispatched per round.
+    pub fn scale(&self) -> u64 {
+        self.scale
+    }
+
+    /// Evaluate the current interval (once per second), given the CPU time consumed so far by
+    /// the scheduler (`cpu_ns`) and the amount of online CPUs.
+    pub fn evaluate(&mut self, now: u64, cpu_ns: u64, nr_cpus: u64) {
+        let elapsed = now.saturating_sub(self.interval_ts);
+        if elapsed < NSEC_PER_SEC {
+            return;
+        }
+        let delta_ns = cpu_ns.saturating_sub(self.interval_cpu_ns);
+        let total_ns = elapsed.saturating_mul(nr_cpus.max(1));
+        let ppm = (delta_ns as u128 * 1_000_000 / total_ns as u128) as u64;
+
+        if let Some(max_ppm) = self.max_ppm {
+            if ppm > max_ppm
+                && self.scale < OVERHEAD_MAX_SCALE
+                && self.hysteresis.allow(now, ppm / 100)
+            {
+                self.scale *= 2;
+                println!(
+                    "self-limit: scheduler overhead {} above {}, time slices and tasks per round \
+                     scaled by {}x",
+                    pct(ppm),
+                    pct(max_ppm),
+                    self.scale
+                );
+            } else if ppm < max_ppm / 2 && self.scale > 1 && self.hysteresis.allow(now, ppm / 100) {
+                self.scale /= 2;
+                println!(
+                    "self-limit: scheduler overhead {} below {}, time slices and tasks per round \
+                     scaled by {}x",
+                    pct(ppm),
+                    pct(max_ppm / 2),
+                    self.scale
+                );
+            }
+        }
+
+        self.interval_ts = now;
+        self.interval_cpu_ns = cpu_ns;
+        self.last_ppm = ppm;
+        self.last_rate_ns = (delta_ns as u128 * NSEC_PER_SEC as u128 / elapsed as u128) as u64;
+    }
+
+    /// Print the overhead measured in the last completed interval.
+    pub fn report(&self) {
+        println!(
+            "scheduler cpu: {} of total | {} of one CPU",
+            pct(self.last_ppm),
+            pct(self.last_rate_ns / 1000)
+        );
+    }
+}
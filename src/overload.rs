@@ -0,0 +1,127 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Amount of consecutive overloaded intervals (of one second) that trigger the overload state.
+const OVERLOAD_SUSTAIN: u64 = 3;
+
+// Minimum growth of the backlog (in tasks) to consider an interval overloaded (prevents
+// reporting an overload when only a few tasks are involved).
+const OVERLOAD_MIN_GROWTH: u64 = 16;
+
+// Maximum factor applied to the time slices while shedding load.
+const OVERLOAD_MAX_SLICE_SCALE: u64 = 8;
+
+/// Detector of the intervals where the scheduler can't keep up with the tasks that want to run
+/// (see --overload-thresh-pct).
+///
+/// The arrival rate is the amount of tasks received from the BPF component, plus the growth of
+/// the tasks still queued in the BPF component (nr_queued), and it is compared with the rate of
+/// the tasks dispatched by the scheduler: when the arrival rate exceeds the dispatch rate by at
+/// least `thresh_pct` percent for OVERLOAD_SUSTAIN consecutive seconds, a warning is printed.
+///
+/// With --overload-shed, the scheduler also sheds load while overloaded by doubling the time
+/// slices (up to OVERLOAD_MAX_SLICE_SCALE times, fewer context switches mean fewer scheduling
+/// decisions), restoring them when the dispatch rate catches up; scale changes are filtered by
+/// `hysteresis`, driven by the arrival rate relative to the dispatch rate (in percent).
+pub struct OverloadDetector {
+    thresh_pct: u64,        // Minimum excess of the arrival rate (in percent)
+    shed: bool,             // Shed load by increasing the time slices
+    hysteresis: Hysteresis, // Anti-flapping filter of the time slice scale
+    interval_ts: u64,       // Beginning of the current interval
+    nr_arrivals: u64,       // Tasks received in the current interval
+    nr_dispatches: u64,     // Tasks dispatched in the current interval
+    prev_queued: u64,       // Tasks queued in the BPF component (previous interval)
+    streak: u64,            // Consecutive overloaded intervals
+    overloaded: bool,       // Overload state
+    slice_scale: u64,       // Factor applied to the time slices
+}
+
+impl OverloadDetector {
+    pub fn new(thresh_pct: u64, shed: bool, hysteresis: Hysteresis, now: u64) -> Self {
+        Self {
+            thresh_pct,
+            shed,
+            hysteresis,
+            interval_ts: now,
+            nr_arrivals: 0,
+            nr_dispatches: 0,
+            prev_queued: 0,
+            streak: 0,
+            overloaded: false,
+            slice_scale: 1,
+        }
+    }
+
+    /// Account a task received from the BPF component.
+    pub fn record_arrival(&mut self) {
+        self.nr_arrivals += 1;
+    }
+
+    /// Account a dispatched task.
+    pub fn record_dispatch(&mut self) {
+        self.nr_dispatches += 1;
+    }
+
+    /// Return the factor that needs to be applied to the time slices.
+    pub fn slice_scale(&self) -> u64 {
+        self.slice_scale
+    }
+
+    /// Evaluate the current interval (once per second), given the amount of tasks queued in the
+    /// BPF component (`nr_queued`).
+    pub fn evaluate(&mut self, now: u64, nr_queued: u64) {
+        let elapsed = now.saturating_sub(self.interval_ts);
+        if elapsed < NSEC_PER_SEC {
+            return;
+        }
+        let arrivals = self.nr_arrivals + nr_queued.saturating_sub(self.prev_queued);
+        let dispatches = self.nr_dispatches;
+        let ratio_pct = (arrivals * 100).checked_div(dispatches).unwrap_or(u64::MAX);
+
+        if arrivals >= dispatches + OVERLOAD_MIN_GROWTH
+            && arrivals * 100 >= dispatches * (100 + self.thresh_pct)
+        {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+
+        if self.streak >= OVERLOAD_SUSTAIN {
+            if !self.overloaded {
+                println!(
+                    "WARNING: task arrival rate ({}/s) exceeds the dispatch rate ({}/s) for {}s, \
+                     the scheduler can't keep up",
+                    arrivals * NSEC_PER_SEC / elapsed,
+                    dispatches * NSEC_PER_SEC / elapsed,
+                    self.streak
+                );
+                self.overloaded = true;
+            }
+            if self.shed
+                && self.slice_scale < OVERLOAD_MAX_SLICE_SCALE
+                && self.hysteresis.allow(now, ratio_pct)
+            {
+                self.slice_scale *= 2;
+                println!("overload: time slices scaled by {}x", self.slice_scale);
+            }
+        } else if self.overloaded
+            && self.streak == 0
+            && (self.slice_scale == 1 || self.hysteresis.allow(now, ratio_pct))
+        {
+            println!("overload: the dispatch rate caught up with the arrival rate");
+            self.overloaded = false;
+            self.slice_scale = 1;
+        }
+
+        self.interval_ts = now;
+        self.nr_arrivals = 0;
+        self.nr_dispatches = 0;
+        self.prev_queued = nr_queued;
+    }
+}
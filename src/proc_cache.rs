@@ -0,0 +1,168 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Cache of the information about the tasks that is read from /proc (process, cpuset, kernel
+//! thread flag, parent, scheduling hints, comm), filled by side threads.
+//!
+//! A read from /proc/<pid> can block on the task itself: e.g., reading /proc/<pid>/environ takes
+//! the mmap_lock of the task, so if the task holds it while waiting to be dispatched, a scheduler
+//! reading it synchronously would wait for a task that only the scheduler can run. The reads are
+//! therefore done by a side thread, while the scheduler only looks up the last value read (each
+//! lookup requests a new read, so the value is at most one lookup old, see ProcCache::get()).
+//!
+//! The information needed as soon as a task is received the first time (e.g., its parent) can
+//! be waited for, but only for a bounded time (see ProcCache::get_wait()). Each kind of
+//! information has its own cache and side thread, so a read that blocks only delays the
+//! information of the same kind.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+// Interval after which the entries that have not been looked up in the meantime are dropped
+// (e.g., the tasks that exited).
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+// Cached value of a task.
+struct Entry<V> {
+    value: Option<Option<V>>, // Last value read (None = not read yet)
+    pending: bool,            // Read requested and not completed yet
+    used: bool,               // Looked up since the last sweep
+}
+
+// Cached values, shared with the side thread.
+struct Entries<K, V> {
+    map: HashMap<K, Entry<V>>, // Cached values
+    last_sweep: Instant,       // Last time the unused entries have been dropped
+}
+
+impl<K: Eq + Hash, V> Entries<K, V> {
+    // Drop the entries that have not been looked up since the last sweep.
+    fn sweep(&mut self) {
+        if self.last_sweep.elapsed() < SWEEP_INTERVAL {
+            return;
+        }
+        self.map
+            .retain(|_, entry| std::mem::take(&mut entry.used) || entry.pending);
+        self.last_sweep = Instant::now();
+    }
+}
+
+/// Values read from /proc by a side thread, looked up without ever blocking on the read.
+pub struct ProcCache<K, V> {
+    shared: Arc<(Mutex<Entries<K, V>>, Condvar)>, // Cached values, notified after each read
+    requests: Sender<K>,                          // Reads requested to the side thread
+}
+
+impl<K, V> ProcCache<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    /// Start the side thread that reads the values with `read` (None if the task doesn't exist).
+    pub fn new(read: impl Fn(&K) -> Option<V> + Send + 'static) -> Self {
+        let shared = Arc::new((
+            Mutex::new(Entries {
+                map: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+            Condvar::new(),
+        ));
+        let (tx, rx) = mpsc::channel::<K>();
+
+        let worker = shared.clone();
+        thread::spawn(move || {
+            for key in rx {
+                let value = read(&key);
+                let (entries, read_done) = &*worker;
+                if let Some(entry) = entries.lock().unwrap().map.get_mut(&key) {
+                    entry.value = Some(value);
+                    entry.pending = false;
+                }
+                read_done.notify_all();
+            }
+        });
+
+        Self {
+            shared,
+            requests: tx,
+        }
+    }
+
+    /// Return the last value read for `key` (None if it has not been read yet or the task
+    /// doesn't exist) and request a new read, unless one is already in progress.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.shared.0.lock().unwrap();
+
+        self.lookup(&mut entries, key).flatten()
+    }
+
+    /// Same as get(), but if the value has never been read, wait for it for at most `timeout`.
+    pub fn get_wait(&self, key: &K, timeout: Duration) -> Option<V> {
+        let (entries, read_done) = &*self.shared;
+        let mut entries = entries.lock().unwrap();
+        if let Some(value) = self.lookup(&mut entries, key) {
+            return value;
+        }
+        let (entries, _) = read_done
+            .wait_timeout_while(entries, timeout, |entries| {
+                entries
+                    .map
+                    .get(key)
+                    .is_some_and(|entry| entry.value.is_none())
+            })
+            .unwrap();
+
+        entries.map.get(key)?.value.clone().flatten()
+    }
+
+    // Look up the entry of `key`, requesting a new read: return None if it has never been read.
+    fn lookup(&self, entries: &mut Entries<K, V>, key: &K) -> Option<Option<V>> {
+        entries.sweep();
+        let entry = entries.map.entry(key.clone()).or_insert(Entry {
+            value: None,
+            pending: false,
+            used: false,
+        });
+        entry.used = true;
+        if !entry.pending {
+            entry.pending = true;
+            let _ = self.requests.send(key.clone());
+        }
+
+        entry.value.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_never_wait_for_a_blocked_read() {
+        // Each read waits for a token, to simulate a read that blocks.
+        let (tokens, rx) = mpsc::channel::<()>();
+        let rx = Mutex::new(rx);
+        let cache = ProcCache::new(move |&pid: &i32| {
+            rx.lock().unwrap().recv().ok()?;
+            Some(pid * 2)
+        });
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get_wait(&1, Duration::from_millis(10)), None);
+
+        tokens.send(()).unwrap();
+        assert_eq!(cache.get_wait(&1, Duration::from_secs(10)), Some(2));
+        // The last value is returned while the following read is still blocked.
+        assert_eq!(cache.get(&1), Some(2));
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Named profiles: bundles of settings that can be switched at runtime (see --profile).
+//!
+//! Each profile is defined on the command line as NAME:KEY=VALUE[,KEY=VALUE...], e.g.:
+//!
+//!   $ scx_rust_scheduler --profile gaming:policy=fair,slice-us=2000,compute-boost=true \
+//!                        --profile server:policy=fifo,slice-us=20000
+//!
+//! Supported keys:
+//!
+//!  - `policy`: policy used to order the tasks (fifo, fair, wrr or edf, see --policy),
+//!  - `slice-us`: maximum time slice, in microseconds (see --slice-us),
+//!  - `compute-boost`: larger time slices for the compute-bound tasks (true or false, see
+//!    --compute-boost).
+//!
+//! The settings that are not part of a profile keep the values of the command line, so that the
+//! effective settings only depend on the active profile, not on the previous ones. The command
+//! line settings are also available as the `default` profile, that is active at startup.
+//!
+//! The active profile can be switched with the `profile <name>` command of the control socket
+//! (see --control-socket), or with SIGUSR2, that switches to the next profile (in alphabetical
+//! order, starting from `default`). Profiles are validated when the command line is parsed, and
+//! all the settings of a profile are applied together, between two scheduling rounds.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use clap::ValueEnum;
+
+use crate::Policy;
+
+/// Name of the profile that contains the command line settings.
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Set by the SIGUSR2 handler, consumed by the scheduler.
+static SWITCH_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigusr2(_sig: libc::c_int) {
+    SWITCH_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Install a SIGUSR2 handler that requests a switch to the next profile (see
+/// switch_requested()).
+pub fn install_signal_handler() {
+    unsafe {
+        libc::signal(
+            libc::SIGUSR2,
+            handle_sigusr2 as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// Return true if a switch to the next profile has been requested via SIGUSR2 since the last
+/// call.
+pub fn switch_requested() -> bool {
+    SWITCH_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Settings of a profile (None = the value of the command line).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileSettings {
+    pub policy: Option<Policy>, // Policy used to order the tasks (--policy)
+    pub slice_us: Option<u64>,  // Maximum time slice (--slice-us)
+    pub compute_boost: Option<bool>, // Larger slices for compute-bound tasks (--compute-boost)
+}
+
+impl fmt::Display for ProfileSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(policy) = self.policy {
+            settings.push(format!("policy={:?}", policy).to_lowercase());
+        }
+        if let Some(slice_us) = self.slice_us {
+            settings.push(format!("slice-us={}", slice_us));
+        }
+        if let Some(compute_boost) = self.compute_boost {
+            settings.push(format!("compute-boost={}", compute_boost));
+        }
+        match settings.is_empty() {
+            true => write!(f, "command line settings"),
+            false => write!(f, "{}", settings.join(",")),
+        }
+    }
+}
+
+/// Profile provided on the command line (NAME:KEY=VALUE[,KEY=VALUE...]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedProfile {
+    pub name: String,
+    pub settings: ProfileSettings,
+}
+
+/// Parse a profile in the format NAME:KEY=VALUE[,KEY=VALUE...].
+pub fn parse(text: &str) -> Result<NamedProfile, String> {
+    let Some((name, settings)) = text.split_once(':') else {
+        return Err(format!(
+            "expected NAME:KEY=VALUE[,KEY=VALUE...], found '{}'",
+            text
+        ));
+    };
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("invalid profile name '{}'", name));
+    }
+    if name == DEFAULT_PROFILE {
+        return Err(format!(
+            "'{}' is reserved for the command line settings",
+            DEFAULT_PROFILE
+        ));
+    }
+
+    let mut profile = ProfileSettings::default();
+    for setting in settings.split(',') {
+        let Some((key, value)) = setting.split_once('=') else {
+            return Err(format!("expected KEY=VALUE, found '{}'", setting));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "policy" => {
+                let policy = Policy::from_str(value, true)
+                    .map_err(|_| format!("invalid policy '{}'", value))?;
+                profile.policy = Some(policy);
+            }
+            "slice-us" => match value.parse() {
+                Ok(slice_us) if slice_us > 0 => profile.slice_us = Some(slice_us),
+                _ => return Err(format!("invalid time slice '{}' (microseconds)", value)),
+            },
+            "compute-boost" => match value.parse() {
+                Ok(compute_boost) => profile.compute_boost = Some(compute_boost),
+                Err(_) => return Err(format!("invalid compute-boost '{}' (true or false)", value)),
+            },
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+    }
+
+    Ok(NamedProfile {
+        name: name.to_string(),
+        settings: profile,
+    })
+}
+
+/// Check that each profile is defined only once.
+pub fn validate(profiles: &[NamedProfile]) -> Result<(), String> {
+    for (i, profile) in profiles.iter().enumerate() {
+        if profiles[..i].iter().any(|p| p.name == profile.name) {
+            return Err(format!("profile '{}' defined more than once", profile.name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Profiles available to the scheduler and the active one.
+pub struct Profiles {
+    profiles: BTreeMap<String, ProfileSettings>, // Settings of each profile (except default)
+    active: String,                              // Active profile
+}
+
+impl Profiles {
+    pub fn new(profiles: &[NamedProfile]) -> Self {
+        Self {
+            profiles: profiles
+                .iter()
+                .map(|profile| (profile.name.clone(), profile.settings))
+                .collect(),
+            active: DEFAULT_PROFILE.to_string(),
+        }
+    }
+
+    /// Return the name of the active profile.
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    /// Return the name of the profile that follows the active one (in alphabetical order,
+    /// starting from the default profile).
+    pub fn next(&self) -> String {
+        let next = match self.active.as_str() {
+            DEFAULT_PROFILE => self.profiles.keys().next(),
+            active => self.profiles.keys().find(|name| name.as_str() > active),
+        };
+
+        next.cloned().unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// Make `name` the active profile and return its settings (an error if the profile
+    /// doesn't exist, in which case the active profile is not changed).
+    pub fn switch(&mut self, name: &str) -> Result<ProfileSettings, String> {
+        let settings = match name {
+            DEFAULT_PROFILE => ProfileSettings::default(),
+            name => *self.profiles.get(name).ok_or_else(|| {
+                let names: Vec<&str> = std::iter::once(DEFAULT_PROFILE)
+                    .chain(self.profiles.keys().map(String::as_str))
+                    .collect();
+                format!(
+                    "unknown profile '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                )
+            })?,
+        };
+        self.active = name.to_string();
+
+        Ok(settings)
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::NSEC_PER_SEC;
+
+// Growth rate (in tasks per second) below which the queue is considered steady: the amount of
+// queued tasks always fluctuates a bit between two samples, even when the scheduler keeps up.
+const STEADY_RATE: f64 = 1.0;
+
+// Sample of the queue depth.
+#[derive(Debug, Clone, Copy)]
+struct QueueSample {
+    ts: u64,    // Time of the sample
+    depth: u64, // Tasks waiting to be dispatched
+}
+
+/// Trend of the amount of tasks waiting to be dispatched (in the BPF component and in the
+/// user-space queues), sampled at every stats interval: the growth rate tells whether the
+/// scheduler is keeping up with the workload (steady or shrinking queue) or falling behind
+/// (growing queue), and a shrinking queue also gives an estimate of the time to drain it.
+///
+/// The arrival rate is derived from the growth rate and the dispatch rate (a task either stays
+/// in the queue or it is dispatched), to tell an overload (arrivals above the usual dispatch
+/// rate) from a stall (dispatches dropping while the arrivals don't change).
+pub struct QueueTrend {
+    last: Option<QueueSample>, // Last sample
+    rate: Option<f64>,         // Growth rate in tasks/s (negative = shrinking)
+    dispatch_rate: f64,        // Tasks dispatched per second
+}
+
+impl QueueTrend {
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            rate: None,
+            dispatch_rate: 0.0,
+        }
+    }
+
+    /// Sample the queue at time `now`, with `depth` tasks waiting and `nr_dispatches` tasks
+    /// dispatched since the previous sample.
+    pub fn sample(&mut self, now: u64, depth: u64, nr_dispatches: u64) {
+        let sample = QueueSample { ts: now, depth };
+        if let Some(last) = self.last.filter(|last| now > last.ts) {
+            let secs = (now - last.ts) as f64 / NSEC_PER_SEC as f64;
+            self.rate = Some((depth as f64 - last.depth as f64) / secs);
+            self.dispatch_rate = nr_dispatches as f64 / secs;
+        }
+        self.last = Some(sample);
+    }
+
+    /// Return the growth rate of the queue (in tasks per second, negative while shrinking), 0
+    /// while steady, None before two samples are available.
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+            .map(|rate| if rate.abs() < STEADY_RATE { 0.0 } else { rate })
+    }
+
+    /// Return the estimated time (in seconds) to drain the queue at the current rate, None if
+    /// the queue is not shrinking.
+    pub fn eta_secs(&self) -> Option<f64> {
+        let depth = self.last?.depth as f64;
+
+        self.rate()
+            .filter(|&rate| rate < 0.0)
+            .map(|rate| depth / -rate)
+    }
+
+    /// Return the arrival rate of the tasks (tasks queued per second).
+    fn arrival_rate(&self) -> f64 {
+        (self.dispatch_rate + self.rate.unwrap_or(0.0)).max(0.0)
+    }
+
+    /// Print the trend of the queue.
+    pub fn report(&self) {
+        let (Some(last), Some(rate)) = (self.last, self.rate()) else {
+            return;
+        };
+        let trend = match self.eta_secs() {
+            Some(eta) => format!("shrinking {:.1} tasks/s, empty in {:.1}s", -rate, eta),
+            None if rate > 0.0 => format!("growing {:.1} tasks/s", rate),
+            None => "steady".to_string(),
+        };
+
+        println!(
+            "queue: {} tasks | {} | arrivals/s: {:.0} | dispatches/s: {:.0}",
+            last.depth,
+            trend,
+            self.arrival_rate(),
+            self.dispatch_rate
+        );
+    }
+
+    /// Format the trend as a JSON object (null values before two samples are available and
+    /// for the time to drain a queue that is not shrinking).
+    pub fn json(&self) -> String {
+        let json_value =
+            |value: Option<f64>| value.map_or("null".to_string(), |v| format!("{:.3}", v));
+
+        format!(
+            "{{\"depth\":{},\"rate\":{},\"eta_secs\":{},\"arrival_rate\":{},\"dispatch_rate\":{}}}",
+            self.last.map_or(0, |last| last.depth),
+            json_value(self.rate()),
+            json_value(self.eta_secs()),
+            json_value(self.rate.map(|_| self.arrival_rate())),
+            json_value(self.rate.map(|_| self.dispatch_rate))
+        )
+    }
+}
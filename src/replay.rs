@@ -0,0 +1,602 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Replay of the traces recorded with --record (see trace.rs).
+//!
+//! The recorded events are fed back to the policy, configured with the command line options of
+//! the recorded session, through the main loop of the scheduler: since the policy is
+//! deterministic, it has to take exactly the same decisions, so any difference with respect to
+//! the recorded outputs (dispatched tasks, arguments of select_cpu() and notify_complete()) is
+//! reported.
+//!
+//! Timestamps (including the CPU time of the scheduler) and statistics are queries that the policy may issue a different amount of times
+//! (e.g., when the recorded session was serving the metrics endpoint or the control socket): a
+//! query answered by the trace consumes the recorded value, otherwise the last recorded value is
+//! returned.
+//!
+//! NOTE: the inputs that don't go through the backend are not recorded: the LLC topology (see
+//! --llc-group) and the hwmon sensor (see --thermal-sensor) are read again from the system where
+//! the trace is replayed, the tasks pinned via the control socket are not pinned, and the
+//! profiles scheduled with --profile-at follow the wall clock of the replay.
+
+use std::cell::RefCell;
+use std::fs;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use clap::CommandFactory;
+use clap::FromArgMatches;
+use clap::Parser;
+
+use crate::backend::Dispatch;
+use crate::backend::DispatchError;
+use crate::backend::SchedBackend;
+use crate::backend::Task;
+use crate::llc;
+use crate::trace;
+use crate::trace::Counter;
+use crate::trace::Event;
+use crate::trace::Outcome;
+use crate::whatif;
+use crate::Opts;
+use crate::Scheduler;
+
+// Maximum amount of differences reported.
+const MAX_REPORTED: usize = 20;
+
+/// Options of the replay subcommand.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReplayOpts {
+    /// Trace recorded with --record.
+    file: String,
+
+    /// Command line options that override the ones of the recorded session (e.g., --with
+    /// "--policy fair --slice-us 5000"): instead of checking the recorded decisions, the
+    /// recorded tasks are fed to the policy with the modified parameters and the resulting
+    /// metrics (latency, fairness, migrations) are compared side by side with the recorded
+    /// session (see whatif.rs).
+    #[clap(long, allow_hyphen_values = true)]
+    with: Option<String>,
+}
+
+/// Outcome of a replay.
+pub struct ReplayResult {
+    pub dispatches: Vec<Dispatch>, // Tasks dispatched by the policy
+    pub diffs: Vec<String>,        // Differences with respect to the recorded session
+    pub error: Option<String>,     // Error returned by the policy (if any)
+    pub nr_events: usize,          // Recorded events consumed by the policy
+}
+
+// Position in the trace and last recorded values of the queries.
+struct ReplayState {
+    events: Vec<Event>,                  // Recorded events
+    pos: usize,                          // Next event
+    now_ns: u64,                         // Last recorded timestamp
+    self_cpu_ns: u64,                    // Last recorded CPU time of the scheduler
+    counters: [u64; Counter::ALL.len()], // Last recorded statistics
+    dispatches: Vec<Dispatch>,           // Tasks dispatched by the policy
+    diffs: Vec<String>,                  // Differences with respect to the recording
+    stopped: bool,                       // The policy diverged from the trace
+}
+
+impl ReplayState {
+    // Consume the recorded queries up to the first one that satisfies `matches`, if it is found
+    // before the next event with side effects.
+    fn query(&mut self, matches: impl Fn(&Event) -> bool) {
+        let Some(n) = self.events[self.pos..]
+            .iter()
+            .take_while(|event| event.is_query())
+            .position(matches)
+        else {
+            return;
+        };
+        for _ in 0..=n {
+            self.consume();
+        }
+    }
+
+    // Consume the next event, updating the last recorded values of the queries, and return it.
+    fn consume(&mut self) -> Option<(usize, &Event)> {
+        let i = self.pos;
+        let event = self.events.get(i)?;
+        match event {
+            Event::Now(ns) => self.now_ns = *ns,
+            Event::SelfCpu(ns) => self.self_cpu_ns = *ns,
+            Event::Counter(counter, value) => self.counters[*counter as usize] = *value,
+            _ => {}
+        }
+        self.pos += 1;
+
+        Some((i, event))
+    }
+
+    // Consume the next event with side effects (None if the trace ended or the replay is
+    // stopped).
+    fn next_action(&mut self) -> Option<(usize, Event)> {
+        if self.stopped {
+            return None;
+        }
+        while let Some((i, event)) = self.consume() {
+            if !event.is_query() {
+                return Some((i, event.clone()));
+            }
+        }
+
+        None
+    }
+
+    // Stop the replay: the policy issued `replayed` while the trace contains `recorded`.
+    fn diverge(&mut self, recorded: Option<(usize, Event)>, replayed: &str) {
+        if self.stopped {
+            return;
+        }
+        let diff = match recorded {
+            Some((i, event)) => format!("event {}: recorded '{}', replayed {}", i, event, replayed),
+            None => format!("the trace ended, replayed {}", replayed),
+        };
+        self.diffs.push(diff);
+        self.stopped = true;
+    }
+
+    // Report a difference in the outputs of the policy (the replay can continue).
+    fn differ(&mut self, i: usize, recorded: &Event, replayed: &Event) {
+        self.diffs.push(format!(
+            "event {}: recorded '{}', replayed '{}'",
+            i, recorded, replayed
+        ));
+    }
+}
+
+/// Backend that answers the policy with the events of a recorded trace.
+pub struct ReplayBackend {
+    state: RefCell<ReplayState>, // Replay progress (also used by now_ns())
+    counters: [u64; Counter::ALL.len()], // Statistics returned by the nr_*_mut() methods
+}
+
+impl ReplayBackend {
+    pub fn new(events: Vec<Event>) -> Self {
+        Self {
+            state: RefCell::new(ReplayState {
+                events,
+                pos: 0,
+                now_ns: 0,
+                self_cpu_ns: 0,
+                counters: [0; Counter::ALL.len()],
+                dispatches: Vec::new(),
+                diffs: Vec::new(),
+                stopped: false,
+            }),
+            counters: [0; Counter::ALL.len()],
+        }
+    }
+
+    fn counter(&mut self, counter: Counter) -> &mut u64 {
+        let state = self.state.get_mut();
+        state.query(|event| matches!(event, Event::Counter(c, _) if *c == counter));
+        self.counters[counter as usize] = state.counters[counter as usize];
+
+        &mut self.counters[counter as usize]
+    }
+}
+
+impl SchedBackend for ReplayBackend {
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((_, Event::Dequeue(result))) => result,
+            recorded => {
+                state.diverge(recorded, "dequeue_task()");
+                Ok(None)
+            }
+        }
+    }
+
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, flags: u64) -> i32 {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::SelectCpu(r_pid, r_prev_cpu, r_flags, cpu))) => {
+                if (r_pid, r_prev_cpu, r_flags) != (pid, prev_cpu, flags) {
+                    state.differ(
+                        i,
+                        &Event::SelectCpu(r_pid, r_prev_cpu, r_flags, cpu),
+                        &Event::SelectCpu(pid, prev_cpu, flags, cpu),
+                    );
+                }
+                cpu
+            }
+            recorded => {
+                state.diverge(recorded, &format!("select_cpu() for pid {}", pid));
+                -libc::EBUSY
+            }
+        }
+    }
+
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Dispatch(recorded, outcome))) => {
+                if recorded != *task {
+                    state.differ(
+                        i,
+                        &Event::Dispatch(recorded, outcome),
+                        &Event::Dispatch(task.clone(), outcome),
+                    );
+                }
+                match outcome {
+                    Outcome::Ok => {
+                        state.dispatches.push(task.clone());
+                        Ok(())
+                    }
+                    Outcome::Busy => Err(DispatchError::Busy),
+                    Outcome::Fatal => Err(DispatchError::Fatal(anyhow!(
+                        "dispatch of pid {} failed in the recorded session",
+                        task.pid
+                    ))),
+                }
+            }
+            recorded => {
+                state.diverge(recorded, &format!("dispatch_task() for pid {}", task.pid));
+                Err(DispatchError::Busy)
+            }
+        }
+    }
+
+    fn notify_complete(&mut self, nr_pending: u64) {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Notify(recorded))) if recorded != nr_pending => {
+                state.differ(i, &Event::Notify(recorded), &Event::Notify(nr_pending));
+            }
+            Some((_, Event::Notify(_))) => {}
+            recorded => state.diverge(recorded, "notify_complete()"),
+        }
+    }
+
+    fn exited(&mut self) -> bool {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((_, Event::Exited(exited))) => exited,
+            // The end of the trace terminates the replay.
+            None => true,
+            recorded => {
+                state.diverge(recorded, "exited()");
+                true
+            }
+        }
+    }
+
+    fn exists(&mut self, pid: i32) -> bool {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Exists(recorded, exists))) => {
+                if recorded != pid {
+                    state.differ(
+                        i,
+                        &Event::Exists(recorded, exists),
+                        &Event::Exists(pid, exists),
+                    );
+                }
+                exists
+            }
+            recorded => {
+                state.diverge(recorded, &format!("exists() for pid {}", pid));
+                false
+            }
+        }
+    }
+
+    fn tgid(&mut self, pid: i32) -> Option<i32> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Tgid(recorded, tgid))) => {
+                if recorded != pid {
+                    state.differ(i, &Event::Tgid(recorded, tgid), &Event::Tgid(pid, tgid));
+                }
+                tgid
+            }
+            recorded => {
+                state.diverge(recorded, &format!("tgid() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::ParentCpu(recorded, cpu))) => {
+                if recorded != pid {
+                    state.differ(
+                        i,
+                        &Event::ParentCpu(recorded, cpu),
+                        &Event::ParentCpu(pid, cpu),
+                    );
+                }
+                cpu
+            }
+            recorded => {
+                state.diverge(recorded, &format!("parent_cpu() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn is_kthread(&mut self, pid: i32) -> Option<bool> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Kthread(recorded, kthread))) => {
+                if recorded != pid {
+                    state.differ(
+                        i,
+                        &Event::Kthread(recorded, kthread),
+                        &Event::Kthread(pid, kthread),
+                    );
+                }
+                kthread
+            }
+            recorded => {
+                state.diverge(recorded, &format!("is_kthread() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn waker(&mut self, pid: i32) -> Option<i32> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Waker(recorded, waker))) => {
+                if recorded != pid {
+                    state.differ(i, &Event::Waker(recorded, waker), &Event::Waker(pid, waker));
+                }
+                waker
+            }
+            recorded => {
+                state.diverge(recorded, &format!("waker() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::AllowedCpus(recorded, cpus))) => {
+                if recorded != pid {
+                    state.differ(
+                        i,
+                        &Event::AllowedCpus(recorded, cpus.clone()),
+                        &Event::AllowedCpus(pid, cpus.clone()),
+                    );
+                }
+                cpus
+            }
+            recorded => {
+                state.diverge(recorded, &format!("allowed_cpus() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::EnvHint(recorded, r_name, value))) => {
+                if (recorded, r_name.as_str()) != (pid, name) {
+                    state.differ(
+                        i,
+                        &Event::EnvHint(recorded, r_name, value),
+                        &Event::EnvHint(pid, name.to_string(), value),
+                    );
+                }
+                value
+            }
+            recorded => {
+                state.diverge(recorded, &format!("env_hint() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn comm(&mut self, pid: i32) -> Option<String> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((i, Event::Comm(recorded, comm))) => {
+                if recorded != pid {
+                    state.differ(
+                        i,
+                        &Event::Comm(recorded, comm.clone()),
+                        &Event::Comm(pid, comm.clone()),
+                    );
+                }
+                comm
+            }
+            recorded => {
+                state.diverge(recorded, &format!("comm() for pid {}", pid));
+                None
+            }
+        }
+    }
+
+    fn online_cpus(&mut self) -> Option<Vec<usize>> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((_, Event::OnlineCpus(cpus))) => cpus,
+            recorded => {
+                state.diverge(recorded, "online_cpus()");
+                None
+            }
+        }
+    }
+
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>> {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((_, Event::IsolatedCpus(cpus))) => cpus,
+            recorded => {
+                state.diverge(recorded, "isolated_cpus()");
+                None
+            }
+        }
+    }
+
+    fn local_minute(&mut self) -> u32 {
+        let state = self.state.get_mut();
+        match state.next_action() {
+            Some((_, Event::LocalMinute(minute))) => minute,
+            recorded => {
+                state.diverge(recorded, "local_minute()");
+                0
+            }
+        }
+    }
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::OnlineCpus)
+    }
+
+    fn nr_queued_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::Queued)
+    }
+
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::UserDispatches)
+    }
+
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::KernelDispatches)
+    }
+
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::BounceDispatches)
+    }
+
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::CancelDispatches)
+    }
+
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64 {
+        self.counter(Counter::FailedDispatches)
+    }
+
+    fn now_ns(&self) -> u64 {
+        let mut state = self.state.borrow_mut();
+        state.query(|event| matches!(event, Event::Now(_)));
+
+        state.now_ns
+    }
+
+    fn self_cpu_ns(&self) -> u64 {
+        let mut state = self.state.borrow_mut();
+        state.query(|event| matches!(event, Event::SelfCpu(_)));
+
+        state.self_cpu_ns
+    }
+}
+
+/// Feed the recorded `events` to the policy configured by `opts` (confining the processes to
+/// `llc_domains`, if specified) and collect its decisions.
+pub fn replay(
+    opts: &Opts,
+    events: Vec<Event>,
+    llc_domains: Option<Vec<Vec<usize>>>,
+) -> ReplayResult {
+    let mut sched = Scheduler::new(ReplayBackend::new(events), opts, None, None);
+    if let Some(domains) = llc_domains {
+        sched.set_llc_domains(domains);
+    }
+    let error = sched.run_loop().err().map(|err| err.to_string());
+    let state = sched.bpf.state.into_inner();
+
+    ReplayResult {
+        dispatches: state.dispatches,
+        diffs: state.diffs,
+        error,
+        nr_events: state.pos,
+    }
+}
+
+/// Replay the trace `replay.file` and report the differences with respect to the recorded
+/// dispatch decisions (exit code is nonzero if the decisions differ).
+pub fn run(replay_opts: &ReplayOpts) -> Result<()> {
+    let text = fs::read_to_string(&replay_opts.file)
+        .with_context(|| format!("Failed to read {}", replay_opts.file))?;
+    let trace =
+        trace::parse(&text).with_context(|| format!("Invalid trace {}", replay_opts.file))?;
+    let opts = Opts::try_parse_from(&trace.args)
+        .context("Invalid command line in the recorded session")?;
+    let llc_domains = opts.llc_group.then(llc::topology_domains).transpose()?;
+    if let Some(with) = &replay_opts.with {
+        return what_if(&trace.args, with, trace.events, llc_domains);
+    }
+    let nr_events = trace.events.len();
+
+    let result = replay(&opts, trace.events, llc_domains);
+    println!(
+        "replay: {} of {} events, {} tasks dispatched",
+        result.nr_events,
+        nr_events,
+        result.dispatches.len()
+    );
+    if let Some(err) = &result.error {
+        println!("replay: the policy failed: {}", err);
+    }
+    if !result.diffs.is_empty() {
+        for diff in result.diffs.iter().take(MAX_REPORTED) {
+            eprintln!("replay: {}", diff);
+        }
+        bail!(
+            "replay failed: {} differences with respect to the recorded session",
+            result.diffs.len()
+        );
+    }
+    println!("replay: OK, all the decisions match the recorded session");
+
+    Ok(())
+}
+
+/// Return the options of the command line `args` of a recorded session, modified by the
+/// options `with` (the last occurrence of an option wins).
+pub fn override_opts(args: &[String], with: &str) -> Result<Opts> {
+    let args = args
+        .iter()
+        .map(String::as_str)
+        .chain(with.split_whitespace());
+    let matches = Opts::command()
+        .args_override_self(true)
+        .try_get_matches_from(args)
+        .context("Invalid command line options in --with")?;
+
+    Ok(Opts::from_arg_matches(&matches)?)
+}
+
+/// Replay the recorded `events` with the command line `args` of the recorded session modified by
+/// the options `with` and compare the metrics with the recorded session.
+fn what_if(
+    args: &[String],
+    with: &str,
+    events: Vec<Event>,
+    llc_domains: Option<Vec<Vec<usize>>>,
+) -> Result<()> {
+    let args = args
+        .iter()
+        .map(String::as_str)
+        .chain(with.split_whitespace());
+    let matches = Opts::command()
+        .args_override_self(true)
+        .try_get_matches_from(args)
+        .context("Invalid command line options in --with")?;
+    let opts = Opts::from_arg_matches(&matches)?;
+
+    let recorded = whatif::recorded_metrics(&events);
+    let modified = whatif::simulate(&opts, events, llc_domains)
+        .map_err(|err| anyhow!("the policy failed with the modified parameters: {}", err))?;
+    println!("replay: recorded session vs. {}", with.trim());
+    whatif::compare(&recorded, &modified);
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+use crate::TaskClass;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Format an amount of parts per million as a percentage.
+fn pct(ppm: u64) -> String {
+    format!("{}.{:02}%", ppm / 10_000, ppm % 10_000 / 100)
+}
+
+// Index of a class in the per-class accounting.
+fn idx(class: TaskClass) -> usize {
+    match class {
+        TaskClass::Interactive => 0,
+        TaskClass::Batch => 1,
+    }
+}
+
+// Return the class competing with `class`.
+fn other(class: TaskClass) -> TaskClass {
+    match class {
+        TaskClass::Interactive => TaskClass::Batch,
+        TaskClass::Batch => TaskClass::Interactive,
+    }
+}
+
+// Time slice assigned to a dispatched task that has not been received again yet.
+struct InFlight {
+    class: usize,  // Class of the task when it has been dispatched
+    slice_ns: u64, // Assigned time slice
+    ts: u64,       // Dispatch time
+}
+
+/// Accountant of the CPU capacity reserved to each task class (see --reserve-interactive-pct
+/// and --reserve-batch-pct).
+///
+/// The CPU time used by the tasks of each class (sum_exec_runtime delta, accounted when a task
+/// is received again) is accounted over one-second intervals, relative to the total CPU
+/// capacity elapsed in the interval (elapsed time multiplied by the online CPUs), in parts per
+/// million.
+///
+/// Reserving a share of the capacity to a class caps the other class to the rest of it: while
+/// a class is above its cap and the other one is below its reservation, the class is throttled
+/// (its tasks are held back in the queues) until its usage drops back below the cap. The time
+/// slices of the tasks of a class that are still running (dispatched and not received again)
+/// count towards its cap, otherwise the class would exceed it by the time they are accounted.
+///
+/// The cap only applies if the class with the reservation has been active in the current
+/// interval, otherwise the capacity it doesn't use is left to the other class.
+pub struct Reservation {
+    reserved_ppm: [u64; 2],            // Capacity reserved to each class
+    used_ns: [u64; 2],                 // CPU time used by each class in the current interval
+    in_flight: HashMap<i32, InFlight>, // Tasks dispatched and not received again yet
+    in_flight_ns: [u64; 2],            // Time slices of the tasks in flight for each class
+    active: [bool; 2],                 // Classes that received tasks in the current interval
+    interval_ts: u64,                  // Beginning of the current interval
+    last_ppm: [u64; 2],                // Capacity used by each class in the last interval
+    nr_throttled: u64,                 // Times a class has been held back (since last report)
+}
+
+impl Reservation {
+    pub fn new(interactive_ppm: u64, batch_ppm: u64, now: u64) -> Self {
+        Self {
+            reserved_ppm: [interactive_ppm, batch_ppm],
+            used_ns: [0; 2],
+            in_flight: HashMap::new(),
+            in_flight_ns: [0; 2],
+            active: [false; 2],
+            interval_ts: now,
+            last_ppm: [0; 2],
+            nr_throttled: 0,
+        }
+    }
+
+    /// Account a task of `class` dispatched at time `now` with a time slice of `slice_ns`.
+    pub fn record_dispatch(&mut self, pid: i32, class: TaskClass, slice_ns: u64, now: u64) {
+        self.land(pid);
+        self.in_flight.insert(
+            pid,
+            InFlight {
+                class: idx(class),
+                slice_ns,
+                ts: now,
+            },
+        );
+        self.in_flight_ns[idx(class)] = self.in_flight_ns[idx(class)].saturating_add(slice_ns);
+    }
+
+    /// Account `runtime_ns` of CPU time used by a task of `class`, received again.
+    pub fn record(&mut self, pid: i32, class: TaskClass, runtime_ns: u64) {
+        self.land(pid);
+        self.used_ns[idx(class)] = self.used_ns[idx(class)].saturating_add(runtime_ns);
+        self.active[idx(class)] = true;
+    }
+
+    // Stop accounting the time slice of a task in flight.
+    fn land(&mut self, pid: i32) {
+        if let Some(task) = self.in_flight.remove(&pid) {
+            self.in_flight_ns[task.class] =
+                self.in_flight_ns[task.class].saturating_sub(task.slice_ns);
+        }
+    }
+
+    /// Return true if the tasks of `class` need to be held back at time `now` to preserve the
+    /// capacity reserved to the other class.
+    pub fn throttled(&self, class: TaskClass, now: u64, nr_cpus: u64) -> bool {
+        let reserved_ppm = self.reserved_ppm[idx(other(class))];
+        if reserved_ppm == 0 || !self.active[idx(other(class))] {
+            return false;
+        }
+        let capacity = now.saturating_sub(self.interval_ts) as u128 * nr_cpus.max(1) as u128;
+        let used = self.used_ns[idx(class)].saturating_add(self.in_flight_ns[idx(class)]);
+        let used = used as u128 * 1_000_000;
+        let other_used = self.used_ns[idx(other(class))] as u128 * 1_000_000;
+
+        used > (1_000_000 - reserved_ppm) as u128 * capacity
+            && other_used < reserved_ppm as u128 * capacity
+    }
+
+    /// Account a task held back by the reservation.
+    pub fn record_throttled(&mut self) {
+        self.nr_throttled += 1;
+    }
+
+    /// Close the current interval (once per second), given the amount of online CPUs.
+    pub fn evaluate(&mut self, now: u64, nr_cpus: u64) {
+        let elapsed = now.saturating_sub(self.interval_ts);
+        if elapsed < NSEC_PER_SEC {
+            return;
+        }
+        let capacity = elapsed as u128 * nr_cpus.max(1) as u128;
+        for (last_ppm, used_ns) in self.last_ppm.iter_mut().zip(self.used_ns) {
+            *last_ppm = (used_ns as u128 * 1_000_000 / capacity) as u64;
+        }
+
+        // Forget the tasks that have been in flight for a whole interval (e.g., exited tasks).
+        let interval_ts = self.interval_ts;
+        let in_flight_ns = &mut self.in_flight_ns;
+        self.in_flight.retain(|_, task| {
+            let keep = task.ts >= interval_ts;
+            if !keep {
+                in_flight_ns[task.class] = in_flight_ns[task.class].saturating_sub(task.slice_ns);
+            }
+            keep
+        });
+
+        self.interval_ts = now;
+        self.used_ns = [0; 2];
+        self.active = [false; 2];
+    }
+
+    /// Print the capacity used by each class in the last completed interval and the amount of
+    /// tasks held back, resetting the latter.
+    pub fn report(&mut self) {
+        println!(
+            "reservation: interactive {} (reserved {}) | batch {} (reserved {}) | throttled: {}",
+            pct(self.last_ppm[0]),
+            pct(self.reserved_ppm[0]),
+            pct(self.last_ppm[1]),
+            pct(self.reserved_ppm[1]),
+            self.nr_throttled
+        );
+        self.nr_throttled = 0;
+    }
+}
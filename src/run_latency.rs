@@ -0,0 +1,144 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+use crate::metrics;
+use crate::metrics::LatencyHistogram;
+
+// State of a dispatched task, sampled when the task has been dispatched.
+struct DispatchState {
+    ts: u64,      // Time of the dispatch
+    runtime: u64, // Total CPU time of the task when it has been dispatched
+    nvcsw: u64,   // Voluntary context switches of the task when it has been dispatched
+}
+
+/// Dispatch-to-run latency: time elapsed between the dispatch of a task and the moment it
+/// actually starts running on a CPU (exposed by the metrics endpoint, see --metrics-addr).
+///
+/// Unlike the scheduling latency (time spent in the user-space queues), this also includes the
+/// time spent by the task in the dispatch queues of the BPF component and of the kernel, waiting
+/// for a CPU. Each dispatched task is sampled when it is received again, from one of these
+/// sources:
+///
+///  - kernel: the time when the task started running, reported by the backend (see
+///    SchedBackend::run_start_ns()); this is the exact latency, also for the tasks that blocked
+///    after running, and it is used whenever it is available,
+///
+///  - estimated: if the start time is not available (scx_rustland_core doesn't report it yet),
+///    the latency is estimated as the time elapsed since the dispatch minus the CPU time used in
+///    the meantime (sum_exec_runtime delta). This is only accurate for the tasks that ran without
+///    releasing the CPU (no voluntary context switch since the dispatch) and it is an upper
+///    bound: it also includes the time the task spent preempted and the time between the end of
+///    its time slice and the moment it has been received again (usually a few microseconds),
+///    while the tasks that blocked are not sampled at all, since their sleep time is unknown.
+///
+/// The two sources are kept in separate histograms, so that the estimates are never mistaken
+/// for the exact values.
+pub struct RunLatency {
+    dispatched: HashMap<i32, DispatchState>, // Tasks dispatched and not received again yet
+    kernel: LatencyHistogram,                // Latencies from the start time of the backend
+    estimated: LatencyHistogram,             // Latencies estimated from the CPU time used
+    nr_unsampled: u64,                       // Tasks not sampled (blocked without start time)
+}
+
+impl RunLatency {
+    pub fn new() -> Self {
+        Self {
+            dispatched: HashMap::new(),
+            kernel: LatencyHistogram::new(),
+            estimated: LatencyHistogram::new(),
+            nr_unsampled: 0,
+        }
+    }
+
+    /// Account a task dispatched at time `now`, with a total CPU time of `runtime` and `nvcsw`
+    /// voluntary context switches.
+    pub fn record_dispatch(&mut self, pid: i32, now: u64, runtime: u64, nvcsw: u64) {
+        self.dispatched.insert(
+            pid,
+            DispatchState {
+                ts: now,
+                runtime,
+                nvcsw,
+            },
+        );
+    }
+
+    /// Sample the latency of a task received again at time `now`, with a total CPU time of
+    /// `runtime` and `nvcsw` voluntary context switches, given the time it started running
+    /// reported by the backend (if any).
+    pub fn record_enqueue(
+        &mut self,
+        pid: i32,
+        now: u64,
+        runtime: u64,
+        nvcsw: u64,
+        run_start: Option<u64>,
+    ) {
+        let Some(state) = self.dispatched.remove(&pid) else {
+            return;
+        };
+
+        // Ignore a start time that precedes the dispatch: the backend doesn't know when the
+        // task started running after this dispatch.
+        match run_start.filter(|&ts| ts >= state.ts && ts <= now) {
+            Some(ts) => self.kernel.record(pid, ts - state.ts),
+            None if nvcsw == state.nvcsw => {
+                let used_ns = runtime.saturating_sub(state.runtime);
+                let elapsed_ns = now.saturating_sub(state.ts);
+                self.estimated
+                    .record(pid, elapsed_ns.saturating_sub(used_ns));
+            }
+            None => self.nr_unsampled += 1,
+        }
+    }
+
+    /// Drop the dispatched tasks that don't satisfy `alive` (e.g., exited tasks).
+    pub fn retain(&mut self, alive: impl Fn(i32) -> bool) {
+        self.dispatched.retain(|&pid, _| alive(pid));
+    }
+
+    /// Drop the dispatch of a task that is not tracked anymore (see --max-tracked-pids).
+    pub fn forget(&mut self, pid: i32) {
+        self.dispatched.remove(&pid);
+    }
+
+    /// Return the amount of samples and their sum (in nanoseconds) from the kernel start time
+    /// and estimated, and the amount of tasks that haven't been sampled.
+    pub fn totals(&self) -> ((u64, u64), (u64, u64), u64) {
+        (
+            self.kernel.total(),
+            self.estimated.total(),
+            self.nr_unsampled,
+        )
+    }
+
+    /// Append the histograms to `out` in OpenMetrics text format, optionally including the
+    /// exemplars.
+    pub fn write_openmetrics(&self, out: &mut String, exemplars: bool) {
+        self.kernel.write_openmetrics(
+            out,
+            "scx_rust_scheduler_run_latency_seconds",
+            "Time between the dispatch of the tasks and the moment they started running, \
+             from the start time reported by the kernel (exact).",
+            exemplars,
+        );
+        self.estimated.write_openmetrics(
+            out,
+            "scx_rust_scheduler_run_latency_estimated_seconds",
+            "Estimated time between the dispatch of the tasks and the moment they started \
+             running (time since the dispatch minus the CPU time used, upper bound), only for \
+             the tasks without a kernel start time that didn't block.",
+            exemplars,
+        );
+        metrics::write_counter(
+            out,
+            "scx_rust_scheduler_run_latency_unsampled",
+            "Dispatched tasks without a kernel start time that blocked (latency not estimated).",
+            self.nr_unsampled,
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Integration with the scx_stats framework (built with `--features stats`), so that the stats
+//! of the scheduler can be queried with the same tooling used for the other sched_ext
+//! schedulers.
+//!
+//! The stats are served on the standard UNIX socket (/var/run/scx/root/stats) as the `top`
+//! target, with the same counters of the stats snapshot of the control socket (see
+//! StatsSnapshot), e.g.:
+//!
+//!   $ echo '{"req":"stats","args":{"target":"top"}}' | \
+//!       sudo socat - UNIX-CONNECT:/var/run/scx/root/stats
+//!
+//! and the description of the counters is returned by the `stats_meta` request.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use scx_stats::prelude::*;
+use scx_stats_derive::Stats;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::control::StatsSnapshot;
+
+/// Standard socket of the scx_stats server of the sched_ext schedulers.
+pub const SCX_STATS_PATH: &str = "/var/run/scx/root/stats";
+
+/// Counters registered with scx_stats.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Stats)]
+#[stat(top, desc = "scx_rust_scheduler statistics")]
+pub struct Metrics {
+    #[stat(desc = "Snapshot timestamp (CLOCK_MONOTONIC, ns)")]
+    pub now_ns: u64,
+    #[stat(desc = "Tasks dispatched by the user-space scheduler")]
+    pub user_dispatches: u64,
+    #[stat(desc = "Tasks dispatched directly by the BPF component")]
+    pub kernel_dispatches: u64,
+    #[stat(desc = "Tasks waiting in the interactive queue")]
+    pub interactive: u64,
+    #[stat(desc = "Tasks waiting in the batch queue")]
+    pub batch: u64,
+    #[stat(desc = "Dispatch attempts retried (BPF component busy)")]
+    pub dispatch_retries: u64,
+    #[stat(desc = "Tasks re-queued after exhausting all the dispatch retries")]
+    pub dispatch_requeues: u64,
+    #[stat(desc = "Tasks tracked by the scheduler")]
+    pub tasks: u64,
+    #[stat(desc = "Dispatches bounced by the BPF component (invalid CPU)")]
+    pub bounce_dispatches: u64,
+    #[stat(desc = "Dispatches cancelled by the BPF component")]
+    pub cancel_dispatches: u64,
+    #[stat(desc = "Dispatches failed by the BPF component")]
+    pub failed_dispatches: u64,
+    #[stat(desc = "Response to the failed dispatches (0 = disabled, 1 = idle)")]
+    pub backpressure_scale: u64,
+}
+
+impl From<&StatsSnapshot> for Metrics {
+    fn from(s: &StatsSnapshot) -> Self {
+        Self {
+            now_ns: s.now_ns,
+            user_dispatches: s.nr_user_dispatches,
+            kernel_dispatches: s.nr_kernel_dispatches,
+            interactive: s.nr_interactive,
+            batch: s.nr_batch,
+            dispatch_retries: s.nr_dispatch_retries,
+            dispatch_requeues: s.nr_dispatch_requeues,
+            tasks: s.nr_tasks,
+            bounce_dispatches: s.nr_bounce_dispatches,
+            cancel_dispatches: s.nr_cancel_dispatches,
+            failed_dispatches: s.nr_failed_dispatches,
+            backpressure_scale: s.backpressure_scale,
+        }
+    }
+}
+
+/// scx_stats server.
+///
+/// As with the metrics endpoint, the counters are periodically published by the scheduler
+/// (see update()) and served from the threads of the server, that only read the last published
+/// copy.
+pub struct ScxStats {
+    _server: StatsServer<(), ()>,   // Running server (stopped when dropped)
+    published: Arc<Mutex<Metrics>>, // Last counters published by the scheduler
+}
+
+impl ScxStats {
+    /// Start serving the stats on the UNIX socket `path` (see SCX_STATS_PATH).
+    pub fn start(path: &str) -> Result<Self> {
+        let published = Arc::new(Mutex::new(Metrics::default()));
+
+        let shared = published.clone();
+        let data = StatsServerData::<(), ()>::new()
+            .add_meta(Metrics::meta())
+            .add_stats(
+                "top",
+                Box::new(move |_args, (_tx, _rx)| shared.lock().unwrap().to_json()),
+            );
+        let server = StatsServer::new(data).set_path(path).launch()?;
+
+        Ok(Self {
+            _server: server,
+            published,
+        })
+    }
+
+    /// Replace the counters served by the server.
+    pub fn update(&self, snapshot: &StatsSnapshot) {
+        *self.published.lock().unwrap() = Metrics::from(snapshot);
+    }
+}
@@ -0,0 +1,6106 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::env;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::UdpSocket;
+use std::os::unix::net::UnixDatagram;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use clap::Parser;
+
+use crate::backend::Dispatch;
+use crate::backend::SchedBackend;
+use crate::backend::Task;
+use crate::batch_size::BatchSizes;
+use crate::bpf::RL_CPU_ANY;
+use crate::cache::CacheMonitor;
+use crate::comm_cap;
+use crate::control::StatsSnapshot;
+use crate::cpulist;
+use crate::cpulist::CpuList;
+use crate::dispatch_ratio::DispatchRatio;
+use crate::export::DecisionExporter;
+use crate::export::CSV_HEADER;
+use crate::forkbomb::FORK_BOMB_SLICE_NS;
+use crate::hysteresis::Hysteresis;
+use crate::idle::IdleHistory;
+use crate::invariants;
+use crate::invariants::Bounds;
+use crate::invariants::InvariantChecker;
+use crate::latency_target::LatencyTarget;
+use crate::loop_gap::LoopGap;
+use crate::lru::PidLru;
+use crate::metrics::MetricsServer;
+use crate::migration::MigrationLock;
+use crate::migration::MIGRATION_OVERLOAD;
+use crate::mock::MockBackend;
+use crate::numa::NumaAffinity;
+use crate::numa::NumaFallback;
+use crate::profiles;
+use crate::queue_trend::QueueTrend;
+use crate::replay;
+use crate::run_latency::RunLatency;
+#[cfg(feature = "stats")]
+use crate::scx_stats_server::Metrics;
+#[cfg(feature = "stats")]
+use crate::scx_stats_server::ScxStats;
+use crate::shadow::ShadowPolicy;
+use crate::slice_expr;
+use crate::slice_override;
+use crate::slice_override::LATENCY_MAX_NS;
+use crate::slice_override::LATENCY_MIN_NS;
+use crate::snapshot;
+use crate::state_log::StateLog;
+use crate::systemd::Notifier;
+use crate::timetable;
+use crate::topview::TopView;
+use crate::trace;
+use crate::trace::Event;
+use crate::trace::Recorder;
+use crate::udp_stats::UdpStats;
+use crate::validate;
+use crate::wake_affine::WAKE_AFFINE_WINDOW_NS;
+use crate::wakeup_gap::WakeupGap;
+use crate::weight_classes::WeightBounds;
+use crate::whatif;
+use crate::DuplicatePid;
+use crate::InitialCpu;
+use crate::InvariantMode;
+use crate::Mode;
+use crate::Opts;
+use crate::Order;
+use crate::Policy;
+use crate::Scheduler;
+use crate::TaskClass;
+use crate::CPUSET_REFRESH_NS;
+use crate::DEQUEUE_ERRORS_RESTART;
+use crate::DISPATCH_RETRIES;
+use crate::IDLE_STATS_HEARTBEAT_SECS;
+use crate::INTERACTIVE_SLICE_NS;
+use crate::MAX_CHARGE_NS;
+use crate::NSEC_PER_SEC;
+use crate::ROUND_BUDGET_CHECK;
+use crate::STARVATION_NS;
+
+// Amount of simulated CPUs.
+const NR_CPUS: u64 = 4;
+
+// Simulated time (in nanoseconds) between two consecutive scheduling rounds.
+const ROUND_NS: u64 = 1_000_000;
+
+// Amount of simulated scheduling rounds (10s of simulated time).
+const NR_ROUNDS: u64 = 10_000;
+
+// Total simulated time (in nanoseconds).
+pub const SIM_NS: u64 = NR_ROUNDS * ROUND_NS;
+
+// Maximum amount of rounds that a runnable task with the default weight can wait before being
+// considered starved (the limit is scaled for tasks with a lower weight, since a fair policy is
+// expected to delay them proportionally).
+const MAX_WAIT_ROUNDS: u64 = 2 * STARVATION_NS / ROUND_NS;
+
+// Additional rounds that a task can wait after exceeding --starve-timeout-ms (other tasks may
+// have exceeded the timeout at the same time, or the backend may be congested).
+const STARVE_SLACK_ROUNDS: u64 = 10;
+
+// Starvation timeout (in milliseconds) and time slice (in microseconds) used with the
+// adversarial weights (see check_starve_timeout()).
+const STARVE_CHECK_TIMEOUT_MS: u64 = 20;
+const STARVE_CHECK_SLICE_US: u64 = 5000;
+
+// Interval (in rounds) between two simulated congestion events: during a congestion event the
+// mock backend rejects more dispatch attempts than the scheduler is willing to retry.
+const CONGESTION_ROUNDS: u64 = 100;
+
+// LLC domains of the simulated CPUs (used with --llc-group).
+const LLC_DOMAINS: [&[usize]; 2] = [&[0, 1], &[2, 3]];
+
+// Cpusets of the task confined to a subset of the CPUs (used with --cpuset-aware): the hog
+// without a previously used CPU is confined to the CPUs of the second LLC domain and then moved
+// to a single CPU of the same domain at CPUSET_MOVE_ROUND (the task is not confined if one of
+// the cpusets only contains CPUs excluded by --cpus-offline, since it couldn't run anywhere).
+const CPUSET_PID: i32 = 200;
+const CPUSETS: [&[usize]; 2] = [&[2, 3], &[3]];
+const CPUSET_MOVE_ROUND: u64 = NR_ROUNDS / 2;
+
+// Parameters of the session recorded to verify the replay (see check_replay()): amount of
+// rounds (less than one second, so that no stats are printed), tasks and random seed.
+const REPLAY_ROUNDS: u64 = 500;
+const REPLAY_TASKS: i32 = 12;
+const REPLAY_SEED: u64 = 42;
+
+// Interval (in rounds) between two simulated duplicate tasks: a task that wakes up is received
+// twice in the same round.
+const DUPLICATE_ROUNDS: u64 = 1000;
+
+// Latency requested by the tasks of the EDF check (in microseconds, None if the task doesn't
+// request any latency), in the order they are received: the last one tries to abuse the hint
+// with a latency below the accepted bounds.
+const EDF_LATENCIES: [Option<u64>; 4] = [Some(50_000), None, Some(2_000), Some(1)];
+
+// Thresholds (in microseconds) of the wakeup gap controller and synthetic series of wakeup gaps
+// used to drive it (see check_wakeup_gap()): duration of each phase (in seconds), gap between
+// two consecutive wakeups (in microseconds), whether tasks are left waiting between the wakeups
+// and the expected time slice scale at the end of the phase.
+const WAKEUP_GAP_HIGH_US: u64 = 2_000;
+const WAKEUP_GAP_LOW_US: u64 = 1_000;
+const WAKEUP_GAP_SERIES: [(u64, u64, bool, u64); 5] = [
+    (5, 200, true, 1),     // Scheduler keeping up
+    (5, 5_000, true, 8),   // Scheduler lagging behind: scaled up to the maximum
+    (3, 1_500, true, 8),   // Between the thresholds: no change
+    (3, 50_000, false, 8), // Idle system: the gaps are not accounted
+    (5, 200, true, 1),     // Scheduler caught up: time slices restored
+];
+
+// CPU where the task pinned via the control socket is dispatched (different from its previously
+// used CPU) and amount of rounds it is received while pinned (see check_pin()).
+const PIN_CPU: i32 = 2;
+const PIN_ROUNDS: u64 = 100;
+
+// Overhead ceiling (in percent of the total CPU time) and simulated CPU usage of the scheduler
+// (in percent of one CPU) above and below the ceiling (see check_overhead()).
+const OVERHEAD_MAX_PCT: f64 = 1.0;
+const OVERHEAD_HIGH_PCT: u64 = 20;
+const OVERHEAD_LOW_PCT: u64 = 1;
+
+// Amount of tasks that wake up together after sleeping, all expecting a full time slice of
+// wakeup credit, and of one-second intervals where they wake up (see check_boost_budget()).
+const BOOST_TASKS: i32 = 32;
+const BOOST_INTERVALS: u64 = 3;
+
+// Fork bomb simulation (see check_fork_bomb()): tasks already known when the surge begins,
+// threshold of new tasks per second and amount of new tasks received all together.
+const FORK_BOMB_KNOWN: i32 = 2;
+const FORK_BOMB_THRESH: u64 = 8;
+const FORK_BOMB_SURGE: i32 = 200;
+
+// Reservation simulation (see check_reservation()): capacity reserved to the interactive tasks
+// (in percent), CPU hogs, interactive tasks and their bursts and sleeps (each one would use 2/3
+// of a CPU), time slice, simulated time (including the first second, to get the tasks
+// classified) and time between two scheduling rounds (shorter than the time slices, that are
+// scaled down by the many hogs waiting).
+const RESERVE_INTERACTIVE_PCT: u64 = 50;
+const RESERVE_HOGS: i32 = 16;
+const RESERVE_INTERACTIVE: i32 = NR_CPUS as i32;
+const RESERVE_RUN_NS: u64 = 2_000_000;
+const RESERVE_SLEEP_NS: u64 = 1_000_000;
+const RESERVE_SLICE_US: u64 = 5000;
+const RESERVE_SECS: u64 = 4;
+const RESERVE_ROUND_NS: u64 = 100_000;
+
+// Policy activation simulation (see check_policy_activation()): tasks waiting that engage the
+// edf policy and new tasks received in each round, with the expected state of the policy (the
+// second round engages it, the third one keeps it engaged between half of the threshold and the
+// threshold, the last one doesn't engage it below the threshold).
+const ACTIVATION_THRESH: u64 = 8;
+const ACTIVATION_ROUNDS: [(i32, bool); 5] =
+    [(3, false), (10, true), (0, true), (0, false), (6, false)];
+
+// Weight change simulation (see check_weight_change()): initial and new weight of the task that
+// is reniced, and rounds before the renice.
+const RENICE_WEIGHTS: (u64, u64) = (10000, 100);
+const RENICE_ROUND: u64 = 10;
+
+// Dispatch order simulation (see check_order()): tasks received in each round on a single CPU
+// (one task dispatched per round) and expected dispatch order with each discipline.
+const ORDER_ARRIVALS: [&[i32]; 6] = [&[1, 2, 3], &[4, 5], &[], &[6], &[], &[]];
+const ORDER_EXPECTED: [(Order, [i32; 6]); 2] = [
+    (Order::Fifo, [1, 2, 3, 4, 5, 6]),
+    (Order::Lifo, [3, 5, 4, 6, 2, 1]),
+];
+
+// Cache miss rates simulation (see check_cache_aware()): threshold (in misses per thousand
+// instructions) and sequence of per-CPU miss rates, with the expected thrashing CPUs and the CPU
+// picked for a task that would run on CPU 0 (CPU 3 has no counters in the second sample).
+const CACHE_MISS_THRESH: u64 = 20;
+type CacheSample = ([Option<u64>; 4], [bool; 4], Option<usize>);
+const CACHE_SAMPLES: [CacheSample; 3] = [
+    (
+        [Some(30), Some(5), Some(2), Some(15)],
+        [true, false, false, false],
+        Some(2),
+    ),
+    (
+        [Some(12), Some(25), Some(4), None],
+        [true, true, false, false],
+        Some(2),
+    ),
+    (
+        [Some(9), Some(25), Some(4), Some(1)],
+        [false, true, false, false],
+        None,
+    ),
+];
+
+// Grace period (in seconds) of the per-task state (see check_pid_gc()) and virtual runtime of
+// the task that sleeps for most of it.
+const PID_GC_SECS: u64 = 3;
+const PID_GC_VTIME: u64 = 123_456;
+
+// NUMA fallback simulation (see check_numa_fallback()): CPUs of each node, rounds where no
+// idle CPU is available (one task per CPU is dispatched in each round) and CPU excluded from
+// dispatch in the second run.
+const NUMA_NODES: [&[usize]; 2] = [&[0, 1], &[2, 3]];
+const NUMA_ROUNDS: u64 = 50;
+const NUMA_EXCLUDED_CPU: usize = 3;
+
+// Comm cap simulation (see check_comm_cap()): cap of the tasks named COMM_CAPPED (in
+// microseconds per second, not a multiple of the time slices), simulated time (in seconds) and
+// time of the exec() that renames an uncapped task to COMM_CAPPED.
+const COMM_CAPPED: &str = "stress-ng";
+const COMM_CAP_US: u64 = 201_500;
+const COMM_CAP_SECS: u64 = 6;
+const COMM_EXEC_SECS: u64 = 2;
+
+// Latency target simulation (see check_latency_target()): target latency, minimum time between
+// two relaxing steps and, for each one-second interval, the latency of the dispatched tasks (0 =
+// nothing dispatched), with the expected divisor of the time slices and factor of the tasks per
+// round at the end of the interval. Each interval also has one outlier of LATENCY_OUTLIER_NS,
+// that must not affect the 99th percentile.
+const LATENCY_TARGET_US: u64 = 500;
+const LATENCY_HYSTERESIS_NS: u64 = 2 * NSEC_PER_SEC;
+const LATENCY_OUTLIER_NS: u64 = 100_000_000;
+const LATENCY_FEEDBACK: [(u64, u64, u64); 15] = [
+    (2000, 2, 1),
+    (2000, 4, 1),
+    (2000, 8, 1),
+    (2000, 8, 2),
+    (2000, 8, 4),
+    (2000, 8, 4),
+    (100, 8, 2),
+    (100, 8, 2),
+    (100, 8, 1),
+    (400, 8, 1),
+    (0, 8, 1),
+    (100, 4, 1),
+    (800, 8, 1),
+    (100, 8, 1),
+    (100, 4, 1),
+];
+
+// Yield simulation (see check_yield()): minimum time slice (in microseconds) used in the second
+// run and time slice (in microseconds) requested by a task below the minimum.
+const YIELD_SLICE_MIN_US: u64 = 250;
+const YIELD_SHORT_SLICE_US: u64 = 10;
+
+// systemd notifications (see check_systemd()): watchdog timeout (in microseconds, heartbeats
+// are expected at half of it) and rounds of the simulated session (less than one second, so that
+// no stats are printed).
+const SYSTEMD_WATCHDOG_USEC: u64 = 400_000;
+const SYSTEMD_ROUNDS: u64 = 500;
+
+// notify_complete() circuit breaker (see check_notify_stall()): threshold (in milliseconds),
+// consecutive slow calls that trip it, and rounds of the simulated sessions.
+const NOTIFY_STALL_MS: u64 = 50;
+const NOTIFY_STALL_COUNT: u64 = 3;
+const NOTIFY_STALL_ROUNDS: u64 = 200;
+
+// Sessions of the notify_complete() circuit breaker: --notify-stall-ms, duration of the slow
+// calls (in milliseconds, each call also includes the simulated round, one more millisecond),
+// amount of slow calls at the beginning of the session, and expected result (stalled).
+const NOTIFY_STALL_SESSIONS: [(Option<u64>, u64, u64, bool); 4] = [
+    (
+        Some(NOTIFY_STALL_MS),
+        NOTIFY_STALL_MS * 2,
+        NOTIFY_STALL_COUNT,
+        true,
+    ),
+    (
+        Some(NOTIFY_STALL_MS),
+        NOTIFY_STALL_MS * 2,
+        NOTIFY_STALL_COUNT - 1,
+        false,
+    ),
+    (
+        Some(NOTIFY_STALL_MS),
+        NOTIFY_STALL_MS - 1,
+        NOTIFY_STALL_COUNT + 1,
+        false,
+    ),
+    (None, NOTIFY_STALL_MS * 2, NOTIFY_STALL_COUNT + 1, false),
+];
+
+// Decision export (see check_export()): rounds of the simulated session (less than one second,
+// so that no stats are printed) and amount of tasks.
+const EXPORT_ROUNDS: u64 = 200;
+const EXPORT_TASKS: i32 = 6;
+
+// Named profiles (see check_profiles()): profiles defined on the command line, with the
+// expected policy, time slice (in microseconds) and compute boost once activated, and profile
+// definitions that must be rejected.
+const PROFILES: [(&str, &str, Policy, u64, bool); 2] = [
+    (
+        "gaming",
+        "gaming:policy=fair,slice-us=2000,compute-boost=true",
+        Policy::Fair,
+        2000,
+        true,
+    ),
+    (
+        "server",
+        "server: policy=EDF, slice-us=20000",
+        Policy::Edf,
+        20_000,
+        false,
+    ),
+];
+const PROFILES_INVALID: [&str; 7] = [
+    "default:policy=fair",
+    "gaming",
+    ":slice-us=1000",
+    "gaming:policy=cfs",
+    "gaming:slice-us=0",
+    "gaming:compute-boost=yes",
+    "gaming:nice=-20",
+];
+
+// Time slice divergence (see check_slice_divergence()): CPU time used by the simulated tasks
+// per time slice (in percent) and expected outcome (diverging), simulated time of each session
+// (in seconds), tolerance and maximum time slice (in microseconds, large enough for a
+// divergence to exceed the tick of slack allowed to each task).
+const DIVERGENCE_RUNTIME: [(u64, bool); 5] = [
+    (100, false),
+    (140, false),
+    (300, true),
+    (20, true),
+    (60, false),
+];
+const DIVERGENCE_SECS: u64 = 4;
+const DIVERGENCE_TOLERANCE_PCT: u64 = 50;
+const DIVERGENCE_SLICE_US: u64 = 20_000;
+
+// Stats of the idle intervals (see check_idle_stats()): simulated time (in seconds) and
+// intervals with user-space dispatches (the rest only have kernel dispatches at IDLE_KERNEL_SEC).
+const IDLE_STATS_SECS: u64 = 160;
+const IDLE_ACTIVE_SECS: [u64; 3] = [1, 2, 3];
+const IDLE_KERNEL_SEC: u64 = 100;
+
+// CPU reserved to the kernel dispatches (see check_kernel_cpu()) and scheduling rounds of each
+// session (the tasks previously used the reserved CPU).
+const KERNEL_CPU: usize = 2;
+const KERNEL_CPU_ROUNDS: u64 = 50;
+
+// Shadow policy simulation (see check_shadow()): tasks with different weights (more than the
+// CPUs, so that the policy decides which ones run) and scheduling rounds of each session.
+const SHADOW_TASKS: i32 = 12;
+const SHADOW_ROUNDS: u64 = 500;
+
+// Dequeue errors simulation (see check_dequeue_errors()): scheduling rounds of each session
+// and failed calls to dequeue_task() of the transient burst of errors.
+const DEQUEUE_ERROR_ROUNDS: u64 = 300;
+const DEQUEUE_ERROR_BURST: u64 = 3;
+
+// I/O boost simulation (see check_io_boost()): maximum boost, profiles of the tasks (name, CPU
+// time and voluntary context switches per wakeup, expected boost), interval between wakeups
+// and simulated time (in seconds).
+const IO_BOOST_FACTOR: u64 = 4;
+const IO_BOOST_PROFILES: [(&str, u64, u64, bool); 4] = [
+    ("I/O-bound", 1_000_000, 5, true),
+    ("I/O-bound waking up rarely", 1_000_000, 1, true),
+    ("CPU-bound", 100_000_000, 1, false),
+    ("CPU-bound switching often", 100_000_000, 50, false),
+];
+const IO_BOOST_PERIOD_NS: u64 = 100_000_000;
+const IO_BOOST_SECS: u64 = 5;
+
+// Cap of the tracked tasks (see check_max_tracked_pids()), order in which the tasks are received
+// (task 1 is received again before task 4 exceeds the cap, so task 2 is the coldest), task left
+// waiting in the queues and new tasks received after it (enough to make it the coldest).
+const MAX_TRACKED_PIDS: u64 = 3;
+const TRACKED_PIDS_ORDER: [i32; 5] = [1, 2, 3, 1, 4];
+const TRACKED_QUEUED_PID: i32 = 5;
+const TRACKED_NEW_PIDS: [i32; 3] = [6, 7, 8];
+
+// Dispatch-to-run latency simulation (see check_run_latency()): time between the dispatch of a
+// task and the moment it is received again, CPU time used in the meantime and, for each case,
+// the start time reported by the backend (as an offset from the dispatch, None = unknown,
+// negative = a stale start time preceding the dispatch) and if the task blocked.
+const RUN_LATENCY_ELAPSED_NS: u64 = 5_000_000;
+const RUN_LATENCY_USED_NS: u64 = 3_000_000;
+const RUN_LATENCY_START_NS: i64 = 1_500_000;
+const RUN_LATENCY_CASES: [(&str, Option<i64>, bool); 4] = [
+    ("unknown start time", None, false),
+    ("unknown start time, blocked", None, true),
+    (
+        "kernel start time, blocked",
+        Some(RUN_LATENCY_START_NS),
+        true,
+    ),
+    ("stale start time", Some(-RUN_LATENCY_START_NS), false),
+];
+
+// Time-of-day schedule of the profiles (see check_profile_at()): entries of the schedule (of the
+// profiles of check_profiles()), entries that must be rejected and, for each evaluation of the
+// mocked wall clock, the time of the day (minutes since midnight), the profile activated manually
+// before the evaluation (if any) and the profile expected to be active after it.
+const PROFILE_AT: [&str; 3] = ["22:00=server", "07:30=gaming", "12:00=default"];
+const PROFILE_AT_INVALID: [&str; 6] = [
+    "24:00=server",
+    "12:60=server",
+    "1200=server",
+    "noon=server",
+    "12:00=",
+    "12:00",
+];
+const PROFILE_AT_STEPS: [(u32, Option<&str>, &str); 10] = [
+    // Startup before the first entry: slot of the previous day
+    (5 * 60, None, "server"),
+    // Right before a boundary
+    (7 * 60 + 29, None, "server"),
+    // Boundary crossed
+    (7 * 60 + 30, None, "gaming"),
+    // Next boundary
+    (12 * 60, None, "default"),
+    // Manual switch kept within the slot
+    (12 * 60 + 30, Some("gaming"), "gaming"),
+    // Next boundary overrides the manual switch
+    (22 * 60, None, "server"),
+    // Same slot
+    (23 * 60 + 59, None, "server"),
+    // Midnight is not a boundary
+    (0, None, "server"),
+    // Wall clock jump forward: only the slot reached
+    (10 * 60, None, "gaming"),
+    // Wall clock jump backward (e.g., DST change)
+    (7 * 60, None, "server"),
+];
+
+// Validation of the CPUs returned by select_cpu() (see check_strict_select_cpu()): for each case,
+// the CPU returned by the mock, the CPU unplugged after startup (if any) and the CPU expected for
+// the task (RL_CPU_ANY = fallback).
+const STRICT_CPU_CASES: [(&str, i32, Option<usize>, i32); 3] = [
+    ("out of range", NR_CPUS as i32 + 3, None, RL_CPU_ANY),
+    ("offline", 1, Some(1), RL_CPU_ANY),
+    ("online", 2, Some(1), 2),
+];
+
+// Quantum multiplier of the batch tasks (see check_batch_quantum()): base time slice and maximum
+// time slice (in microseconds), and multipliers with the expected batch time slice.
+const BATCH_QUANTUM_SLICE_US: u64 = 4000;
+const BATCH_QUANTUM_MAX_SLICE_US: u64 = 20_000;
+const BATCH_QUANTUM_CASES: [(u64, u64); 3] = [(1, 4000), (3, 12_000), (8, 20_000)];
+
+// Cap of the time slice by the budget left to a task (see check_cap_remaining_slice()): base and
+// minimum time slice (in microseconds) and, for each case, whether --cap-remaining-slice is set,
+// the budget left to the task and the expected time slice (in microseconds).
+const CAP_SLICE_US: u64 = 5000;
+const CAP_SLICE_MIN_US: u64 = 100;
+const CAP_SLICE_CASES: [(bool, u64, u64); 5] = [
+    (false, 2000, 5000),
+    (true, 0, 5000),
+    (true, 2000, 2000),
+    (true, 8000, 5000),
+    (true, 50, 100),
+];
+
+// Validation of the policy invariants (see check_debug_invariants()): largest time slice and, for
+// each case, the dispatches (a new round before the dispatch, pid, CPU, time slice and virtual
+// runtime) and the amount of violations expected.
+const INVARIANT_MAX_SLICE_NS: u64 = 10_000_000;
+type InvariantDispatch = (bool, i32, i32, u64, u64);
+const INVARIANT_CASES: [(&str, [InvariantDispatch; 2], u64); 7] = [
+    (
+        "valid",
+        [
+            (true, 1, 0, 1000, 10),
+            (false, 2, RL_CPU_ANY, INVARIANT_MAX_SLICE_NS, 5),
+        ],
+        0,
+    ),
+    (
+        "same pid in two rounds",
+        [(true, 1, 0, 1000, 10), (true, 1, 1, 1000, 10)],
+        0,
+    ),
+    (
+        "dispatched twice",
+        [(true, 1, 0, 1000, 10), (false, 1, 1, 1000, 10)],
+        1,
+    ),
+    (
+        "zero time slice",
+        [(true, 1, 0, 1000, 10), (false, 2, 1, 0, 10)],
+        1,
+    ),
+    (
+        "time slice too long",
+        [
+            (true, 1, 0, 1000, 10),
+            (false, 2, 1, INVARIANT_MAX_SLICE_NS + 1, 10),
+        ],
+        1,
+    ),
+    (
+        "invalid CPU",
+        [(true, 1, 0, 1000, 10), (false, 2, NR_CPUS as i32, 1000, 10)],
+        1,
+    ),
+    (
+        "virtual runtime backwards",
+        [(true, 1, 0, 1000, 10), (true, 1, 0, 1000, 9)],
+        1,
+    ),
+];
+
+// State saved across restarts (see check_state_file()): tasks and rounds simulated before the
+// state is saved, task that exits while the scheduler is stopped and maximum age of the state
+// (in seconds).
+const STATE_TASKS: i32 = 4;
+const STATE_ROUNDS: u64 = 50;
+const STATE_EXITED_PID: i32 = 4;
+const STATE_MAX_AGE_SECS: u64 = 10;
+
+// Trend of the queued tasks (see check_queue_trend()): for each sample, the seconds since the
+// previous one, the queued tasks and the tasks dispatched in the meantime, with the expected
+// growth rate (tasks/s) and time to drain the queue (seconds).
+type QueueTrendSample = (u64, u64, u64, Option<f64>, Option<f64>);
+const QUEUE_TREND_STEPS: [QueueTrendSample; 6] = [
+    (0, 10, 0, None, None),
+    (1, 30, 100, Some(20.0), None),
+    (2, 10, 200, Some(-10.0), Some(1.0)),
+    // Fluctuation below STEADY_RATE
+    (2, 11, 200, Some(0.0), None),
+    (1, 11, 100, Some(0.0), None),
+    (1, 0, 100, Some(-11.0), Some(0.0)),
+];
+
+// Boundary values of a malformed task (see check_boundary_values()): CPU time, weight and tasks
+// waiting to be dispatched (the voluntary context switches are always u64::MAX).
+const BOUNDARY_CASES: [(&str, u64, u64, u64); 5] = [
+    ("max runtime", u64::MAX, 100, 0),
+    ("weight 1", u64::MAX, 1, 0),
+    ("weight 0", u64::MAX, 0, 0),
+    ("max weight", u64::MAX, u64::MAX, 0),
+    ("max waiting", u64::MAX, 1, u64::MAX),
+];
+
+// Validation of the configuration (see check_validate()): for each command line, the problems
+// expected with NR_CPUS CPUs.
+type ValidateCase = (&'static [&'static str], &'static [&'static str]);
+const VALIDATE_CASES: [ValidateCase; 9] = [
+    (&["--policy", "fair", "--cpus-offline", "3"], &[]),
+    (
+        &[
+            "--reserve-interactive-pct",
+            "60",
+            "--reserve-batch-pct",
+            "50",
+        ],
+        &["error: --reserve-interactive-pct and --reserve-batch-pct exceed 100% in total"],
+    ),
+    (
+        &[
+            "--profile",
+            "a:slice-us=1000",
+            "--profile",
+            "a:slice-us=2000",
+            "--mode",
+            "auto",
+        ],
+        &[
+            "error: --profile: profile 'a' defined more than once",
+            "error: --profile can't be used with --mode auto",
+        ],
+    ),
+    (
+        &["--profile", "a:slice-us=1000", "--profile-at", "08:00=b"],
+        &["error: --profile-at: unknown profile 'b' at 08:00 (available: default, a)"],
+    ),
+    (
+        &["--cpus-offline", "2,7", "--kernel-cpu", "2"],
+        &[
+            "warning: --kernel-cpu 2 is also excluded by --cpus-offline",
+            "warning: --cpus-offline: CPU 7 doesn't exist (CPUs: 0-3)",
+        ],
+    ),
+    (
+        &["--slice-min-us", "500", "--interactive-max-slice-us", "200"],
+        &["warning: --interactive-max-slice-us 200 is below --slice-min-us 500"],
+    ),
+    (
+        &["--policy", "fair", "--deadline-slice-pct", "50"],
+        &["warning: --deadline-slice-pct is ignored without --policy edf"],
+    ),
+    (
+        &["--cpus-offline", "0-2", "--kernel-cpu", "3"],
+        &["warning: --cpus-offline and --kernel-cpu exclude all the CPUs, they are ignored"],
+    ),
+    (
+        &[
+            "--slice-us",
+            "50",
+            "--wakeup-gap-high-us",
+            "100",
+            "--wakeup-gap-low-us",
+            "200",
+        ],
+        &[
+            "warning: --slice-min-us 100 is above --slice-us 50",
+            "warning: --wakeup-gap-low-us 200 is above --wakeup-gap-high-us 100",
+        ],
+    ),
+];
+
+// Validation of the configuration files (see check_validate_config()): for each file, the
+// problems expected with NR_CPUS CPUs.
+const VALIDATE_CONFIG_CASES: [(&str, &[&str]); 5] = [
+    (
+        "# Desktop\n\
+         policy = \"fair\"\n\
+         cpus_offline = \"3\"\n\
+         slice-us = 5_000 # 5ms\n\
+         debug-accounting = true\n\
+         comm-cap = [\"^make$=200000\", '^cc1$=500000']\n",
+        &[],
+    ),
+    (
+        "polcy = \"fair\"\n\
+         slice-us = \"abc\"\n\
+         debug-accounting = 1\n\
+         policy = \"rr\"\n\
+         comm-cap = [\"make\"]\n\
+         slice-min-us = [100, 200]\n",
+        &[
+            "error: line 1: polcy: unknown option",
+            "error: line 2: slice-us: invalid value 'abc' for '--slice-us <SLICE_US>': invalid \
+             digit found in string",
+            "error: line 3: debug-accounting: expected true or false",
+            "error: line 4: policy: invalid value 'rr' for '--policy <POLICY>' [possible values: \
+             fifo, fair, wrr, edf]",
+            "error: line 5: comm-cap: invalid value 'make' for '--comm-cap <PATTERN=US>': \
+             expected PATTERN=US, found 'make'",
+            "error: line 6: slice-min-us: expected a single value, not an array",
+        ],
+    ),
+    (
+        "[scheduler]\n\
+         policy = fair\n\
+         slice-us = 1000\n\
+         slice_us = 2000\n",
+        &[
+            "error: line 1: tables are not supported",
+            "error: line 2: invalid value 'fair' (strings must be quoted)",
+            "error: line 4: slice_us defined more than once (line 3)",
+        ],
+    ),
+    (
+        "profile-at = \"08:00=a\"\n",
+        &[
+            "error: the following required arguments were not provided: --profile \
+           <NAME:KEY=VALUE,...>",
+        ],
+    ),
+    (
+        "reserve-interactive-pct = 60\n\
+         reserve-batch-pct = 50.0\n\
+         cpus-offline = \"2,7\"\n\
+         kernel-cpu = 2\n",
+        &[
+            "error: --reserve-interactive-pct and --reserve-batch-pct exceed 100% in total",
+            "warning: --kernel-cpu 2 is also excluded by --cpus-offline",
+            "warning: --cpus-offline: CPU 7 doesn't exist (CPUs: 0-3)",
+        ],
+    ),
+];
+
+// Response to the failed dispatches (see check_failed_dispatches()): failed dispatches per second
+// that trigger it, tasks queued, rounds where all the dispatches fail and expected tasks
+// dispatched per round before the failures, right after them and after an interval without
+// failures.
+const FAILED_DISPATCH_THRESH: u64 = 10;
+const FAILED_DISPATCH_TASKS: i32 = 64;
+const FAILED_DISPATCH_ROUNDS: u64 = 5;
+const FAILED_DISPATCH_BATCHES: [usize; 3] =
+    [NR_CPUS as usize, NR_CPUS as usize / 2, NR_CPUS as usize];
+
+// Anti-hang guard of the dequeue loop (see check_max_dequeue()): maximum amount of tasks dequeued
+// per round and times that the endless BPF queue returns the same task (far more).
+const MAX_DEQUEUE_PER_ROUND: u64 = 100;
+const MAX_DEQUEUE_ENDLESS: u64 = 100 * MAX_DEQUEUE_PER_ROUND;
+
+// Weight classes (see check_weight_classes()): bounds of the normal class and weight of the
+// dispatched task with its expected class (0 = low, 1 = normal, 2 = high), including the bounds.
+const WEIGHT_CLASS_BOUNDS: WeightBounds = WeightBounds { low: 50, high: 200 };
+const WEIGHT_CLASS_CASES: [(u64, usize); 7] = [
+    (1, 0),
+    (49, 0),
+    (50, 1),
+    (100, 1),
+    (200, 1),
+    (201, 2),
+    (10000, 2),
+];
+
+// Push of the stats over UDP (see check_udp_stats()): time to wait for a datagram on the
+// loopback interface and datagrams sent to a closed port until a send error is reported.
+const UDP_STATS_TIMEOUT_MS: u64 = 1000;
+const UDP_STATS_SEND_ATTEMPTS: u64 = 10;
+
+// NUMA affinity (see check_numa_affinity()): CPU time used by the task on each CPU in the past,
+// previously used CPU, CPU forced by select_cpu() (None = previous CPU, if idle), expected node of
+// the dispatch (on the nodes of NUMA_NODES) and expected cross-node dispatches.
+type NumaAffinityCase = (&'static [(i32, u64)], i32, Option<i32>, usize, u64);
+const NUMA_AFFINITY_CASES: [NumaAffinityCase; 5] = [
+    // No history: the task stays on its previous CPU.
+    (&[], 0, None, 0, 0),
+    // Not enough CPU time to infer a home node.
+    (&[(2, 1_000_000)], 0, None, 0, 0),
+    // Moved away from its home node: back to the home node.
+    (&[(2, 50_000_000), (3, 50_000_000)], 0, None, 1, 0),
+    // Most of the CPU time on node 0, also used a bit on node 1.
+    (&[(0, 80_000_000), (3, 20_000_000)], 2, None, 0, 0),
+    // No idle CPU in the home node: cross-node dispatch.
+    (&[(2, 100_000_000)], 0, Some(1), 0, 1),
+];
+
+// Migration damping (see check_migration_lock()): time a task is locked to the CPU where it
+// migrated and rounds of a task, with the time elapsed since the previous round, the CPU selected
+// for the task (its previous CPU is where it has been dispatched in the previous round) and the
+// expected CPU of the dispatch.
+const MIGRATION_LOCK_US: u64 = 10_000;
+const MIGRATION_LOCK_ROUNDS: [(u64, i32, i32); 5] = [
+    // First migration: allowed, the task is locked to CPU 1.
+    (0, 1, 1),
+    // Within the lock window: the task stays on CPU 1.
+    (1_000_000, 2, 1),
+    (5_000_000, 3, 1),
+    // The lock expired: the task migrates and it is locked to CPU 2.
+    (10_000_000, 2, 2),
+    (1_000_000, 0, 2),
+];
+
+// Placement of the brand-new tasks (see check_initial_cpu()): policy, tasks dispatched before the
+// new task (pid, tgid, CPU), CPU of the parent of the new task, process of the new task, its
+// previously used CPU (-1 = none) and the expected CPU of its first dispatch.
+type InitialCpuCase = (
+    InitialCpu,
+    &'static [(i32, i32, i32)],
+    Option<i32>,
+    i32,
+    i32,
+    i32,
+);
+const INITIAL_CPU_CASES: [InitialCpuCase; 6] = [
+    (InitialCpu::Default, &[(10, 10, 1)], Some(3), 10, 2, 2),
+    (
+        InitialCpu::Spread,
+        &[(10, 10, 0), (11, 11, 1)],
+        None,
+        1,
+        0,
+        2,
+    ),
+    (InitialCpu::LocalToParent, &[(10, 10, 3)], None, 10, 0, 3),
+    // No other thread of the process dispatched yet: generic CPU selection.
+    (InitialCpu::LocalToParent, &[(10, 10, 3)], None, 1, 0, 0),
+    (InitialCpu::FollowWaker, &[], Some(2), 1, -1, 2),
+    (InitialCpu::FollowWaker, &[], Some(3), 1, 1, 3),
+];
+
+// Previously used CPU of the new task when it is received again (it is not new anymore).
+const INITIAL_CPU_NEXT: i32 = 1;
+
+// Floor of the time slices (see check_slice_floor()): base time slice, minimum time slice, class
+// of the task, waiting tasks and expected time slice (in microseconds). The queue depths that
+// would round the time slice down to (almost) nothing get the minimum time slice instead.
+type SliceFloorCase = (u64, u64, TaskClass, u64, u64);
+const SLICE_FLOOR_CASES: [SliceFloorCase; 6] = [
+    (5000, 100, TaskClass::Batch, 9, 500),
+    (5000, 100, TaskClass::Batch, 1_000_000, 100),
+    (5000, 100, TaskClass::Interactive, 1_000_000, 100),
+    (5000, 1, TaskClass::Batch, 10_000_000, 1),
+    (5000, 1, TaskClass::Interactive, u64::MAX, 1),
+    (1, 1, TaskClass::Batch, 2, 1),
+];
+
+// Kernel thread received by the scheduler (see check_skip_kthreads()): pid, CPU previously used
+// and CPU returned by select_cpu(), and user tasks received along with it (none to go through
+// the fast path).
+const KTHREAD_PID: i32 = 2;
+const KTHREAD_PREV_CPU: i32 = 3;
+const KTHREAD_SELECTED_CPU: i32 = 1;
+const KTHREAD_NR_USER_TASKS: [i32; 2] = [0, 3];
+
+// Snapshots written to the state log (see check_state_log()), one per second, rotated at a few
+// snapshots, and tasks dispatched in each second.
+const STATE_LOG_SNAPSHOTS: u64 = 24;
+const STATE_LOG_MAX_SIZE: u64 = 4096;
+const STATE_LOG_TASKS: i32 = NR_CPUS as i32;
+
+// Waker-wakee locality (see check_wake_affine()): the waker (pid 1) runs on WAKE_AFFINE_CPU, then
+// a task previously running on CPU 0 is woken up in each round: pid, process, waker reported by
+// the backend, time since the waker ran and expected CPU (with --wake-affine, without it the
+// task always stays on CPU 0).
+const WAKE_AFFINE_CPU: i32 = 3;
+const WAKE_AFFINE_ROUNDS: [(i32, i32, Option<i32>, u64, i32); 3] = [
+    // Woken up by the waker: placed on the CPU of the waker.
+    (2, 2, Some(1), ROUND_NS, WAKE_AFFINE_CPU),
+    // Unknown waker, thread of the same process: placed on the CPU of the process.
+    (3, 1, None, 2 * ROUND_NS, WAKE_AFFINE_CPU),
+    // The waker didn't run recently: the task stays on its previously used CPU.
+    (2, 2, Some(1), WAKE_AFFINE_WINDOW_NS, 0),
+];
+
+// Cap of the interactive time slices (see check_interactive_max_slice()) and boosts of the time
+// slice: command line, time slice requested with --slice-env (in microseconds) and expected time
+// slice of the batch tasks (never capped).
+const INTERACTIVE_MAX_SLICE_US: u64 = 500;
+type InteractiveSliceCase = (&'static [&'static str], Option<u64>, u64);
+const INTERACTIVE_SLICE_CASES: [InteractiveSliceCase; 4] = [
+    (&["--slice-us", "5000"], None, 5000),
+    (&["--slice-us", "5000", "--slice-env"], Some(4000), 4000),
+    (
+        &["--slice-us", "5000", "--slice-expr", "slice * 4"],
+        None,
+        20000,
+    ),
+    (
+        &["--slice-us", "5000", "--slice-min-us", "2000"],
+        None,
+        5000,
+    ),
+];
+
+// Dispatch attempts that fail before the reset (see check_reset()), so that the retries are
+// counted too.
+const RESET_FAILED_DISPATCHES: u64 = 2;
+
+// Long queue processed with a time budget per round (see check_round_budget()): budget, time
+// spent by the backend in each call (in nanoseconds), CPUs and queued tasks (the queue takes
+// several budgets to be drained).
+const ROUND_BUDGET_US: u64 = 100;
+const ROUND_BUDGET_CALL_NS: u64 = 1000;
+const ROUND_BUDGET_NR_CPUS: u64 = 256;
+const ROUND_BUDGET_TASKS: i32 = 1000;
+const ROUND_BUDGET_MAX_ROUNDS: u64 = 100;
+
+// Share of the kernel dispatches (see check_dispatch_ratio()): user-space and kernel dispatches
+// in an interval, warning threshold, expected kernel share (in percent) and JSON.
+type DispatchRatioCase = (u64, u64, u64, Option<f64>, &'static str);
+const DISPATCH_RATIO_CASES: [DispatchRatioCase; 6] = [
+    (
+        0,
+        0,
+        50,
+        None,
+        "{\"user\":0,\"kernel\":0,\"kernel_pct\":null,\"bypassed\":false}",
+    ),
+    (
+        750,
+        250,
+        50,
+        Some(25.0),
+        "{\"user\":750,\"kernel\":250,\"kernel_pct\":25.0,\"bypassed\":false}",
+    ),
+    // At the threshold: not bypassed yet.
+    (
+        500,
+        500,
+        50,
+        Some(50.0),
+        "{\"user\":500,\"kernel\":500,\"kernel_pct\":50.0,\"bypassed\":false}",
+    ),
+    (
+        250,
+        750,
+        50,
+        Some(75.0),
+        "{\"user\":250,\"kernel\":750,\"kernel_pct\":75.0,\"bypassed\":true}",
+    ),
+    (
+        0,
+        3,
+        90,
+        Some(100.0),
+        "{\"user\":0,\"kernel\":3,\"kernel_pct\":100.0,\"bypassed\":true}",
+    ),
+    (
+        10,
+        0,
+        0,
+        Some(0.0),
+        "{\"user\":10,\"kernel\":0,\"kernel_pct\":0.0,\"bypassed\":false}",
+    ),
+];
+
+// Isolated CPUs simulation (see check_isolation()): CPUs isolated on the kernel command line,
+// --ignore-isolcpus, --cpus-offline and CPUs expected to receive the dispatches.
+type IsolationCase = (
+    &'static [usize],
+    bool,
+    Option<&'static str>,
+    &'static [usize],
+);
+const ISOLATION_CASES: [IsolationCase; 5] = [
+    (&[2, 3], false, None, &[0, 1]),
+    (&[2, 3], true, None, &[0, 1, 2, 3]),
+    (&[1, 7], false, Some("0"), &[2, 3]),
+    // No CPU left: the isolated CPUs are ignored.
+    (&[0, 1, 2, 3], false, None, &[0, 1, 2, 3]),
+    (&[0, 2, 3], false, Some("1"), &[0, 2, 3]),
+];
+const ISOLATION_ROUNDS: u64 = 50;
+
+// What-if replay (see check_what_if()): command line of the recorded session, options that
+// override it and the resulting maximum time slice (in microseconds).
+const WHAT_IF_ARGS: [&str; 5] = [
+    "scx_rust_scheduler",
+    "--slice-us",
+    "20000",
+    "--policy",
+    "fair",
+];
+const WHAT_IF_WITH: &str = " --policy fifo --slice-us 1000 ";
+const WHAT_IF_SLICE_US: u64 = 1000;
+
+// Scheduler starvation (see check_loop_gap()): threshold (in milliseconds), duration of the
+// simulated sessions (rounds) and iterations of the main loop (duration, time slept in
+// notify_complete(), in milliseconds) with the expected gaps.
+const LOOP_GAP_MS: u64 = 50;
+const LOOP_GAP_ROUNDS: u64 = 100;
+const LOOP_GAP_ITERATIONS: [(u64, u64, bool); 5] = [
+    (10, 0, false),
+    (50, 0, false),
+    (51, 0, true),
+    (500, 460, false),
+    (500, 449, true),
+];
+
+// Deadline-proportional time slices (see check_deadline_slice()): share of the slack assigned,
+// minimum time slice and tasks received in the same round, as (latency requested, time slice
+// requested), the tightest deadline first (in microseconds).
+const DEADLINE_SLICE_PCT: u64 = 25;
+const DEADLINE_SLICE_MIN_US: u64 = 100;
+const DEADLINE_SLICE_TASKS: [(Option<u64>, Option<u64>); 4] = [
+    (Some(1_000), None),
+    (Some(2_000), None),
+    (None, None),
+    (Some(1_000), Some(3_000)),
+];
+
+// Boost groups (see check_boost_group()): CPU hogs competing for the CPUs, members of the boost
+// group with its level, and duration of the simulated session (rounds).
+const BOOST_GROUP_TASKS: i32 = 8;
+const BOOST_GROUP_PIDS: [i32; 2] = [3, 6];
+const BOOST_GROUP_LEVEL: u64 = 4;
+const BOOST_GROUP_ROUNDS: u64 = 200;
+
+// Dispatch batch sizes (see check_batch_size()): scripted tasks dispatched per round, expected
+// JSON summary of the interval, and CPU hogs and duration (rounds) of the simulated session.
+const BATCH_SIZE_ROUNDS: [u64; 6] = [0, 1, 3, 3, 8, 40];
+const BATCH_SIZE_JSON: &str =
+    "{\"rounds\":6,\"min\":0,\"mean\":9.167,\"max\":40,\"histogram\":[1,1,2,0,1,0,1]}";
+const BATCH_SIZE_EMPTY_JSON: &str =
+    "{\"rounds\":0,\"min\":null,\"mean\":null,\"max\":null,\"histogram\":[0,0,0,0,0,0,0]}";
+const BATCH_SIZE_TASKS: i32 = 8;
+const BATCH_SIZE_SESSION_ROUNDS: u64 = 100;
+
+// Housekeeping in the idle rounds (see check_idle_maintenance()): interval between two passes
+// (in milliseconds) and duration of the simulated sessions (rounds).
+const IDLE_MAINTENANCE_MS: u64 = 10;
+const IDLE_MAINTENANCE_ROUNDS: u64 = 100;
+
+// Interactive and batch queues (see check_class_queues()): tasks that contend for a single CPU,
+// the batch ones queued first.
+const CLASS_QUEUE_BATCH: [i32; 3] = [10, 11, 12];
+const CLASS_QUEUE_INTERACTIVE: [i32; 3] = [1, 2, 3];
+
+// Compute-bound tasks (see check_compute_boost()): duration of the simulated session (seconds,
+// one round per second) and CPU time used per second by the task that is mostly sleeping.
+const COMPUTE_BOOST_SECS: u64 = 12;
+const COMPUTE_BOOST_LIGHT_NS: u64 = 10_000_000;
+
+// Placement of the new tasks (see check_new_task_vtime()): rounds run by the CPU hogs before the
+// new task shows up, and pid of the new task.
+const NEW_TASK_ROUNDS: u64 = 100;
+const NEW_TASK_PID: i32 = 50;
+
+// Single task fast path (see check_fast_path()): previously used CPU and weight of the tasks
+// received one per round, in turn, and duration of the simulated sessions (rounds).
+const FAST_PATH_TASKS: [(i32, u64); 3] = [(2, 100), (-1, 300), (0, 1)];
+const FAST_PATH_ROUNDS: u64 = 50;
+
+// Weighted round-robin (see check_wrr_ratio()): weights of two CPU hogs sharing a single CPU and
+// duration of the simulated session (rounds).
+const WRR_WEIGHTS: [u64; 2] = [100, 300];
+const WRR_ROUNDS: u64 = 400;
+
+// Starvation of the batch tasks with --order lifo (see check_lifo_starvation()): pid of the
+// first batch task (the following ones get the next pids) and rounds of the simulated session.
+const LIFO_BATCH_PID: i32 = 10000;
+const LIFO_ROUNDS: u64 = 2 * STARVATION_NS / ROUND_NS;
+
+// Dispatch retries (see check_dispatch_retries()): tasks received in the same round, more than
+// the retries allowed per round, with a backend that rejects every other dispatch.
+const RETRY_TASKS: i32 = DISPATCH_RETRIES as i32 + 1;
+
+// Rounds (one second apart) run with the largest durations accepted on the command line (see
+// check_oversized_options()).
+const OVERSIZED_ROUNDS: u64 = 20;
+
+// Maximum time (in milliseconds) to wait for a response of the metrics endpoint, shorter than
+// its request timeout (see check_metrics_server()).
+const METRICS_TIMEOUT_MS: u64 = 1000;
+
+// Stress of the dispatcher (see check_workers()): CPUs, tasks, rounds receiving a random subset
+// of the tasks not queued yet, and rounds allowed to dispatch the tasks left at the end.
+const WORKERS_CPUS: u64 = 64;
+const WORKERS_TASKS: i32 = 1024;
+const WORKERS_ROUNDS: u64 = 200;
+const WORKERS_DRAIN_ROUNDS: u64 = 2 * STARVATION_NS / ROUND_NS;
+
+// Profiles of a replayed session (see check_replay_timetable()), recorded at midnight.
+const REPLAY_PROFILES: [&str; 1] = ["short:slice-us=1000"];
+const REPLAY_PROFILE_AT: [&str; 2] = ["00:00=short", "00:01=default"];
+
+// Maximum amount of violations reported.
+const MAX_REPORTED: usize = 10;
+
+// Behavior of a simulated task.
+enum Behavior {
+    Interactive { run_ns: u64, sleep_rounds: u64 }, // Runs briefly, then sleeps
+    Hog,                                            // Always uses its entire time slice
+}
+
+// Task of the canned workload.
+struct SimTask {
+    task: Task,                // Task state reported to the scheduler
+    behavior: Behavior,        // How the task uses the CPU
+    wake_round: u64,           // Round when the task becomes runnable again
+    queued_round: Option<u64>, // Round when the task has been queued (None = not queued)
+}
+
+impl SimTask {
+    fn new(pid: i32, cpu: i32, weight: u64, behavior: Behavior) -> Self {
+        Self {
+            task: Task {
+                pid,
+                cpu,
+                flags: 0,
+                sum_exec_runtime: 0,
+                nvcsw: 0,
+                weight,
+                slice: 0,
+                vtime: 0,
+            },
+            behavior,
+            wake_round: 0,
+            queued_round: None,
+        }
+    }
+}
+
+// Canned workload: a few interactive tasks competing with CPU hogs of different weights
+// (including the extreme ones), one of them without a previously used CPU.
+//
+// The interactive tasks are threads of the same process, as well as the hogs (except the one
+// without a previously used CPU), see process().
+fn workload() -> Vec<SimTask> {
+    let mut tasks = Vec::new();
+
+    for pid in 1..=4 {
+        tasks.push(SimTask::new(
+            pid,
+            pid % NR_CPUS as i32,
+            100,
+            Behavior::Interactive {
+                run_ns: 200_000,
+                sleep_rounds: 3,
+            },
+        ));
+    }
+    for (i, weight) in [100, 100, 200, 1, 10000].into_iter().enumerate() {
+        let pid = 100 + i as i32;
+        tasks.push(SimTask::new(
+            pid,
+            pid % NR_CPUS as i32,
+            weight,
+            Behavior::Hog,
+        ));
+    }
+    tasks.push(SimTask::new(200, -1, 100, Behavior::Hog));
+
+    tasks
+}
+
+// Return the process (tgid) of a task of the canned workload.
+fn process(pid: i32) -> i32 {
+    match pid {
+        1..=4 => 1,
+        100..=104 => 100,
+        _ => pid,
+    }
+}
+
+// Return a CPU hog `pid` with the default weight, received on `cpu` (-1 = no previous CPU).
+fn hog(pid: i32, cpu: i32) -> Task {
+    SimTask::new(pid, cpu, 100, Behavior::Hog).task
+}
+
+// Fixture of the checks: the scheduler under test attached to a mock backend, built with the
+// tasks queued before the first round.
+struct Fixture {
+    bpf: MockBackend, // Backend of the scheduler under test
+}
+
+impl Fixture {
+    fn new(nr_cpus: u64) -> Self {
+        Self {
+            bpf: MockBackend::new(nr_cpus),
+        }
+    }
+
+    // Queue `tasks` before the first round.
+    fn tasks(mut self, tasks: impl IntoIterator<Item = Task>) -> Self {
+        for task in tasks {
+            self.bpf.enqueue(task);
+        }
+        self
+    }
+
+    // Queue the CPU hogs `pids`, each one received on the CPU pid % CPUs.
+    fn hogs(mut self, pids: impl IntoIterator<Item = i32>) -> Self {
+        let nr_cpus = *self.bpf.nr_online_cpus_mut() as i32;
+        self.tasks(pids.into_iter().map(|pid| hog(pid, pid % nr_cpus)))
+    }
+
+    // Simulate the execution of the dispatched tasks for `nr_rounds` rounds (see
+    // MockBackend::closed_loop()).
+    fn closed_loop(mut self, nr_rounds: u64) -> Self {
+        self.bpf.closed_loop(ROUND_NS, nr_rounds);
+        self
+    }
+
+    // Configure the mock backend with `setup`.
+    fn backend(mut self, setup: impl FnOnce(&mut MockBackend)) -> Self {
+        setup(&mut self.bpf);
+        self
+    }
+
+    // Return the scheduler under test, configured with `opts`.
+    fn scheduler(self, opts: &Opts) -> Scheduler<'_, MockBackend> {
+        Scheduler::new(self.bpf, opts, None, None)
+    }
+}
+
+/// Run the scheduling policy against the mock backend using a canned workload and verify its
+/// invariants:
+///  - no task waits for more than MAX_WAIT_ROUNDS rounds (scaled by weight) before being
+///    dispatched, or more than --starve-timeout-ms (regardless of the weight) if specified,
+///  - all the assigned time slices are within (0, max slice],
+///  - all the target CPUs are valid and never excluded by --cpus-offline,
+///  - with --llc-group, all the threads of a process always run in the same LLC domain,
+///  - with --cpuset-aware, a task confined to a cpuset never runs outside of it, also after
+///    being moved to a different cpuset (once the cached cpuset is refreshed),
+///  - only queued tasks are dispatched, and only once, also when dispatches fail or when a task
+///    is received twice in the same round (handled according to --duplicate-pid),
+///  - with --debug-accounting, no task uses more CPU time than its assigned time slice,
+///  - the binary stats frame can be decoded back to the original stats,
+///  - the policy never panics or fails, also with the boundary values of a malformed task,
+///  - --starve-timeout-ms lets the lowest-weight task run on time with the fair policy,
+///  - the coldest CPU is selected according to a canned idle history (see --spread-idle),
+///  - replaying a recorded session reproduces exactly the recorded decisions, every time.
+pub fn run(opts: &Opts) -> Result<()> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| simulate(opts)));
+    let Ok(SimResult { mut violations, .. }) = result else {
+        bail!("selftest failed: the scheduling policy panicked");
+    };
+    violations.extend(check_cpuset(opts));
+    violations.extend(check_duplicate_pid(opts));
+    violations.extend(check_edf(opts));
+    violations.extend(check_pin(opts));
+    violations.extend(check_starve_timeout(opts));
+    violations.extend(check_idle_history());
+    violations.extend(check_wakeup_gap());
+    violations.extend(check_overhead(opts));
+    violations.extend(check_boost_budget(opts));
+    violations.extend(check_fork_bomb(opts));
+    violations.extend(check_reservation(opts));
+    violations.extend(check_reservation_single(opts));
+    violations.extend(check_top_view());
+    violations.extend(check_policy_activation(opts));
+    violations.extend(check_weight_change(opts));
+    violations.extend(check_order(opts));
+    violations.extend(check_slice_expr(opts));
+    violations.extend(check_cache_aware(opts));
+    violations.extend(check_pid_gc(opts));
+    violations.extend(check_numa_fallback(opts));
+    violations.extend(check_comm_cap(opts));
+    violations.extend(check_latency_target(opts));
+    violations.extend(check_yield(opts));
+    violations.extend(check_systemd(opts));
+    violations.extend(check_notify_stall(opts));
+    violations.extend(check_export(opts));
+    violations.extend(check_profiles(opts));
+    violations.extend(check_profile_at(opts));
+    violations.extend(check_slice_divergence(opts));
+    violations.extend(check_idle_stats(opts));
+    violations.extend(check_kernel_cpu(opts));
+    violations.extend(check_shadow(opts));
+    violations.extend(check_dequeue_errors(opts));
+    violations.extend(check_io_boost(opts));
+    violations.extend(check_max_tracked_pids(opts));
+    violations.extend(check_strict_select_cpu(opts));
+    violations.extend(check_batch_quantum(opts));
+    violations.extend(check_cap_remaining_slice(opts));
+    violations.extend(check_run_latency(opts));
+    violations.extend(check_debug_invariants(opts));
+    violations.extend(check_state_file(opts));
+    violations.extend(check_queue_trend());
+    violations.extend(check_boundary_values(opts));
+    violations.extend(check_validate());
+    violations.extend(check_validate_config());
+    violations.extend(check_failed_dispatches(opts));
+    violations.extend(check_max_dequeue(opts));
+    violations.extend(check_weight_classes(opts));
+    violations.extend(check_udp_stats(opts));
+    violations.extend(check_numa_affinity(opts));
+    violations.extend(check_migration_lock(opts));
+    violations.extend(check_initial_cpu(opts));
+    violations.extend(check_slice_floor(opts));
+    violations.extend(check_deadline_dump(opts));
+    violations.extend(check_skip_kthreads(opts));
+    violations.extend(check_state_log(opts));
+    violations.extend(check_wake_affine(opts));
+    violations.extend(check_interactive_max_slice());
+    violations.extend(check_reset(opts));
+    violations.extend(check_round_budget(opts));
+    violations.extend(check_dispatch_ratio());
+    violations.extend(check_isolation(opts));
+    violations.extend(check_what_if(opts));
+    violations.extend(check_loop_gap(opts));
+    violations.extend(check_deadline_slice(opts));
+    violations.extend(check_boost_group(opts));
+    violations.extend(check_batch_size(opts));
+    violations.extend(check_idle_maintenance(opts));
+    violations.extend(check_class_queues(opts));
+    violations.extend(check_cpu_any_shortcut(opts));
+    violations.extend(check_compute_boost(opts));
+    violations.extend(check_new_task_vtime(opts));
+    violations.extend(check_fast_path(opts));
+    violations.extend(check_wrr_ratio(opts));
+    violations.extend(check_lifo_starvation(opts));
+    violations.extend(check_dispatch_retries(opts));
+    violations.extend(check_oversized_options(opts));
+    violations.extend(check_metrics_server());
+    violations.extend(check_workers(opts));
+    #[cfg(feature = "stats")]
+    violations.extend(check_scx_stats(opts));
+    violations.extend(check_replay(opts));
+    violations.extend(check_replay_timetable(opts));
+
+    if !violations.is_empty() {
+        for violation in violations.iter().take(MAX_REPORTED) {
+            eprintln!("selftest: {}", violation);
+        }
+        bail!("selftest failed: {} violations detected", violations.len());
+    }
+    println!("selftest: OK ({} rounds, {} CPUs)", NR_ROUNDS, NR_CPUS);
+
+    Ok(())
+}
+
+// Verify that a task confined to a cpuset is never dispatched outside of it (see simulate()),
+// regardless of --cpuset-aware.
+fn check_cpuset(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        cpuset_aware: true,
+        ..opts.clone()
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| simulate(&opts)));
+    let Ok(result) = result else {
+        return vec!["cpuset: the scheduling policy panicked".to_string()];
+    };
+
+    result
+        .violations
+        .into_iter()
+        .map(|violation| format!("cpuset: {}", violation))
+        .collect()
+}
+
+// Verify the handling of a task received twice in the same round: the task must be dispatched
+// once, with the state of the latest instance with --duplicate-pid coalesce, or with the state of
+// the first instance with --duplicate-pid skip (the two instances have different enqueue flags).
+fn check_duplicate_pid(opts: &Opts) -> Vec<String> {
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let first = hog(1, 0);
+    let latest = Task {
+        flags: 1 << 3,
+        ..first.clone()
+    };
+    let expected = match opts.duplicate_pid {
+        DuplicatePid::Coalesce => &latest,
+        DuplicatePid::Skip => &first,
+    };
+    let mut violations = Vec::new();
+
+    sched.bpf.enqueue(first.clone());
+    sched.bpf.enqueue(latest.clone());
+    if let Err(err) = sched.schedule() {
+        return vec![format!("duplicate pid: schedule() failed: {}", err)];
+    }
+
+    let dispatched = sched.bpf.take_dispatched();
+    match dispatched.as_slice() {
+        [d] if d.pid == expected.pid && d.flags == expected.flags => {}
+        _ => violations.push(format!(
+            "duplicate pid: expected a single dispatch with flags {:#x} ({:?}), got {:?}",
+            expected.flags, opts.duplicate_pid, dispatched
+        )),
+    }
+    if sched.nr_duplicate_pids != 1 {
+        violations.push(format!(
+            "duplicate pid: {} duplicates detected, expected 1",
+            sched.nr_duplicate_pids
+        ));
+    }
+
+    violations
+}
+
+// Verify that the edf policy orders the tasks received in the same round by soft deadline: a
+// task requesting a tight latency must be dispatched ahead of the tasks requesting a loose one
+// (or no latency at all), and the requested latencies must be bounded.
+fn check_edf(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Edf,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let mut expected = Vec::new();
+
+    sched.bpf.advance(ROUND_NS);
+    let now = sched.bpf.now_ns();
+    for (i, latency_us) in EDF_LATENCIES.into_iter().enumerate() {
+        let task = hog(i as i32 + 1, i as i32);
+        let latency_ns = match latency_us {
+            Some(latency_us) => {
+                sched
+                    .bpf
+                    .set_env_hint(task.pid, slice_override::LATENCY_ENV, latency_us);
+                (latency_us * 1000).clamp(LATENCY_MIN_NS, LATENCY_MAX_NS)
+            }
+            None => opts.slice_us * 1000,
+        };
+        expected.push((task.pid, now + latency_ns));
+        sched.bpf.enqueue(task);
+    }
+    expected.sort_by_key(|&(_, deadline)| deadline);
+
+    if let Err(err) = sched.schedule() {
+        return vec![format!("edf: schedule() failed: {}", err)];
+    }
+    let dispatched: Vec<(i32, u64)> = sched
+        .bpf
+        .take_dispatched()
+        .iter()
+        .map(|d| (d.pid, d.vtime))
+        .collect();
+    if dispatched != expected {
+        return vec![format!(
+            "edf: expected (pid, deadline) dispatches {:?}, got {:?}",
+            expected, dispatched
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify the pin control command: a pinned task must always be dispatched to its pinned CPU,
+// without going through the CPU selection, unpinning it must restore the regular CPU selection,
+// and the pin must be cleared when the task exits.
+fn check_pin(opts: &Opts) -> Vec<String> {
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let task = hog(1, 0);
+    let mut violations = Vec::new();
+
+    if sched.pin(task.pid, NR_CPUS as i32).is_ok() {
+        violations.push(format!("pin: pinning to CPU {} accepted", NR_CPUS));
+    }
+    if let Err(err) = sched.pin(task.pid, PIN_CPU) {
+        violations.push(format!("pin: pinning to CPU {} failed: {}", PIN_CPU, err));
+    }
+    for round in 0..=PIN_ROUNDS {
+        let pinned = round < PIN_ROUNDS;
+        if !pinned {
+            sched.unpin(task.pid);
+        }
+        sched.bpf.advance(ROUND_NS);
+        sched.bpf.enqueue(task.clone());
+        if let Err(err) = sched.schedule() {
+            violations.push(format!("pin: schedule() failed: {}", err));
+            return violations;
+        }
+        let cpus: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+        let selected = sched.bpf.take_selected();
+        if pinned && (cpus != [PIN_CPU] || !selected.is_empty()) {
+            violations.push(format!(
+                "pin: task pinned to CPU {} dispatched to {:?} (CPU selection for {:?})",
+                PIN_CPU, cpus, selected
+            ));
+        }
+        if !pinned && (cpus.len() != 1 || selected != [task.pid]) {
+            violations.push(format!(
+                "pin: unpinned task dispatched to {:?} (CPU selection for {:?})",
+                cpus, selected
+            ));
+        }
+    }
+
+    if let Err(err) = sched.pin(task.pid, PIN_CPU) {
+        violations.push(format!("pin: pinning to CPU {} failed: {}", PIN_CPU, err));
+    }
+    sched.bpf.exit_task(task.pid);
+    sched.gc_tasks();
+    if sched.pins.contains_key(&task.pid) {
+        violations.push("pin: pin not cleared after the task exited".to_string());
+    }
+
+    violations
+}
+
+// Verify that the starvation timeout fires with an adversarial weight configuration: with the
+// fair policy the weight-1 hog (the victim) competes with a weight-10000 hog and would wait for
+// hundreds of milliseconds, while with the timeout it must still run within the deadline (with
+// time slices shorter than the timeout, regardless of the defaults of the build).
+fn check_starve_timeout(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Fair,
+        slice_us: STARVE_CHECK_SLICE_US,
+        batch_quantum_mult: 1,
+        starve_timeout_ms: Some(STARVE_CHECK_TIMEOUT_MS),
+        ..opts.clone()
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| simulate(&opts)));
+    let Ok(result) = result else {
+        return vec!["starvation timeout: the scheduling policy panicked".to_string()];
+    };
+    let mut violations: Vec<String> = result
+        .violations
+        .into_iter()
+        .map(|violation| format!("starvation timeout: {}", violation))
+        .collect();
+
+    if result.nr_starve_timeouts == 0 {
+        violations.push("starvation timeout: the timeout never fired".to_string());
+    }
+    if !result
+        .hogs
+        .iter()
+        .any(|&(weight, runtime)| weight == 1 && runtime > 0)
+    {
+        violations.push("starvation timeout: the weight-1 task never ran".to_string());
+    }
+
+    violations
+}
+
+// Verify the coldest-first CPU selection against a canned idle history: CPU 3 never received a
+// task, CPU 1 is idle since t=5, CPU 0 since t=10 and CPU 2 is busy until t=20.
+fn check_idle_history() -> Vec<String> {
+    let mut idle = IdleHistory::new(4);
+    let mut violations = Vec::new();
+
+    idle.record_dispatch(0, 0, 10, false);
+    idle.record_dispatch(1, 0, 5, false);
+    idle.record_dispatch(2, 0, 20, false);
+
+    let cases: [(u64, bool, Option<usize>); 4] = [
+        (12, true, Some(3)),  // CPUs that never received a task are the coldest
+        (12, false, Some(1)), // Otherwise the CPU that has been idle for the longest time
+        (25, false, Some(1)), // Regardless of how many CPUs are idle
+        (4, false, None),     // Busy CPUs are never selected
+    ];
+    for (now, allow_cpu3, expected) in cases {
+        let coldest = idle.coldest(now, |cpu| allow_cpu3 || cpu != 3);
+        if coldest != expected {
+            violations.push(format!(
+                "idle history: coldest CPU at t={} is {:?}, expected {:?}",
+                now, coldest, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the wakeup gap controller scales the time slices up while the scheduler lags
+// behind and restores them when it catches up, driving it with a synthetic series of wakeup
+// gaps (without hysteresis, that is verified separately).
+fn check_wakeup_gap() -> Vec<String> {
+    let mut gap = WakeupGap::new(
+        WAKEUP_GAP_HIGH_US * 1000,
+        WAKEUP_GAP_LOW_US * 1000,
+        Hysteresis::new(0, 0),
+        0,
+    );
+    let mut now = 0;
+    let mut violations = Vec::new();
+
+    for (i, (secs, gap_us, backlog, expected)) in WAKEUP_GAP_SERIES.into_iter().enumerate() {
+        let end = now + secs * 1_000_000_000;
+        while now < end {
+            now += gap_us * 1000;
+            gap.record_wakeup(now, backlog);
+            gap.evaluate(now);
+            if gap.slice_scale() > expected.max(WAKEUP_GAP_SERIES[i.saturating_sub(1)].3) {
+                violations.push(format!(
+                    "wakeup gap: phase {} ({}us gaps): time slices scaled by {}x at t={}ms",
+                    i,
+                    gap_us,
+                    gap.slice_scale(),
+                    now / 1_000_000
+                ));
+                return violations;
+            }
+        }
+        if gap.slice_scale() != expected {
+            violations.push(format!(
+                "wakeup gap: phase {} ({}us gaps): time slices scaled by {}x, expected {}x",
+                i,
+                gap_us,
+                gap.slice_scale(),
+                expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the scheduler coarsens its behavior while its own CPU usage exceeds the ceiling
+// (more tasks dispatched per round) and that it restores it when the usage goes back to normal
+// (the hysteresis configured by `opts` is respected).
+fn check_overhead(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        self_cpu_max_pct: Some(OVERHEAD_MAX_PCT),
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let mut violations = Vec::new();
+
+    // Simulate `secs` seconds where the scheduler uses `pct` percent of one CPU, then return the
+    // amount of tasks dispatched in one round with a backlog of many tasks.
+    let mut run = |sched: &mut Scheduler<MockBackend>, secs: u64, pct: u64| -> usize {
+        for _ in 0..secs {
+            sched.bpf.advance(NSEC_PER_SEC);
+            sched.bpf.consume_self_cpu(NSEC_PER_SEC * pct / 100);
+            sched.update_overhead();
+        }
+        for pid in 1..=(NR_CPUS * 16) as i32 {
+            sched.bpf.enqueue(hog(pid, -1));
+        }
+        if let Err(err) = sched.schedule() {
+            violations.push(format!("overhead: schedule() failed: {}", err));
+        }
+        sched.interactive.clear();
+        sched.batch.clear();
+        sched.bpf.take_dispatched().len()
+    };
+
+    let nr_high = run(&mut sched, 2, OVERHEAD_HIGH_PCT);
+    let nr_low = run(&mut sched, opts.hysteresis_ms / 1000 + 2, OVERHEAD_LOW_PCT);
+    let nr_cpus = sched.throttle(NR_CPUS) as usize;
+    if nr_high <= nr_cpus {
+        violations.push(format!(
+            "overhead: {} tasks dispatched per round above the ceiling, expected more than {}",
+            nr_high, nr_cpus
+        ));
+    }
+    if opts.hysteresis_delta <= OVERHEAD_HIGH_PCT * 100 / NR_CPUS / 2 && nr_low != nr_cpus {
+        violations.push(format!(
+            "overhead: {} tasks dispatched per round below the ceiling, expected {}",
+            nr_low, nr_cpus
+        ));
+    }
+
+    violations
+}
+
+// Verify that the boost budget caps the total wakeup credit granted in each interval with many
+// boosted tasks (the budget is a quarter of the credit they would get without it), without
+// preventing the boost entirely.
+fn check_boost_budget(opts: &Opts) -> Vec<String> {
+    let budget_us = opts.slice_us * BOOST_TASKS as u64 / 4;
+    let unbounded = boost_credits(&Opts {
+        policy: Policy::Fair,
+        boost_budget_us: None,
+        ..opts.clone()
+    });
+    let bounded = boost_credits(&Opts {
+        policy: Policy::Fair,
+        boost_budget_us: Some(budget_us),
+        ..opts.clone()
+    });
+    let mut violations = Vec::new();
+
+    for (interval, (&total, &unbounded)) in bounded.iter().zip(unbounded.iter()).enumerate() {
+        if unbounded <= budget_us * 1000 {
+            violations.push(format!(
+                "boost budget: interval {}: {}ns of credit without a budget, expected more than \
+                 {}us",
+                interval, unbounded, budget_us
+            ));
+        }
+        if total > budget_us * 1000 || total == 0 {
+            violations.push(format!(
+                "boost budget: interval {}: {}ns of credit granted with a budget of {}us",
+                interval, total, budget_us
+            ));
+        }
+    }
+
+    violations
+}
+
+// Simulate a fork bomb: FORK_BOMB_SURGE new tasks received together with the tasks already
+// known. The tasks beyond the threshold must be dispatched with the minimal time slice and only
+// after the known tasks, whose time slices must not be scaled down by the flood; once the surge
+// ends, the tasks of the flood must be scheduled as regular tasks again.
+fn check_fork_bomb(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        fork_bomb_thresh: Some(FORK_BOMB_THRESH),
+        fork_bomb_throttle: true,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let known = |pid: i32| pid <= FORK_BOMB_KNOWN;
+    let flood = |pid: i32| pid > FORK_BOMB_KNOWN + FORK_BOMB_THRESH as i32;
+    let mut violations = Vec::new();
+
+    // Get the known tasks tracked, then start a new interval for the detector.
+    for pid in 1..=FORK_BOMB_KNOWN {
+        sched.bpf.enqueue(hog(pid, -1));
+    }
+    let schedule = |sched: &mut Scheduler<MockBackend>| {
+        sched.bpf.advance(ROUND_NS);
+        sched.schedule().map(|_| sched.bpf.take_dispatched())
+    };
+    if let Err(err) = schedule(&mut sched) {
+        return vec![format!("fork bomb: schedule() failed: {}", err)];
+    }
+    sched.bpf.advance(NSEC_PER_SEC);
+    sched.update_overload();
+
+    // Receive the surge and the known tasks in the same round.
+    for pid in FORK_BOMB_KNOWN + 1..=FORK_BOMB_KNOWN + FORK_BOMB_SURGE {
+        sched.bpf.enqueue(hog(pid, -1));
+    }
+    for pid in 1..=FORK_BOMB_KNOWN {
+        sched.bpf.enqueue(hog(pid, -1));
+    }
+    let nr_regular = (FORK_BOMB_KNOWN as u64 + FORK_BOMB_THRESH + 1).max(1);
+    let min_slice_ns = sched.throttle((sched.slice_ns / nr_regular).min(INTERACTIVE_SLICE_NS));
+    let flood_slice_ns = sched.throttle(FORK_BOMB_SLICE_NS);
+    let mut dispatched = Vec::new();
+    for _ in 0..FORK_BOMB_SURGE {
+        match schedule(&mut sched) {
+            Ok(round) => dispatched.extend(round),
+            Err(err) => return vec![format!("fork bomb: schedule() failed: {}", err)],
+        }
+        if sched.nr_pending() == 0 {
+            break;
+        }
+    }
+    if !sched.fork_bomb.as_ref().is_some_and(|f| f.active()) {
+        violations.push("fork bomb: surge not detected".to_string());
+    }
+
+    let mut nr_known = 0;
+    for d in &dispatched {
+        if flood(d.pid) && nr_known < FORK_BOMB_KNOWN {
+            violations.push(format!(
+                "fork bomb: pid {} of the flood dispatched before the known tasks",
+                d.pid
+            ));
+        }
+        if flood(d.pid) && d.slice_ns != flood_slice_ns {
+            violations.push(format!(
+                "fork bomb: pid {} of the flood dispatched with a {}ns time slice, expected {}ns",
+                d.pid, d.slice_ns, flood_slice_ns
+            ));
+        }
+        if known(d.pid) {
+            nr_known += 1;
+            if d.slice_ns < min_slice_ns {
+                violations.push(format!(
+                    "fork bomb: known pid {} dispatched with a {}ns time slice, expected at least \
+                     {}ns",
+                    d.pid, d.slice_ns, min_slice_ns
+                ));
+            }
+        }
+    }
+    if dispatched.len() != (FORK_BOMB_KNOWN + FORK_BOMB_SURGE) as usize {
+        violations.push(format!(
+            "fork bomb: {} tasks dispatched, expected {}",
+            dispatched.len(),
+            FORK_BOMB_KNOWN + FORK_BOMB_SURGE
+        ));
+    }
+
+    // Let the surge end: the interval of the surge is closed first, then a quiet one.
+    for _ in 0..2 {
+        sched.bpf.advance(NSEC_PER_SEC);
+        sched.update_overload();
+    }
+    let pid = FORK_BOMB_KNOWN + FORK_BOMB_SURGE;
+    sched.bpf.enqueue(hog(pid, -1));
+    let dispatched = match schedule(&mut sched) {
+        Ok(dispatched) => dispatched,
+        Err(err) => return vec![format!("fork bomb: schedule() failed: {}", err)],
+    };
+    if sched.is_flood(pid) || dispatched.iter().any(|d| d.slice_ns == flood_slice_ns) {
+        violations.push(format!(
+            "fork bomb: pid {} still throttled after the end of the surge",
+            pid
+        ));
+    }
+
+    violations
+}
+
+// Verify that the interactive tasks get at least the capacity reserved to them when they compete
+// with many CPU hogs, that would otherwise take most of it (the policy, the order, the time
+// slice and the classifier are fixed, since the simulation relies on them: any task that sleeps
+// is interactive).
+fn check_reservation(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Fifo,
+        order: Order::Fifo,
+        slice_us: RESERVE_SLICE_US,
+        batch_quantum_mult: 1,
+        nvcsw_thresh: 1,
+        wakeup_gap_high_us: None,
+        ..opts.clone()
+    };
+    let unreserved = interactive_share(&opts);
+    let reserved = interactive_share(&Opts {
+        reserve_interactive_pct: Some(RESERVE_INTERACTIVE_PCT as f64),
+        ..opts.clone()
+    });
+
+    match (unreserved, reserved) {
+        (Ok(unreserved), Ok(reserved)) if reserved < RESERVE_INTERACTIVE_PCT => vec![format!(
+            "reservation: interactive tasks got {}% of the capacity with {}% reserved ({}% \
+             without the reservation)",
+            reserved, RESERVE_INTERACTIVE_PCT, unreserved
+        )],
+        (Err(err), _) | (_, Err(err)) => vec![format!("reservation: schedule() failed: {}", err)],
+        _ => Vec::new(),
+    }
+}
+
+// Verify that a single task is held back by the reservation of the other class like the tasks
+// of a longer queue (the fast path of a round with a single task must not bypass it): with the
+// batch tasks above their cap, a batch task received alone is not dispatched until the next
+// interval.
+fn check_reservation_single(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        reserve_interactive_pct: Some(RESERVE_INTERACTIVE_PCT as f64),
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(1).scheduler(&opts);
+    sched.bpf.advance(ROUND_NS);
+    // An interactive task is active without using its reservation, while the batch tasks
+    // used the whole capacity.
+    if let Some(reservation) = sched.reservation.as_mut() {
+        reservation.record(2, TaskClass::Interactive, 0);
+        reservation.record(3, TaskClass::Batch, ROUND_NS);
+    }
+
+    let mut dispatched = Vec::new();
+    sched.bpf.enqueue(hog(1, 0));
+    for _ in 0..2 {
+        if let Err(err) = sched.schedule() {
+            return vec![format!("single reservation: schedule() failed: {}", err)];
+        }
+        dispatched.push(sched.bpf.take_dispatched().len());
+        sched.bpf.advance(NSEC_PER_SEC);
+        sched.update_overload();
+    }
+    if dispatched != [0, 1] {
+        return vec![format!(
+            "single reservation: {:?} tasks dispatched in the interval with the batch tasks \
+             above their cap and in the following one, expected [0, 1]",
+            dispatched
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that the tasks are dispatched in FIFO order while less than the threshold of tasks is
+// waiting and by deadline once the edf policy is engaged, against a reference model of the
+// queue: the tasks of each round request decreasing latencies, so that the two orders differ.
+fn check_policy_activation(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Edf,
+        order: Order::Fifo,
+        policy_activation_threshold: Some(ACTIVATION_THRESH),
+        starve_timeout_ms: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let mut queue: VecDeque<(i32, u64)> = VecDeque::new();
+    let mut next_pid = 1;
+
+    for (round, (nr_new, engaged)) in ACTIVATION_ROUNDS.into_iter().enumerate() {
+        sched.bpf.advance(ROUND_NS);
+        let now = sched.bpf.now_ns();
+        for i in 0..nr_new {
+            let task = hog(next_pid, 0);
+            let latency_ns = (nr_new - i) as u64 * LATENCY_MIN_NS;
+            sched
+                .bpf
+                .set_env_hint(task.pid, slice_override::LATENCY_ENV, latency_ns / 1000);
+            queue.push_back((task.pid, now + latency_ns));
+            sched.bpf.enqueue(task);
+            next_pid += 1;
+        }
+        if engaged {
+            queue
+                .make_contiguous()
+                .sort_by_key(|&(_, deadline)| deadline);
+        }
+        let expected: Vec<(i32, u64)> = queue
+            .drain(..queue.len().min(NR_CPUS as usize))
+            .map(|(pid, deadline)| (pid, if engaged { deadline } else { 0 }))
+            .collect();
+
+        if let Err(err) = sched.schedule() {
+            return vec![format!("policy activation: schedule() failed: {}", err)];
+        }
+        let dispatched: Vec<(i32, u64)> = sched
+            .bpf
+            .take_dispatched()
+            .iter()
+            .map(|d| (d.pid, d.vtime))
+            .collect();
+        if sched.policy_active != engaged {
+            return vec![format!(
+                "policy activation: policy {} in round {} with {} tasks waiting (threshold {})",
+                if engaged { "not engaged" } else { "engaged" },
+                round,
+                dispatched.len() as u64 + sched.nr_pending(),
+                ACTIVATION_THRESH
+            )];
+        }
+        if dispatched != expected {
+            return vec![format!(
+                "policy activation: expected (pid, deadline) dispatches {:?} in round {}, got {:?}",
+                expected, round, dispatched
+            )];
+        }
+    }
+
+    Vec::new()
+}
+
+// Verify that a weight change reported by the kernel takes effect in the round the task is
+// received with it: with the wrr policy on a single CPU, a task with the maximum weight keeps
+// the CPU for itself (it has many dispatch credits left), until it is reniced to the default
+// weight, that entitles it to a single dispatch in a row, so the other task must be dispatched
+// in the very next round.
+fn check_weight_change(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Wrr,
+        order: Order::Fifo,
+        starve_timeout_ms: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(1).scheduler(&opts);
+    let (weight, new_weight) = RENICE_WEIGHTS;
+    let mut heavy = SimTask::new(1, 0, weight, Behavior::Hog).task;
+    sched.bpf.enqueue(heavy.clone());
+    sched.bpf.enqueue(hog(2, 0));
+
+    for round in 0..=RENICE_ROUND + 1 {
+        sched.bpf.advance(ROUND_NS);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("weight change: schedule() failed: {}", err)];
+        }
+        let dispatched: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.pid).collect();
+        let expected = if round <= RENICE_ROUND { 1 } else { 2 };
+        if dispatched != [expected] {
+            return vec![format!(
+                "weight change: expected pid {} dispatched in round {} (pid 1 reniced from {} \
+                 to {} after round {}), got {:?}",
+                expected, round, weight, new_weight, RENICE_ROUND, dispatched
+            )];
+        }
+
+        // The reniced task is received again after using the CPU, with the new weight after
+        // the renice.
+        if expected == 1 {
+            if round == RENICE_ROUND {
+                heavy.weight = new_weight;
+            }
+            sched.bpf.enqueue(heavy.clone());
+        }
+    }
+
+    Vec::new()
+}
+
+// Verify that the tasks are dispatched according to the configured order (oldest or most
+// recently queued first), given a known arrival sequence.
+fn check_order(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (order, expected) in ORDER_EXPECTED {
+        let opts = Opts {
+            policy: Policy::Fifo,
+            order,
+            policy_activation_threshold: None,
+            starve_timeout_ms: None,
+            fork_bomb_thresh: None,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(1).scheduler(&opts);
+        let mut dispatched = Vec::new();
+
+        for pids in ORDER_ARRIVALS {
+            for &pid in pids {
+                sched.bpf.enqueue(hog(pid, 0));
+            }
+            sched.bpf.advance(ROUND_NS);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("order: schedule() failed: {}", err)];
+            }
+            dispatched.extend(sched.bpf.take_dispatched().iter().map(|d| d.pid));
+        }
+        if dispatched != expected {
+            violations.push(format!(
+                "order: expected {:?} dispatch order {:?}, got {:?}",
+                order, expected, dispatched
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the time slices assigned to the tasks follow the expression (see --slice-expr),
+// within the bounds (also when the result is infinite or undefined).
+fn check_slice_expr(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let max_slice_ns = opts.slice_us.max(opts.compute_max_slice_us) * 1000;
+    for (text, expected_ns) in [
+        ("weight * 10", 500_000.max(opts.slice_min_us * 1000)),
+        ("1 / 0", max_slice_ns),
+        ("0 / 0", opts.slice_min_us * 1000),
+    ] {
+        let opts = Opts {
+            slice_expr: slice_expr::parse(text).ok(),
+            fork_bomb_thresh: None,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched
+            .bpf
+            .enqueue(SimTask::new(1, 0, 50, Behavior::Hog).task);
+        sched.bpf.advance(ROUND_NS);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("slice expr: schedule() failed: {}", err)];
+        }
+        let expected_ns = sched.throttle(expected_ns);
+        let slices: Vec<u64> = sched
+            .bpf
+            .take_dispatched()
+            .iter()
+            .map(|d| d.slice_ns)
+            .collect();
+        if slices != [expected_ns] {
+            violations.push(format!(
+                "slice expr: '{}' assigned time slices {:?}, expected [{}]",
+                text, slices, expected_ns
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the classification of the CPUs that are thrashing their cache, with mocked miss rates
+// (including the hysteresis between the threshold and half of it and the CPUs without
+// counters), then that a compute-bound task is moved away from a thrashing CPU, while the other
+// tasks keep using their previous CPU.
+fn check_cache_aware(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let mut cache = CacheMonitor::new(None, CACHE_MISS_THRESH);
+
+    for (i, (mpki, thrashing, target)) in CACHE_SAMPLES.iter().enumerate() {
+        cache.update(mpki);
+        let got: Vec<bool> = (0..mpki.len()).map(|cpu| cache.thrashing(cpu)).collect();
+        if got != thrashing {
+            violations.push(format!(
+                "cache aware: sample {} ({:?} MPKI): thrashing CPUs {:?}, expected {:?}",
+                i, mpki, got, thrashing
+            ));
+        }
+        let got = cache.pick_cpu(0, |_| true);
+        if got != *target {
+            violations.push(format!(
+                "cache aware: sample {} ({:?} MPKI): picked CPU {:?} for CPU 0, expected {:?}",
+                i, mpki, got, target
+            ));
+        }
+    }
+    if cache
+        .pick_cpu(1, |cpu| cpu != 0 && cpu != 2 && cpu != 3)
+        .is_some()
+    {
+        violations.push("cache aware: picked a CPU that is not usable".to_string());
+    }
+
+    let opts = Opts {
+        llc_group: false,
+        spread_idle: false,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let mut cache = CacheMonitor::new(None, CACHE_MISS_THRESH);
+    cache.update(&CACHE_SAMPLES[0].0);
+    sched.cache = Some(cache);
+
+    // Get the first task tracked, then make it compute-bound.
+    sched.bpf.enqueue(hog(1, 0));
+    sched.bpf.advance(ROUND_NS);
+    if let Err(err) = sched.schedule() {
+        return vec![format!("cache aware: schedule() failed: {}", err)];
+    }
+    sched.bpf.take_dispatched();
+    if let Some(info) = sched.tasks.get_mut(&1) {
+        info.avg_util = 100;
+        info.avg_nvcsw_rt = 0;
+    }
+
+    sched.bpf.enqueue(hog(1, 0));
+    sched.bpf.enqueue(hog(2, 0));
+    sched.bpf.advance(ROUND_NS);
+    if let Err(err) = sched.schedule() {
+        return vec![format!("cache aware: schedule() failed: {}", err)];
+    }
+    let mut dispatched: Vec<(i32, i32)> = sched
+        .bpf
+        .take_dispatched()
+        .iter()
+        .map(|d| (d.pid, d.cpu))
+        .collect();
+    dispatched.sort();
+    let expected = [(1, CACHE_SAMPLES[0].2.unwrap_or(0) as i32), (2, 0)];
+    if dispatched != expected {
+        violations.push(format!(
+            "cache aware: expected (pid, cpu) dispatches {:?} with CPU 0 thrashing, got {:?}",
+            expected, dispatched
+        ));
+    }
+
+    violations
+}
+
+// Verify that the state of a task that is not received anymore (e.g., its virtual runtime)
+// survives the periodic GC pass up to the grace period and that it is reclaimed after it.
+fn check_pid_gc(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        pid_gc_secs: PID_GC_SECS,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+
+    sched.bpf.enqueue(hog(1, 0));
+    sched.bpf.advance(ROUND_NS);
+    if let Err(err) = sched.schedule() {
+        return vec![format!("pid gc: schedule() failed: {}", err)];
+    }
+    sched.bpf.take_dispatched();
+    if let Some(info) = sched.tasks.get_mut(&1) {
+        info.vtime = PID_GC_VTIME;
+    }
+
+    // Run the GC pass once per second (like the main loop), until the grace period is about to
+    // expire (one round before it), then right after it.
+    for _ in 1..PID_GC_SECS {
+        sched.bpf.advance(NSEC_PER_SEC);
+        sched.gc_tasks();
+    }
+    sched.bpf.advance(NSEC_PER_SEC - ROUND_NS);
+    sched.gc_tasks();
+    let vtime = sched.tasks.get(&1).map(|info| info.vtime);
+    if vtime != Some(PID_GC_VTIME) {
+        return vec![format!(
+            "pid gc: state of the task lost before the grace period ({}s), vtime {:?}",
+            PID_GC_SECS, vtime
+        )];
+    }
+
+    sched.bpf.advance(ROUND_NS);
+    sched.gc_tasks();
+    if sched.tasks.contains_key(&1) {
+        return vec![format!(
+            "pid gc: state of the task kept after the grace period ({}s)",
+            PID_GC_SECS
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that, when no idle CPU is ever available, the tasks are distributed evenly across the
+// NUMA nodes and across the CPUs of each node of a mocked two-node topology, also when a CPU is
+// excluded from dispatch (its share goes to the other CPU of its node).
+fn check_numa_fallback(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for excluded in [None, Some(NUMA_EXCLUDED_CPU)] {
+        let opts = Opts {
+            cpus_offline: excluded.map(|cpu| CpuList(vec![cpu])),
+            kernel_cpu: None,
+            cpu_any_shortcut: false,
+            llc_group: false,
+            cpuset_aware: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let nodes = NUMA_NODES.iter().map(|cpus| cpus.to_vec()).collect();
+        sched.numa = Some(NumaFallback::new(nodes));
+        sched.bpf.saturate();
+
+        let mut nr_cpu_tasks = vec![0; NR_CPUS as usize];
+        for _ in 0..NUMA_ROUNDS {
+            for pid in 1..=NR_CPUS as i32 {
+                sched.bpf.enqueue(hog(pid, pid - 1));
+            }
+            sched.bpf.advance(ROUND_NS);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("numa fallback: schedule() failed: {}", err)];
+            }
+            for d in sched.bpf.take_dispatched() {
+                match nr_cpu_tasks.get_mut(d.cpu as usize).filter(|_| d.cpu >= 0) {
+                    Some(nr) => *nr += 1,
+                    None => violations.push(format!(
+                        "numa fallback: pid {} dispatched to CPU {}",
+                        d.pid, d.cpu
+                    )),
+                }
+            }
+        }
+
+        let nr_tasks = NUMA_ROUNDS * NR_CPUS;
+        let nr_node_tasks = nr_tasks / NUMA_NODES.len() as u64;
+        let distribution = sched.numa.as_ref().map(|numa| numa.distribution().to_vec());
+        if distribution != Some(vec![nr_node_tasks; NUMA_NODES.len()]) {
+            violations.push(format!(
+                "numa fallback: {} tasks distributed across the nodes as {:?}, expected {} per \
+                 node (excluded CPU {:?})",
+                nr_tasks, distribution, nr_node_tasks, excluded
+            ));
+        }
+        let expected: Vec<u64> = NUMA_NODES
+            .iter()
+            .flat_map(|cpus| {
+                let usable = cpus.iter().filter(|&&cpu| Some(cpu) != excluded).count() as u64;
+                cpus.iter().map(move |&cpu| {
+                    if Some(cpu) == excluded {
+                        0
+                    } else {
+                        nr_node_tasks / usable
+                    }
+                })
+            })
+            .collect();
+        if nr_cpu_tasks != expected {
+            violations.push(format!(
+                "numa fallback: tasks per CPU {:?}, expected {:?} (excluded CPU {:?})",
+                nr_cpu_tasks, expected, excluded
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the tasks whose comm matches a --comm-cap pattern together never get more than the
+// cap in a one-second interval and that they get (almost) all of it, while a task that doesn't
+// match is dispatched in every round: one CPU hog per CPU, two of them named COMM_CAPPED, one
+// renamed to COMM_CAPPED by an exec() after COMM_EXEC_SECS (it joins the cap within the next
+// second) and one that is never capped.
+fn check_comm_cap(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let Ok(rule) = comm_cap::parse(&format!("^{}$={}", COMM_CAPPED, COMM_CAP_US)) else {
+        return vec!["comm cap: failed to parse the cap".to_string()];
+    };
+    let opts = Opts {
+        policy: Policy::Fifo,
+        order: Order::Fifo,
+        comm_cap: vec![rule],
+        starve_timeout_ms: None,
+        wakeup_gap_high_us: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let (uncapped, renamed) = (3, 4);
+    let mut sched = Fixture::new(NR_CPUS)
+        .tasks((1..=NR_CPUS as i32).map(|pid| hog(pid, pid - 1)))
+        .backend(|bpf| {
+            for pid in 1..=NR_CPUS as i32 {
+                bpf.set_comm(pid, if pid <= 2 { COMM_CAPPED } else { "sh" });
+            }
+        })
+        .closed_loop(u64::MAX)
+        .scheduler(&opts);
+    let nr_rounds = NSEC_PER_SEC / ROUND_NS;
+    let mut capped_ns = vec![0; COMM_CAP_SECS as usize];
+
+    for round in 0..COMM_CAP_SECS * nr_rounds {
+        let secs = round / nr_rounds;
+        if round == COMM_EXEC_SECS * nr_rounds {
+            sched.bpf.set_comm(renamed, COMM_CAPPED);
+        }
+        if let Err(err) = sched.schedule() {
+            return vec![format!("comm cap: schedule() failed: {}", err)];
+        }
+        sched.update_overload();
+
+        let dispatched = sched.bpf.take_dispatched();
+        let capped = |pid: i32| pid <= 2 || (pid == renamed && secs > COMM_EXEC_SECS);
+        capped_ns[secs as usize] += dispatched
+            .iter()
+            .filter(|d| capped(d.pid))
+            .map(|d| d.slice_ns)
+            .sum::<u64>();
+        if let Some(d) = dispatched.iter().find(|d| d.slice_ns == 0) {
+            violations.push(format!(
+                "comm cap: pid {} dispatched without CPU time left in round {}",
+                d.pid, round
+            ));
+        }
+        let mut missing = vec![uncapped];
+        if secs < COMM_EXEC_SECS {
+            missing.push(renamed);
+        }
+        missing.retain(|&pid| !dispatched.iter().any(|d| d.pid == pid));
+        if !missing.is_empty() {
+            violations.push(format!(
+                "comm cap: uncapped tasks {:?} not dispatched in round {}",
+                missing, round
+            ));
+        }
+    }
+
+    // The interval of the exec() is not checked: the renamed task joins the cap as soon as its
+    // comm is matched again.
+    let cap_ns = COMM_CAP_US * 1000;
+    for (secs, used_ns) in capped_ns.into_iter().enumerate() {
+        if secs as u64 != COMM_EXEC_SECS && (used_ns > cap_ns || used_ns < cap_ns * 9 / 10) {
+            violations.push(format!(
+                "comm cap: capped tasks got {}us of CPU time in second {}, expected {}us",
+                used_ns / 1000,
+                secs,
+                COMM_CAP_US
+            ));
+        }
+    }
+
+    // The comm of the tasks is recorded in the traces, including the spaces.
+    let event = Event::Comm(1, Some("Web Content".to_string()));
+    if event.to_string().parse::<Event>().as_ref() != Ok(&event) {
+        violations.push(format!(
+            "comm cap: '{}' not parsed back from a trace",
+            event
+        ));
+    }
+
+    violations
+}
+
+// Verify the response of the latency controller to a synthetic latency feedback (see
+// LATENCY_FEEDBACK) and that the scheduler applies its adjustments: with the maximum
+// adjustment, the time slices are divided by 8 and up to 4 tasks per CPU are dispatched in a
+// round.
+fn check_latency_target(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let hysteresis = Hysteresis::new(LATENCY_HYSTERESIS_NS, 0);
+    let mut target = LatencyTarget::new(LATENCY_TARGET_US * 1000, hysteresis, 0);
+
+    for (i, (latency_us, slice_div, batch_scale)) in LATENCY_FEEDBACK.into_iter().enumerate() {
+        if latency_us > 0 {
+            for pid in 1..=200 {
+                target.record(pid, latency_us * 1000);
+            }
+            target.record(0, LATENCY_OUTLIER_NS);
+        }
+        target.evaluate((i as u64 + 1) * NSEC_PER_SEC);
+        let state = (1000 / target.slice_ns(1000), target.batch_scale());
+        if state != (slice_div, batch_scale) {
+            violations.push(format!(
+                "latency target: interval {} ({}us): time slices divided by {}, tasks per round \
+                 {}x, expected {} and {}x",
+                i, latency_us, state.0, state.1, slice_div, batch_scale
+            ));
+        }
+    }
+
+    // Put the scheduler under the maximum adjustment and compare the dispatched tasks against
+    // a scheduler without the target.
+    let opts = Opts {
+        policy: Policy::Fifo,
+        order: Order::Fifo,
+        slice_expr: None,
+        wakeup_gap_high_us: None,
+        ..opts.clone()
+    };
+    let tuned_opts = Opts {
+        latency_target_us: Some(LATENCY_TARGET_US),
+        ..opts.clone()
+    };
+    let dispatch = |opts: &Opts, tune: bool| {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+        if let Some(target) = sched.latency_target.as_mut().filter(|_| tune) {
+            for secs in 1..=LATENCY_FEEDBACK.len() as u64 {
+                target.record(1, LATENCY_OUTLIER_NS);
+                target.evaluate(secs * NSEC_PER_SEC);
+            }
+        }
+        for pid in 1..=4 * NR_CPUS as i32 {
+            sched.bpf.enqueue(hog(pid, -1));
+        }
+        sched.schedule().map(|_| sched.bpf.take_dispatched())
+    };
+    match (dispatch(&opts, false), dispatch(&tuned_opts, true)) {
+        (Ok(base), Ok(tuned)) => {
+            if base.len() != NR_CPUS as usize || tuned.len() != 4 * NR_CPUS as usize {
+                violations.push(format!(
+                    "latency target: {} tasks dispatched in a round ({} without the target), \
+                     expected {}",
+                    tuned.len(),
+                    base.len(),
+                    4 * NR_CPUS
+                ));
+            }
+            if let (Some(base), Some(tuned)) = (base.first(), tuned.first()) {
+                if tuned.slice_ns != base.slice_ns / 8 {
+                    violations.push(format!(
+                        "latency target: time slice {}ns ({}ns without the target), expected \
+                         {}ns",
+                        tuned.slice_ns,
+                        base.slice_ns,
+                        base.slice_ns / 8
+                    ));
+                }
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            violations.push(format!("latency target: schedule() failed: {}", err))
+        }
+    }
+
+    violations
+}
+
+// Receive the notifications sent to `socket` so far (one per datagram, e.g. "READY=1").
+fn recv_notifications(socket: &UnixDatagram) -> io::Result<Vec<String>> {
+    let mut notifications = Vec::new();
+    let mut buf = [0u8; 256];
+
+    loop {
+        match socket.recv(&mut buf) {
+            Ok(len) => notifications.push(String::from_utf8_lossy(&buf[..len]).trim().to_string()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(notifications),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Verify the notifications to systemd, with a local socket in place of the service manager:
+// nothing is enabled without NOTIFY_SOCKET, no heartbeat is sent without a watchdog, and a
+// session with a watchdog reports READY=1, a WATCHDOG=1 heartbeat every half of the watchdog
+// timeout, and STOPPING=1 at the end.
+fn check_systemd(opts: &Opts) -> Vec<String> {
+    let vars = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+    let saved: Vec<_> = vars.iter().map(env::var_os).collect();
+    let path = env::temp_dir().join(format!(
+        "scx_rust_scheduler-selftest-{}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let socket = match UnixDatagram::bind(&path).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => socket,
+        Err(err) => {
+            return vec![format!(
+                "systemd: failed to bind {}: {}",
+                path.display(),
+                err
+            )]
+        }
+    };
+    let mut violations = Vec::new();
+
+    vars.iter().for_each(|var| env::remove_var(var));
+    if Notifier::detect().is_some() {
+        violations.push("systemd: notifications enabled without NOTIFY_SOCKET".to_string());
+    }
+
+    env::set_var("NOTIFY_SOCKET", &path);
+    for watchdog_usec in [None, Some(SYSTEMD_WATCHDOG_USEC)] {
+        match watchdog_usec {
+            Some(usec) => env::set_var("WATCHDOG_USEC", usec.to_string()),
+            None => env::remove_var("WATCHDOG_USEC"),
+        }
+        let Some(notifier) = Notifier::detect() else {
+            violations.push("systemd: notifications not enabled with NOTIFY_SOCKET".to_string());
+            continue;
+        };
+        let mut sched = Fixture::new(NR_CPUS)
+            .tasks((1..=NR_CPUS as i32 * 2).map(|pid| hog(pid, -1)))
+            .closed_loop(SYSTEMD_ROUNDS)
+            .scheduler(opts);
+        sched.notifier = Some(&notifier);
+        notifier.ready(sched.now_ns());
+        if let Err(err) = sched.run_loop() {
+            violations.push(format!("systemd: run_loop() failed: {}", err));
+        }
+        notifier.stopping();
+
+        let notifications = match recv_notifications(&socket) {
+            Ok(notifications) => notifications,
+            Err(err) => {
+                violations.push(format!(
+                    "systemd: failed to receive the notifications: {}",
+                    err
+                ));
+                continue;
+            }
+        };
+        let nr_heartbeats = notifications.iter().filter(|n| *n == "WATCHDOG=1").count() as u64;
+        let expected = watchdog_usec.map_or(0, |usec| SYSTEMD_ROUNDS * ROUND_NS / (usec * 500));
+        if notifications.first().map(String::as_str) != Some("READY=1")
+            || notifications.last().map(String::as_str) != Some("STOPPING=1")
+            || !(expected.saturating_sub(1)..=expected).contains(&nr_heartbeats)
+            || nr_heartbeats + 2 != notifications.len() as u64
+        {
+            violations.push(format!(
+                "systemd: watchdog {:?}us: expected READY=1, {} heartbeats and STOPPING=1, \
+                 received {:?}",
+                watchdog_usec, expected, notifications
+            ));
+        }
+    }
+
+    for (var, value) in vars.iter().zip(saved) {
+        match value {
+            Some(value) => env::set_var(var, value),
+            None => env::remove_var(var),
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+
+    violations
+}
+
+// Verify the circuit breaker of notify_complete(), with a mock whose notify_complete() blocks
+// for too long: the main loop must stop (so that the scheduler is restarted) at the
+// --notify-stall-count-th consecutive slow call, and only then; calls that last exactly the
+// threshold, slow calls interleaved with regular ones, slow calls without --notify-stall-ms and
+// the long sleeps of an idle system (no task waiting) must not trip it.
+fn check_notify_stall(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let session = |stall_ms: Option<u64>, duration_ms: u64, nr_calls: u64| {
+        let opts = Opts {
+            notify_stall_ms: stall_ms,
+            notify_stall_count: NOTIFY_STALL_COUNT,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS)
+            .tasks((1..=NR_CPUS as i32 * 2).map(|pid| hog(pid, -1)))
+            .closed_loop(NOTIFY_STALL_ROUNDS)
+            .backend(|bpf| bpf.stall_notify(duration_ms * 1_000_000, nr_calls))
+            .scheduler(&opts);
+        let result = sched
+            .run_loop()
+            .map(|_| (sched.needs_restart(), sched.bpf.exited()));
+
+        result.map_err(|err| format!("notify stall: run_loop() failed: {}", err))
+    };
+
+    for (stall_ms, duration_ms, nr_calls, expected) in NOTIFY_STALL_SESSIONS {
+        match session(stall_ms, duration_ms, nr_calls) {
+            Ok((stalled, exited)) if stalled != expected || exited == expected => {
+                violations.push(format!(
+                    "notify stall: --notify-stall-ms {:?}, {} calls of {}ms: stalled {} \
+                     (expected {}), all the rounds completed {}",
+                    stall_ms, nr_calls, duration_ms, stalled, expected, exited
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => violations.push(err),
+        }
+    }
+
+    // Slow calls interleaved with regular ones, with tasks waiting, and slow calls only, on an
+    // idle system (the scheduler is simply sleeping).
+    let opts = Opts {
+        notify_stall_ms: Some(NOTIFY_STALL_MS),
+        notify_stall_count: NOTIFY_STALL_COUNT,
+        ..opts.clone()
+    };
+    for (nr_tasks, interleaved) in [(NR_CPUS as i32 * 2, true), (0, false)] {
+        let mut sched = Fixture::new(NR_CPUS)
+            .hogs(1..=nr_tasks)
+            .closed_loop(NOTIFY_STALL_ROUNDS)
+            .scheduler(&opts);
+        for round in 0..NOTIFY_STALL_COUNT * 4 {
+            let nr_calls = if interleaved { round % 2 } else { 1 };
+            sched
+                .bpf
+                .stall_notify(NOTIFY_STALL_MS * 2_000_000, nr_calls);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("notify stall: schedule() failed: {}", err)];
+            }
+        }
+        if sched.needs_restart() {
+            violations.push(format!(
+                "notify stall: tripped by {} with {} tasks",
+                if interleaved {
+                    "non-consecutive slow calls"
+                } else {
+                    "the slow calls of an idle system"
+                },
+                nr_tasks
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the export of the scheduling decisions (see --export-csv): the file must contain the
+// header and one row per dispatched task, in the order of the dispatches, with the selected CPU
+// (including the first CPU available, see --cpu-any-shortcut) and the time slice actually sent
+// to the backend, and consistent features.
+fn check_export(opts: &Opts) -> Vec<String> {
+    let path = env::temp_dir().join(format!(
+        "scx_rust_scheduler-selftest-{}.csv",
+        std::process::id()
+    ));
+    let Some(path_str) = path.to_str() else {
+        return vec![format!("export: invalid path {}", path.display())];
+    };
+    let export = match DecisionExporter::create(path_str) {
+        Ok(export) => export,
+        Err(err) => return vec![format!("export: {:#}", err)],
+    };
+    let tasks = (1..=EXPORT_TASKS).map(|pid| {
+        let mut task =
+            SimTask::new(pid, pid % NR_CPUS as i32, 100 * pid as u64, Behavior::Hog).task;
+        if pid % 2 == 0 {
+            task.flags |= RL_CPU_ANY as u64;
+        }
+        task
+    });
+    let opts = Opts {
+        cpu_any_shortcut: true,
+        cpus_offline: None,
+        kernel_cpu: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS)
+        .tasks(tasks)
+        .closed_loop(EXPORT_ROUNDS)
+        .scheduler(&opts);
+    sched.export = Some(export);
+    if let Err(err) = sched.run_loop().and_then(|_| sched.finish_export()) {
+        return vec![format!("export: {:#}", err)];
+    }
+    let dispatched = sched.bpf.take_dispatched();
+    let text = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let text = match text {
+        Ok(text) => text,
+        Err(err) => {
+            return vec![format!(
+                "export: failed to read {}: {}",
+                path.display(),
+                err
+            )]
+        }
+    };
+
+    let mut violations = Vec::new();
+    let mut lines = text.lines();
+    if lines.next() != Some(CSV_HEADER) {
+        violations.push("export: missing header".to_string());
+    }
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    if rows.len() != dispatched.len() || !dispatched.iter().any(|d| d.cpu == RL_CPU_ANY) {
+        violations.push(format!(
+            "export: {} rows, {} dispatched tasks",
+            rows.len(),
+            dispatched.len()
+        ));
+    }
+    let nr_columns = CSV_HEADER.split(',').count();
+    for (row, d) in rows.iter().zip(&dispatched) {
+        let cpu = if d.cpu == RL_CPU_ANY { -1 } else { d.cpu };
+        let expected = [d.pid.to_string(), cpu.to_string(), d.slice_ns.to_string()];
+        let found = [row.get(1), row.get(11), row.get(12)].map(|v| v.copied().unwrap_or(""));
+        let weight: u64 = row.get(2).and_then(|w| w.parse().ok()).unwrap_or(0);
+        if row.len() != nr_columns
+            || expected
+                .iter()
+                .zip(found)
+                .any(|(expected, found)| expected != found)
+            || weight != 100 * d.pid as u64
+            || !matches!(row.get(7), Some(&"interactive") | Some(&"batch"))
+            || row.iter().any(|v| v.is_empty())
+        {
+            violations.push(format!("export: row {:?} for dispatch {:?}", row, d));
+        }
+    }
+
+    violations
+}
+
+// Verify the named profiles (see --profile): invalid and duplicate definitions are rejected,
+// activating a profile applies all its settings together (the effective policy and time slice,
+// with the other settings of the command line), an unknown profile leaves the active one
+// unchanged, and SIGUSR2 switches to the next profile between two rounds.
+fn check_profiles(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for text in PROFILES_INVALID {
+        if let Ok(profile) = profiles::parse(text) {
+            violations.push(format!("profiles: '{}' accepted as {:?}", text, profile));
+        }
+    }
+    let mut defined = Vec::new();
+    for (_, text, _, _, _) in PROFILES {
+        match profiles::parse(text) {
+            Ok(profile) => defined.push(profile),
+            Err(err) => violations.push(format!("profiles: '{}' rejected: {}", text, err)),
+        }
+    }
+    let duplicated = [defined.clone(), defined.clone()].concat();
+    if profiles::validate(&defined).is_err() || profiles::validate(&duplicated).is_ok() {
+        violations.push("profiles: duplicate profiles not detected".to_string());
+    }
+
+    let opts = Opts {
+        policy: Policy::Fifo,
+        slice_us: 5000,
+        compute_boost: false,
+        policy_activation_threshold: None,
+        mode: Mode::Manual,
+        profile: defined,
+        profile_at: Vec::new(),
+        batch_quantum_mult: 1,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let task = hog(1, 0);
+    let effective = |sched: &mut Scheduler<MockBackend>| {
+        let slice_ns = sched.compute_slice(&task, TaskClass::Batch, 0);
+        (sched.ordering(), slice_ns / 1000, sched.compute_boost)
+    };
+    let cmdline = (opts.policy, opts.slice_us, opts.compute_boost);
+    let expected = PROFILES
+        .iter()
+        .map(|&(name, _, policy, slice_us, boost)| (name, (policy, slice_us, boost)))
+        .chain([("default", cmdline)]);
+    for (name, settings) in expected {
+        if let Err(err) = sched.switch_profile(name) {
+            violations.push(format!("profiles: failed to activate {}: {}", name, err));
+        }
+        let found = effective(&mut sched);
+        if found != settings {
+            violations.push(format!(
+                "profiles: {}: expected {:?}, found {:?}",
+                name, settings, found
+            ));
+        }
+    }
+
+    sched.switch_profile(PROFILES[1].0).ok();
+    let before = effective(&mut sched);
+    if sched.switch_profile("battery").is_ok() || effective(&mut sched) != before {
+        violations.push("profiles: unknown profile activated".to_string());
+    }
+
+    // SIGUSR2 cycles through the profiles in alphabetical order, starting from default.
+    sched.switch_profile("default").ok();
+    sched.bpf.closed_loop(ROUND_NS, 1);
+    profiles::install_signal_handler();
+    unsafe { libc::raise(libc::SIGUSR2) };
+    if let Err(err) = sched.run_loop() {
+        violations.push(format!("profiles: run_loop() failed: {}", err));
+    }
+    let active = sched.profiles.as_ref().map(|p| p.active().to_string());
+    if active.as_deref() != Some(PROFILES[0].0) || effective(&mut sched).1 != PROFILES[0].3 {
+        violations.push(format!(
+            "profiles: SIGUSR2 switched to {:?}, expected {}",
+            active, PROFILES[0].0
+        ));
+    }
+
+    violations
+}
+
+// Verify the time-of-day schedule of the profiles (see --profile-at) against a mocked wall clock:
+// invalid entries, unknown profiles and times scheduled twice are rejected, the profile of the
+// current slot is activated at startup and at each boundary crossing (including the slot that
+// wraps around midnight and the jumps of the wall clock), and only then.
+fn check_profile_at(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for text in PROFILE_AT_INVALID {
+        if let Ok(entry) = timetable::parse(text) {
+            violations.push(format!("profile at: '{}' accepted as {:?}", text, entry));
+        }
+    }
+    let mut entries = Vec::new();
+    for text in PROFILE_AT {
+        match timetable::parse(text) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => violations.push(format!("profile at: '{}' rejected: {}", text, err)),
+        }
+    }
+    let names: Vec<&str> = std::iter::once(profiles::DEFAULT_PROFILE)
+        .chain(PROFILES.iter().map(|&(name, _, _, _, _)| name))
+        .collect();
+    let duplicated = [entries.clone(), entries.clone()].concat();
+    if timetable::validate(&entries, &names).is_err()
+        || timetable::validate(&duplicated, &names).is_ok()
+        || timetable::validate(&entries, &names[..1]).is_ok()
+    {
+        violations.push("profile at: duplicate times or unknown profiles not detected".to_string());
+    }
+
+    let opts = Opts {
+        mode: Mode::Manual,
+        profile: PROFILES
+            .iter()
+            .filter_map(|&(_, text, _, _, _)| profiles::parse(text).ok())
+            .collect(),
+        profile_at: entries,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    for (minute, manual, expected) in PROFILE_AT_STEPS {
+        if let Some(name) = manual {
+            sched.switch_profile(name).ok();
+        }
+        sched.update_timetable(minute);
+        let active = sched.profiles.as_ref().map(|p| p.active().to_string());
+        if active.as_deref() != Some(expected) {
+            violations.push(format!(
+                "profile at: {:02}:{:02}: active profile {:?}, expected {}",
+                minute / 60,
+                minute % 60,
+                active,
+                expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the detection of the time slices that are not taking effect (see --debug-accounting),
+// with a mock whose tasks use more or less CPU time than their time slice: a systematic
+// divergence beyond the tolerance must be reported (and cleared once the tasks use their time
+// slices again), a CPU time within the tolerance must not.
+fn check_slice_divergence(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        debug_accounting: true,
+        accounting_tolerance_pct: DIVERGENCE_TOLERANCE_PCT,
+        slice_us: DIVERGENCE_SLICE_US,
+        ..opts.clone()
+    };
+    let nr_rounds = NSEC_PER_SEC / ROUND_NS;
+
+    for (runtime_pct, expected) in DIVERGENCE_RUNTIME {
+        let mut sched = Fixture::new(NR_CPUS)
+            .tasks((1..=NR_CPUS as i32).map(|pid| hog(pid, -1)))
+            .closed_loop(u64::MAX)
+            .backend(|bpf| bpf.scale_runtime(runtime_pct))
+            .scheduler(&opts);
+
+        // Run the simulated sessions, then one more second using the entire time slices.
+        let mut diverging = Vec::new();
+        for secs in 0..=DIVERGENCE_SECS {
+            if secs == DIVERGENCE_SECS {
+                sched.bpf.scale_runtime(100);
+            }
+            for _ in 0..nr_rounds {
+                if let Err(err) = sched.schedule() {
+                    return vec![format!("slice divergence: schedule() failed: {}", err)];
+                }
+                sched.bpf.take_dispatched();
+            }
+            if let Some(accounting) = sched.accounting.as_mut() {
+                accounting.report();
+                diverging.push(accounting.diverging());
+            }
+        }
+        if diverging[DIVERGENCE_SECS as usize - 1] != expected
+            || diverging[DIVERGENCE_SECS as usize]
+        {
+            violations.push(format!(
+                "slice divergence: tasks using {}% of their time slices: diverging {:?} \
+                 (expected {}, then false)",
+                runtime_pct, diverging, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that a task that yields (requesting a time slice of 0 with --slice-env) is dispatched
+// with the minimum time slice, never with slice_ns = 0 (that would be the default time slice of
+// the BPF component), as well as a task that requests less than the minimum and any time slice
+// that the policy scales down to 0, both with the default and with a custom --slice-min-us.
+fn check_yield(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for slice_min_us in [opts.slice_min_us, YIELD_SLICE_MIN_US] {
+        let opts = Opts {
+            slice_env: true,
+            slice_min_us,
+            slice_expr: None,
+            fork_bomb_thresh: None,
+            thermal_sensor: None,
+            comm_cap: Vec::new(),
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let (yielding, short) = (1, 2);
+        sched
+            .bpf
+            .set_env_hint(yielding, slice_override::SLICE_ENV, 0);
+        sched
+            .bpf
+            .set_env_hint(short, slice_override::SLICE_ENV, YIELD_SHORT_SLICE_US);
+        for pid in [yielding, short] {
+            sched.bpf.enqueue(hog(pid, -1));
+        }
+        if let Err(err) = sched.schedule() {
+            return vec![format!("yield: schedule() failed: {}", err)];
+        }
+
+        let min_slice_ns = slice_min_us * 1000;
+        for d in sched.bpf.take_dispatched() {
+            if d.slice_ns != min_slice_ns {
+                violations.push(format!(
+                    "yield: pid {} dispatched with slice_ns {}, expected the minimum time slice \
+                     {}ns",
+                    d.pid, d.slice_ns, min_slice_ns
+                ));
+            }
+        }
+        if sched.dispatch_slice_ns(0) != min_slice_ns {
+            violations.push(format!(
+                "yield: a time slice of 0 is dispatched as slice_ns {}, expected {}",
+                sched.dispatch_slice_ns(0),
+                min_slice_ns
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the per-CPU load reported by the live view, both as load bars (on a terminal) and as
+// regular lines: one CPU is assigned half of the interval, another one none of it.
+fn check_top_view() -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for tty in [true, false] {
+        let mut out = Vec::new();
+        let mut view = TopView::new(&mut out, tty, 2, 0);
+        view.record(Some(0), NSEC_PER_SEC / 4);
+        view.record(Some(0), NSEC_PER_SEC / 4);
+        view.record(None, NSEC_PER_SEC);
+        if let Err(err) = view.draw(NSEC_PER_SEC) {
+            violations.push(format!("top view: drawing failed: {}", err));
+        }
+        drop(view);
+        let out = String::from_utf8_lossy(&out);
+        let expected: &[&str] = if tty {
+            &[
+                "\x1b[4;r",
+                "[|||||||||||||||||||||||||                         ]  50.0%",
+                "any    1.00 CPUs",
+                "\x1b[r",
+            ]
+        } else {
+            &["cpu0    50.0% | cpu1     0.0%", "any 1.00 CPUs"]
+        };
+        for pattern in expected.iter().filter(|&pattern| !out.contains(pattern)) {
+            violations.push(format!("top view: {:?} not found in {:?}", pattern, out));
+        }
+    }
+
+    violations
+}
+
+// Simulate the interactive tasks competing with the CPU hogs for RESERVE_SECS seconds and
+// return the capacity used by the interactive tasks after the first second (in percent).
+//
+// The dispatched tasks are queued to a shared queue, refilled by the scheduler once it has been
+// drained, and run when a CPU is available, for their whole time slice (the hogs) or until the
+// end of their burst (the interactive tasks).
+fn interactive_share(opts: &Opts) -> Result<u64> {
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let interactive = |pid: i32| pid <= RESERVE_INTERACTIVE;
+    let mut tasks: HashMap<i32, (SimTask, u64, u64)> = (1..=RESERVE_INTERACTIVE + RESERVE_HOGS)
+        .map(|pid| {
+            let task = SimTask::new(pid, -1, 100, Behavior::Hog);
+            (pid, (task, 0, RESERVE_RUN_NS))
+        })
+        .collect();
+    let mut pids: Vec<i32> = tasks.keys().copied().collect();
+    pids.sort();
+    let mut dsq = VecDeque::new();
+    let mut cpus = vec![0; NR_CPUS as usize];
+    let mut used_ns = 0;
+
+    for round in 0..RESERVE_SECS * NSEC_PER_SEC / RESERVE_ROUND_NS {
+        let now = sched.bpf.now_ns();
+        for pid in &pids {
+            let (t, wake_ts, _) = tasks.get_mut(pid).unwrap();
+            if t.queued_round.is_none() && *wake_ts <= now {
+                t.queued_round = Some(round);
+                sched.bpf.enqueue(t.task.clone());
+            }
+        }
+        if dsq.is_empty() {
+            sched.schedule()?;
+            dsq.extend(sched.bpf.take_dispatched());
+        }
+
+        for busy_ts in cpus.iter_mut().filter(|ts| **ts <= now) {
+            let Some(d) = dsq.pop_front() else {
+                break;
+            };
+            let (t, wake_ts, burst_ns) = tasks.get_mut(&d.pid).unwrap();
+            let run_ns = if interactive(d.pid) {
+                d.slice_ns.min(*burst_ns)
+            } else {
+                d.slice_ns
+            };
+            t.task.sum_exec_runtime += run_ns;
+            t.queued_round = None;
+            *busy_ts = now + run_ns;
+            *wake_ts = *busy_ts;
+
+            // The interactive tasks sleep at the end of each burst (a burst interrupted by the
+            // end of the time slice continues as soon as the task is dispatched again).
+            if interactive(d.pid) {
+                *burst_ns -= run_ns;
+                if *burst_ns == 0 {
+                    t.task.nvcsw += 1;
+                    *burst_ns = RESERVE_RUN_NS;
+                    *wake_ts += RESERVE_SLEEP_NS;
+                }
+                if now >= NSEC_PER_SEC {
+                    used_ns += run_ns;
+                }
+            }
+        }
+
+        sched.bpf.advance(RESERVE_ROUND_NS);
+        sched.update_overload();
+    }
+
+    Ok(used_ns * 100 / ((RESERVE_SECS - 1) * NSEC_PER_SEC * NR_CPUS))
+}
+
+// Wake up BOOST_TASKS tasks together once per interval, while the minimum virtual runtime is
+// moved forward by the CPU-bound tasks, and return the total wakeup credit granted in each
+// interval.
+fn boost_credits(opts: &Opts) -> Vec<u64> {
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let tasks: Vec<Task> = (1..=BOOST_TASKS)
+        .map(|pid| hog(pid, pid % NR_CPUS as i32))
+        .collect();
+
+    for task in &tasks {
+        sched.prepare_task(task.clone(), sched.bpf.now_ns());
+    }
+    (0..BOOST_INTERVALS)
+        .map(|_| {
+            sched.bpf.advance(NSEC_PER_SEC);
+            sched.min_vtime += NSEC_PER_SEC;
+            let now = sched.bpf.now_ns();
+            tasks
+                .iter()
+                .map(|task| {
+                    let (pending, _) = sched.prepare_task(task.clone(), now);
+                    sched.min_vtime - pending.vtime
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// Verify the stats printed with --no-stats-on-idle: all the intervals with user-space or kernel
+// dispatches, plus one heartbeat every IDLE_STATS_HEARTBEAT_SECS during the idle stretches
+// (without the option, the stats of every interval).
+fn check_idle_stats(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for no_stats_on_idle in [false, true] {
+        let opts = Opts {
+            no_stats_on_idle,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let (mut prev_user, mut prev_kernel) = (0, 0);
+        let mut printed = Vec::new();
+        for sec in 1..=IDLE_STATS_SECS {
+            if IDLE_ACTIVE_SECS.contains(&sec) {
+                *sched.bpf.nr_user_dispatches_mut() += 1;
+            }
+            if sec == IDLE_KERNEL_SEC {
+                *sched.bpf.nr_kernel_dispatches_mut() += 1;
+            }
+            if sched.stats_due(sec, prev_user, prev_kernel) {
+                prev_user = *sched.bpf.nr_user_dispatches_mut();
+                prev_kernel = *sched.bpf.nr_kernel_dispatches_mut();
+                printed.push(sec);
+            }
+        }
+
+        let last_active = IDLE_ACTIVE_SECS[IDLE_ACTIVE_SECS.len() - 1];
+        let expected: Vec<u64> = match no_stats_on_idle {
+            false => (1..=IDLE_STATS_SECS).collect(),
+            true => IDLE_ACTIVE_SECS
+                .into_iter()
+                .chain([
+                    last_active + IDLE_STATS_HEARTBEAT_SECS,
+                    IDLE_KERNEL_SEC,
+                    IDLE_KERNEL_SEC + IDLE_STATS_HEARTBEAT_SECS,
+                ])
+                .collect(),
+        };
+        if printed != expected {
+            violations.push(format!(
+                "idle stats: no_stats_on_idle={}: stats printed at {:?} (expected {:?})",
+                no_stats_on_idle, printed, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the CPU reserved by --kernel-cpu never receives user-space dispatches, neither from
+// the idle CPU selection nor from the fallback when no idle CPU is available, also for the tasks
+// that previously ran on it or that can run on any CPU (RL_CPU_ANY).
+fn check_kernel_cpu(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for saturated in [false, true] {
+        let opts = Opts {
+            kernel_cpu: Some(KERNEL_CPU),
+            cpus_offline: None,
+            cpu_any_shortcut: true,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        if saturated {
+            sched.bpf.saturate();
+        }
+
+        let mut nr_cpu_tasks = vec![0; NR_CPUS as usize];
+        for _ in 0..KERNEL_CPU_ROUNDS {
+            for pid in 1..=NR_CPUS as i32 {
+                let mut task = hog(pid, KERNEL_CPU as i32);
+                if pid % 2 == 0 {
+                    task.flags |= RL_CPU_ANY as u64;
+                }
+                sched.bpf.enqueue(task);
+            }
+            sched.bpf.advance(ROUND_NS);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("kernel cpu: schedule() failed: {}", err)];
+            }
+            for d in sched.bpf.take_dispatched() {
+                match nr_cpu_tasks.get_mut(d.cpu as usize).filter(|_| d.cpu >= 0) {
+                    Some(nr) => *nr += 1,
+                    None => violations.push(format!(
+                        "kernel cpu: pid {} dispatched to CPU {} (saturated={})",
+                        d.pid, d.cpu, saturated
+                    )),
+                }
+            }
+        }
+
+        let nr_used = nr_cpu_tasks.iter().filter(|&&nr| nr > 0).count();
+        if nr_cpu_tasks[KERNEL_CPU] > 0 || nr_used != NR_CPUS as usize - 1 {
+            violations.push(format!(
+                "kernel cpu: dispatches per CPU {:?} with CPU {} reserved (saturated={})",
+                nr_cpu_tasks, KERNEL_CPU, saturated
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the shadow mode (see --shadow-policy): a candidate policy must never change the
+// dispatches, it must fully agree with the policy that is driving the scheduler and it must
+// disagree with a different policy when the weights of the tasks make a difference.
+fn check_shadow(opts: &Opts) -> Vec<String> {
+    let session = |policy: Policy, shadow_policy: Option<Policy>| {
+        let tasks = (1..=SHADOW_TASKS).map(|pid| {
+            let weight = 100 * (1 + pid as u64 % 4);
+            SimTask::new(pid, pid % NR_CPUS as i32, weight, Behavior::Hog).task
+        });
+        let fixture = Fixture::new(NR_CPUS)
+            .tasks(tasks)
+            .closed_loop(SHADOW_ROUNDS);
+        let opts = Opts {
+            policy,
+            shadow_policy,
+            policy_activation_threshold: None,
+            mode: Mode::Manual,
+            ..opts.clone()
+        };
+        let mut sched = fixture.scheduler(&opts);
+        sched.run_loop().map(|_| {
+            let dispatched: Vec<(i32, i32)> = sched
+                .bpf
+                .take_dispatched()
+                .iter()
+                .map(|d| (d.pid, d.cpu))
+                .collect();
+            let agreement = sched.shadow.as_ref().and_then(ShadowPolicy::agreement_pct);
+            (dispatched, agreement)
+        })
+    };
+
+    let mut violations = Vec::new();
+    for (policy, shadow_policy) in [(Policy::Fair, Policy::Fair), (Policy::Fifo, Policy::Fair)] {
+        let (reference, shadowed) =
+            match (session(policy, None), session(policy, Some(shadow_policy))) {
+                (Ok((reference, _)), Ok(shadowed)) => (reference, shadowed),
+                (Err(err), _) | (_, Err(err)) => return vec![format!("shadow policy: {:#}", err)],
+            };
+        if shadowed.0 != reference {
+            violations.push(format!(
+                "shadow policy: {:?} changed the dispatches of {:?}",
+                shadow_policy, policy
+            ));
+        }
+        let agreement = shadowed.1.unwrap_or(0.0);
+        let expected = policy == shadow_policy;
+        if (agreement == 100.0) != expected || agreement == 0.0 {
+            violations.push(format!(
+                "shadow policy: {:?} agreed with {:?} on {:.1}% of the dispatches",
+                shadow_policy, policy, agreement
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the handling of the errors of dequeue_task(): a transient burst of errors must be
+// counted, without losing any task or stopping the scheduler, while a sustained streak of errors
+// must trigger a restart of the scheduler.
+fn check_dequeue_errors(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (nr_errors, restart) in [(DEQUEUE_ERROR_BURST, false), (DEQUEUE_ERRORS_RESTART, true)] {
+        let mut sched = Fixture::new(NR_CPUS)
+            .tasks((1..=NR_CPUS as i32 * 2).map(|pid| hog(pid, -1)))
+            .closed_loop(DEQUEUE_ERROR_ROUNDS)
+            .backend(|bpf| bpf.fail_dequeues(nr_errors))
+            .scheduler(opts);
+        if let Err(err) = sched.run_loop() {
+            return vec![format!("dequeue errors: run_loop() failed: {}", err)];
+        }
+
+        let pids: HashSet<i32> = sched.bpf.take_dispatched().iter().map(|d| d.pid).collect();
+        let found = (
+            sched.nr_dequeue_errors,
+            sched.needs_restart(),
+            sched.bpf.exited(),
+            pids.len() as i32,
+        );
+        let expected = match restart {
+            false => (nr_errors, false, true, NR_CPUS as i32 * 2),
+            true => (nr_errors, true, false, 0),
+        };
+        if found != expected {
+            violations.push(format!(
+                "dequeue errors: {} consecutive errors: (errors, restart, exited, tasks \
+                 dispatched) = {:?}, expected {:?}",
+                nr_errors, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the weight boost of the I/O-bound tasks (see --io-boost): a task that sleeps most of the
+// time, releasing the CPU voluntarily, must be boosted and its virtual runtime must advance
+// accordingly slower, also when it wakes up rarely (the rate of context switches is normalized
+// against the CPU time), while the CPU-bound tasks must not, also when they switch often.
+fn check_io_boost(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for io_boost in [None, Some(IO_BOOST_FACTOR)] {
+        let opts = Opts {
+            io_boost,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        for (i, &(name, runtime_ns, nvcsw, boosted)) in IO_BOOST_PROFILES.iter().enumerate() {
+            let mut task = hog(i as i32 + 1, 0);
+            let mut vtime = (0, 0);
+            for _ in 0..IO_BOOST_SECS * NSEC_PER_SEC / IO_BOOST_PERIOD_NS {
+                task.sum_exec_runtime += runtime_ns;
+                task.nvcsw += nvcsw;
+                sched.bpf.advance(IO_BOOST_PERIOD_NS);
+                let now = sched.now_ns();
+                vtime.0 = sched.tasks.get(&task.pid).map_or(0, |info| info.vtime);
+                vtime.1 = sched.prepare_task(task.clone(), now).0.vtime;
+            }
+
+            let boost_pct = sched.io_boost_pct(task.pid);
+            let expected = boosted && io_boost.is_some();
+            let weight = task.weight * boost_pct / 100;
+            if (boost_pct > 100) != expected || vtime.1 - vtime.0 != runtime_ns * 100 / weight {
+                violations.push(format!(
+                    "io boost: {} task with --io-boost {:?}: boost {}%, vtime advanced by {} for \
+                     {}ns of CPU time (expected boost: {})",
+                    name,
+                    io_boost,
+                    boost_pct,
+                    vtime.1 - vtime.0,
+                    runtime_ns,
+                    expected
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// Verify the cap of the tracked tasks (see --max-tracked-pids): when a new task exceeds it, the
+// state of the least recently seen task must be evicted (and only that one), except for the
+// tasks waiting in the queues, and the order of the tasks must follow the state that is dropped
+// by the GC pass.
+fn check_max_tracked_pids(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        max_tracked_pids: Some(MAX_TRACKED_PIDS),
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    for pid in TRACKED_PIDS_ORDER {
+        sched.bpf.advance(ROUND_NS);
+        let now = sched.now_ns();
+        sched.prepare_task(hog(pid, 0), now);
+    }
+
+    let mut violations = Vec::new();
+    let mut tracked: Vec<i32> = sched.tasks.keys().copied().collect();
+    tracked.sort();
+    if tracked != [1, 3, 4] || sched.nr_evicted_pids != 1 {
+        violations.push(format!(
+            "max tracked pids: tasks {:?} tracked after receiving {:?} ({} evicted), expected \
+             [1, 3, 4] (1 evicted)",
+            tracked, TRACKED_PIDS_ORDER, sched.nr_evicted_pids
+        ));
+    }
+
+    let now = sched.now_ns();
+    let queued = hog(TRACKED_QUEUED_PID, 0);
+    sched.receive_task(queued, now);
+    for pid in TRACKED_NEW_PIDS {
+        sched.bpf.advance(ROUND_NS);
+        let now = sched.now_ns();
+        sched.prepare_task(hog(pid, 0), now);
+    }
+    if !sched.tasks.contains_key(&TRACKED_QUEUED_PID)
+        || sched.tasks.len() as u64 != MAX_TRACKED_PIDS
+    {
+        violations.push(format!(
+            "max tracked pids: waiting task {} evicted ({} tasks tracked)",
+            TRACKED_QUEUED_PID,
+            sched.tasks.len()
+        ));
+    }
+
+    sched.bpf.advance(opts.pid_gc_secs * NSEC_PER_SEC);
+    sched.gc_tasks();
+    let nr_lru = sched.lru.as_ref().map(PidLru::len);
+    if !sched.tasks.is_empty() || nr_lru != Some(0) {
+        violations.push(format!(
+            "max tracked pids: {} tasks and {:?} ordered tasks left after the GC pass",
+            sched.tasks.len(),
+            nr_lru
+        ));
+    }
+
+    violations
+}
+
+// Verify the validation of the CPUs returned by select_cpu() (see --strict-select-cpu): a CPU
+// out of range, or unplugged after startup, must never be used (the task goes to the fallback),
+// while an online CPU must be kept.
+fn check_strict_select_cpu(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        strict_select_cpu: true,
+        cpus_offline: None,
+        kernel_cpu: None,
+        ..opts.clone()
+    };
+
+    for (name, cpu, offline, expected) in STRICT_CPU_CASES {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched.bpf.force_select_cpu(cpu);
+        if let Some(offline) = offline {
+            sched.bpf.set_cpu_offline(offline);
+            sched.refresh_online_cpus();
+        }
+        sched.bpf.enqueue(hog(1, 0));
+        if let Err(err) = sched.schedule() {
+            return vec![format!("strict select_cpu: schedule() failed: {}", err)];
+        }
+
+        let found: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+        let nr_invalid = (expected != cpu) as u64;
+        if found != [expected] || sched.nr_invalid_cpus != nr_invalid {
+            violations.push(format!(
+                "strict select_cpu: {} CPU {}: dispatched to {:?} ({} invalid), expected {} \
+                 ({} invalid)",
+                name, cpu, found, sched.nr_invalid_cpus, expected, nr_invalid
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the quantum multiplier of the batch tasks (see --batch-quantum-mult): the batch tasks
+// must get the multiplied time slice, capped to --compute-max-slice-us, while the time slice of
+// the interactive tasks must not change.
+fn check_batch_quantum(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let task = hog(1, 0);
+    let slices_ns = |mult| {
+        let opts = Opts {
+            slice_us: BATCH_QUANTUM_SLICE_US,
+            compute_max_slice_us: BATCH_QUANTUM_MAX_SLICE_US,
+            batch_quantum_mult: mult,
+            compute_boost: false,
+            slice_expr: None,
+            latency_target_us: None,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        (
+            sched.compute_slice(&task, TaskClass::Batch, 0),
+            sched.compute_slice(&task, TaskClass::Interactive, 0),
+        )
+    };
+
+    let (_, base_interactive_ns) = slices_ns(1);
+    for (mult, expected_us) in BATCH_QUANTUM_CASES {
+        let found = slices_ns(mult);
+        if found != (expected_us * 1000, base_interactive_ns) {
+            violations.push(format!(
+                "batch quantum: x{}: (batch, interactive) time slices {:?}ns, expected ({}, {})",
+                mult,
+                found,
+                expected_us * 1000,
+                base_interactive_ns
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the cap of the time slice by the budget left to a task (see --cap-remaining-slice):
+// with the option, a nonzero budget must cap the time slice, but never below the minimum time
+// slice, and the time slice must not change without it.
+fn check_cap_remaining_slice(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (cap, budget_us, expected_us) in CAP_SLICE_CASES {
+        let opts = Opts {
+            slice_us: CAP_SLICE_US,
+            slice_min_us: CAP_SLICE_MIN_US,
+            cap_remaining_slice: cap,
+            batch_quantum_mult: 1,
+            compute_boost: false,
+            slice_expr: None,
+            latency_target_us: None,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let mut task = hog(1, 0);
+        task.slice = budget_us * 1000;
+
+        let found = sched.compute_slice(&task, TaskClass::Batch, 0);
+        if found != expected_us * 1000 {
+            violations.push(format!(
+                "cap remaining slice: cap {}, budget {}us: time slice {}ns, expected {}",
+                cap,
+                budget_us,
+                found,
+                expected_us * 1000
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the dispatch-to-run latency (see run_latency.rs): the start time reported by the
+// backend must be used whenever it follows the dispatch, otherwise the latency must be estimated
+// from the CPU time used, only if the task didn't block.
+fn check_run_latency(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (name, start, blocked) in RUN_LATENCY_CASES {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+        sched.run_latency = Some(RunLatency::new());
+
+        let mut task = hog(1, 0);
+        sched.bpf.advance(ROUND_NS);
+        let dispatch_ts = sched.now_ns();
+        sched.bpf.enqueue(task.clone());
+        if let Err(err) = sched.schedule() {
+            return vec![format!("run latency: schedule() failed: {}", err)];
+        }
+        sched.bpf.take_dispatched();
+
+        if let Some(start) = start {
+            let start_ts = dispatch_ts.saturating_add_signed(start);
+            sched.bpf.set_run_start(task.pid, start_ts);
+        }
+        sched.bpf.advance(RUN_LATENCY_ELAPSED_NS);
+        task.sum_exec_runtime += RUN_LATENCY_USED_NS;
+        task.nvcsw += blocked as u64;
+        sched.bpf.enqueue(task);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("run latency: schedule() failed: {}", err)];
+        }
+
+        let estimated = (1, RUN_LATENCY_ELAPSED_NS - RUN_LATENCY_USED_NS);
+        let expected = match (start, blocked) {
+            (Some(start), _) if start >= 0 => ((1, start as u64), (0, 0), 0),
+            (_, false) => ((0, 0), estimated, 0),
+            (_, true) => ((0, 0), (0, 0), 1),
+        };
+        let found = sched.run_latency.as_ref().map(RunLatency::totals);
+        if found != Some(expected) {
+            violations.push(format!(
+                "run latency: {}: (kernel, estimated) samples and sum, unsampled = {:?}, \
+                 expected {:?}",
+                name, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the validation of the policy invariants (see --debug-invariants): crafted dispatches
+// that violate each invariant must be detected, a CPU out of range returned by select_cpu()
+// must stop the scheduler with --debug-invariants abort, and the simulated workload must not
+// violate any invariant (only checked if the validation is compiled in).
+fn check_debug_invariants(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let bounds = Bounds {
+        max_slice_ns: INVARIANT_MAX_SLICE_NS,
+        nr_cpus: NR_CPUS as i32,
+        fair: true,
+    };
+    for (name, dispatches, expected) in INVARIANT_CASES {
+        let mut invariants = InvariantChecker::new();
+        for (new_round, pid, cpu, slice_ns, vtime) in dispatches {
+            if new_round {
+                invariants.begin_round();
+            }
+            let task = Dispatch {
+                pid,
+                cpu,
+                flags: 0,
+                slice_ns,
+                vtime,
+            };
+            invariants.check(&task, &bounds);
+        }
+        if invariants.nr_violations() != expected {
+            violations.push(format!(
+                "debug invariants: {}: {} violations detected, expected {}",
+                name,
+                invariants.nr_violations(),
+                expected
+            ));
+        }
+    }
+
+    for mode in [InvariantMode::Log, InvariantMode::Abort] {
+        let opts = Opts {
+            debug_invariants: Some(mode),
+            strict_select_cpu: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched.invariants = Some(InvariantChecker::new());
+        sched.bpf.force_select_cpu(NR_CPUS as i32 + 3);
+        sched.bpf.enqueue(hog(1, 0));
+        let failed = sched.schedule().is_err();
+        let detected = sched.invariants.as_ref().map_or(0, |i| i.nr_violations());
+        if detected != 1 || failed != (mode == InvariantMode::Abort) {
+            violations.push(format!(
+                "debug invariants: {:?}: invalid CPU detected {} times (schedule() failed: {})",
+                mode, detected, failed
+            ));
+        }
+    }
+
+    if invariants::COMPILED {
+        let opts = Opts {
+            debug_invariants: Some(InvariantMode::Abort),
+            policy: Policy::Fair,
+            ..opts.clone()
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| simulate(&opts)));
+        let Ok(result) = result else {
+            return vec!["debug invariants: the scheduling policy panicked".to_string()];
+        };
+        violations.extend(
+            result
+                .violations
+                .into_iter()
+                .map(|violation| format!("debug invariants: {}", violation)),
+        );
+    }
+
+    violations
+}
+
+// Verify the state saved across restarts (see --state-file): the state must survive a round trip
+// through the file, a scheduler restored from it must learn exactly like the scheduler that saved
+// it (unlike a scheduler started from scratch), dropping the tasks that exited, and a state that
+// is too old (or saved before a reboot) must be ignored.
+fn check_state_file(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        state_max_age_secs: STATE_MAX_AGE_SECS,
+        max_tracked_pids: None,
+        ..opts.clone()
+    };
+    let mut violations = Vec::new();
+    let mut tasks: Vec<Task> = (1..=STATE_TASKS)
+        .map(|pid| SimTask::new(pid, 0, 100 * pid as u64, Behavior::Hog).task)
+        .collect();
+    // Run a round with the tasks, each one using a different amount of CPU time, with the even
+    // ones releasing the CPU voluntarily.
+    let run_round = |sched: &mut Scheduler<MockBackend>, tasks: &mut Vec<Task>| {
+        sched.bpf.advance(ROUND_NS);
+        for task in tasks.iter_mut() {
+            task.sum_exec_runtime += task.pid as u64 * ROUND_NS / 10;
+            task.nvcsw += (task.pid % 2 == 0) as u64;
+            sched.bpf.enqueue(task.clone());
+        }
+        let result = sched.schedule();
+        sched.bpf.take_dispatched();
+        result
+    };
+
+    let mut saved = Fixture::new(NR_CPUS).scheduler(&opts);
+    for _ in 0..STATE_ROUNDS {
+        if let Err(err) = run_round(&mut saved, &mut tasks) {
+            return vec![format!("state file: schedule() failed: {}", err)];
+        }
+    }
+    if let Err(err) = saved.pin(1, PIN_CPU) {
+        return vec![format!(
+            "state file: pinning to CPU {} failed: {}",
+            PIN_CPU, err
+        )];
+    }
+
+    let path = env::temp_dir().join(format!(
+        "scx_rust_scheduler-selftest-{}.state",
+        std::process::id()
+    ));
+    let Some(path_str) = path.to_str() else {
+        return vec![format!("state file: invalid path {}", path.display())];
+    };
+    let state = saved.snapshot();
+    let loaded = snapshot::save(path_str, &state).and_then(|_| snapshot::load(path_str));
+    let _ = std::fs::remove_file(&path);
+    match loaded {
+        Ok(Some(loaded)) if loaded == state => {}
+        Ok(_) => violations.push("state file: state changed by save/load".to_string()),
+        Err(err) => violations.push(format!("state file: {:#}", err)),
+    }
+    if !matches!(snapshot::load(path_str), Ok(None)) {
+        violations.push("state file: missing file not reported as no state".to_string());
+    }
+
+    // Restart from the saved state, with a task that exited in the meantime, and from scratch.
+    let restart_ns = state.saved_ns + ROUND_NS;
+    let mut restored = Fixture::new(NR_CPUS).scheduler(&opts);
+    restored.bpf.advance(restart_ns);
+    restored.bpf.exit_task(STATE_EXITED_PID);
+    let nr_restored = restored.restore(state.clone());
+    if nr_restored != Some(STATE_TASKS as usize - 1) || restored.pins.get(&1) != Some(&PIN_CPU) {
+        violations.push(format!(
+            "state file: {:?} tasks restored, expected {} (pins {:?})",
+            nr_restored,
+            STATE_TASKS - 1,
+            restored.pins
+        ));
+    }
+    let mut scratch = Fixture::new(NR_CPUS).scheduler(&opts);
+    scratch.bpf.advance(restart_ns);
+
+    saved.bpf.advance(restart_ns - saved.now_ns());
+    tasks.retain(|task| task.pid != STATE_EXITED_PID);
+    let mut learned = Vec::new();
+    for sched in [&mut saved, &mut restored, &mut scratch] {
+        if let Err(err) = run_round(sched, &mut tasks.clone()) {
+            return vec![format!("state file: schedule() failed: {}", err)];
+        }
+        let mut state = sched.snapshot().tasks;
+        state.remove(&STATE_EXITED_PID);
+        learned.push(state);
+    }
+    if learned[1] != learned[0] {
+        violations.push("state file: restored state diverged from the saved one".to_string());
+    }
+    if learned[2] == learned[0] {
+        violations
+            .push("state file: no difference from a scheduler started from scratch".to_string());
+    }
+
+    // A stale state, a state from the future (the wall clock moved back) and a state saved
+    // before a reboot (however recent) must be ignored.
+    for (name, now, reboot) in [
+        (
+            "stale",
+            state.saved_ns + (STATE_MAX_AGE_SECS + 1) * NSEC_PER_SEC,
+            false,
+        ),
+        ("from the future", state.saved_ns - ROUND_NS, false),
+        ("previous boot", restart_ns, true),
+    ] {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched.bpf.advance(now);
+        if reboot {
+            sched.bpf.reboot();
+        }
+        if let Some(nr_tasks) = sched.restore(state.clone()) {
+            violations.push(format!(
+                "state file: {} state restored ({} tasks)",
+                name, nr_tasks
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the trend of the queued tasks (see QueueTrend): the growth rate must follow the queue
+// depth over time, a steady queue must report no growth and no time to drain, and a shrinking
+// queue must report the time to drain it at the current rate.
+fn check_queue_trend() -> Vec<String> {
+    let mut trend = QueueTrend::new();
+    let mut now = 0;
+    let mut violations = Vec::new();
+
+    for (i, (secs, depth, nr_dispatches, rate, eta)) in QUEUE_TREND_STEPS.into_iter().enumerate() {
+        now += secs * NSEC_PER_SEC;
+        trend.sample(now, depth, nr_dispatches);
+        if trend.rate() != rate || trend.eta_secs() != eta {
+            violations.push(format!(
+                "queue trend: sample {}: rate {:?} and time to drain {:?}, expected {:?} and {:?}",
+                i,
+                trend.rate(),
+                trend.eta_secs(),
+                rate,
+                eta
+            ));
+        }
+        let steady = rate.is_some_and(|rate| rate >= 0.0);
+        if steady && !trend.json().contains("\"eta_secs\":null") {
+            violations.push(format!(
+                "queue trend: sample {}: invalid JSON {}",
+                i,
+                trend.json()
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the share of the kernel dispatches reported in the stats (see --kernel-dispatch-warn-pct):
+// the warning must be raised only above the threshold, and never without dispatches.
+fn check_dispatch_ratio() -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (nr_user, nr_kernel, warn_pct, kernel_pct, json) in DISPATCH_RATIO_CASES {
+        let mut ratio = DispatchRatio::new(warn_pct);
+        ratio.sample(nr_user, nr_kernel);
+        if ratio.kernel_pct() != kernel_pct || ratio.json() != json {
+            violations.push(format!(
+                "dispatch ratio: {} user, {} kernel dispatches (warning above {}%): kernel share \
+                 {:?}, JSON {}, expected {:?}, {}",
+                nr_user,
+                nr_kernel,
+                warn_pct,
+                ratio.kernel_pct(),
+                ratio.json(),
+                kernel_pct,
+                json
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the arithmetic of the policy against the boundary values of a malformed task (see
+// BOUNDARY_CASES): receiving the task must not panic, its virtual runtime must be advanced by at
+// most MAX_CHARGE_NS (scaled by the weight) and its time slices must stay within (0, max slice].
+fn check_boundary_values(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        policy: Policy::Fair,
+        io_boost: Some(16),
+        compute_boost: true,
+        batch_quantum_mult: 64,
+        slice_expr: None,
+        latency_target_us: None,
+        ..opts.clone()
+    };
+    let max_slice_ns = opts.slice_us.max(opts.compute_max_slice_us) * 1000;
+
+    for (name, runtime, weight, nr_waiting) in BOUNDARY_CASES {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+            let mut task = SimTask::new(1, 0, weight, Behavior::Hog).task;
+            for _ in 0..2 {
+                sched.bpf.enqueue(task.clone());
+                sched.schedule()?;
+                sched.bpf.take_dispatched();
+                sched.bpf.advance(2 * NSEC_PER_SEC);
+                task.sum_exec_runtime = runtime;
+                task.nvcsw = u64::MAX;
+            }
+            let vtime = sched.tasks.get(&task.pid).map_or(0, |info| info.vtime);
+            let slices_ns = [TaskClass::Interactive, TaskClass::Batch].map(|class| {
+                let slice_ns = sched.compute_slice(&task, class, nr_waiting);
+                sched.dispatch_slice_ns(slice_ns)
+            });
+
+            Ok::<_, anyhow::Error>((vtime, sched.min_vtime, slices_ns))
+        }));
+        let (vtime, min_vtime, slices_ns) = match result {
+            Ok(Ok(found)) => found,
+            Ok(Err(err)) => {
+                violations.push(format!(
+                    "boundary values: {}: schedule() failed: {}",
+                    name, err
+                ));
+                continue;
+            }
+            Err(_) => {
+                violations.push(format!("boundary values: {}: the policy panicked", name));
+                continue;
+            }
+        };
+
+        let max_vtime = MAX_CHARGE_NS * 100;
+        if vtime > max_vtime || min_vtime > max_vtime {
+            violations.push(format!(
+                "boundary values: {}: vtime {}, min_vtime {}, expected at most {}",
+                name, vtime, min_vtime, max_vtime
+            ));
+        }
+        if slices_ns
+            .iter()
+            .any(|&slice_ns| slice_ns == 0 || slice_ns > max_slice_ns)
+        {
+            violations.push(format!(
+                "boundary values: {}: (interactive, batch) time slices {:?}ns, expected within \
+                 (0, {}]",
+                name, slices_ns, max_slice_ns
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the validation of the configuration (see the validate subcommand): each command line in
+// VALIDATE_CASES must report exactly the expected problems.
+fn check_validate() -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (args, expected) in VALIDATE_CASES {
+        let argv = std::iter::once("scx_rust_scheduler").chain(args.iter().copied());
+        let opts = match Opts::try_parse_from(argv) {
+            Ok(opts) => opts,
+            Err(err) => {
+                violations.push(format!(
+                    "validate: {:?}: invalid command line: {}",
+                    args, err
+                ));
+                continue;
+            }
+        };
+        let found = validate::problems(&opts, Some(NR_CPUS as usize));
+        if found != expected {
+            violations.push(format!(
+                "validate: {:?}: problems {:?}, expected {:?}",
+                args, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the problems reported for each configuration file of VALIDATE_CONFIG_CASES, by line
+// for the keys that can't be loaded.
+fn check_validate_config() -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (text, expected) in VALIDATE_CONFIG_CASES {
+        let found = validate::config_problems(text, Some(NR_CPUS as usize));
+        if found != expected {
+            violations.push(format!(
+                "validate config: {:?}: problems {:?}, expected {:?}",
+                text, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the response to the dispatches failed by the BPF component (see
+// --failed-dispatch-thresh): failures above the threshold must halve the tasks dispatched per
+// round, and an interval without failures must restore them.
+fn check_failed_dispatches(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        failed_dispatch_thresh: Some(FAILED_DISPATCH_THRESH),
+        hysteresis_ms: 0,
+        hysteresis_delta: 0,
+        self_cpu_max_pct: None,
+        latency_target_us: None,
+        thermal_sensor: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    for pid in 1..=FAILED_DISPATCH_TASKS {
+        sched.bpf.enqueue(hog(pid, 0));
+    }
+    let mut violations = Vec::new();
+
+    for (i, expected) in FAILED_DISPATCH_BATCHES.into_iter().enumerate() {
+        // All the dispatches fail right after the first round.
+        if i == 1 {
+            sched.bpf.drop_dispatches(FAILED_DISPATCH_ROUNDS * NR_CPUS);
+            for _ in 0..FAILED_DISPATCH_ROUNDS {
+                if let Err(err) = sched.schedule() {
+                    return vec![format!("failed dispatches: schedule() failed: {}", err)];
+                }
+            }
+        }
+        if i > 0 {
+            sched.bpf.advance(NSEC_PER_SEC);
+            sched.update_backpressure();
+        }
+        if let Err(err) = sched.schedule() {
+            return vec![format!("failed dispatches: schedule() failed: {}", err)];
+        }
+
+        let found = sched.bpf.take_dispatched().len();
+        if found != expected {
+            violations.push(format!(
+                "failed dispatches: step {}: {} tasks dispatched per round, expected {}",
+                i, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the anti-hang guard of the dequeue loop (see --max-dequeue-per-round): with a BPF queue
+// that never drains, the round must stop dequeuing after the limit (plus the two tasks of the
+// fast path), dispatch the tasks and give control back to the BPF component.
+fn check_max_dequeue(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        max_dequeue_per_round: MAX_DEQUEUE_PER_ROUND,
+        duplicate_pid: DuplicatePid::Coalesce,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+    let task = hog(1, 0);
+    sched.bpf.endless_dequeue(task, MAX_DEQUEUE_ENDLESS);
+
+    if let Err(err) = sched.schedule() {
+        return vec![format!("max dequeue: schedule() failed: {}", err)];
+    }
+    let nr_dequeued = MAX_DEQUEUE_ENDLESS - sched.bpf.endless_left();
+    if sched.nr_dequeue_breaks != 1 || nr_dequeued > MAX_DEQUEUE_PER_ROUND + 2 {
+        violations.push(format!(
+            "max dequeue: {} tasks dequeued ({} breaks), expected at most {} (1 break)",
+            nr_dequeued,
+            sched.nr_dequeue_breaks,
+            MAX_DEQUEUE_PER_ROUND + 2
+        ));
+    }
+    if sched.bpf.take_dispatched().len() != 1 {
+        violations.push("max dequeue: the task received has not been dispatched".to_string());
+    }
+
+    violations
+}
+
+// Verify the accounting of the weight classes (see --weight-classes): each dispatched task must
+// be accounted in the class of its weight, and only there.
+fn check_weight_classes(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        weight_classes: Some(WEIGHT_CLASS_BOUNDS),
+        ..opts.clone()
+    };
+
+    for (weight, class) in WEIGHT_CLASS_CASES {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched
+            .bpf
+            .enqueue(SimTask::new(1, 0, weight, Behavior::Hog).task);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("weight classes: schedule() failed: {}", err)];
+        }
+
+        let mut expected = [0; 3];
+        expected[class] = 1;
+        let found = sched
+            .weight_classes
+            .as_ref()
+            .map(|classes| classes.nr_dispatches());
+        if found != Some(expected) {
+            violations.push(format!(
+                "weight classes: task with weight {} accounted as {:?}, expected {:?}",
+                weight, found, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the push of the stats over UDP (see --stats-udp): a collector listening on the loopback
+// interface must receive the JSON stats with the host identifier, and the datagrams sent once
+// the collector is gone must be counted as errors, without failing the scheduler.
+fn check_udp_stats(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let collector = match UdpSocket::bind("127.0.0.1:0") {
+        Ok(collector) => collector,
+        Err(err) => return vec![format!("udp stats: failed to bind the collector: {}", err)],
+    };
+    let addr = collector.local_addr().map(|addr| addr.to_string());
+    let udp_stats = addr
+        .map_err(anyhow::Error::from)
+        .and_then(|addr| UdpStats::open(&addr));
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    sched.udp_stats = match udp_stats {
+        Ok(udp_stats) => Some(udp_stats),
+        Err(err) => return vec![format!("udp stats: failed to connect: {:#}", err)],
+    };
+
+    sched.push_udp_stats();
+    let mut buf = [0u8; 65536];
+    let _ = collector.set_read_timeout(Some(Duration::from_millis(UDP_STATS_TIMEOUT_MS)));
+    match collector.recv(&mut buf) {
+        Ok(len) => {
+            let json = String::from_utf8_lossy(&buf[..len]);
+            let is_object = json.starts_with('{') && json.ends_with('}');
+            let has_fields = json.contains("\"host\":\"") && json.contains("\"user_dispatches\":");
+            if !is_object || !has_fields {
+                violations.push(format!("udp stats: unexpected datagram {}", json));
+            }
+        }
+        Err(err) => violations.push(format!("udp stats: no datagram received: {}", err)),
+    }
+
+    // The loopback interface reports the closed port (ICMP unreachable) as an error of the
+    // following sends.
+    drop(collector);
+    for _ in 0..UDP_STATS_SEND_ATTEMPTS {
+        sched.push_udp_stats();
+        if sched.udp_stats.as_ref().map_or(0, UdpStats::nr_errors) > 0 {
+            return violations;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    violations.push("udp stats: the datagrams sent to a closed port are not counted".to_string());
+
+    violations
+}
+
+// Verify the NUMA affinity (see --numa-affinity) with a mocked node-affinity history on a
+// two-node topology: a task moved away from the node where it used most of its CPU time must be
+// dispatched back to that node, unless no CPU of the node is idle (a cross-node dispatch).
+fn check_numa_affinity(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        cpus_offline: None,
+        kernel_cpu: None,
+        cpu_any_shortcut: false,
+        llc_group: false,
+        cpuset_aware: false,
+        spread_idle: false,
+        ..opts.clone()
+    };
+
+    for (i, (history, prev_cpu, forced, node, nr_cross)) in NUMA_AFFINITY_CASES.iter().enumerate() {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let nodes = NUMA_NODES.iter().map(|cpus| cpus.to_vec()).collect();
+        let mut affinity = NumaAffinity::new(nodes);
+        for &(cpu, runtime_ns) in history.iter() {
+            affinity.record(1, cpu, runtime_ns);
+        }
+        sched.numa_affinity = Some(affinity);
+        if let Some(cpu) = forced {
+            sched.bpf.force_select_cpu(*cpu);
+        }
+
+        sched.bpf.enqueue(hog(1, *prev_cpu));
+        if let Err(err) = sched.schedule() {
+            return vec![format!("numa affinity: schedule() failed: {}", err)];
+        }
+        let cpus: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+        let is_expected = match cpus[..] {
+            [cpu] => cpu >= 0 && NUMA_NODES[*node].contains(&(cpu as usize)),
+            _ => false,
+        };
+        if !is_expected {
+            violations.push(format!(
+                "numa affinity: case {}: task dispatched to CPUs {:?}, expected node {}",
+                i, cpus, node
+            ));
+        }
+        let found = sched.numa_affinity.as_ref().map_or(0, |a| a.dispatches().1);
+        if found != *nr_cross {
+            violations.push(format!(
+                "numa affinity: case {}: {} cross-node dispatches, expected {}",
+                i, found, nr_cross
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the migration damping (see --migration-lock-us): a task that recently migrated must not
+// migrate again within the lock window, unless its CPU is badly overloaded.
+fn check_migration_lock(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        migration_lock_us: Some(MIGRATION_LOCK_US),
+        cpus_offline: None,
+        kernel_cpu: None,
+        cpu_any_shortcut: false,
+        llc_group: false,
+        cpuset_aware: false,
+        spread_idle: false,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+
+    let mut prev_cpu = 0;
+    for (round, (delta_ns, selected, expected)) in MIGRATION_LOCK_ROUNDS.into_iter().enumerate() {
+        sched.bpf.advance(delta_ns);
+        sched.bpf.force_select_cpu(selected);
+        sched.bpf.enqueue(hog(1, prev_cpu));
+        if let Err(err) = sched.schedule() {
+            return vec![format!("migration lock: schedule() failed: {}", err)];
+        }
+        let cpus: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+        if cpus != [expected] {
+            violations.push(format!(
+                "migration lock: round {}: task on CPU {} dispatched to CPUs {:?}, expected {}",
+                round, prev_cpu, cpus, expected
+            ));
+        }
+        prev_cpu = expected;
+    }
+
+    // A locked task can still leave a badly overloaded CPU.
+    let mut migration = MigrationLock::new(MIGRATION_LOCK_US * 1000);
+    migration.pick_cpu(1, 0, 1, 0);
+    for _ in 0..MIGRATION_OVERLOAD {
+        migration.record_dispatch(1);
+    }
+    let cpu = migration.pick_cpu(1, 1, 2, 0);
+    if cpu != 2 || migration.migrations() != (2, 0) {
+        violations.push(format!(
+            "migration lock: task locked to an overloaded CPU moved to CPU {}, expected 2",
+            cpu
+        ));
+    }
+
+    violations
+}
+
+// Verify the placement of the brand-new tasks (see --initial-cpu): the first dispatch of a new
+// task must follow the policy, while the following dispatches must use the generic CPU
+// selection (starting from the previously used CPU).
+fn check_initial_cpu(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (i, (policy, before, parent_cpu, tgid, prev_cpu, expected)) in
+        INITIAL_CPU_CASES.into_iter().enumerate()
+    {
+        let opts = Opts {
+            initial_cpu: policy,
+            cpus_offline: None,
+            kernel_cpu: None,
+            cpu_any_shortcut: false,
+            llc_group: false,
+            cpuset_aware: false,
+            spread_idle: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        for &(pid, tgid, cpu) in before {
+            sched.bpf.set_tgid(pid, tgid);
+            sched.bpf.enqueue(hog(pid, cpu));
+        }
+        sched.bpf.set_tgid(1, tgid);
+        if let Some(cpu) = parent_cpu {
+            sched.bpf.set_parent_cpu(1, cpu);
+        }
+
+        for (round, prev_cpu, expected) in [(0, prev_cpu, expected), (1, INITIAL_CPU_NEXT, 1)] {
+            if let Err(err) = sched.schedule() {
+                return vec![format!("initial cpu: schedule() failed: {}", err)];
+            }
+            sched.bpf.take_dispatched();
+            sched.bpf.advance(ROUND_NS);
+
+            sched.bpf.enqueue(hog(1, prev_cpu));
+            if let Err(err) = sched.schedule() {
+                return vec![format!("initial cpu: schedule() failed: {}", err)];
+            }
+            let cpus: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+            if cpus != [expected] {
+                violations.push(format!(
+                    "initial cpu: case {}, {:?}, round {}: task dispatched to CPUs {:?}, \
+                     expected {}",
+                    i, policy, round, cpus, expected
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// Verify that the time slices scaled down by the queue depth never round down to 0 (the default
+// time slice of the BPF component) or to a few nanoseconds, but to the minimum time slice.
+fn check_slice_floor(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (slice_us, slice_min_us, class, nr_waiting, expected_us) in SLICE_FLOOR_CASES {
+        let opts = Opts {
+            slice_us,
+            slice_min_us,
+            batch_quantum_mult: 1,
+            compute_boost: false,
+            slice_expr: None,
+            latency_target_us: None,
+            cap_remaining_slice: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let task = hog(1, 0);
+
+        let found = sched.compute_slice(&task, class, nr_waiting);
+        if found != expected_us * 1000 {
+            violations.push(format!(
+                "slice floor: {}us (min {}us), {:?} task, {} waiting: time slice {}ns, expected {}",
+                slice_us,
+                slice_min_us,
+                class,
+                nr_waiting,
+                found,
+                expected_us * 1000
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify the scx_stats server (built with --features stats): the `top` target must return the
+// counters of the last stats snapshot published by the scheduler.
+#[cfg(feature = "stats")]
+fn check_scx_stats(opts: &Opts) -> Vec<String> {
+    let path = env::temp_dir().join(format!("scx_rust_scheduler-{}.stats", std::process::id()));
+    let server = match ScxStats::start(&path.to_string_lossy()) {
+        Ok(server) => server,
+        Err(err) => return vec![format!("scx_stats: failed to start the server: {:#}", err)],
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    sched.scx_stats = Some(&server);
+
+    sched.bpf.enqueue(hog(1, 0));
+    if let Err(err) = sched.schedule() {
+        return vec![format!("scx_stats: schedule() failed: {}", err)];
+    }
+    sched.update_scx_stats();
+    let expected = *sched.bpf.nr_user_dispatches_mut();
+
+    let target = vec![("target".to_string(), "top".to_string())];
+    let found = scx_stats::StatsClient::new()
+        .set_path(&path)
+        .connect()
+        .and_then(|mut client| client.request::<Metrics>("stats", target));
+    let _ = std::fs::remove_file(&path);
+
+    match found {
+        Ok(metrics) if metrics.user_dispatches == expected && expected > 0 => Vec::new(),
+        Ok(metrics) => vec![format!(
+            "scx_stats: {} user dispatches served, expected {}",
+            metrics.user_dispatches, expected
+        )],
+        Err(err) => vec![format!("scx_stats: request failed: {:#}", err)],
+    }
+}
+
+// Verify the dump of the deadlines (see the `dump deadlines` command): the queued tasks must be
+// reported in order of deadline, with the deadlines computed when they have been received, and
+// the dump must be refused by the other policies.
+fn check_deadline_dump(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        policy: Policy::Edf,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+
+    sched.bpf.advance(ROUND_NS);
+    let now = sched.bpf.now_ns();
+    let mut expected = Vec::new();
+    for (i, latency_us) in EDF_LATENCIES.into_iter().enumerate() {
+        let pid = i as i32 + 1;
+        if let Some(latency_us) = latency_us {
+            sched
+                .bpf
+                .set_env_hint(pid, slice_override::LATENCY_ENV, latency_us);
+        }
+        let (pending, _) = sched.prepare_task(hog(pid, 0), now);
+        expected.push((pid, pending.deadline));
+        sched.batch.push_back(pending);
+    }
+    expected.sort_by_key(|&(_, deadline)| deadline);
+    sched.bpf.advance(ROUND_NS);
+
+    match sched.deadline_dump() {
+        Ok(dump) => {
+            let found: Vec<(i32, u64)> = dump.queued.iter().map(|t| (t.pid, t.deadline)).collect();
+            if found != expected {
+                violations.push(format!(
+                    "deadline dump: expected (pid, deadline) {:?}, got {:?}",
+                    expected, found
+                ));
+            }
+            if dump.queued.iter().any(|t| t.wait_ns != ROUND_NS) {
+                violations
+                    .push("deadline dump: wrong waiting time of the queued tasks".to_string());
+            }
+            if !dump
+                .json()
+                .starts_with(&format!("{{\"now_ns\":{},", now + ROUND_NS))
+            {
+                violations.push(format!("deadline dump: unexpected JSON {}", dump.json()));
+            }
+        }
+        Err(err) => violations.push(format!("deadline dump: refused by edf: {}", err)),
+    }
+    if sched.batch.len() != EDF_LATENCIES.len() {
+        violations.push("deadline dump: the queued tasks have been changed".to_string());
+    }
+
+    sched.policy = Policy::Fifo;
+    if sched.deadline_dump().is_ok() {
+        violations.push("deadline dump: accepted by the fifo policy".to_string());
+    }
+
+    violations
+}
+
+// Verify the bypass of the kernel threads (see --skip-kthreads): a kernel thread must be
+// dispatched to its previously used CPU with the default time slice, without going through the
+// CPU selection and the classifier, while the user tasks still go through the scheduling policy
+// (without --skip-kthreads, the kernel threads are scheduled as the user tasks), but never to an
+// excluded CPU, and without escaping the invariant checks (see --debug-invariants).
+fn check_skip_kthreads(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for skip_kthreads in [true, false] {
+        for nr_user in KTHREAD_NR_USER_TASKS {
+            let opts = Opts {
+                skip_kthreads,
+                policy: Policy::Fair,
+                cpus_offline: None,
+                kernel_cpu: None,
+                cpu_any_shortcut: false,
+                ..opts.clone()
+            };
+            let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+            sched.bpf.set_kthread(KTHREAD_PID);
+            sched.bpf.force_select_cpu(KTHREAD_SELECTED_CPU);
+            sched.bpf.enqueue(hog(KTHREAD_PID, KTHREAD_PREV_CPU));
+            for pid in 10..10 + nr_user {
+                sched.bpf.enqueue(hog(pid, KTHREAD_PREV_CPU));
+            }
+            if let Err(err) = sched.schedule() {
+                return vec![format!("skip kthreads: schedule() failed: {}", err)];
+            }
+
+            let dispatched = sched.bpf.take_dispatched();
+            let bypassed = dispatched.iter().any(|d| {
+                d.pid == KTHREAD_PID
+                    && d.cpu == KTHREAD_PREV_CPU
+                    && d.slice_ns == opts.slice_us * 1000
+                    && d.vtime == 0
+            }) && !sched.tasks.contains_key(&KTHREAD_PID);
+            if bypassed != skip_kthreads || sched.nr_kthread_dispatches != bypassed as u64 {
+                violations.push(format!(
+                    "skip kthreads: --skip-kthreads {}, {} user tasks: kernel thread bypassed \
+                     {} ({} bypasses), dispatches {:?}",
+                    skip_kthreads, nr_user, bypassed, sched.nr_kthread_dispatches, dispatched
+                ));
+            }
+            let nr_classified = (10..10 + nr_user)
+                .filter(|pid| sched.tasks.contains_key(pid))
+                .count();
+            if dispatched.len() != nr_user as usize + 1 || nr_classified != nr_user as usize {
+                violations.push(format!(
+                    "skip kthreads: --skip-kthreads {}: {} of {} user tasks scheduled by the \
+                     policy, dispatches {:?}",
+                    skip_kthreads, nr_classified, nr_user, dispatched
+                ));
+            }
+        }
+    }
+
+    // A kernel thread that previously ran on an excluded CPU must be moved to an allowed one,
+    // and its dispatches must be validated: receiving it twice in the same round dispatches it
+    // twice.
+    let opts = Opts {
+        skip_kthreads: true,
+        policy: Policy::Fair,
+        cpus_offline: Some(CpuList(vec![KTHREAD_PREV_CPU as usize])),
+        kernel_cpu: None,
+        debug_invariants: Some(InvariantMode::Log),
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS)
+        .tasks([
+            hog(KTHREAD_PID, KTHREAD_PREV_CPU),
+            hog(KTHREAD_PID, KTHREAD_PREV_CPU),
+        ])
+        .backend(|bpf| bpf.set_kthread(KTHREAD_PID))
+        .scheduler(&opts);
+    if let Err(err) = sched.schedule() {
+        return vec![format!("skip kthreads: schedule() failed: {}", err)];
+    }
+    let dispatched = sched.bpf.take_dispatched();
+    let nr_violations = sched.invariants.as_ref().map_or(0, |i| i.nr_violations());
+    let excluded = dispatched
+        .iter()
+        .any(|d| d.cpu == RL_CPU_ANY || sched.is_cpu_excluded(d.cpu));
+    if dispatched.len() != 2 || excluded || nr_violations != 1 {
+        violations.push(format!(
+            "skip kthreads: kernel thread on the excluded CPU {} received twice: dispatches {:?}, \
+             {} invariant violations, expected 2 dispatches to allowed CPUs and 1 violation",
+            KTHREAD_PREV_CPU, dispatched, nr_violations
+        ));
+    }
+
+    violations
+}
+
+// Verify the state log (see --state-log): a snapshot must be appended only once per interval,
+// with the counters, the per-CPU loads and the top consumers, and the log must be rotated when
+// it exceeds its maximum size.
+fn check_state_log(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let path = env::temp_dir().join(format!(
+        "scx_rust_scheduler-selftest-{}.state",
+        std::process::id()
+    ));
+    let Some(path_str) = path.to_str() else {
+        return vec![format!("state log: invalid path {}", path.display())];
+    };
+    let rotated = format!("{}.1", path_str);
+    let _ = std::fs::remove_file(path_str);
+    let _ = std::fs::remove_file(&rotated);
+
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let now = sched.bpf.now_ns();
+    sched.state_log = match StateLog::create(path_str, STATE_LOG_MAX_SIZE, 1, now) {
+        Ok(state_log) => Some(state_log),
+        Err(err) => return vec![format!("state log: {:#}", err)],
+    };
+    for _ in 0..STATE_LOG_SNAPSHOTS {
+        for pid in 1..=STATE_LOG_TASKS {
+            sched.bpf.enqueue(hog(pid, pid % NR_CPUS as i32));
+        }
+        if let Err(err) = sched.schedule() {
+            return vec![format!("state log: schedule() failed: {}", err)];
+        }
+        sched.bpf.take_dispatched();
+        sched.log_state();
+        sched.bpf.advance(NSEC_PER_SEC);
+        sched.log_state();
+    }
+    let nr_dropped = match sched.state_log.take().map(StateLog::finish) {
+        Some(Ok(nr_dropped)) => nr_dropped,
+        Some(Err(err)) => return vec![format!("state log: {:#}", err)],
+        None => return vec!["state log: log not open".to_string()],
+    };
+
+    let mut timestamps = Vec::new();
+    for file in [rotated.as_str(), path_str] {
+        let text = std::fs::read_to_string(file).unwrap_or_default();
+        if text.len() as u64 > STATE_LOG_MAX_SIZE {
+            violations.push(format!(
+                "state log: {} not rotated ({} bytes)",
+                file,
+                text.len()
+            ));
+        }
+        for line in text.lines() {
+            let now_ns = line
+                .strip_prefix("{\"now_ns\":")
+                .and_then(|line| line.split(',').next());
+            timestamps.push(now_ns.and_then(|ns| ns.parse::<u64>().ok()).unwrap_or(0));
+            let has_members = [
+                "\"user_dispatches\":",
+                "\"cpu_load_pct\":[",
+                "\"top\":[{\"pid\":",
+            ]
+            .iter()
+            .all(|member| line.contains(member));
+            let loads = line.split("\"cpu_load_pct\":[").nth(1).unwrap_or("");
+            let is_loaded = loads
+                .split(']')
+                .next()
+                .is_some_and(|loads| loads.contains(|c: char| ('1'..='9').contains(&c)));
+            if !line.starts_with('{') || !line.ends_with('}') || !has_members || !is_loaded {
+                violations.push(format!("state log: unexpected snapshot {}", line));
+            }
+        }
+    }
+    // The snapshots that didn't fit in the channel leave a gap (the writer may not keep up with
+    // the simulated clock).
+    let is_periodic = timestamps
+        .windows(2)
+        .all(|ts| ts[1] > ts[0] && (ts[1] - ts[0]).is_multiple_of(NSEC_PER_SEC));
+    let nr_missing: u64 = timestamps
+        .windows(2)
+        .map(|ts| (ts[1].saturating_sub(ts[0]) / NSEC_PER_SEC).saturating_sub(1))
+        .sum();
+    if timestamps.is_empty()
+        || timestamps.len() as u64 >= STATE_LOG_SNAPSHOTS
+        || !is_periodic
+        || nr_missing > nr_dropped
+    {
+        violations.push(format!(
+            "state log: snapshots at {:?} after the rotation ({} dropped), expected fewer than \
+             {}, one per second",
+            timestamps, nr_dropped, STATE_LOG_SNAPSHOTS
+        ));
+    }
+    let _ = std::fs::remove_file(path_str);
+    let _ = std::fs::remove_file(&rotated);
+
+    violations
+}
+
+// Verify the waker-wakee locality (see --wake-affine): a woken task must be dispatched to the CPU
+// where its waker recently ran (or, if the waker is unknown, another thread of its process),
+// and the associations must be reported in the stats.
+fn check_wake_affine(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for wake_affine in [false, true] {
+        let opts = Opts {
+            wake_affine,
+            cpus_offline: None,
+            kernel_cpu: None,
+            cpu_any_shortcut: false,
+            llc_group: false,
+            cpuset_aware: false,
+            spread_idle: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+
+        sched.bpf.enqueue(hog(1, WAKE_AFFINE_CPU));
+        if let Err(err) = sched.schedule() {
+            return vec![format!("wake affine: schedule() failed: {}", err)];
+        }
+        sched.bpf.take_dispatched();
+
+        let mut now = 0;
+        for (round, (pid, tgid, waker, delta_ns, expected)) in
+            WAKE_AFFINE_ROUNDS.into_iter().enumerate()
+        {
+            sched.bpf.advance(delta_ns - now);
+            now = delta_ns;
+            sched.bpf.set_tgid(pid, tgid);
+            if let Some(waker) = waker {
+                sched.bpf.set_waker(pid, waker);
+            }
+            sched.bpf.enqueue(hog(pid, 0));
+            if let Err(err) = sched.schedule() {
+                return vec![format!("wake affine: schedule() failed: {}", err)];
+            }
+            let expected = if wake_affine { expected } else { 0 };
+            let cpus: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.cpu).collect();
+            if cpus != [expected] {
+                violations.push(format!(
+                    "wake affine: {}, round {}: pid {} dispatched to CPUs {:?}, expected {}",
+                    if wake_affine { "enabled" } else { "disabled" },
+                    round,
+                    pid,
+                    cpus,
+                    expected
+                ));
+            }
+        }
+
+        // One wakeup near the waker, one near the process, two without a recent association
+        // (including the first dispatch of the waker), both placed as expected.
+        if let Some(affinity) = sched.wake_affinity.as_ref() {
+            if affinity.wakeups() != (1, 1, 2, 2) {
+                violations.push(format!(
+                    "wake affine: wakeups (waker, process, unknown, hits) {:?}, expected \
+                     (1, 1, 2, 2)",
+                    affinity.wakeups()
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// Verify the cap of the interactive time slices (see --interactive-max-slice-us): the interactive
+// tasks must never exceed the cap, even when their time slice is boosted above it (requested,
+// computed by an expression or raised by the minimum time slice), while the batch tasks must not
+// be affected.
+fn check_interactive_max_slice() -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (args, slice_req_us, batch_us) in INTERACTIVE_SLICE_CASES {
+        for max_slice_us in [None, Some(INTERACTIVE_MAX_SLICE_US)] {
+            let cap_arg = max_slice_us.map(|max_slice_us| max_slice_us.to_string());
+            let argv = std::iter::once("scx_rust_scheduler")
+                .chain(args.iter().copied())
+                .chain(
+                    cap_arg
+                        .iter()
+                        .flat_map(|cap| ["--interactive-max-slice-us", cap]),
+                );
+            let opts = match Opts::try_parse_from(argv) {
+                Ok(opts) => opts,
+                Err(err) => {
+                    return vec![format!(
+                        "interactive max slice: {:?}: invalid command line: {}",
+                        args, err
+                    )]
+                }
+            };
+            let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+            let task = hog(1, 0);
+            if let Some(slice_us) = slice_req_us {
+                sched
+                    .bpf
+                    .set_env_hint(task.pid, slice_override::SLICE_ENV, slice_us);
+            }
+            let now = sched.now_ns();
+            sched.prepare_task(task.clone(), now);
+
+            let interactive = sched.compute_slice(&task, TaskClass::Interactive, 0);
+            let batch = sched.compute_slice(&task, TaskClass::Batch, 0);
+            let boosted = interactive > INTERACTIVE_MAX_SLICE_US * 1000;
+            if boosted != max_slice_us.is_none() || batch != batch_us * 1000 {
+                violations.push(format!(
+                    "interactive max slice: {:?}, cap {:?}: interactive time slice {}ns, batch \
+                     time slice {}ns (expected {}ns)",
+                    args,
+                    max_slice_us,
+                    interactive,
+                    batch,
+                    batch_us * 1000
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// Verify the reset command: after a reset, the counters must read relative to the reset (0 until
+// the next dispatch, even if the counters of the BPF component can't be reset) and the learned
+// state of the tasks must be dropped.
+fn check_reset(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+
+    let mut snapshots = Vec::new();
+    for reset in [false, true] {
+        if reset {
+            sched.reset_stats();
+            let stats = sched.stats_snapshot();
+            let expected = StatsSnapshot {
+                now_ns: stats.now_ns,
+                backpressure_scale: stats.backpressure_scale,
+                ..StatsSnapshot::default()
+            };
+            if stats != expected || !sched.tasks.is_empty() {
+                violations.push(format!(
+                    "reset: stats after the reset {:?} ({} tasks tracked), expected {:?}",
+                    stats,
+                    sched.tasks.len(),
+                    expected
+                ));
+            }
+        }
+        sched.bpf.fail_dispatches(RESET_FAILED_DISPATCHES);
+        for pid in 1..=NR_CPUS as i32 {
+            sched.bpf.enqueue(hog(pid, -1));
+        }
+        if let Err(err) = sched.schedule() {
+            return vec![format!("reset: schedule() failed: {}", err)];
+        }
+        sched.bpf.take_dispatched();
+        sched.bpf.advance(ROUND_NS);
+        snapshots.push(sched.stats_snapshot());
+    }
+
+    // The same round before and after the reset must report the same counters.
+    let (before, after) = (snapshots[0], snapshots[1]);
+    if after.nr_user_dispatches != NR_CPUS
+        || after.nr_dispatch_retries != RESET_FAILED_DISPATCHES
+        || (
+            after.nr_user_dispatches,
+            after.nr_dispatch_retries,
+            after.nr_tasks,
+        ) != (
+            before.nr_user_dispatches,
+            before.nr_dispatch_retries,
+            before.nr_tasks,
+        )
+    {
+        violations.push(format!(
+            "reset: stats of a round after the reset {:?}, expected the same counters as before \
+             the reset {:?}",
+            after, before
+        ));
+    }
+
+    violations
+}
+
+// Verify the time budget of the scheduling rounds (see --round-budget-us): with a long queue, no
+// round must last longer than the budget (plus the tasks processed between two checks of the
+// clock, while dequeuing and while dispatching), and all the tasks must still be dispatched.
+fn check_round_budget(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let opts = Opts {
+        round_budget_us: Some(ROUND_BUDGET_US),
+        cpus_offline: None,
+        kernel_cpu: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(ROUND_BUDGET_NR_CPUS).scheduler(&opts);
+    sched.bpf.set_call_cost(ROUND_BUDGET_CALL_NS);
+    for pid in 1..=ROUND_BUDGET_TASKS {
+        sched.bpf.enqueue(hog(pid, -1));
+    }
+
+    let max_round_ns = ROUND_BUDGET_US * 1000 + 2 * ROUND_BUDGET_CHECK * ROUND_BUDGET_CALL_NS;
+    let mut nr_dispatched = 0;
+    for round in 0..ROUND_BUDGET_MAX_ROUNDS {
+        let start = sched.now_ns();
+        if let Err(err) = sched.schedule() {
+            return vec![format!("round budget: schedule() failed: {}", err)];
+        }
+        let round_ns = sched.now_ns() - start;
+        if round_ns > max_round_ns {
+            violations.push(format!(
+                "round budget: round {} lasted {}ns, expected at most {}ns",
+                round, round_ns, max_round_ns
+            ));
+        }
+        nr_dispatched += sched.bpf.take_dispatched().len() as i32;
+        if nr_dispatched >= ROUND_BUDGET_TASKS {
+            break;
+        }
+    }
+    if nr_dispatched != ROUND_BUDGET_TASKS || sched.nr_budget_breaks == 0 {
+        violations.push(format!(
+            "round budget: {} tasks dispatched ({} rounds cut), expected {}",
+            nr_dispatched, sched.nr_budget_breaks, ROUND_BUDGET_TASKS
+        ));
+    }
+
+    violations
+}
+
+// Check the exclusion of the CPUs isolated on the kernel command line from the dispatches
+// (unless --ignore-isolcpus is set, or they leave no CPU to the scheduler).
+fn check_isolation(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for (isolated, ignore_isolcpus, offline, expected) in ISOLATION_CASES {
+        let opts = Opts {
+            ignore_isolcpus,
+            cpus_offline: offline.map(|list| cpulist::parse(list).unwrap()),
+            kernel_cpu: None,
+            cpu_any_shortcut: false,
+            llc_group: false,
+            cpuset_aware: false,
+            spread_idle: false,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS)
+            .backend(|bpf| bpf.set_isolated_cpus(isolated))
+            .scheduler(&opts);
+
+        let mut used = HashSet::new();
+        for _ in 0..ISOLATION_ROUNDS {
+            for pid in 1..=NR_CPUS as i32 {
+                let task = hog(pid, pid % NR_CPUS as i32);
+                sched.bpf.enqueue(task);
+            }
+            sched.bpf.advance(ROUND_NS);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("isolation: schedule() failed: {}", err)];
+            }
+            for d in sched.bpf.take_dispatched() {
+                used.insert(d.cpu);
+            }
+        }
+
+        let mut used: Vec<i32> = used.into_iter().collect();
+        used.sort_unstable();
+        let expected: Vec<i32> = expected.iter().map(|&cpu| cpu as i32).collect();
+        if used != expected {
+            violations.push(format!(
+                "isolation: dispatches to CPUs {:?} with CPUs {:?} isolated \
+                 (--ignore-isolcpus={}, --cpus-offline={:?}), expected {:?}",
+                used, isolated, ignore_isolcpus, offline, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Check the detection of the scheduler being starved: the iterations of the main loop longer
+// than --loop-gap-ms are detected, excluding the time slept in notify_complete() (an idle
+// system), and the scheduler raises its own priority only once.
+fn check_loop_gap(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let mut loop_gap = LoopGap::new(LOOP_GAP_MS * 1_000_000, 0);
+    let mut now = 0;
+    for (duration_ms, sleep_ms, expected) in LOOP_GAP_ITERATIONS {
+        now += duration_ms * 1_000_000;
+        loop_gap.sleep(sleep_ms * 1_000_000);
+        let gap = loop_gap.sample(now);
+        if gap.is_some() != expected {
+            violations.push(format!(
+                "loop gap: iteration of {}ms sleeping {}ms reported as {:?} (threshold {}ms)",
+                duration_ms, sleep_ms, gap, LOOP_GAP_MS
+            ));
+        }
+    }
+
+    // Session with a normal main loop, one sleeping in notify_complete() and one where each
+    // call to the backend takes longer than the threshold (the scheduler doesn't run).
+    let opts = Opts {
+        loop_gap_ms: Some(LOOP_GAP_MS),
+        ..opts.clone()
+    };
+    for (stall_ms, call_cost_ms, expected) in
+        [(0, 0, 0), (LOOP_GAP_MS * 4, 0, 0), (0, LOOP_GAP_MS, 1)]
+    {
+        let mut sched = Fixture::new(NR_CPUS)
+            .hogs(1..=NR_CPUS as i32 * 2)
+            .closed_loop(LOOP_GAP_ROUNDS)
+            .backend(|bpf| {
+                bpf.stall_notify(stall_ms * 1_000_000, LOOP_GAP_ROUNDS);
+                bpf.set_call_cost(call_cost_ms * 1_000_000);
+            })
+            .scheduler(&opts);
+        if let Err(err) = sched.run_loop() {
+            violations.push(format!("loop gap: run_loop() failed: {}", err));
+            continue;
+        }
+        let nr_boosts = sched.bpf.nr_self_boosts();
+        if nr_boosts != expected {
+            violations.push(format!(
+                "loop gap: {} self boosts with notify_complete() sleeping {}ms and calls taking \
+                 {}ms, expected {}",
+                nr_boosts, stall_ms, call_cost_ms, expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Check the deadline-proportional time slices of the edf policy: the tasks received in the
+// same round get --deadline-slice-pct of the time left until their deadline, bounded by
+// --slice-min-us and by the time slice decided by the policy (the time slice of the same round
+// without --deadline-slice-pct), except for the time slices requested with --slice-env.
+fn check_deadline_slice(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let slices = |deadline_slice_pct| {
+        let opts = Opts {
+            policy: Policy::Edf,
+            slice_env: true,
+            slice_min_us: DEADLINE_SLICE_MIN_US,
+            deadline_slice_pct,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        sched.bpf.advance(ROUND_NS);
+        for (i, (latency_us, slice_us)) in DEADLINE_SLICE_TASKS.into_iter().enumerate() {
+            let task = hog(i as i32 + 1, i as i32);
+            if let Some(latency_us) = latency_us {
+                sched
+                    .bpf
+                    .set_env_hint(task.pid, slice_override::LATENCY_ENV, latency_us);
+            }
+            if let Some(slice_us) = slice_us {
+                sched
+                    .bpf
+                    .set_env_hint(task.pid, slice_override::SLICE_ENV, slice_us);
+            }
+            sched.bpf.enqueue(task);
+        }
+        sched.schedule().map(|_| {
+            let mut slices: Vec<(i32, u64)> = sched
+                .bpf
+                .take_dispatched()
+                .iter()
+                .map(|d| (d.pid, d.slice_ns))
+                .collect();
+            slices.sort_unstable();
+            slices
+        })
+    };
+    let (flat, proportional) = match (slices(None), slices(Some(DEADLINE_SLICE_PCT))) {
+        (Ok(flat), Ok(proportional)) => (flat, proportional),
+        (Err(err), _) | (_, Err(err)) => {
+            return vec![format!("deadline slice: schedule() failed: {}", err)]
+        }
+    };
+
+    let min_slice_ns = DEADLINE_SLICE_MIN_US * 1000;
+    let expected: Vec<(i32, u64)> = flat
+        .iter()
+        .zip(DEADLINE_SLICE_TASKS)
+        .map(|(&(pid, slice_ns), (latency_us, slice_us))| {
+            if slice_us.is_some() {
+                return (pid, slice_ns);
+            }
+            let latency_ns = latency_us.map_or(opts.slice_us * 1000, |us| {
+                (us * 1000).clamp(LATENCY_MIN_NS, LATENCY_MAX_NS)
+            });
+            let slice = (latency_ns / 100 * DEADLINE_SLICE_PCT).max(min_slice_ns);
+            (pid, slice.min(slice_ns))
+        })
+        .collect();
+    if flat.len() != DEADLINE_SLICE_TASKS.len() || proportional != expected {
+        violations.push(format!(
+            "deadline slice: (pid, slice) {:?} with --deadline-slice-pct {}, expected {:?} \
+             (without it: {:?})",
+            proportional, DEADLINE_SLICE_PCT, expected, flat
+        ));
+    }
+    // The tighter the deadline, the shorter the time slice.
+    if proportional.windows(2).take(2).any(|w| w[0].1 > w[1].1) {
+        violations.push(format!(
+            "deadline slice: (pid, slice) {:?}, a tighter deadline got a longer time slice",
+            proportional
+        ));
+    }
+
+    violations
+}
+
+// Check the boost groups: the members of a group must get more CPU time than the other CPU
+// hogs, and the group must be cleared once all its members exited.
+fn check_boost_group(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let opts = Opts {
+        policy: Policy::Fair,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS)
+        .hogs(1..=BOOST_GROUP_TASKS)
+        .closed_loop(BOOST_GROUP_ROUNDS)
+        .scheduler(&opts);
+    if let Err(err) = sched.boost_group("game", BOOST_GROUP_LEVEL, &BOOST_GROUP_PIDS) {
+        return vec![format!("boost group: boost failed: {}", err)];
+    }
+    if let Err(err) = sched.run_loop() {
+        return vec![format!("boost group: run_loop() failed: {}", err)];
+    }
+
+    let mut nr_dispatches: HashMap<i32, u64> = HashMap::new();
+    for d in sched.bpf.take_dispatched() {
+        *nr_dispatches.entry(d.pid).or_default() += 1;
+    }
+    let count = |pid| nr_dispatches.get(&pid).copied().unwrap_or(0);
+    let min_boosted = BOOST_GROUP_PIDS.iter().map(|&pid| count(pid)).min();
+    let max_other = (1..=BOOST_GROUP_TASKS)
+        .filter(|pid| !BOOST_GROUP_PIDS.contains(pid))
+        .map(count)
+        .max();
+    if min_boosted <= max_other {
+        violations.push(format!(
+            "boost group: dispatches per pid {:?} with pids {:?} boosted at level {}",
+            nr_dispatches, BOOST_GROUP_PIDS, BOOST_GROUP_LEVEL
+        ));
+    }
+
+    // The group is kept until all its members exited.
+    for (i, &pid) in BOOST_GROUP_PIDS.iter().enumerate() {
+        sched.bpf.exit_task(pid);
+        sched.gc_tasks();
+        let remaining = &BOOST_GROUP_PIDS[i + 1..];
+        if sched.boost_groups.is_boosted(pid)
+            || remaining
+                .iter()
+                .any(|&pid| !sched.boost_groups.is_boosted(pid))
+        {
+            violations.push(format!(
+                "boost group: pid {} exited, boosted pids {:?}, expected {:?}",
+                pid,
+                sched.boost_groups.pids(),
+                remaining
+            ));
+        }
+    }
+    if sched.boost_groups.clear("game") {
+        violations.push("boost group: group left after all its members exited".to_string());
+    }
+
+    violations
+}
+
+// Check the distribution of the dispatch batch sizes: the summary of an interval must follow the
+// scripted batches, an interval without rounds must be reported as such, and the batches
+// measured by the scheduler must account all its dispatches, at most one per CPU and round.
+fn check_batch_size(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let mut batch_sizes = BatchSizes::new();
+    for batch in BATCH_SIZE_ROUNDS {
+        for _ in 0..batch {
+            batch_sizes.record_dispatch();
+        }
+        batch_sizes.end_round();
+    }
+    batch_sizes.sample();
+    if batch_sizes.json() != BATCH_SIZE_JSON {
+        violations.push(format!(
+            "batch size: batches {:?} summarized as {}, expected {}",
+            BATCH_SIZE_ROUNDS,
+            batch_sizes.json(),
+            BATCH_SIZE_JSON
+        ));
+    }
+    batch_sizes.sample();
+    if batch_sizes.summary().is_some() || batch_sizes.json() != BATCH_SIZE_EMPTY_JSON {
+        violations.push(format!(
+            "batch size: interval without rounds summarized as {}",
+            batch_sizes.json()
+        ));
+    }
+
+    let opts = Opts {
+        policy: Policy::Fair,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS)
+        .hogs(1..=BATCH_SIZE_TASKS)
+        .closed_loop(BATCH_SIZE_SESSION_ROUNDS)
+        .scheduler(&opts);
+    if let Err(err) = sched.run_loop() {
+        return vec![format!("batch size: run_loop() failed: {}", err)];
+    }
+    sched.batch_sizes.sample();
+    let nr_dispatches = sched.bpf.take_dispatched().len() as u64;
+    let nr_rounds: u64 = sched.batch_sizes.histogram().iter().sum();
+    let total = sched
+        .batch_sizes
+        .summary()
+        .map(|(_, mean, _)| (mean * nr_rounds as f64).round() as u64);
+    let max = sched.batch_sizes.summary().map(|(_, _, max)| max);
+    if total != Some(nr_dispatches) || max.is_none_or(|max| max > NR_CPUS) {
+        violations.push(format!(
+            "batch size: {} dispatches on {} CPUs summarized as {}",
+            nr_dispatches,
+            NR_CPUS,
+            sched.batch_sizes.json()
+        ));
+    }
+
+    violations
+}
+
+// Check the housekeeping in the idle rounds (see --idle-maintenance-ms): the passes must run in
+// the rounds that dispatch nothing, at most once per interval, never in the busy rounds, never
+// in the rounds that dispatch nothing with tasks waiting (the dispatches or the dequeues of the
+// congested backend fail) and never without the option.
+fn check_idle_maintenance(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    let nr_expected = IDLE_MAINTENANCE_ROUNDS * ROUND_NS / (IDLE_MAINTENANCE_MS * 1_000_000);
+
+    let congested = Some(MockBackend::fail_dispatches as fn(&mut MockBackend, u64));
+    let dequeue_errors = Some(MockBackend::fail_dequeues as fn(&mut MockBackend, u64));
+    for (idle_maintenance_ms, nr_tasks, congestion, expected) in [
+        (Some(IDLE_MAINTENANCE_MS), 0, None, nr_expected),
+        (None, 0, None, 0),
+        (Some(IDLE_MAINTENANCE_MS), NR_CPUS as i32 * 2, None, 0),
+        (Some(IDLE_MAINTENANCE_MS), NR_CPUS as i32 * 2, congested, 0),
+        (
+            Some(IDLE_MAINTENANCE_MS),
+            NR_CPUS as i32 * 2,
+            dequeue_errors,
+            0,
+        ),
+    ] {
+        let opts = Opts {
+            idle_maintenance_ms,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS)
+            .hogs(1..=nr_tasks)
+            .closed_loop(IDLE_MAINTENANCE_ROUNDS)
+            .backend(|bpf| {
+                if let Some(congest) = congestion {
+                    congest(bpf, IDLE_MAINTENANCE_ROUNDS / 2);
+                }
+            })
+            .scheduler(&opts);
+        if let Err(err) = sched.run_loop() {
+            violations.push(format!("idle maintenance: run_loop() failed: {}", err));
+            continue;
+        }
+        if sched.nr_idle_passes != expected {
+            violations.push(format!(
+                "idle maintenance: {} passes in {} rounds with {} CPU hogs and interval {:?}ms \
+                 (congested={}), expected {}",
+                sched.nr_idle_passes,
+                IDLE_MAINTENANCE_ROUNDS,
+                nr_tasks,
+                idle_maintenance_ms,
+                congestion.is_some(),
+                expected
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that the interactive tasks drain before the batch ones when they contend for a single
+// CPU, even if the batch tasks have been queued first.
+//
+// All the tasks are seen once before, so that the classifier has a history of their voluntary
+// context switches: the interactive ones switched many times per second, the batch ones never.
+fn check_class_queues(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Fifo,
+        order: Order::Fifo,
+        policy_activation_threshold: None,
+        starve_timeout_ms: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let pids: Vec<i32> = CLASS_QUEUE_BATCH
+        .into_iter()
+        .chain(CLASS_QUEUE_INTERACTIVE)
+        .collect();
+    let mut sched = Fixture::new(1)
+        .tasks(pids.iter().map(|&pid| hog(pid, 0)))
+        .scheduler(&opts);
+    let mut dispatched = Vec::new();
+
+    for warm in [true, false] {
+        if !warm {
+            sched.bpf.advance(NSEC_PER_SEC);
+            for &pid in &pids {
+                let mut task = hog(pid, 0);
+                if CLASS_QUEUE_INTERACTIVE.contains(&pid) {
+                    task.nvcsw = opts.nvcsw_thresh * 8;
+                }
+                sched.bpf.enqueue(task);
+            }
+        }
+        for _ in &pids {
+            sched.bpf.advance(ROUND_NS);
+            if let Err(err) = sched.schedule() {
+                return vec![format!("class queues: schedule() failed: {}", err)];
+            }
+            let pids = sched.bpf.take_dispatched().into_iter().map(|d| d.pid);
+            if !warm {
+                dispatched.extend(pids);
+            }
+        }
+    }
+    let expected: Vec<i32> = CLASS_QUEUE_INTERACTIVE
+        .into_iter()
+        .chain(CLASS_QUEUE_BATCH)
+        .collect();
+    if dispatched != expected {
+        return vec![format!(
+            "class queues: dispatch order {:?}, expected the interactive tasks first {:?}",
+            dispatched, expected
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that, with --cpu-any-shortcut, a task that carries the RL_CPU_ANY bit in its enqueue
+// flags skips select_cpu() and is dispatched to RL_CPU_ANY (without the bit), while the other
+// tasks still go through the idle CPU selection; without the option the bit is ignored.
+fn check_cpu_any_shortcut(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for cpu_any_shortcut in [true, false] {
+        let opts = Opts {
+            cpu_any_shortcut,
+            cpus_offline: None,
+            kernel_cpu: None,
+            ..opts.clone()
+        };
+        let mut any = hog(1, 0);
+        any.flags |= RL_CPU_ANY as u64;
+        let mut sched = Fixture::new(NR_CPUS)
+            .tasks([any, hog(2, 1)])
+            .scheduler(&opts);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("cpu any shortcut: schedule() failed: {}", err)];
+        }
+        let selected = sched.bpf.take_selected();
+        let expected: &[i32] = if cpu_any_shortcut { &[2] } else { &[1, 2] };
+        if selected != expected {
+            violations.push(format!(
+                "cpu any shortcut: select_cpu() called for {:?} (shortcut={}), expected {:?}",
+                selected, cpu_any_shortcut, expected
+            ));
+        }
+        for d in sched.bpf.take_dispatched() {
+            let any_cpu = d.cpu == RL_CPU_ANY;
+            if any_cpu != (cpu_any_shortcut && d.pid == 1) || d.flags & RL_CPU_ANY as u64 != 0 {
+                violations.push(format!(
+                    "cpu any shortcut: pid {} dispatched to CPU {} with flags {:#x} (shortcut={})",
+                    d.pid, d.cpu, d.flags, cpu_any_shortcut
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+// Verify that, with --compute-boost, the time slice of a task that uses all of its CPU time
+// without voluntary context switches grows once it is recognized as compute-bound, while the
+// time slice of a batch task that is mostly sleeping stays the same (and without the option
+// both tasks get the same time slice).
+fn check_compute_boost(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for compute_boost in [true, false] {
+        let opts = Opts {
+            compute_boost,
+            batch_quantum_mult: 1,
+            slice_expr: None,
+            latency_target_us: None,
+            fork_bomb_thresh: None,
+            ..opts.clone()
+        };
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let (mut compute, mut light) = (hog(1, 0), hog(2, 1));
+        let mut slices = Vec::new();
+        for _ in 0..COMPUTE_BOOST_SECS {
+            sched.bpf.enqueue(compute.clone());
+            sched.bpf.enqueue(light.clone());
+            if let Err(err) = sched.schedule() {
+                return vec![format!("compute boost: schedule() failed: {}", err)];
+            }
+            let dispatched = sched.bpf.take_dispatched();
+            let slice_ns = |pid| dispatched.iter().find(|d| d.pid == pid).map(|d| d.slice_ns);
+            slices.push((slice_ns(compute.pid), slice_ns(light.pid)));
+            sched.bpf.advance(NSEC_PER_SEC);
+            compute.sum_exec_runtime += NSEC_PER_SEC;
+            light.sum_exec_runtime += COMPUTE_BOOST_LIGHT_NS;
+        }
+        let (first, last) = (slices[0], slices[slices.len() - 1]);
+        let is_boosted = last.0 > first.0 && last.0 > last.1;
+        if first.0.is_none()
+            || first.0 != first.1
+            || last.1 != first.1
+            || is_boosted != compute_boost
+        {
+            violations.push(format!(
+                "compute boost: time slices [compute, light] {:?} in the first round and {:?} \
+                 after {}s (boost={})",
+                first, last, COMPUTE_BOOST_SECS, compute_boost
+            ));
+        }
+    }
+
+    violations
+}
+
+// Verify that, with the fair policy, a newly seen task is placed at the current minimum virtual
+// runtime, after the CPU hogs already moved it forward: not at 0 (that would let it monopolize
+// the CPUs), and not after the other tasks either.
+fn check_new_task_vtime(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Fair,
+        policy_activation_threshold: None,
+        boost_budget_us: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(NR_CPUS)
+        .hogs(1..=NR_CPUS as i32 * 2)
+        .closed_loop(NR_ROUNDS)
+        .scheduler(&opts);
+    for _ in 0..NEW_TASK_ROUNDS {
+        if let Err(err) = sched.schedule() {
+            return vec![format!("new task vtime: schedule() failed: {}", err)];
+        }
+    }
+    sched.bpf.take_dispatched();
+
+    let min_vtime = sched.min_vtime;
+    sched.bpf.enqueue(hog(NEW_TASK_PID, 0));
+    if let Err(err) = sched.schedule() {
+        return vec![format!("new task vtime: schedule() failed: {}", err)];
+    }
+    let vtime = sched.tasks.get(&NEW_TASK_PID).map(|info| info.vtime);
+    let dispatched = sched.bpf.take_dispatched();
+    let is_first = dispatched.first().map(|d| (d.pid, d.vtime)) == Some((NEW_TASK_PID, min_vtime));
+    if min_vtime == 0 || vtime != Some(min_vtime) || !is_first {
+        return vec![format!(
+            "new task vtime: new task placed at {:?} with min_vtime {}, dispatched {:?}",
+            vtime,
+            min_vtime,
+            dispatched
+                .iter()
+                .map(|d| (d.pid, d.vtime))
+                .collect::<Vec<_>>()
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that the fast path of the rounds with a single task dispatches it exactly as the general
+// path would: the same sequence of tasks, one per round, is dispatched by a scheduler that takes
+// the fast path and by one that goes through the queues (the task is received before the round,
+// so the fast path is skipped).
+fn check_fast_path(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for policy in [Policy::Fifo, Policy::Fair, Policy::Wrr, Policy::Edf] {
+        let opts = Opts {
+            policy,
+            policy_activation_threshold: None,
+            ..opts.clone()
+        };
+        let mut fast = Fixture::new(NR_CPUS).scheduler(&opts);
+        let mut general = Fixture::new(NR_CPUS).scheduler(&opts);
+        let mut tasks: Vec<Task> = FAST_PATH_TASKS
+            .iter()
+            .enumerate()
+            .map(|(i, &(cpu, weight))| SimTask::new(i as i32 + 1, cpu, weight, Behavior::Hog).task)
+            .collect();
+        for round in 0..FAST_PATH_ROUNDS {
+            let task = &mut tasks[round as usize % FAST_PATH_TASKS.len()];
+            task.sum_exec_runtime += ROUND_NS;
+            fast.bpf.enqueue(task.clone());
+            let now = general.now_ns();
+            general.receive_task(task.clone(), now);
+            if let Err(err) = fast.schedule().and_then(|_| general.schedule()) {
+                return vec![format!("fast path: schedule() failed: {}", err)];
+            }
+            let (fast_dispatched, dispatched) =
+                (fast.bpf.take_dispatched(), general.bpf.take_dispatched());
+            if fast_dispatched != dispatched {
+                violations.push(format!(
+                    "fast path: round {} ({:?}) dispatched {:?}, the general path {:?}",
+                    round, policy, fast_dispatched, dispatched
+                ));
+            }
+            fast.bpf.advance(ROUND_NS);
+            general.bpf.advance(ROUND_NS);
+        }
+    }
+
+    violations
+}
+
+// Verify that the wrr policy dispatches the tasks in proportion to their weight: sharing a single
+// CPU, the task with three times the weight of the other one must get three dispatches in each
+// cycle of the round-robin, in a row.
+fn check_wrr_ratio(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Wrr,
+        order: Order::Fifo,
+        policy_activation_threshold: None,
+        starve_timeout_ms: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let tasks = WRR_WEIGHTS
+        .iter()
+        .enumerate()
+        .map(|(i, &weight)| SimTask::new(i as i32 + 1, 0, weight, Behavior::Hog).task);
+    let mut sched = Fixture::new(1)
+        .tasks(tasks)
+        .closed_loop(WRR_ROUNDS)
+        .scheduler(&opts);
+    if let Err(err) = sched.run_loop() {
+        return vec![format!("wrr ratio: run_loop() failed: {}", err)];
+    }
+    let dispatched: Vec<i32> = sched.bpf.take_dispatched().iter().map(|d| d.pid).collect();
+
+    // Every cycle is a run of dispatches of each task, as long as its weight / 100.
+    let mut runs: Vec<(i32, u64)> = Vec::new();
+    for &pid in &dispatched {
+        match runs.last_mut() {
+            Some((last, len)) if *last == pid => *len += 1,
+            _ => runs.push((pid, 1)),
+        }
+    }
+    // The last run may be cut short by the end of the session.
+    runs.pop();
+    let (light, heavy) = (WRR_WEIGHTS[0] / 100, WRR_WEIGHTS[1] / 100);
+    let is_weighted = runs
+        .iter()
+        .all(|&(pid, len)| len == if pid == 1 { light } else { heavy });
+    let nr_dispatches = |pid| dispatched.iter().filter(|&&p| p == pid).count();
+    let (nr_light, nr_heavy) = (nr_dispatches(1), nr_dispatches(2));
+    if runs.len() < 2
+        || !is_weighted
+        || nr_heavy.abs_diff(nr_light * (heavy / light) as usize) > heavy as usize
+    {
+        return vec![format!(
+            "wrr ratio: weights {:?} got {} and {} dispatches in {} rounds, runs {:?}",
+            WRR_WEIGHTS,
+            nr_light,
+            nr_heavy,
+            WRR_ROUNDS,
+            &runs[..runs.len().min(8)]
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that the batch tasks can't be starved by a steady stream of interactive tasks with
+// --order lifo: a new interactive task and a new batch task arrive in every round on a single
+// CPU, so the head of the batch queue is always the newest task, but the first batch task must
+// still run once it has been waiting for STARVATION_NS.
+//
+// The interactive tasks are the members of a boost group, so that they are interactive as soon
+// as they are received.
+fn check_lifo_starvation(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        policy: Policy::Fifo,
+        order: Order::Lifo,
+        policy_activation_threshold: None,
+        starve_timeout_ms: None,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(1).scheduler(&opts);
+    sched.boost_groups.set("stream", 1, 1..=LIFO_ROUNDS as i32);
+
+    let max_rounds = STARVATION_NS / ROUND_NS + 1;
+    for round in 0..LIFO_ROUNDS {
+        sched.bpf.enqueue(hog(round as i32 + 1, 0));
+        sched.bpf.enqueue(hog(LIFO_BATCH_PID + round as i32, 0));
+        sched.bpf.advance(ROUND_NS);
+        if let Err(err) = sched.schedule() {
+            return vec![format!("lifo starvation: schedule() failed: {}", err)];
+        }
+        let dispatched = sched.bpf.take_dispatched();
+        if dispatched.iter().any(|d| d.pid == LIFO_BATCH_PID) {
+            if round > max_rounds {
+                return vec![format!(
+                    "lifo starvation: first batch task dispatched after {} rounds, expected at \
+                     most {}",
+                    round, max_rounds
+                )];
+            }
+            return Vec::new();
+        }
+    }
+
+    vec![format!(
+        "lifo starvation: first batch task not dispatched in {} rounds",
+        LIFO_ROUNDS
+    )]
+}
+
+// Verify that the dispatches rejected by the BPF component are retried at most DISPATCH_RETRIES
+// times per round, whatever the amount of tasks: when each task is rejected once, the retries
+// are exhausted by the first DISPATCH_RETRIES tasks and the following one is queued again,
+// without any retry, and dispatched in the next round.
+fn check_dispatch_retries(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        skip_kthreads: false,
+        fork_bomb_thresh: None,
+        ..opts.clone()
+    };
+    let mut sched = Fixture::new(RETRY_TASKS as u64)
+        .hogs(1..=RETRY_TASKS)
+        .backend(|bpf| bpf.flaky_dispatches(2 * RETRY_TASKS as u64))
+        .scheduler(&opts);
+    let mut rounds = Vec::new();
+    for _ in 0..2 {
+        if let Err(err) = sched.schedule() {
+            return vec![format!("dispatch retries: schedule() failed: {}", err)];
+        }
+        rounds.push((
+            sched.bpf.take_dispatched().len(),
+            sched.nr_dispatch_retries,
+            sched.nr_dispatch_requeues,
+        ));
+        sched.bpf.advance(ROUND_NS);
+    }
+    let retries = DISPATCH_RETRIES as u64;
+    let expected = [(retries as usize, retries, 1), (1, retries, 1)];
+    if rounds != expected {
+        return vec![format!(
+            "dispatch retries: (dispatched, retries, requeues) {:?} with {} tasks rejected once \
+             each, expected {:?}",
+            rounds, RETRY_TASKS, expected
+        )];
+    }
+
+    Vec::new()
+}
+
+// Verify that the largest values accepted on the command line for the durations never make
+// the scheduler panic, over OVERSIZED_ROUNDS rounds one second apart (long enough for the
+// periodic refreshes and the expiration of the tasks) and a restart from a snapshot: the
+// conversions to nanoseconds saturate instead of overflowing.
+fn check_oversized_options(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        slice_us: u64::MAX,
+        slice_min_us: u64::MAX,
+        compute_max_slice_us: u64::MAX,
+        hysteresis_ms: u64::MAX,
+        starve_timeout_ms: Some(u64::MAX),
+        pid_gc_secs: u64::MAX,
+        state_max_age_secs: u64::MAX,
+        ..opts.clone()
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut sched = Fixture::new(NR_CPUS).scheduler(&opts);
+        let mut violations = Vec::new();
+        for round in 0..OVERSIZED_ROUNDS {
+            for pid in 1..=NR_CPUS as i32 {
+                sched.bpf.enqueue(hog(pid, pid - 1));
+            }
+            if let Err(err) = sched.schedule() {
+                return vec![format!("oversized options: schedule() failed: {}", err)];
+            }
+            sched.update_overload();
+            if let Some(d) = sched.bpf.take_dispatched().iter().find(|d| d.slice_ns == 0) {
+                violations.push(format!(
+                    "oversized options: pid {} dispatched without time slice in round {}",
+                    d.pid, round
+                ));
+            }
+            sched.bpf.advance(NSEC_PER_SEC);
+        }
+        let state = sched.snapshot();
+        let mut restored = Fixture::new(NR_CPUS).scheduler(&opts);
+        restored.bpf.advance(state.saved_ns);
+        if restored.restore(state).is_none() {
+            violations.push("oversized options: snapshot too old to be restored".to_string());
+        }
+        violations
+    }));
+
+    result.unwrap_or_else(|_| vec!["oversized options: the scheduler panicked".to_string()])
+}
+
+// Verify the metrics endpoint (see --metrics-addr): a client that never sends its request must
+// not delay the others, and each response must close the connection once the metrics are sent.
+fn check_metrics_server() -> Vec<String> {
+    let addr = TcpListener::bind("127.0.0.1:0").and_then(|listener| listener.local_addr());
+    let addr = match addr {
+        Ok(addr) => addr.to_string(),
+        Err(err) => return vec![format!("metrics server: no free port: {}", err)],
+    };
+    let server = match MetricsServer::start(&addr, false) {
+        Ok(server) => server,
+        Err(err) => return vec![format!("metrics server: failed to start: {:#}", err)],
+    };
+    server.update("selftest_metric 1\n".to_string());
+
+    let stuck = TcpStream::connect(&addr);
+    let response = TcpStream::connect(&addr).and_then(|mut client| {
+        client.set_read_timeout(Some(Duration::from_millis(METRICS_TIMEOUT_MS)))?;
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n")?;
+        let mut response = String::new();
+        client.read_to_string(&mut response)?;
+        Ok(response)
+    });
+    drop(stuck);
+
+    match response {
+        Ok(response)
+            if response.starts_with("HTTP/1.1 200 OK\r\n")
+                && response.contains("\r\nConnection: close\r\n")
+                && response.ends_with("\r\n\r\nselftest_metric 1\n# EOF\n") =>
+        {
+            Vec::new()
+        }
+        Ok(response) => vec![format!(
+            "metrics server: unexpected response {:?}",
+            response
+        )],
+        Err(err) => vec![format!(
+            "metrics server: no response with a stuck client connected: {}",
+            err
+        )],
+    }
+}
+
+// Verify that --workers accepts only the single-threaded dispatcher (the default), then stress
+// it with WORKERS_TASKS tasks on WORKERS_CPUS CPUs, each one received again at a random round
+// after being dispatched: every task received must be dispatched exactly once, never twice and
+// never while it is not queued.
+fn check_workers(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+    if Opts::parse_from(["scx_rust_scheduler"]).workers != 1 {
+        violations.push("workers: --workers doesn't default to 1".to_string());
+    }
+    if Opts::try_parse_from(["scx_rust_scheduler", "--workers", "2"]).is_ok() {
+        violations.push("workers: --workers 2 accepted without a sharded dispatcher".to_string());
+    }
+
+    let opts = Opts {
+        workers: 1,
+        ..opts.clone()
+    };
+    let mut seed = REPLAY_SEED;
+    let mut random = |n: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    };
+    let mut sched = Fixture::new(WORKERS_CPUS).scheduler(&opts);
+    let mut queued = HashSet::new();
+    for round in 0..WORKERS_ROUNDS + WORKERS_DRAIN_ROUNDS {
+        if round < WORKERS_ROUNDS {
+            for pid in 1..=WORKERS_TASKS {
+                if !queued.contains(&pid) && random(4) == 0 {
+                    let cpu = random(WORKERS_CPUS + 1) as i32 - 1;
+                    sched.bpf.enqueue(hog(pid, cpu));
+                    queued.insert(pid);
+                }
+            }
+        } else if queued.is_empty() {
+            break;
+        }
+        sched.bpf.advance(ROUND_NS);
+        if let Err(err) = sched.schedule() {
+            violations.push(format!("workers: schedule() failed: {}", err));
+            return violations;
+        }
+        for task in sched.bpf.take_dispatched() {
+            if !queued.remove(&task.pid) {
+                violations.push(format!(
+                    "workers: task {} dispatched at round {} while not queued",
+                    task.pid, round
+                ));
+            }
+        }
+    }
+    if !queued.is_empty() {
+        violations.push(format!(
+            "workers: {} tasks never dispatched after {} rounds",
+            queued.len(),
+            WORKERS_DRAIN_ROUNDS
+        ));
+    }
+
+    violations
+}
+
+// Record a session of the scheduler main loop against the mock backend in closed loop (tasks
+// with random weights and previously used CPUs, drawn from a fixed seed), failing the first
+// `nr_failed` dispatches, and return the recorded dispatches and events.
+fn record_session(opts: &Opts, nr_failed: u64) -> Result<(Vec<Dispatch>, Vec<Event>), String> {
+    let mut seed = REPLAY_SEED;
+    let mut random = |n: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    };
+    let mut bpf = MockBackend::new(NR_CPUS);
+    for pid in 1..=REPLAY_TASKS {
+        let cpu = random(NR_CPUS + 1) as i32 - 1;
+        let weight = 1 + random(10000);
+        bpf.enqueue(SimTask::new(pid, cpu, weight, Behavior::Hog).task);
+    }
+    bpf.fail_dispatches(nr_failed);
+    bpf.closed_loop(ROUND_NS, REPLAY_ROUNDS);
+
+    let mut out = Vec::new();
+    if let Err(err) = trace::write_header(&mut out, &[]) {
+        return Err(format!("replay: failed to write the trace: {}", err));
+    }
+    let mut sched = Scheduler::new(Recorder::new(bpf, Some(out)), opts, None, None);
+    if opts.llc_group {
+        sched.set_llc_domains(llc_domains());
+    }
+    if let Err(err) = sched.run_loop() {
+        return Err(format!("replay: the recorded session failed: {}", err));
+    }
+    let recorded = sched.bpf.inner_mut().take_dispatched();
+    let trace = match sched.bpf.finish() {
+        Ok(Some(out)) => trace::parse(&String::from_utf8_lossy(&out)),
+        Ok(None) => return Err("replay: nothing recorded".to_string()),
+        Err(err) => return Err(format!("replay: failed to write the trace: {}", err)),
+    };
+    match trace {
+        Ok(trace) => Ok((recorded, trace.events)),
+        Err(err) => Err(format!("replay: invalid trace: {:#}", err)),
+    }
+}
+
+// Record a session with a congested backend at the beginning (see record_session()), then
+// verify that replaying the trace reproduces exactly the recorded decisions, and that two
+// replays take the same decisions.
+fn check_replay(opts: &Opts) -> Vec<String> {
+    let (recorded, events) = match record_session(opts, DISPATCH_RETRIES as u64 + 2) {
+        Ok(session) => session,
+        Err(err) => return vec![err],
+    };
+
+    let llc = || opts.llc_group.then(llc_domains);
+    let first = replay::replay(opts, events.clone(), llc());
+    let second = replay::replay(opts, events, llc());
+    let mut violations: Vec<String> = first
+        .diffs
+        .iter()
+        .map(|diff| format!("replay: {}", diff))
+        .collect();
+
+    if let Some(err) = &first.error {
+        violations.push(format!("replay: the policy failed: {}", err));
+    }
+    if first.dispatches != recorded {
+        violations.push(format!(
+            "replay: {} tasks dispatched, {} recorded",
+            first.dispatches.len(),
+            recorded.len()
+        ));
+    }
+    if first.dispatches != second.dispatches {
+        violations.push("replay: two replays of the same trace diverged".to_string());
+    }
+
+    violations
+}
+
+// Verify that a replay switches profile according to the time of the day of the recorded
+// session, not to the current one: the session is recorded at midnight on the mock clock, in
+// the slot of --profile-at of a profile with shorter time slices, that the replay must use too.
+fn check_replay_timetable(opts: &Opts) -> Vec<String> {
+    let opts = Opts {
+        mode: Mode::Manual,
+        profile: REPLAY_PROFILES
+            .iter()
+            .filter_map(|text| profiles::parse(text).ok())
+            .collect(),
+        profile_at: REPLAY_PROFILE_AT
+            .iter()
+            .filter_map(|text| timetable::parse(text).ok())
+            .collect(),
+        ..opts.clone()
+    };
+    let (recorded, events) = match record_session(&opts, 0) {
+        Ok(session) => session,
+        Err(err) => return vec![err],
+    };
+
+    let result = replay::replay(&opts, events, opts.llc_group.then(llc_domains));
+    let mut violations: Vec<String> = result
+        .diffs
+        .iter()
+        .map(|diff| format!("replay timetable: {}", diff))
+        .collect();
+    if result.dispatches != recorded {
+        violations.push(format!(
+            "replay timetable: the replay diverged from the session recorded at {}",
+            REPLAY_PROFILE_AT[0]
+        ));
+    }
+
+    violations
+}
+
+// Check the what-if replay (see whatif.rs): the recorded tasks replayed with the recorded
+// parameters must reproduce the metrics of the recorded session, while the modified parameters
+// (see replay::override_opts()) must only change the decisions of the policy, not the workload.
+fn check_what_if(opts: &Opts) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    let args: Vec<String> = WHAT_IF_ARGS.iter().map(|arg| arg.to_string()).collect();
+    match replay::override_opts(&args, WHAT_IF_WITH) {
+        Ok(modified)
+            if modified.slice_us != WHAT_IF_SLICE_US || modified.policy != Policy::Fifo =>
+        {
+            violations.push(format!(
+                "what-if: --with '{}' parsed as --slice-us {} --policy {:?}",
+                WHAT_IF_WITH, modified.slice_us, modified.policy
+            ))
+        }
+        Ok(_) => {}
+        Err(err) => violations.push(format!("what-if: --with '{}': {:#}", WHAT_IF_WITH, err)),
+    }
+
+    let events = match record_session(opts, 0) {
+        Ok((_, events)) => events,
+        Err(err) => return vec![err],
+    };
+    let llc = || opts.llc_group.then(llc_domains);
+    let recorded = whatif::recorded_metrics(&events);
+    match whatif::simulate(opts, events.clone(), llc()) {
+        Ok(same) if same != recorded => violations.push(format!(
+            "what-if: the recorded parameters give {:?}, recorded {:?}",
+            same, recorded
+        )),
+        Ok(_) => {}
+        Err(err) => violations.push(format!("what-if: the policy failed: {}", err)),
+    }
+
+    let modified_opts = Opts {
+        slice_us: WHAT_IF_SLICE_US,
+        slice_min_us: WHAT_IF_SLICE_US.min(opts.slice_min_us),
+        ..opts.clone()
+    };
+    match whatif::simulate(&modified_opts, events, llc()) {
+        Ok(modified) => {
+            if modified.nr_tasks != recorded.nr_tasks {
+                violations.push(format!(
+                    "what-if: {} tasks received with the modified parameters, {} recorded",
+                    modified.nr_tasks, recorded.nr_tasks
+                ));
+            }
+            if modified.nr_dispatches == 0 || modified.avg_slice_ns > WHAT_IF_SLICE_US * 1000 {
+                violations.push(format!(
+                    "what-if: {} dispatches with an average slice of {} ns (--slice-us {})",
+                    modified.nr_dispatches, modified.avg_slice_ns, WHAT_IF_SLICE_US
+                ));
+            }
+        }
+        Err(err) => violations.push(format!("what-if: the policy failed: {}", err)),
+    }
+
+    violations
+}
+
+// LLC domains of the simulated CPUs.
+fn llc_domains() -> Vec<Vec<usize>> {
+    LLC_DOMAINS.iter().map(|cpus| cpus.to_vec()).collect()
+}
+
+/// Outcome of a simulation of the canned workload.
+pub struct SimResult {
+    pub violations: Vec<String>, // Detected violations of the policy invariants
+    pub nr_dispatches: u64,      // Total amount of dispatched tasks
+    pub interactive_waits: Vec<u64>, // Time (in nanoseconds) waited by the interactive tasks
+    pub hogs: Vec<(u64, u64)>,   // Weight and total CPU time (in nanoseconds) of the hogs
+    pub nr_starve_timeouts: u64, // Tasks dispatched first by --starve-timeout-ms
+}
+
+/// Simulate the canned workload with the scheduling policy configured by `opts`.
+pub fn simulate(opts: &Opts) -> SimResult {
+    let mut sched = Fixture::new(NR_CPUS).scheduler(opts);
+    let mut tasks: HashMap<i32, SimTask> =
+        workload().into_iter().map(|t| (t.task.pid, t)).collect();
+    for &pid in tasks.keys() {
+        sched.bpf.set_tgid(pid, process(pid));
+    }
+    let confined = opts.cpuset_aware
+        && CPUSETS
+            .iter()
+            .all(|cpus| cpus.iter().any(|&cpu| !sched.is_cpu_excluded(cpu as i32)));
+    if confined {
+        sched.bpf.set_allowed_cpus(CPUSET_PID, CPUSETS[0].to_vec());
+    }
+    if opts.llc_group {
+        sched.set_llc_domains(llc_domains());
+    }
+    let mut domains: HashMap<i32, Option<usize>> = HashMap::new();
+    let max_slice_ns = opts
+        .slice_us
+        .max(opts.compute_max_slice_us)
+        .saturating_mul(1000);
+    let mut violations = Vec::new();
+    let mut nr_dispatches = 0;
+    let mut interactive_waits = Vec::new();
+    let mut nr_stale_dispatches = 0;
+
+    for round in 0..NR_ROUNDS {
+        // Wake up the tasks that are ready to run (periodically sending one of them twice).
+        let mut duplicate = round % DUPLICATE_ROUNDS == 0;
+        for t in tasks.values_mut() {
+            if t.queued_round.is_none() && t.wake_round <= round {
+                t.queued_round = Some(round);
+                sched.bpf.enqueue(t.task.clone());
+                if duplicate {
+                    sched.bpf.enqueue(t.task.clone());
+                    duplicate = false;
+                }
+            }
+        }
+
+        // Move the confined task to a different cpuset.
+        if confined && round == CPUSET_MOVE_ROUND {
+            sched.bpf.set_allowed_cpus(CPUSET_PID, CPUSETS[1].to_vec());
+        }
+
+        // Periodically simulate a congested backend.
+        if round % CONGESTION_ROUNDS == 0 {
+            sched.bpf.fail_dispatches(DISPATCH_RETRIES as u64 + 2);
+        }
+
+        if let Err(err) = sched.schedule() {
+            violations.push(format!("round {}: schedule() failed: {}", round, err));
+            break;
+        }
+
+        // Check the scheduling decisions and simulate the execution of the dispatched tasks.
+        for d in sched.bpf.take_dispatched() {
+            let Some(t) = tasks.get_mut(&d.pid) else {
+                violations.push(format!("round {}: unknown pid {} dispatched", round, d.pid));
+                continue;
+            };
+            nr_dispatches += 1;
+            match t.queued_round.take() {
+                Some(queued_round) => {
+                    if matches!(t.behavior, Behavior::Interactive { .. }) {
+                        interactive_waits.push((round - queued_round) * ROUND_NS);
+                    }
+                }
+                None => {
+                    violations.push(format!("round {}: pid {} dispatched twice", round, d.pid));
+                }
+            }
+            if d.slice_ns == 0 || d.slice_ns > max_slice_ns {
+                violations.push(format!(
+                    "round {}: pid {} got an invalid time slice ({} ns)",
+                    round, d.pid, d.slice_ns
+                ));
+            }
+            if d.cpu != RL_CPU_ANY && (d.cpu < 0 || d.cpu as u64 >= NR_CPUS) {
+                violations.push(format!(
+                    "round {}: pid {} dispatched to an invalid CPU ({})",
+                    round, d.pid, d.cpu
+                ));
+            }
+            if !sched.excluded_cpus.is_empty()
+                && (d.cpu == RL_CPU_ANY || sched.is_cpu_excluded(d.cpu))
+            {
+                violations.push(format!(
+                    "round {}: pid {} dispatched to an excluded CPU ({})",
+                    round, d.pid, d.cpu
+                ));
+            }
+            if confined && d.pid == CPUSET_PID && d.cpu != RL_CPU_ANY {
+                // The previous cpuset is still allowed until the cached one is refreshed (the
+                // new cpuset is a subset of the previous one).
+                let cpuset = if round < CPUSET_MOVE_ROUND + CPUSET_REFRESH_NS / ROUND_NS {
+                    CPUSETS[0]
+                } else {
+                    CPUSETS[1]
+                };
+                if round >= CPUSET_MOVE_ROUND && !CPUSETS[1].contains(&(d.cpu as usize)) {
+                    nr_stale_dispatches += 1;
+                }
+                if !cpuset.contains(&(d.cpu as usize)) {
+                    violations.push(format!(
+                        "round {}: pid {} dispatched outside of its cpuset {:?} ({})",
+                        round, d.pid, cpuset, d.cpu
+                    ));
+                }
+            }
+            if let Some(llc) = &sched.llc {
+                let domain = llc.cpu_domain(d.cpu);
+                let tgid = process(d.pid);
+                if *domains.entry(tgid).or_insert(domain) != domain || domain.is_none() {
+                    violations.push(format!(
+                        "round {}: pid {} dispatched outside the LLC domain of process {} ({})",
+                        round, d.pid, tgid, d.cpu
+                    ));
+                }
+            }
+            if d.cpu != RL_CPU_ANY {
+                t.task.cpu = d.cpu;
+            }
+
+            match t.behavior {
+                Behavior::Interactive {
+                    run_ns,
+                    sleep_rounds,
+                } => {
+                    t.task.sum_exec_runtime += run_ns.min(d.slice_ns);
+                    t.task.nvcsw += 1;
+                    t.wake_round = round + 1 + sleep_rounds;
+                }
+                Behavior::Hog => {
+                    // The task is runnable again as soon as its time slice expires.
+                    t.task.sum_exec_runtime += d.slice_ns;
+                    t.wake_round = round + d.slice_ns.div_ceil(ROUND_NS).max(1);
+                }
+            }
+        }
+
+        // Detect starved tasks.
+        for t in tasks.values_mut() {
+            if let Some(queued_round) = t.queued_round {
+                let max_wait_rounds = match opts.starve_timeout_ms {
+                    Some(timeout_ms) => timeout_ms * 1_000_000 / ROUND_NS + STARVE_SLACK_ROUNDS,
+                    None => MAX_WAIT_ROUNDS * (100 / t.task.weight.max(1)).max(1),
+                };
+                if round - queued_round > max_wait_rounds {
+                    violations.push(format!(
+                        "round {}: pid {} starved (waiting since round {})",
+                        round, t.task.pid, queued_round
+                    ));
+                    t.queued_round = Some(round);
+                }
+            }
+        }
+
+        sched.bpf.advance(ROUND_NS);
+        sched.update_mode();
+        sched.update_overload();
+    }
+
+    if let Some(accounting) = &sched.accounting {
+        if accounting.total_discrepancies() > 0 {
+            violations.push(format!(
+                "{} time slice accounting discrepancies",
+                accounting.total_discrepancies()
+            ));
+        }
+    }
+
+    // The backend bounces the dispatches outside of the current cpuset: this can only happen
+    // while the cached cpuset is stale.
+    let nr_bounce_dispatches = *sched.bpf.nr_bounce_dispatches_mut();
+    if nr_bounce_dispatches != nr_stale_dispatches {
+        violations.push(format!(
+            "{} dispatches bounced by the backend, expected {} (stale cpuset)",
+            nr_bounce_dispatches, nr_stale_dispatches
+        ));
+    }
+
+    // Verify that the stats reported via the control socket survive the binary encoding.
+    let stats = sched.stats_snapshot();
+    match StatsSnapshot::decode(&stats.encode()) {
+        Ok(decoded) if decoded == stats => {}
+        Ok(decoded) => violations.push(format!(
+            "binary stats frame mismatch: {:?} != {:?}",
+            decoded, stats
+        )),
+        Err(err) => violations.push(format!("binary stats frame decoding failed: {}", err)),
+    }
+
+    let hogs = tasks
+        .values()
+        .filter(|t| matches!(t.behavior, Behavior::Hog))
+        .map(|t| (t.task.weight, t.task.sum_exec_runtime))
+        .collect();
+
+    SimResult {
+        violations,
+        nr_dispatches,
+        interactive_waits,
+        hogs,
+        nr_starve_timeouts: sched.nr_starve_timeouts,
+    }
+}
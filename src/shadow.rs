@@ -0,0 +1,97 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::Policy;
+use crate::TaskClass;
+
+/// Candidate policy evaluated in shadow mode (see --shadow-policy).
+///
+/// The policies only differ in the order of the tasks within each queue (the choice between
+/// the interactive and the batch queue doesn't depend on the policy), so in each round the
+/// shadow policy orders a copy of the queues, before the tasks are dispatched, and then it is
+/// compared to the tasks that have actually been dispatched: if N tasks of a class have been
+/// dispatched, the shadow policy would have dispatched the first N tasks of its own order of the
+/// same queue, and each dispatched task that is among them is an agreement.
+///
+/// The shadow policy never affects the dispatches. The wrr credits are applied when the tasks
+/// are queued, so a shadow wrr policy orders the tasks like fifo.
+pub struct ShadowPolicy {
+    policy: Policy,        // Candidate policy
+    interactive: Vec<i32>, // Order of the interactive tasks according to the candidate policy
+    batch: Vec<i32>,       // Order of the batch tasks according to the candidate policy
+    nr_decisions: u64,     // Dispatched tasks compared in the current interval
+    nr_agreed: u64,        // Dispatched tasks that the candidate would have picked as well
+}
+
+impl ShadowPolicy {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            interactive: Vec::new(),
+            batch: Vec::new(),
+            nr_decisions: 0,
+            nr_agreed: 0,
+        }
+    }
+
+    /// Order the tasks queued in `class` according to the candidate policy, given as pid,
+    /// virtual runtime and deadline, in the order of the queue before the tasks of the round
+    /// are sorted.
+    pub fn order(&mut self, class: TaskClass, tasks: impl Iterator<Item = (i32, u64, u64)>) {
+        let mut tasks: Vec<(u64, i32)> = tasks
+            .enumerate()
+            .map(|(idx, (pid, vtime, deadline))| match self.policy {
+                Policy::Fair => (vtime, pid),
+                Policy::Edf => (deadline, pid),
+                Policy::Fifo | Policy::Wrr => (idx as u64, pid),
+            })
+            .collect();
+        tasks.sort_by_key(|&(key, _)| key);
+
+        let order = match class {
+            TaskClass::Interactive => &mut self.interactive,
+            TaskClass::Batch => &mut self.batch,
+        };
+        order.clear();
+        order.extend(tasks.into_iter().map(|(_, pid)| pid));
+    }
+
+    /// Compare the tasks dispatched in the round (pid and class) to the order of the candidate
+    /// policy.
+    pub fn record(&mut self, dispatched: &[(i32, TaskClass)]) {
+        for (class, order) in [
+            (TaskClass::Interactive, &self.interactive),
+            (TaskClass::Batch, &self.batch),
+        ] {
+            let pids = dispatched.iter().filter(|&&(_, c)| c == class);
+            let picked = &order[..pids.clone().count().min(order.len())];
+
+            self.nr_decisions += pids.clone().count() as u64;
+            self.nr_agreed += pids.filter(|(pid, _)| picked.contains(pid)).count() as u64;
+        }
+    }
+
+    /// Return the share of the dispatched tasks (in percent) that the candidate policy would
+    /// have picked in the current interval, None if no task has been compared.
+    pub fn agreement_pct(&self) -> Option<f64> {
+        (self.nr_decisions > 0).then(|| self.nr_agreed as f64 * 100.0 / self.nr_decisions as f64)
+    }
+
+    /// Print the agreement with the candidate policy in the current interval and start a new
+    /// interval.
+    pub fn report(&mut self) {
+        if let Some(pct) = self.agreement_pct() {
+            println!(
+                "shadow policy: {} agreement {:.1}% ({} of {} dispatches)",
+                format!("{:?}", self.policy).to_lowercase(),
+                pct,
+                self.nr_agreed,
+                self.nr_decisions
+            );
+        }
+        self.nr_decisions = 0;
+        self.nr_agreed = 0;
+    }
+}
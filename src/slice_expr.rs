@@ -0,0 +1,295 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Time slice formula provided on the command line (see --slice-expr).
+//!
+//! The formula is an arithmetic expression evaluated for each dispatched task, that returns
+//! the time slice of the task in microseconds, e.g.:
+//!
+//!   $ scx_rust_scheduler --slice-expr 'max(500, slice * weight / 100 / (nr_waiting + 1))'
+//!
+//! Supported syntax:
+//!  - numbers (e.g., 100, 2.5),
+//!  - the variables listed in VARIABLES,
+//!  - the operators +, -, *, / and % (with the usual precedence), unary minus and parentheses,
+//!  - the functions min(a, b) and max(a, b).
+//!
+//! The expression is evaluated in floating point: the result is rounded down to an amount of
+//! nanoseconds and clamped by the caller (a division by zero results in the maximum time slice,
+//! an undefined result, e.g., 0 / 0, in the minimum one).
+
+/// Variables that can be used in the expression (see SliceVars).
+pub const VARIABLES: [&str; 5] = ["weight", "nr_waiting", "nr_cpus", "runtime", "slice"];
+
+/// Values of the variables for a specific task.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SliceVars {
+    pub weight: u64,     // Weight of the task (1..10000, default is 100)
+    pub nr_waiting: u64, // Tasks waiting to be dispatched
+    pub nr_cpus: u64,    // Online CPUs
+    pub runtime_us: u64, // Total CPU time used by the task (runtime)
+    pub slice_us: u64,   // Default time slice (slice, see --slice-us)
+}
+
+impl SliceVars {
+    // Return the value of the variable with index `idx` in VARIABLES.
+    fn get(&self, idx: usize) -> f64 {
+        let value = match idx {
+            0 => self.weight,
+            1 => self.nr_waiting,
+            2 => self.nr_cpus,
+            3 => self.runtime_us,
+            _ => self.slice_us,
+        };
+
+        value as f64
+    }
+}
+
+// Node of the parsed expression.
+#[derive(Debug, Clone)]
+enum Node {
+    Num(f64),
+    Var(usize), // Index in VARIABLES
+    Neg(Box<Node>),
+    Op(char, Box<Node>, Box<Node>),
+    Min(Box<Node>, Box<Node>),
+    Max(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, vars: &SliceVars) -> f64 {
+        match self {
+            Node::Num(value) => *value,
+            Node::Var(idx) => vars.get(*idx),
+            Node::Neg(node) => -node.eval(vars),
+            Node::Op(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(vars), rhs.eval(vars));
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    '/' => lhs / rhs,
+                    _ => lhs % rhs,
+                }
+            }
+            Node::Min(lhs, rhs) => lhs.eval(vars).min(rhs.eval(vars)),
+            Node::Max(lhs, rhs) => lhs.eval(vars).max(rhs.eval(vars)),
+        }
+    }
+}
+
+/// Parsed time slice formula.
+#[derive(Debug, Clone)]
+pub struct SliceExpr {
+    root: Node, // Parsed expression
+}
+
+impl SliceExpr {
+    /// Evaluate the expression for a task and return its time slice (in microseconds).
+    pub fn eval(&self, vars: &SliceVars) -> f64 {
+        self.root.eval(vars)
+    }
+
+    /// Return the time slice (in nanoseconds) of a task, clamped to [min_ns, max_ns].
+    pub fn slice_ns(&self, vars: &SliceVars, min_ns: u64, max_ns: u64) -> u64 {
+        ((self.eval(vars) * 1000.0) as u64).clamp(min_ns, max_ns.max(min_ns))
+    }
+}
+
+/// Parse a time slice formula (see the module documentation for the syntax).
+pub fn parse(text: &str) -> Result<SliceExpr, String> {
+    let mut parser = Parser {
+        text,
+        chars: text.char_indices().collect(),
+        pos: 0,
+    };
+    let root = parser.expr()?;
+    if let Some(&(at, c)) = parser.chars.get(parser.pos) {
+        return Err(format!("unexpected '{}' at position {}", c, at + 1));
+    }
+
+    Ok(SliceExpr { root })
+}
+
+// Recursive descent parser of the expressions.
+struct Parser<'a> {
+    text: &'a str,             // Expression
+    chars: Vec<(usize, char)>, // Characters of the expression (with their byte offset)
+    pos: usize,                // Next character to parse
+}
+
+impl Parser<'_> {
+    // Skip the blanks and return the next character, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|(_, c)| c.is_whitespace())
+        {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).map(|&(_, c)| c)
+    }
+
+    // Consume the next character if it is `c`.
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.pos += 1;
+        }
+
+        matched
+    }
+
+    // Consume the next character, that must be `c`.
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        match self.chars.get(self.pos) {
+            Some(&(at, found)) => Err(format!(
+                "expected '{}' at position {}, found '{}'",
+                c,
+                at + 1,
+                found
+            )),
+            None => Err(format!("expected '{}' at the end of the expression", c)),
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            node = Node::Op(op, Box::new(node), Box::new(self.term()?));
+        }
+
+        Ok(node)
+    }
+
+    // term := factor (('*' | '/' | '%') factor)*
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.factor()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.pos += 1;
+            node = Node::Op(op, Box::new(node), Box::new(self.factor()?));
+        }
+
+        Ok(node)
+    }
+
+    // factor := '-' factor | '(' expr ')' | number | variable | function '(' expr ',' expr ')'
+    fn factor(&mut self) -> Result<Node, String> {
+        let Some(c) = self.peek() else {
+            return Err("unexpected end of the expression".to_string());
+        };
+        let at = self.chars[self.pos].0;
+
+        if self.eat('-') {
+            return Ok(Node::Neg(Box::new(self.factor()?)));
+        }
+        if self.eat('(') {
+            let node = self.expr()?;
+            self.expect(')')?;
+            return Ok(node);
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let len = self.token_len(|c| c.is_ascii_digit() || c == '.');
+            let token = &self.text[at..at + len];
+            return token
+                .parse()
+                .map(Node::Num)
+                .map_err(|_| format!("invalid number '{}' at position {}", token, at + 1));
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let len = self.token_len(|c| c.is_ascii_alphanumeric() || c == '_');
+            let name = &self.text[at..at + len];
+            if let Some(idx) = VARIABLES.iter().position(|&var| var == name) {
+                return Ok(Node::Var(idx));
+            }
+            if name == "min" || name == "max" {
+                self.expect('(')?;
+                let lhs = Box::new(self.expr()?);
+                self.expect(',')?;
+                let rhs = Box::new(self.expr()?);
+                self.expect(')')?;
+                return Ok(if name == "min" {
+                    Node::Min(lhs, rhs)
+                } else {
+                    Node::Max(lhs, rhs)
+                });
+            }
+            return Err(format!(
+                "unknown variable '{}' at position {} (expected one of {} or the functions min \
+                 and max)",
+                name,
+                at + 1,
+                VARIABLES.join(", ")
+            ));
+        }
+
+        Err(format!("unexpected '{}' at position {}", c, at + 1))
+    }
+
+    // Consume the (ASCII) characters of a token matching `pred` and return its length.
+    fn token_len(&mut self, pred: impl Fn(char) -> bool) -> usize {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|&(_, c)| pred(c)) {
+            self.pos += 1;
+        }
+
+        self.pos - start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values of the variables of the tests.
+    const VARS: SliceVars = SliceVars {
+        weight: 200,
+        nr_waiting: 3,
+        nr_cpus: 4,
+        runtime_us: 1500,
+        slice_us: 5000,
+    };
+
+    #[test]
+    fn eval() {
+        for (text, expected) in [
+            ("slice / (nr_waiting + 1)", 1250.0),
+            ("max(500, slice * weight / 100 / (nr_waiting + 1))", 2500.0),
+            ("2 + 3 * 4 - 10 % 4", 12.0),
+            ("-(runtime - 1000) + min(nr_cpus, 2.5) * 1000", 2000.0),
+            (" weight*nr_cpus ", 800.0),
+        ] {
+            let expr = parse(text).unwrap_or_else(|err| panic!("'{}': {}", text, err));
+            assert_eq!(expr.eval(&VARS), expected, "'{}'", text);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for text in [
+            "", "slice +", "foo * 2", "min(1)", "(1 + 2", "1 2", "1..2", "3 $ 4",
+        ] {
+            assert!(parse(text).is_err(), "'{}' accepted", text);
+        }
+    }
+
+    #[test]
+    fn slice_ns_is_clamped() {
+        let slice_ns = |text| parse(text).unwrap().slice_ns(&VARS, 1_000, 10_000_000);
+
+        assert_eq!(slice_ns("slice"), 5_000_000);
+        assert_eq!(slice_ns("slice * 10"), 10_000_000);
+        assert_eq!(slice_ns("-slice"), 1_000);
+        assert_eq!(slice_ns("1 / 0"), 10_000_000);
+        assert_eq!(slice_ns("0 / 0"), 1_000);
+    }
+}
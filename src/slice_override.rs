@@ -0,0 +1,129 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Per-task scheduling hints: time slice and latency requests.
+//!
+//! A task can request a specific time slice (in microseconds) by running with the
+//! SCX_SLICE_US environment variable set, and a specific latency (in microseconds, used as the
+//! relative deadline of the task by the edf policy) with SCX_LATENCY_US, e.g.:
+//!
+//!   $ SCX_SLICE_US=500 SCX_LATENCY_US=2000 ./my-app
+//!
+//! The requests are read from /proc/<pid>/environ, that reflects the environment of the task at
+//! exec() time, so they can't be changed while the task is running.
+//!
+//! NOTE: any unprivileged process can set its own environment, so the requests are always
+//! bounded:
+//!
+//!  - the requested time slice is clamped to [min_slice_ns, max_slice_ns], where min_slice_ns
+//!    is --slice-min-us and max_slice_ns is the default maximum time slice: a task can use this
+//!    mechanism to run with a shorter (or more predictable) time slice, but never to get more
+//!    CPU time than a regular task that has the CPU for itself (a request of 0 is a yield: the
+//!    task gets the minimum time slice, see "Time slices" in lib.rs);
+//!
+//!  - the requested latency is clamped to [LATENCY_MIN_NS, LATENCY_MAX_NS]: a task can get
+//!    ahead of the tasks received up to LATENCY_MIN_NS after it, but it can't indefinitely
+//!    delay the tasks that have been waiting for longer.
+
+use std::fs;
+
+// Environment variable used by the tasks to request a specific time slice (in microseconds).
+pub const SLICE_ENV: &str = "SCX_SLICE_US";
+
+// Environment variable used by the tasks to request a specific latency (in microseconds).
+pub const LATENCY_ENV: &str = "SCX_LATENCY_US";
+
+// Minimum and maximum latency (in nanoseconds) that a task can request.
+pub const LATENCY_MIN_NS: u64 = 1_000_000;
+pub const LATENCY_MAX_NS: u64 = 1_000_000_000;
+
+/// Parse the content of /proc/<pid>/environ and return the value of the hint `name` (in
+/// microseconds).
+///
+/// Return None if the task didn't set the hint or if the value is not a valid amount of
+/// microseconds.
+pub fn parse(environ: &[u8], name: &str) -> Option<u64> {
+    let value = environ.split(|&c| c == 0).find_map(|var| {
+        var.strip_prefix(name.as_bytes())
+            .and_then(|rest| rest.strip_prefix(b"="))
+    })?;
+
+    std::str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+/// Return the value of the hint `name` set by task `pid` (see parse()).
+///
+/// Tasks whose environment can't be read (e.g., tasks that already exited or kernel threads)
+/// are considered as not having set any hint.
+pub fn read(pid: i32, name: &str) -> Option<u64> {
+    let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+
+    parse(&environ, name)
+}
+
+/// Return the time slice (in nanoseconds) granted to a task that requested `slice_us`,
+/// clamped to [min_slice_ns, max_slice_ns].
+pub fn slice_ns(slice_us: u64, min_slice_ns: u64, max_slice_ns: u64) -> u64 {
+    slice_us
+        .saturating_mul(1000)
+        .clamp(min_slice_ns, max_slice_ns.max(min_slice_ns))
+}
+
+/// Return the latency (in nanoseconds) granted to a task that requested `latency_us`, clamped
+/// to [LATENCY_MIN_NS, LATENCY_MAX_NS].
+pub fn latency_ns(latency_us: u64) -> u64 {
+    latency_us
+        .saturating_mul(1000)
+        .clamp(LATENCY_MIN_NS, LATENCY_MAX_NS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENVIRON: &[u8] = b"HOME=/root\0SCX_SLICE_US_MAX=9\0SCX_SLICE_US=500\0SCX_LATENCY_US=x\0";
+
+    #[test]
+    fn parse_hints() {
+        assert_eq!(parse(ENVIRON, SLICE_ENV), Some(500));
+        assert_eq!(parse(ENVIRON, LATENCY_ENV), None);
+        assert_eq!(parse(ENVIRON, "SCX_MISSING"), None);
+        assert_eq!(parse(b"", SLICE_ENV), None);
+        assert_eq!(parse(b"SCX_SLICE_US=0", SLICE_ENV), Some(0));
+        assert_eq!(parse(b"SCX_SLICE_US= 42 \0", SLICE_ENV), Some(42));
+        assert_eq!(parse(b"SCX_SLICE_US=-1\0", SLICE_ENV), None);
+    }
+
+    #[test]
+    fn read_missing_task() {
+        assert_eq!(read(i32::MAX, SLICE_ENV), None);
+    }
+
+    #[test]
+    fn slice_is_clamped() {
+        for (slice_us, expected) in [
+            (0, 100_000),
+            (50, 100_000),
+            (500, 500_000),
+            (5000, 5_000_000),
+            (u64::MAX, 5_000_000),
+        ] {
+            assert_eq!(
+                slice_ns(slice_us, 100_000, 5_000_000),
+                expected,
+                "{}us",
+                slice_us
+            );
+        }
+        assert_eq!(slice_ns(500, 1_000_000, 100_000), 1_000_000);
+    }
+
+    #[test]
+    fn latency_is_clamped() {
+        assert_eq!(latency_ns(0), LATENCY_MIN_NS);
+        assert_eq!(latency_ns(2000), 2_000_000);
+        assert_eq!(latency_ns(u64::MAX), LATENCY_MAX_NS);
+    }
+}
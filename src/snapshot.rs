@@ -0,0 +1,91 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Snapshot of the per-task state learned by the policy (see --state-file), saved when the
+//! scheduler stops and restored when it starts again, so that a restart doesn't reset the
+//! virtual runtimes and the statistics of the tasks (e.g., all the tasks would be considered
+//! batch tasks again, with the same virtual runtime, until their statistics build up).
+//!
+//! The snapshot is stored in JSON and it is only restored if it has been saved during the
+//! current boot and it is fresh enough (see --state-max-age-secs): the per-task timestamps come
+//! from the monotonic clock of the scheduler, which keeps running across restarts but starts
+//! again at every boot, so they are only valid within the same boot (compared by the boot
+//! identifier of the kernel, /proc/sys/kernel/random/boot_id), while the age of the snapshot is
+//! measured with the wall clock (CLOCK_REALTIME). The tasks that exited in the meantime are
+//! dropped, the cached values that the scheduler reads again anyway (scheduling hints, cpusets,
+//! caps, processes) are not saved.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Learned state of a task (see TaskInfo).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskState {
+    pub nvcsw: u64,        // Voluntary context switches at the beginning of the window
+    pub nvcsw_ts: u64,     // Timestamp of the beginning of the current window
+    pub avg_nvcsw: u64,    // Average amount of voluntary context switches per second
+    pub runtime: u64,      // Total CPU time at the beginning of the current window
+    pub avg_util: u64,     // Average CPU utilization (in percent)
+    pub avg_nvcsw_rt: u64, // Average voluntary context switches per second of CPU time
+    pub avg_io: u64,       // Average share of the time spent sleeping while I/O-bound
+    pub last_runtime: u64, // Total CPU time the last time the task has been received
+    pub vtime: u64,        // Virtual runtime (used by the fair policy)
+    pub last_seen: u64,    // Last time the task has been received from the BPF component
+    pub weight: u64,       // Weight the last time the task has been received
+    pub wrr_credits: u64,  // Consecutive dispatches left (used by the wrr policy)
+}
+
+/// State of the policy saved across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub saved_ns: u64,                  // Time of the snapshot (wall clock)
+    pub boot_id: Option<String>,        // Boot of the system when the snapshot has been saved
+    pub min_vtime: u64,                 // Minimum virtual runtime of the dispatched tasks
+    pub tasks: HashMap<i32, TaskState>, // Learned state of the tasks
+    pub pins: HashMap<i32, i32>,        // CPU of the pinned tasks (see the pin command)
+}
+
+impl Snapshot {
+    /// Return true if the snapshot has been saved during the boot `boot_id`, at most
+    /// `max_age_ns` before `now` (wall clock): a snapshot from the future (e.g., the wall clock
+    /// has been moved back) is never fresh, and neither is a snapshot from an unknown boot.
+    pub fn is_fresh(&self, now: u64, boot_id: Option<&str>, max_age_ns: u64) -> bool {
+        self.boot_id.is_some()
+            && self.boot_id.as_deref() == boot_id
+            && now
+                .checked_sub(self.saved_ns)
+                .is_some_and(|age| age <= max_age_ns)
+    }
+}
+
+/// Write the snapshot to `path`, replacing it atomically, so that a crash while saving never
+/// leaves a truncated snapshot behind.
+pub fn save(path: &str, snapshot: &Snapshot) -> Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let text = serde_json::to_string(snapshot)?;
+    fs::write(&tmp_path, text).with_context(|| format!("Failed to write {}", tmp_path))?;
+    fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path))?;
+
+    Ok(())
+}
+
+/// Read the snapshot from `path`, None if there is no snapshot yet.
+pub fn load(path: &str) -> Result<Option<Snapshot>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path)),
+    };
+    let snapshot = serde_json::from_str(&text).with_context(|| format!("Invalid {}", path))?;
+
+    Ok(Some(snapshot))
+}
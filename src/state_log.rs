@@ -0,0 +1,179 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Periodic log of the full state of the scheduler (see --state-log), for the post-mortem
+//! analysis of long runs, e.g., to reconstruct what led to a hang or to an unfair distribution
+//! of the CPU time long after the fact.
+//!
+//! Every --state-log-secs, a snapshot of the state is appended to the log as a single line of
+//! JSON: the same members of the JSON stats (see --metrics-dashboard), followed by:
+//!
+//!   - `bpf_queued`: tasks waiting in the BPF component
+//!   - `cpu_load_pct`: load of each CPU since the previous snapshot, as the time slices
+//!     assigned to the tasks dispatched to it, relative to the elapsed time (in percent)
+//!   - `any_load_pct`: load assigned to the first CPU available (in percent of a CPU)
+//!   - `top`: the tasks with the highest CPU utilization (pid, `util` in percent, voluntary
+//!     context switches per second and weight)
+//!
+//! The lines are written by a separate thread, so that the log never blocks the scheduler (the
+//! snapshots that don't fit in the channel are dropped and reported at the end of the session),
+//! and flushed after each snapshot, so that the log is complete up to the last snapshot even if
+//! the scheduler hangs. When the log exceeds --state-log-max-mb, it is renamed to <PATH>.1
+//! (replacing the previous one) and a new log is started.
+
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::thread::JoinHandle;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Maximum amount of snapshots waiting to be written.
+const STATE_LOG_QUEUE_LEN: usize = 16;
+
+// Amount of tasks reported in the top consumers of each snapshot.
+pub const STATE_LOG_TOP_TASKS: usize = 10;
+
+// Log file, renamed when it exceeds the maximum size.
+struct RotatingFile {
+    path: String,         // Path of the current log
+    out: BufWriter<File>, // Current log
+    size: u64,            // Size of the current log
+    max_size: u64,        // Size that triggers the rotation
+}
+
+impl RotatingFile {
+    fn open(path: &str, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_string(),
+            out: BufWriter::new(file),
+            size,
+            max_size,
+        })
+    }
+
+    // Append a line to the log, rotating it first if the line doesn't fit.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.out.flush()?;
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+            self.out = BufWriter::new(File::create(&self.path)?);
+            self.size = 0;
+        }
+        writeln!(self.out, "{}", line)?;
+        self.size += len;
+
+        self.out.flush()
+    }
+}
+
+/// Periodic snapshots of the state, written to a rotating log by a separate thread.
+pub struct StateLog {
+    interval_ns: u64,                   // Time between two snapshots
+    last_ts: u64,                       // Time of the last snapshot
+    assigned_ns: Vec<u64>,              // Time slices assigned to each CPU in the interval
+    any_ns: u64,                        // Time slices assigned to the first CPU available
+    tx: SyncSender<String>,             // Snapshots to be written
+    writer: JoinHandle<io::Result<()>>, // Thread that writes the snapshots
+    nr_dropped: u64,                    // Snapshots dropped because the channel was full
+}
+
+impl StateLog {
+    /// Open the log `path` (appending to it), rotated at `max_size` bytes, and start writing a
+    /// snapshot every `interval_secs` from `now`.
+    pub fn create(path: &str, max_size: u64, interval_secs: u64, now: u64) -> Result<Self> {
+        let mut file = RotatingFile::open(path, max_size)
+            .with_context(|| format!("Failed to open the state log {}", path))?;
+        let (tx, rx) = mpsc::sync_channel::<String>(STATE_LOG_QUEUE_LEN);
+        let writer = thread::spawn(move || {
+            for line in rx {
+                file.write_line(&line)?;
+            }
+            Ok(())
+        });
+
+        Ok(Self {
+            interval_ns: interval_secs.saturating_mul(NSEC_PER_SEC),
+            last_ts: now,
+            assigned_ns: Vec::new(),
+            any_ns: 0,
+            tx,
+            writer,
+            nr_dropped: 0,
+        })
+    }
+
+    /// Return true if a snapshot needs to be taken at time `now`.
+    pub fn due(&self, now: u64) -> bool {
+        now.saturating_sub(self.last_ts) >= self.interval_ns
+    }
+
+    /// Account a time slice of `slice_ns` assigned to `cpu` (None = first CPU available).
+    pub fn record_dispatch(&mut self, cpu: Option<usize>, slice_ns: u64) {
+        match cpu {
+            Some(cpu) => {
+                if cpu >= self.assigned_ns.len() {
+                    self.assigned_ns.resize(cpu + 1, 0);
+                }
+                self.assigned_ns[cpu] += slice_ns;
+            }
+            None => self.any_ns += slice_ns,
+        }
+    }
+
+    /// Return the members of the snapshot with the CPU loads since the previous snapshot (as
+    /// formatted JSON values) and start a new interval at `now`.
+    pub fn take_loads(&mut self, now: u64) -> [(&'static str, String); 2] {
+        let elapsed = now.saturating_sub(self.last_ts).max(1);
+        let load = |ns: u64| {
+            let load = (ns as u128 * 1000 / elapsed as u128) as u64;
+            format!("{}.{}", load / 10, load % 10)
+        };
+        let loads: Vec<String> = self.assigned_ns.iter().map(|&ns| load(ns)).collect();
+        let any = load(self.any_ns);
+
+        self.assigned_ns.iter_mut().for_each(|ns| *ns = 0);
+        self.any_ns = 0;
+        self.last_ts = now;
+
+        [
+            ("cpu_load_pct", format!("[{}]", loads.join(","))),
+            ("any_load_pct", any),
+        ]
+    }
+
+    /// Queue a snapshot, without waiting for the writer (it is dropped if the channel is full).
+    pub fn write(&mut self, json: String) {
+        if self.tx.try_send(json).is_err() {
+            self.nr_dropped += 1;
+        }
+    }
+
+    /// Write the snapshots still queued and return the amount of snapshots that have been
+    /// dropped.
+    pub fn finish(self) -> Result<u64> {
+        drop(self.tx);
+        self.writer
+            .join()
+            .map_err(|_| anyhow!("the state log thread panicked"))?
+            .context("Failed to write the state log")?;
+
+        Ok(self.nr_dropped)
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// Upper bounds (in nanoseconds) of the inter-dispatch gap histogram buckets, gaps above the last
+// bound are accounted in an additional overflow bucket.
+const GAP_BUCKETS_NS: [u64; 4] = [100_000, 1_000_000, 10_000_000, 100_000_000];
+const GAP_BUCKETS_LABEL: &str = "<100us/<1ms/<10ms/<100ms/>=100ms";
+const NR_GAP_BUCKETS: usize = GAP_BUCKETS_NS.len() + 1;
+
+// Amount of CPUs reported on each line of the inter-dispatch gap summary.
+const GAP_CPUS_PER_LINE: usize = 4;
+
+/// Per-CPU histogram of the time elapsed between consecutive dispatches to the same CPU.
+///
+/// The per-CPU arrays are initially sized according to the amount of online CPUs and they are
+/// automatically extended when a task is dispatched to a CPU with a higher id (e.g., when a CPU
+/// is brought online via hotplug).
+pub struct CpuGapStats {
+    last_dispatch: Vec<Option<u64>>, // Timestamp of the last dispatch to each CPU
+    hist: Vec<[u64; NR_GAP_BUCKETS]>, // Per-CPU histogram of the current interval
+}
+
+impl CpuGapStats {
+    pub fn new(nr_cpus: usize) -> Self {
+        Self {
+            last_dispatch: vec![None; nr_cpus],
+            hist: vec![[0; NR_GAP_BUCKETS]; nr_cpus],
+        }
+    }
+
+    /// Account a dispatch to `cpu` happening at time `now` (in nanoseconds).
+    pub fn record(&mut self, cpu: usize, now: u64) {
+        if cpu >= self.last_dispatch.len() {
+            self.last_dispatch.resize(cpu + 1, None);
+            self.hist.resize(cpu + 1, [0; NR_GAP_BUCKETS]);
+        }
+        if let Some(prev) = self.last_dispatch[cpu] {
+            let gap = now.saturating_sub(prev);
+            let bucket = GAP_BUCKETS_NS
+                .iter()
+                .position(|&bound| gap < bound)
+                .unwrap_or(NR_GAP_BUCKETS - 1);
+            self.hist[cpu][bucket] += 1;
+        }
+        self.last_dispatch[cpu] = Some(now);
+    }
+
+    /// Print a compact summary of the per-CPU histograms and start a new interval.
+    pub fn report(&mut self) {
+        println!("cpu dispatch gaps ({}):", GAP_BUCKETS_LABEL);
+        for (line, hist) in self.hist.chunks(GAP_CPUS_PER_LINE).enumerate() {
+            let cpus: Vec<String> = hist
+                .iter()
+                .enumerate()
+                .map(|(i, buckets)| {
+                    let counts: Vec<String> = buckets.iter().map(|n| n.to_string()).collect();
+                    format!(
+                        "cpu{:<3} {:<24}",
+                        line * GAP_CPUS_PER_LINE + i,
+                        counts.join("/")
+                    )
+                })
+                .collect();
+            println!("  {}", cpus.join(" ").trim_end());
+        }
+
+        for buckets in self.hist.iter_mut() {
+            *buckets = [0; NR_GAP_BUCKETS];
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Result;
+
+use clap::ValueEnum;
+
+use crate::selftest;
+use crate::selftest::SimResult;
+use crate::selftest::SIM_NS;
+use crate::Opts;
+use crate::Policy;
+
+/// Grid of parameters evaluated by the sweep subcommand.
+///
+/// Every combination of the listed values is simulated against the selftest workload, all the
+/// other parameters are taken from the regular command line options.
+#[derive(Debug, Clone, clap::Args)]
+pub struct SweepOpts {
+    /// Policies to evaluate (comma-separated).
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "fifo,fair")]
+    policy: Vec<Policy>,
+
+    /// Maximum time slices (in microseconds) to evaluate (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "1000,5000,20000")]
+    slice_us: Vec<u64>,
+
+    /// Interactive classification thresholds (voluntary context switches per second) to evaluate
+    /// (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "5,10,20")]
+    nvcsw_thresh: Vec<u64>,
+}
+
+/// Jain's fairness index of the CPU time received by the hogs (weight and CPU time of each hog),
+/// normalized by their weight: 1.0 means that all the hogs received a share of CPU time exactly
+/// proportional to their weight.
+pub fn fairness(hogs: &[(u64, u64)]) -> f64 {
+    let shares: Vec<f64> = hogs
+        .iter()
+        .map(|&(weight, runtime)| runtime as f64 / weight.max(1) as f64)
+        .collect();
+    let sum: f64 = shares.iter().sum();
+    let sum_sq: f64 = shares.iter().map(|x| x * x).sum();
+
+    if sum_sq == 0.0 {
+        return 0.0;
+    }
+    sum * sum / (shares.len() as f64 * sum_sq)
+}
+
+/// Return the given percentile of a list of samples.
+pub fn percentile(samples: &mut [u64], pct: usize) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    samples.sort_unstable();
+
+    samples[(samples.len() - 1) * pct / 100]
+}
+
+/// Simulate all the combinations of the parameter grid and print the results in CSV format.
+pub fn run(opts: &Opts, sweep: &SweepOpts) -> Result<()> {
+    println!(
+        "policy,slice_us,nvcsw_thresh,fairness,dispatches_per_sec,\
+         avg_latency_us,p99_latency_us,violations"
+    );
+    for &policy in &sweep.policy {
+        for &slice_us in &sweep.slice_us {
+            for &nvcsw_thresh in &sweep.nvcsw_thresh {
+                let opts = Opts {
+                    policy,
+                    slice_us: slice_us.max(1),
+                    nvcsw_thresh,
+                    ..opts.clone()
+                };
+                let SimResult {
+                    violations,
+                    nr_dispatches,
+                    mut interactive_waits,
+                    hogs,
+                    ..
+                } = selftest::simulate(&opts);
+
+                let avg_latency_ns = interactive_waits
+                    .iter()
+                    .sum::<u64>()
+                    .checked_div(interactive_waits.len() as u64)
+                    .unwrap_or(0);
+                let p99_latency_ns = percentile(&mut interactive_waits, 99);
+
+                println!(
+                    "{},{},{},{:.3},{},{},{},{}",
+                    policy.to_possible_value().unwrap().get_name(),
+                    slice_us,
+                    nvcsw_thresh,
+                    fairness(&hogs),
+                    nr_dispatches * 1_000_000_000 / SIM_NS,
+                    avg_latency_ns / 1000,
+                    p99_latency_ns / 1000,
+                    violations.len(),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Service state notifications to systemd (see sd_notify(3)).
+//!
+//! When the scheduler runs as a systemd service (Type=notify), it reports READY=1 once the BPF
+//! component has been initialized, WATCHDOG=1 heartbeats if the unit has a watchdog
+//! (WatchdogSec=) and STOPPING=1 when it shuts down, e.g.:
+//!
+//!   [Service]
+//!   Type=notify
+//!   ExecStart=/usr/bin/scx_rust_scheduler
+//!   WatchdogSec=10
+//!   Restart=on-failure
+//!
+//! The notifications are enabled only when NOTIFY_SOCKET is set (i.e., when the scheduler is
+//! started by systemd): otherwise nothing is sent.
+
+use std::cell::Cell;
+use std::env;
+
+use sd_notify::NotifyState;
+
+/// Notifier of the service state.
+pub struct Notifier {
+    watchdog_ns: Option<u64>, // Interval between the heartbeats (None = no watchdog)
+    last_ts: Cell<u64>,       // Time of the last notification
+}
+
+impl Notifier {
+    /// Return the notifier if the scheduler is running under systemd (NOTIFY_SOCKET is set).
+    pub fn detect() -> Option<Self> {
+        env::var_os("NOTIFY_SOCKET")?;
+
+        // Send the heartbeats at twice the rate required by the watchdog, as recommended by
+        // sd_watchdog_enabled(3).
+        let mut usec = 0;
+        let watchdog_ns = sd_notify::watchdog_enabled(false, &mut usec).then_some(usec * 1000 / 2);
+
+        Some(Self {
+            watchdog_ns,
+            last_ts: Cell::new(0),
+        })
+    }
+
+    /// Report that the scheduler is ready (the BPF component has been initialized).
+    pub fn ready(&self, now: u64) {
+        self.notify(&[NotifyState::Ready]);
+        self.last_ts.set(now);
+    }
+
+    /// Send a heartbeat to the watchdog, if enabled and one is due at time `now`.
+    pub fn heartbeat(&self, now: u64) {
+        let Some(watchdog_ns) = self.watchdog_ns else {
+            return;
+        };
+        if now.saturating_sub(self.last_ts.get()) < watchdog_ns {
+            return;
+        }
+        self.notify(&[NotifyState::Watchdog]);
+        self.last_ts.set(now);
+    }
+
+    /// Report that the scheduler is shutting down.
+    pub fn stopping(&self) {
+        self.notify(&[NotifyState::Stopping]);
+    }
+
+    fn notify(&self, state: &[NotifyState]) {
+        if let Err(err) = sd_notify::notify(false, state) {
+            println!("WARNING: failed to notify systemd: {}", err);
+        }
+    }
+}
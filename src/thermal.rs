@@ -0,0 +1,113 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+
+use crate::hysteresis::Hysteresis;
+
+/// Parse the content of a hwmon temperature attribute (e.g.,
+/// /sys/class/hwmon/hwmon0/temp1_input), expressed in millidegrees Celsius, and return the
+/// temperature in degrees Celsius.
+pub fn parse_temp(text: &str) -> Option<i64> {
+    let millideg: i64 = text.trim().parse().ok()?;
+
+    Some(millideg / 1000)
+}
+
+/// Temperature monitor used to decide when idle time needs to be injected.
+///
+/// Idle injection starts when the temperature reaches `max_temp` and it stops when the
+/// temperature drops below `resume_temp` (the hysteresis prevents the scheduler from
+/// continuously switching between the two states around the threshold). The idle injection
+/// always starts immediately, while stopping it is also filtered by `hysteresis` (only its
+/// minimum time between changes is meaningful here, the temperature deadband is already defined
+/// by the two thresholds).
+///
+/// If the sensor can't be read, idle injection is disabled (a warning is printed every time the
+/// sensor becomes unavailable), so a missing sensor never affects the scheduling activity.
+pub struct Thermal {
+    path: String,           // hwmon temperature attribute
+    max_temp: i64,          // Temperature (in Celsius) that triggers the idle injection
+    resume_temp: i64,       // Temperature (in Celsius) that stops the idle injection
+    throttled: bool,        // Idle injection active
+    available: bool,        // Sensor available (last read succeeded)
+    hysteresis: Hysteresis, // Anti-flapping filter of the idle injection
+}
+
+impl Thermal {
+    pub fn new(path: &str, max_temp: i64, resume_temp: i64, hysteresis: Hysteresis) -> Self {
+        Self {
+            path: path.to_string(),
+            max_temp,
+            resume_temp: resume_temp.min(max_temp),
+            throttled: false,
+            available: true,
+            hysteresis,
+        }
+    }
+
+    /// Read the temperature and update the idle injection state at time `now`.
+    pub fn update(&mut self, now: u64) {
+        let temp = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|text| parse_temp(&text));
+
+        let Some(temp) = temp else {
+            if self.available {
+                eprintln!(
+                    "WARNING: failed to read temperature from {}, idle injection disabled",
+                    self.path
+                );
+            }
+            self.available = false;
+            self.throttled = false;
+            return;
+        };
+        self.available = true;
+
+        if !self.throttled && temp >= self.max_temp {
+            println!(
+                "temperature {}C >= {}C: injecting idle time",
+                temp, self.max_temp
+            );
+            self.throttled = true;
+            self.hysteresis.force(now, temp.max(0) as u64);
+        } else if self.throttled
+            && temp < self.resume_temp
+            && self.hysteresis.allow(now, temp.max(0) as u64)
+        {
+            println!(
+                "temperature {}C < {}C: stop injecting idle time",
+                temp, self.resume_temp
+            );
+            self.throttled = false;
+        }
+    }
+
+    /// Return true if idle time needs to be injected.
+    pub fn throttled(&self) -> bool {
+        self.throttled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_millidegrees() {
+        for (text, expected) in [
+            ("45000", Some(45)),
+            ("45999\n", Some(45)),
+            (" 0\n", Some(0)),
+            ("-5000\n", Some(-5)),
+            ("", None),
+            ("garbage\n", None),
+            ("45.5", None),
+        ] {
+            assert_eq!(parse_temp(text), expected, "'{}'", text.escape_debug());
+        }
+    }
+}
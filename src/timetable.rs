@@ -0,0 +1,139 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Time-of-day schedule of the named profiles (see --profile-at), for the systems with
+//! predictable workload cycles, e.g., batch jobs at night and interactive sessions by day:
+//!
+//!   $ scx_rust_scheduler --profile night:policy=fifo,slice-us=20000 \
+//!                        --profile-at 22:00=night --profile-at 07:30=default
+//!
+//! Each entry activates a profile (defined with --profile, or `default` for the command line
+//! settings) at a time of the day, in local time, every day. The day is divided in slots, from
+//! each entry to the next one (the last slot wraps around midnight to the first entry), and the
+//! profile of the current slot is activated at startup and every time the wall clock enters a
+//! new slot.
+//!
+//! The time of the day is only checked once per second (the interval is measured with the
+//! monotonic clock, like all the other intervals of the scheduler), while the slot is always
+//! determined by the wall clock, so that a jump of the wall clock (e.g., a DST change or an NTP
+//! step) activates the profile of the new slot, without replaying the entries that have been
+//! skipped. A profile activated manually (control socket or SIGUSR2) stays active until the next
+//! slot begins.
+//!
+//! The wall clock is read through the backend (see SchedBackend::local_minute()), so that the
+//! time of the day is recorded in the traces and a replay follows the slots of the recorded
+//! session, whenever it is replayed.
+
+use std::fmt;
+
+/// Minutes in a day.
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Entry of the schedule: activate the profile `name` at `minute` (minutes since midnight).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedProfile {
+    pub minute: u32,  // Time of the day (minutes since midnight, local time)
+    pub name: String, // Profile activated at that time
+}
+
+impl fmt::Display for TimedProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.minute / 60, self.minute % 60)
+    }
+}
+
+/// Parse an entry in the format HH:MM=NAME (24-hour clock).
+pub fn parse(text: &str) -> Result<TimedProfile, String> {
+    let Some((time, name)) = text.split_once('=') else {
+        return Err(format!("expected HH:MM=NAME, found '{}'", text));
+    };
+    let invalid_time = || format!("invalid time of the day '{}' (HH:MM)", time);
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid_time)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid_time())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid_time())?;
+    if hours >= 24 || minutes >= 60 {
+        return Err(invalid_time());
+    }
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("missing profile name in '{}'", text));
+    }
+
+    Ok(TimedProfile {
+        minute: hours * 60 + minutes,
+        name: name.to_string(),
+    })
+}
+
+/// Check that each time of the day is scheduled only once and that every entry activates one of
+/// the profiles in `names`.
+pub fn validate(entries: &[TimedProfile], names: &[&str]) -> Result<(), String> {
+    for (i, entry) in entries.iter().enumerate() {
+        if !names.contains(&entry.name.as_str()) {
+            return Err(format!(
+                "unknown profile '{}' at {} (available: {})",
+                entry.name,
+                entry,
+                names.join(", ")
+            ));
+        }
+        if entries[..i].iter().any(|e| e.minute == entry.minute) {
+            return Err(format!("{} scheduled more than once", entry));
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the current time of the day of the wall clock (minutes since midnight, local time).
+pub fn local_minute() -> u32 {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+
+        (tm.tm_hour * 60 + tm.tm_min) as u32
+    }
+}
+
+/// Schedule of the profiles by time of the day.
+pub struct Timetable {
+    entries: Vec<TimedProfile>, // Entries of the schedule, by time of the day
+    slot: Option<usize>,        // Entry of the current slot (None = not evaluated yet)
+}
+
+impl Timetable {
+    pub fn new(entries: &[TimedProfile]) -> Self {
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| entry.minute);
+
+        Self {
+            entries,
+            slot: None,
+        }
+    }
+
+    /// Evaluate the schedule at the time of the day `minute` (minutes since midnight) and
+    /// return the entry whose profile needs to be activated, if a new slot has begun since the
+    /// last evaluation (or at the first evaluation).
+    pub fn update(&mut self, minute: u32) -> Option<&TimedProfile> {
+        let minute = minute % MINUTES_PER_DAY;
+        // Before the first entry of the day, the last entry of the previous day still applies.
+        let slot = match self
+            .entries
+            .iter()
+            .rposition(|entry| entry.minute <= minute)
+        {
+            Some(slot) => slot,
+            None => self.entries.len().checked_sub(1)?,
+        };
+        if self.slot == Some(slot) {
+            return None;
+        }
+        self.slot = Some(slot);
+
+        self.entries.get(slot)
+    }
+}
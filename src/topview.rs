@@ -0,0 +1,150 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::io::Write;
+
+// Width (in characters) of a load bar.
+const BAR_WIDTH: usize = 50;
+
+// Amount of CPUs reported on each line of the plain output (when stdout is not a terminal).
+const LOAD_CPUS_PER_LINE: usize = 8;
+
+/// Live view of the per-CPU load (see --top-view).
+///
+/// The load of a CPU is the sum of the time slices assigned to the tasks dispatched to it in an
+/// interval, relative to the duration of the interval (tasks dispatched to the first CPU
+/// available are reported separately, as a total amount of CPUs).
+///
+/// On a terminal, the load bars are drawn in the first lines of the screen, that are excluded
+/// from the scrolling region, so that the regular output keeps scrolling below them, and they
+/// are redrawn in place at every interval. Otherwise, the loads are printed as regular lines.
+pub struct TopView<W: Write> {
+    out: W,                // Output (usually stdout)
+    tty: bool,             // Draw the load bars in place
+    assigned_ns: Vec<u64>, // Time slices assigned to each CPU in the current interval
+    any_ns: u64,           // Time slices assigned to the first CPU available
+    interval_ts: u64,      // Beginning of the current interval
+    nr_lines: usize,       // Lines reserved to the load bars on the terminal
+}
+
+impl<W: Write> TopView<W> {
+    pub fn new(out: W, tty: bool, nr_cpus: usize, now: u64) -> Self {
+        Self {
+            out,
+            tty,
+            assigned_ns: vec![0; nr_cpus],
+            any_ns: 0,
+            interval_ts: now,
+            nr_lines: 0,
+        }
+    }
+
+    /// Account a time slice of `slice_ns` assigned to `cpu` (None = first CPU available).
+    pub fn record(&mut self, cpu: Option<usize>, slice_ns: u64) {
+        match cpu {
+            Some(cpu) => {
+                if cpu >= self.assigned_ns.len() {
+                    self.assigned_ns.resize(cpu + 1, 0);
+                }
+                self.assigned_ns[cpu] += slice_ns;
+            }
+            None => self.any_ns += slice_ns,
+        }
+    }
+
+    /// Draw the loads of the interval ending at time `now` and start a new interval.
+    pub fn draw(&mut self, now: u64) -> std::io::Result<()> {
+        let elapsed = now.saturating_sub(self.interval_ts).max(1);
+        let load = |ns: u64| (ns as u128 * 1000 / elapsed as u128) as u64;
+        let loads: Vec<u64> = self.assigned_ns.iter().map(|&ns| load(ns)).collect();
+        let any = load(self.any_ns);
+
+        if self.tty {
+            self.draw_bars(&loads, any)?;
+        } else {
+            self.draw_lines(&loads, any)?;
+        }
+        self.out.flush()?;
+
+        self.assigned_ns.iter_mut().for_each(|ns| *ns = 0);
+        self.any_ns = 0;
+        self.interval_ts = now;
+
+        Ok(())
+    }
+
+    // Redraw the load bars (loads in 1/1000 of a CPU) at the top of the terminal.
+    fn draw_bars(&mut self, loads: &[u64], any: u64) -> std::io::Result<()> {
+        // Reserve the lines of the bars (one per CPU, plus the first CPU available), excluding
+        // them from the scrolling region, the first time and when the amount of CPUs changes.
+        let nr_lines = loads.len() + 1;
+        if nr_lines != self.nr_lines {
+            write!(
+                self.out,
+                "\x1b[2J\x1b[{};r\x1b[{};1H",
+                nr_lines + 1,
+                nr_lines + 1
+            )?;
+            self.nr_lines = nr_lines;
+        }
+
+        // Save the cursor of the regular output, draw the bars from the top-left corner of the
+        // screen, then restore the cursor.
+        write!(self.out, "\x1b7\x1b[H")?;
+        for (cpu, &load) in loads.iter().enumerate() {
+            let filled = (load as usize * BAR_WIDTH / 1000).min(BAR_WIDTH);
+            writeln!(
+                self.out,
+                "cpu{:<3} [{}{}] {:>3}.{}%\x1b[K",
+                cpu,
+                "|".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled),
+                load / 10,
+                load % 10
+            )?;
+        }
+        write!(
+            self.out,
+            "any    {}.{:02} CPUs\x1b[K\x1b8",
+            any / 1000,
+            any % 1000 / 10
+        )
+    }
+
+    // Print the loads (in 1/1000 of a CPU) as regular lines.
+    fn draw_lines(&mut self, loads: &[u64], any: u64) -> std::io::Result<()> {
+        for (line, loads) in loads.chunks(LOAD_CPUS_PER_LINE).enumerate() {
+            let cpus: Vec<String> = loads
+                .iter()
+                .enumerate()
+                .map(|(i, &load)| {
+                    format!(
+                        "cpu{:<3} {:>3}.{}%",
+                        line * LOAD_CPUS_PER_LINE + i,
+                        load / 10,
+                        load % 10
+                    )
+                })
+                .collect();
+            writeln!(self.out, "cpu load: {}", cpus.join(" | "))?;
+        }
+        writeln!(
+            self.out,
+            "cpu load: any {}.{:02} CPUs",
+            any / 1000,
+            any % 1000 / 10
+        )
+    }
+}
+
+impl<W: Write> Drop for TopView<W> {
+    fn drop(&mut self) {
+        // Give the whole screen back to the regular output, moving the cursor to the bottom.
+        if self.nr_lines > 0 {
+            let _ = write!(self.out, "\x1b[r\x1b[999;1H");
+            let _ = self.out.flush();
+        }
+    }
+}
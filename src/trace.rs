@@ -0,0 +1,648 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Task-event traces (see --record and the replay subcommand).
+//!
+//! A trace captures every interaction between the scheduling policy and the backend: the inputs
+//! (tasks received, CPUs returned by select_cpu(), timestamps and statistics) and the outputs
+//! (dispatched tasks), so that the scheduling decisions can be reproduced offline by feeding
+//! the inputs back to the policy (see replay.rs).
+//!
+//! A trace is a text file: a header line (TRACE_HEADER), the command line of the recorded
+//! session (one `arg <value>` line per argument) and then one event per line:
+//!
+//!   now <ns>
+//!   selfcpu <ns>
+//!   dequeue <pid> <cpu> <flags> <sum_exec_runtime> <nvcsw> <weight> <slice> <vtime>
+//!   dequeue none
+//!   dequeue error <code>
+//!   select <pid> <prev_cpu> <flags> <cpu>
+//!   dispatch <pid> <cpu> <flags> <slice_ns> <vtime> ok|busy|fatal
+//!   notify <nr_pending>
+//!   exited 0|1
+//!   exists <pid> 0|1
+//!   tgid <pid> <tgid>|-
+//!   allowed <pid> <cpu,...>|-
+//!   parent <pid> <cpu>|-
+//!   kthread <pid> 0|1|-
+//!   waker <pid> <pid>|-
+//!   hint <pid> <name> <value>|-
+//!   comm <pid> =<comm>|-  (the comm of the task is the rest of the line, it may contain spaces)
+//!   isolated <cpu,...>|-
+//!   minute <minutes since midnight>
+//!   counter <name> <value>
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::backend::Dispatch;
+use crate::backend::DispatchError;
+use crate::backend::SchedBackend;
+use crate::backend::Task;
+
+// First line of a trace (increase the version every time the format changes).
+const TRACE_HEADER: &str = "scx_rust_scheduler trace 9";
+
+/// Statistic of the backend read by the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    OnlineCpus,
+    Queued,
+    UserDispatches,
+    KernelDispatches,
+    BounceDispatches,
+    CancelDispatches,
+    FailedDispatches,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 7] = [
+        Counter::OnlineCpus,
+        Counter::Queued,
+        Counter::UserDispatches,
+        Counter::KernelDispatches,
+        Counter::BounceDispatches,
+        Counter::CancelDispatches,
+        Counter::FailedDispatches,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Counter::OnlineCpus => "online_cpus",
+            Counter::Queued => "queued",
+            Counter::UserDispatches => "user_dispatches",
+            Counter::KernelDispatches => "kernel_dispatches",
+            Counter::BounceDispatches => "bounce_dispatches",
+            Counter::CancelDispatches => "cancel_dispatches",
+            Counter::FailedDispatches => "failed_dispatches",
+        }
+    }
+}
+
+/// Result of a dispatch attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Busy,
+    Fatal,
+}
+
+impl Outcome {
+    fn of(result: &Result<(), DispatchError>) -> Self {
+        match result {
+            Ok(()) => Outcome::Ok,
+            Err(DispatchError::Busy) => Outcome::Busy,
+            Err(DispatchError::Fatal(_)) => Outcome::Fatal,
+        }
+    }
+}
+
+/// Interaction between the policy and the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Now(u64),                             // now_ns()
+    SelfCpu(u64),                         // self_cpu_ns()
+    Dequeue(Result<Option<Task>, i32>),   // dequeue_task()
+    SelectCpu(i32, i32, u64, i32),        // select_cpu() (pid, prev_cpu, flags, result)
+    Dispatch(Dispatch, Outcome),          // dispatch_task()
+    Notify(u64),                          // notify_complete()
+    Exited(bool),                         // exited()
+    Exists(i32, bool),                    // exists()
+    Tgid(i32, Option<i32>),               // tgid()
+    AllowedCpus(i32, Option<Vec<usize>>), // allowed_cpus()
+    ParentCpu(i32, Option<i32>),          // parent_cpu()
+    Kthread(i32, Option<bool>),           // is_kthread()
+    Waker(i32, Option<i32>),              // waker()
+    EnvHint(i32, String, Option<u64>),    // env_hint()
+    Comm(i32, Option<String>),            // comm()
+    OnlineCpus(Option<Vec<usize>>),       // online_cpus()
+    IsolatedCpus(Option<Vec<usize>>),     // isolated_cpus()
+    LocalMinute(u32),                     // local_minute()
+    Counter(Counter, u64),                // nr_*_mut()
+}
+
+impl Event {
+    /// Return true if the event is a query without side effects (timestamps and statistics),
+    /// that the policy may issue a different amount of times, e.g., depending on the
+    /// interactions with the metrics endpoint or the control socket.
+    pub fn is_query(&self) -> bool {
+        matches!(self, Event::Now(_) | Event::SelfCpu(_) | Event::Counter(..))
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Now(ns) => write!(f, "now {}", ns),
+            Event::SelfCpu(ns) => write!(f, "selfcpu {}", ns),
+            Event::Dequeue(Ok(Some(t))) => write!(
+                f,
+                "dequeue {} {} {} {} {} {} {} {}",
+                t.pid, t.cpu, t.flags, t.sum_exec_runtime, t.nvcsw, t.weight, t.slice, t.vtime
+            ),
+            Event::Dequeue(Ok(None)) => write!(f, "dequeue none"),
+            Event::Dequeue(Err(code)) => write!(f, "dequeue error {}", code),
+            Event::SelectCpu(pid, prev_cpu, flags, cpu) => {
+                write!(f, "select {} {} {} {}", pid, prev_cpu, flags, cpu)
+            }
+            Event::Dispatch(d, outcome) => {
+                let outcome = match outcome {
+                    Outcome::Ok => "ok",
+                    Outcome::Busy => "busy",
+                    Outcome::Fatal => "fatal",
+                };
+                write!(
+                    f,
+                    "dispatch {} {} {} {} {} {}",
+                    d.pid, d.cpu, d.flags, d.slice_ns, d.vtime, outcome
+                )
+            }
+            Event::Notify(nr_pending) => write!(f, "notify {}", nr_pending),
+            Event::Exited(exited) => write!(f, "exited {}", *exited as u8),
+            Event::Exists(pid, exists) => write!(f, "exists {} {}", pid, *exists as u8),
+            Event::Tgid(pid, Some(tgid)) => write!(f, "tgid {} {}", pid, tgid),
+            Event::Tgid(pid, None) => write!(f, "tgid {} -", pid),
+            Event::ParentCpu(pid, Some(cpu)) => write!(f, "parent {} {}", pid, cpu),
+            Event::ParentCpu(pid, None) => write!(f, "parent {} -", pid),
+            Event::Kthread(pid, Some(kthread)) => write!(f, "kthread {} {}", pid, *kthread as u8),
+            Event::Kthread(pid, None) => write!(f, "kthread {} -", pid),
+            Event::Waker(pid, Some(waker)) => write!(f, "waker {} {}", pid, waker),
+            Event::Waker(pid, None) => write!(f, "waker {} -", pid),
+            Event::AllowedCpus(pid, Some(cpus)) => {
+                let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+                write!(f, "allowed {} {}", pid, cpus.join(","))
+            }
+            Event::AllowedCpus(pid, None) => write!(f, "allowed {} -", pid),
+            Event::EnvHint(pid, name, Some(value)) => write!(f, "hint {} {} {}", pid, name, value),
+            Event::EnvHint(pid, name, None) => write!(f, "hint {} {} -", pid, name),
+            Event::Comm(pid, Some(comm)) => write!(f, "comm {} ={}", pid, comm),
+            Event::Comm(pid, None) => write!(f, "comm {} -", pid),
+            Event::OnlineCpus(Some(cpus)) => {
+                let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+                write!(f, "online {}", cpus.join(","))
+            }
+            Event::OnlineCpus(None) => write!(f, "online -"),
+            Event::IsolatedCpus(Some(cpus)) => {
+                let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+                write!(f, "isolated {}", cpus.join(","))
+            }
+            Event::IsolatedCpus(None) => write!(f, "isolated -"),
+            Event::LocalMinute(minute) => write!(f, "minute {}", minute),
+            Event::Counter(counter, value) => write!(f, "counter {} {}", counter.name(), value),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let field = |i: usize| fields.get(i).copied().ok_or("missing field".to_string());
+        fn num<T: FromStr>(value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", value))
+        }
+
+        let event = match field(0)? {
+            "now" => Event::Now(num(field(1)?)?),
+            "selfcpu" => Event::SelfCpu(num(field(1)?)?),
+            "dequeue" => match field(1)? {
+                "none" => Event::Dequeue(Ok(None)),
+                "error" => Event::Dequeue(Err(num(field(2)?)?)),
+                _ => Event::Dequeue(Ok(Some(Task {
+                    pid: num(field(1)?)?,
+                    cpu: num(field(2)?)?,
+                    flags: num(field(3)?)?,
+                    sum_exec_runtime: num(field(4)?)?,
+                    nvcsw: num(field(5)?)?,
+                    weight: num(field(6)?)?,
+                    slice: num(field(7)?)?,
+                    vtime: num(field(8)?)?,
+                }))),
+            },
+            "select" => Event::SelectCpu(
+                num(field(1)?)?,
+                num(field(2)?)?,
+                num(field(3)?)?,
+                num(field(4)?)?,
+            ),
+            "dispatch" => {
+                let outcome = match field(6)? {
+                    "ok" => Outcome::Ok,
+                    "busy" => Outcome::Busy,
+                    "fatal" => Outcome::Fatal,
+                    other => return Err(format!("invalid dispatch outcome '{}'", other)),
+                };
+                let dispatch = Dispatch {
+                    pid: num(field(1)?)?,
+                    cpu: num(field(2)?)?,
+                    flags: num(field(3)?)?,
+                    slice_ns: num(field(4)?)?,
+                    vtime: num(field(5)?)?,
+                };
+                Event::Dispatch(dispatch, outcome)
+            }
+            "notify" => Event::Notify(num(field(1)?)?),
+            "exited" => Event::Exited(num::<u8>(field(1)?)? != 0),
+            "exists" => Event::Exists(num(field(1)?)?, num::<u8>(field(2)?)? != 0),
+            "tgid" => match field(2)? {
+                "-" => Event::Tgid(num(field(1)?)?, None),
+                tgid => Event::Tgid(num(field(1)?)?, Some(num(tgid)?)),
+            },
+            "parent" => match field(2)? {
+                "-" => Event::ParentCpu(num(field(1)?)?, None),
+                cpu => Event::ParentCpu(num(field(1)?)?, Some(num(cpu)?)),
+            },
+            "kthread" => match field(2)? {
+                "-" => Event::Kthread(num(field(1)?)?, None),
+                kthread => Event::Kthread(num(field(1)?)?, Some(num::<u8>(kthread)? != 0)),
+            },
+            "waker" => match field(2)? {
+                "-" => Event::Waker(num(field(1)?)?, None),
+                waker => Event::Waker(num(field(1)?)?, Some(num(waker)?)),
+            },
+            "allowed" => match fields.get(2).copied().unwrap_or("") {
+                "-" => Event::AllowedCpus(num(field(1)?)?, None),
+                cpus => {
+                    let cpus = cpus
+                        .split(',')
+                        .filter(|cpu| !cpu.is_empty())
+                        .map(num)
+                        .collect::<Result<_, _>>()?;
+                    Event::AllowedCpus(num(field(1)?)?, Some(cpus))
+                }
+            },
+            "hint" => match field(3)? {
+                "-" => Event::EnvHint(num(field(1)?)?, field(2)?.to_string(), None),
+                value => Event::EnvHint(num(field(1)?)?, field(2)?.to_string(), Some(num(value)?)),
+            },
+            "comm" => match line
+                .splitn(3, ' ')
+                .nth(2)
+                .ok_or("missing field".to_string())?
+            {
+                "-" => Event::Comm(num(field(1)?)?, None),
+                comm => match comm.strip_prefix('=') {
+                    Some(comm) => Event::Comm(num(field(1)?)?, Some(comm.to_string())),
+                    None => return Err(format!("invalid comm '{}'", comm)),
+                },
+            },
+            "online" => match fields.get(1).copied().unwrap_or("") {
+                "-" => Event::OnlineCpus(None),
+                cpus => {
+                    let cpus = cpus
+                        .split(',')
+                        .filter(|cpu| !cpu.is_empty())
+                        .map(num)
+                        .collect::<Result<_, _>>()?;
+                    Event::OnlineCpus(Some(cpus))
+                }
+            },
+            "isolated" => match fields.get(1).copied().unwrap_or("") {
+                "-" => Event::IsolatedCpus(None),
+                cpus => {
+                    let cpus = cpus
+                        .split(',')
+                        .filter(|cpu| !cpu.is_empty())
+                        .map(num)
+                        .collect::<Result<_, _>>()?;
+                    Event::IsolatedCpus(Some(cpus))
+                }
+            },
+            "minute" => Event::LocalMinute(num(field(1)?)?),
+            "counter" => {
+                let name = field(1)?;
+                let Some(counter) = Counter::ALL.into_iter().find(|c| c.name() == name) else {
+                    return Err(format!("unknown counter '{}'", name));
+                };
+                Event::Counter(counter, num(field(2)?)?)
+            }
+            other => return Err(format!("unknown event '{}'", other)),
+        };
+
+        Ok(event)
+    }
+}
+
+/// Trace loaded from a file.
+pub struct Trace {
+    pub args: Vec<String>,  // Command line of the recorded session
+    pub events: Vec<Event>, // Recorded events
+}
+
+/// Write the header of a trace, including the command line of the recorded session.
+pub fn write_header(out: &mut impl Write, args: &[String]) -> io::Result<()> {
+    writeln!(out, "{}", TRACE_HEADER)?;
+    for arg in args {
+        writeln!(out, "arg {}", arg)?;
+    }
+
+    Ok(())
+}
+
+/// Create the trace file `path` (see --record), recording the command line of the current
+/// process.
+pub fn create(path: &str) -> Result<BufWriter<File>> {
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+    let mut out = BufWriter::new(file);
+    let args: Vec<String> = std::env::args().collect();
+    write_header(&mut out, &args).with_context(|| format!("Failed to write {}", path))?;
+
+    Ok(out)
+}
+
+/// Parse a trace.
+pub fn parse(text: &str) -> Result<Trace> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line) != Some(TRACE_HEADER) {
+        bail!(
+            "not a trace (expected '{}' as the first line)",
+            TRACE_HEADER
+        );
+    }
+    let mut trace = Trace {
+        args: Vec::new(),
+        events: Vec::new(),
+    };
+
+    for (i, line) in lines {
+        if let Some(arg) = line.strip_prefix("arg ") {
+            trace.args.push(arg.to_string());
+            continue;
+        }
+        match line.parse() {
+            Ok(event) => trace.events.push(event),
+            Err(err) => bail!("line {}: {} ({})", i + 1, err, line),
+        }
+    }
+
+    Ok(trace)
+}
+
+// Output of a recorder.
+struct TraceOut<W: Write> {
+    out: W,                   // Destination of the events
+    error: Option<io::Error>, // First write error (the following events are dropped)
+}
+
+// Append an event to the trace (if recording).
+fn record<W: Write>(trace: &Option<RefCell<TraceOut<W>>>, event: impl FnOnce() -> Event) {
+    let Some(trace) = trace else {
+        return;
+    };
+    let mut trace = trace.borrow_mut();
+    if trace.error.is_none() {
+        if let Err(err) = writeln!(trace.out, "{}", event()) {
+            trace.error = Some(err);
+        }
+    }
+}
+
+/// Backend that records all the interactions with an inner backend into a trace (see
+/// --record), without altering them.
+///
+/// The trace is written to `W` while the scheduler runs; without a destination the recorder is
+/// just a pass-through.
+pub struct Recorder<B: SchedBackend, W: Write> {
+    inner: B,                            // Recorded backend
+    trace: Option<RefCell<TraceOut<W>>>, // Trace destination (None = not recording)
+}
+
+impl<B: SchedBackend, W: Write> Recorder<B, W> {
+    pub fn new(inner: B, out: Option<W>) -> Self {
+        Self {
+            inner,
+            trace: out.map(|out| RefCell::new(TraceOut { out, error: None })),
+        }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Stop recording: flush the trace and return its destination, or the first error hit while
+    /// writing it.
+    pub fn finish(&mut self) -> io::Result<Option<W>> {
+        let Some(trace) = self.trace.take() else {
+            return Ok(None);
+        };
+        let mut trace = trace.into_inner();
+        if let Some(err) = trace.error {
+            return Err(err);
+        }
+        trace.out.flush()?;
+
+        Ok(Some(trace.out))
+    }
+}
+
+impl<B: SchedBackend, W: Write> SchedBackend for Recorder<B, W> {
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32> {
+        let result = self.inner.dequeue_task();
+        record(&self.trace, || Event::Dequeue(result.clone()));
+
+        result
+    }
+
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, flags: u64) -> i32 {
+        let cpu = self.inner.select_cpu(pid, prev_cpu, flags);
+        record(&self.trace, || Event::SelectCpu(pid, prev_cpu, flags, cpu));
+
+        cpu
+    }
+
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError> {
+        let result = self.inner.dispatch_task(task);
+        record(&self.trace, || {
+            Event::Dispatch(task.clone(), Outcome::of(&result))
+        });
+
+        result
+    }
+
+    fn notify_complete(&mut self, nr_pending: u64) {
+        record(&self.trace, || Event::Notify(nr_pending));
+        self.inner.notify_complete(nr_pending);
+    }
+
+    fn exited(&mut self) -> bool {
+        let exited = self.inner.exited();
+        record(&self.trace, || Event::Exited(exited));
+
+        exited
+    }
+
+    fn exists(&mut self, pid: i32) -> bool {
+        let exists = self.inner.exists(pid);
+        record(&self.trace, || Event::Exists(pid, exists));
+
+        exists
+    }
+
+    fn tgid(&mut self, pid: i32) -> Option<i32> {
+        let tgid = self.inner.tgid(pid);
+        record(&self.trace, || Event::Tgid(pid, tgid));
+
+        tgid
+    }
+
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32> {
+        let cpu = self.inner.parent_cpu(pid);
+        record(&self.trace, || Event::ParentCpu(pid, cpu));
+
+        cpu
+    }
+
+    fn is_kthread(&mut self, pid: i32) -> Option<bool> {
+        let kthread = self.inner.is_kthread(pid);
+        record(&self.trace, || Event::Kthread(pid, kthread));
+
+        kthread
+    }
+
+    fn waker(&mut self, pid: i32) -> Option<i32> {
+        let waker = self.inner.waker(pid);
+        record(&self.trace, || Event::Waker(pid, waker));
+
+        waker
+    }
+
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>> {
+        let cpus = self.inner.allowed_cpus(pid);
+        record(&self.trace, || Event::AllowedCpus(pid, cpus.clone()));
+
+        cpus
+    }
+
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64> {
+        let value = self.inner.env_hint(pid, name);
+        record(&self.trace, || Event::EnvHint(pid, name.to_string(), value));
+
+        value
+    }
+
+    fn comm(&mut self, pid: i32) -> Option<String> {
+        let comm = self.inner.comm(pid);
+        record(&self.trace, || Event::Comm(pid, comm.clone()));
+
+        comm
+    }
+
+    // The start time of the tasks is not recorded: it is only used by the metrics, it never
+    // affects the scheduling decisions.
+    fn run_start_ns(&mut self, pid: i32) -> Option<u64> {
+        self.inner.run_start_ns(pid)
+    }
+
+    // The priority of the scheduler is not recorded: it never affects the scheduling decisions.
+    fn boost_self(&mut self) -> bool {
+        self.inner.boost_self()
+    }
+
+    fn online_cpus(&mut self) -> Option<Vec<usize>> {
+        let cpus = self.inner.online_cpus();
+        record(&self.trace, || Event::OnlineCpus(cpus.clone()));
+
+        cpus
+    }
+
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>> {
+        let cpus = self.inner.isolated_cpus();
+        record(&self.trace, || Event::IsolatedCpus(cpus.clone()));
+
+        cpus
+    }
+
+    fn local_minute(&mut self) -> u32 {
+        let minute = self.inner.local_minute();
+        record(&self.trace, || Event::LocalMinute(minute));
+
+        minute
+    }
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_online_cpus_mut();
+        record(&self.trace, || Event::Counter(Counter::OnlineCpus, *value));
+
+        value
+    }
+
+    fn nr_queued_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_queued_mut();
+        record(&self.trace, || Event::Counter(Counter::Queued, *value));
+
+        value
+    }
+
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_user_dispatches_mut();
+        record(&self.trace, || {
+            Event::Counter(Counter::UserDispatches, *value)
+        });
+
+        value
+    }
+
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_kernel_dispatches_mut();
+        record(&self.trace, || {
+            Event::Counter(Counter::KernelDispatches, *value)
+        });
+
+        value
+    }
+
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_bounce_dispatches_mut();
+        record(&self.trace, || {
+            Event::Counter(Counter::BounceDispatches, *value)
+        });
+
+        value
+    }
+
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_cancel_dispatches_mut();
+        record(&self.trace, || {
+            Event::Counter(Counter::CancelDispatches, *value)
+        });
+
+        value
+    }
+
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64 {
+        let value = self.inner.nr_failed_dispatches_mut();
+        record(&self.trace, || {
+            Event::Counter(Counter::FailedDispatches, *value)
+        });
+
+        value
+    }
+
+    fn now_ns(&self) -> u64 {
+        let now = self.inner.now_ns();
+        record(&self.trace, || Event::Now(now));
+
+        now
+    }
+
+    fn self_cpu_ns(&self) -> u64 {
+        let cpu_ns = self.inner.self_cpu_ns();
+        record(&self.trace, || Event::SelfCpu(cpu_ns));
+
+        cpu_ns
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Push of the stats to a collector over UDP (see --stats-udp), for a fleet-wide aggregation
+//! without scraping each host, e.g.:
+//!
+//!   $ scx_rust_scheduler --stats-udp collector.example.com:8125
+//!
+//! At every stats interval, the stats are sent as a single datagram, containing the same JSON
+//! object served by the dashboard (see --metrics-dashboard), with the additional members:
+//!
+//!   - `host`: host name of the system (see /proc/sys/kernel/hostname)
+//!   - `udp_send_errors`: datagrams that couldn't be sent so far
+//!
+//! The push is fire-and-forget: the socket never blocks the scheduler, the datagrams that can't
+//! be sent (e.g., collector unreachable or socket buffer full) are dropped and only counted.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+// Host name of the system, used as the host identifier.
+const HOSTNAME: &str = "/proc/sys/kernel/hostname";
+
+/// Socket connected to the collector of the stats.
+pub struct UdpStats {
+    socket: UdpSocket, // Non-blocking socket connected to the collector
+    host: String,      // Host identifier included in each datagram
+    nr_errors: u64,    // Datagrams that couldn't be sent
+}
+
+impl UdpStats {
+    /// Resolve the collector `addr` (HOST:PORT) and connect a socket to it.
+    pub fn open(addr: &str) -> Result<Self> {
+        let collector = addr
+            .to_socket_addrs()
+            .with_context(|| format!("failed to resolve the stats collector {}", addr))?
+            .next()
+            .ok_or_else(|| anyhow!("no address found for the stats collector {}", addr))?;
+        let local: SocketAddr = match collector {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local).context("failed to create the stats socket")?;
+        socket
+            .connect(collector)
+            .with_context(|| format!("failed to connect to the stats collector {}", addr))?;
+        socket.set_nonblocking(true)?;
+
+        let host = fs::read_to_string(HOSTNAME).unwrap_or_default();
+        let host = match host.trim() {
+            "" => "unknown".to_string(),
+            host => host.to_string(),
+        };
+
+        Ok(Self {
+            socket,
+            host,
+            nr_errors: 0,
+        })
+    }
+
+    /// Return the members added to the stats (the host identifier and the send errors), as
+    /// formatted JSON values.
+    pub fn extra(&self) -> [(&'static str, String); 2] {
+        [
+            ("host", format!("{:?}", self.host)),
+            ("udp_send_errors", self.nr_errors.to_string()),
+        ]
+    }
+
+    /// Send a datagram with the stats `json`, counting the failures.
+    pub fn send(&mut self, json: &str) {
+        if self.socket.send(json.as_bytes()).is_err() {
+            self.nr_errors += 1;
+        }
+    }
+
+    /// Return the amount of datagrams that couldn't be sent.
+    pub fn nr_errors(&self) -> u64 {
+        self.nr_errors
+    }
+}
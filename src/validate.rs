@@ -0,0 +1,213 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Validation of the command line options without running the scheduler (see the validate
+//! subcommand), e.g., to check the options of a service before deploying it:
+//!
+//!   $ scx_rust_scheduler --policy fair --cpus-offline 6,7 --kernel-cpu 5 validate
+//!
+//! or of a configuration file (see config.rs), instead of the command line:
+//!
+//!   $ scx_rust_scheduler validate --config scheduler.toml
+//!
+//! The parser of the command line already rejects the invalid values (ranges, policy names, CPU
+//! lists), reported by line for a configuration file, then the options are checked against each
+//! other and against the CPUs of the system, reporting all the problems at once. The errors
+//! prevent the scheduler from starting, the warnings are settings that the scheduler adjusts or
+//! ignores when it starts (e.g., a CPU that doesn't exist), that are most likely mistakes.
+
+use std::fs;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config;
+use crate::cpulist;
+use crate::cpulist::CpuList;
+use crate::invariants;
+use crate::profiles;
+use crate::timetable;
+use crate::Mode;
+use crate::Opts;
+use crate::Policy;
+
+// CPUs that can ever be online in the system (the online ones can change).
+const POSSIBLE_CPUS: &str = "/sys/devices/system/cpu/possible";
+
+/// Options of the validate subcommand.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ValidateOpts {
+    /// Check the options defined in a configuration file (TOML, one key per long option, e.g.
+    /// `slice-us = 5000`) instead of the ones given on the command line.
+    #[clap(long)]
+    config: Option<String>,
+}
+
+/// Check the options that prevent the scheduler from starting.
+pub fn errors(opts: &Opts) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let reserved_pct =
+        opts.reserve_interactive_pct.unwrap_or(0.0) + opts.reserve_batch_pct.unwrap_or(0.0);
+    if reserved_pct > 100.0 {
+        errors.push(
+            "--reserve-interactive-pct and --reserve-batch-pct exceed 100% in total".to_string(),
+        );
+    }
+    if let Err(err) = profiles::validate(&opts.profile) {
+        errors.push(format!("--profile: {}", err));
+    }
+    if !opts.profile.is_empty() && opts.mode == Mode::Auto {
+        errors.push("--profile can't be used with --mode auto".to_string());
+    }
+    let names: Vec<&str> = std::iter::once(profiles::DEFAULT_PROFILE)
+        .chain(opts.profile.iter().map(|profile| profile.name.as_str()))
+        .collect();
+    if let Err(err) = timetable::validate(&opts.profile_at, &names) {
+        errors.push(format!("--profile-at: {}", err));
+    }
+
+    errors
+}
+
+/// Check the options that the scheduler adjusts or ignores when it starts, given the amount of
+/// CPUs of the system (if known).
+pub fn warnings(opts: &Opts, nr_cpus: Option<usize>) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if opts.slice_min_us > opts.slice_us {
+        warnings.push(format!(
+            "--slice-min-us {} is above --slice-us {}",
+            opts.slice_min_us, opts.slice_us
+        ));
+    }
+    if let Some(max_slice_us) = opts
+        .interactive_max_slice_us
+        .filter(|&max_slice_us| max_slice_us < opts.slice_min_us)
+    {
+        warnings.push(format!(
+            "--interactive-max-slice-us {} is below --slice-min-us {}",
+            max_slice_us, opts.slice_min_us
+        ));
+    }
+    // The profiles can switch to the edf policy at runtime.
+    if opts.deadline_slice_pct.is_some() && opts.policy != Policy::Edf && opts.profile.is_empty() {
+        warnings.push("--deadline-slice-pct is ignored without --policy edf".to_string());
+    }
+    match (opts.wakeup_gap_high_us, opts.wakeup_gap_low_us) {
+        (None, Some(_)) => {
+            warnings.push("--wakeup-gap-low-us is ignored without --wakeup-gap-high-us".to_string())
+        }
+        (Some(high_us), Some(low_us)) if low_us > high_us => warnings.push(format!(
+            "--wakeup-gap-low-us {} is above --wakeup-gap-high-us {}",
+            low_us, high_us
+        )),
+        _ => {}
+    }
+    if opts.thermal_sensor.is_some() && opts.thermal_resume_temp > opts.thermal_max_temp {
+        warnings.push(format!(
+            "--thermal-resume-temp {} is above --thermal-max-temp {}",
+            opts.thermal_resume_temp, opts.thermal_max_temp
+        ));
+    }
+    if opts.debug_invariants.is_some() && !invariants::COMPILED {
+        warnings.push(
+            "--debug-invariants is not available in this build (rebuild with --features \
+             invariants)"
+                .to_string(),
+        );
+    }
+
+    let offline = opts
+        .cpus_offline
+        .as_ref()
+        .map_or(&[][..], |CpuList(cpus)| cpus.as_slice());
+    if let Some(cpu) = opts.kernel_cpu.filter(|cpu| offline.contains(cpu)) {
+        warnings.push(format!(
+            "--kernel-cpu {} is also excluded by --cpus-offline",
+            cpu
+        ));
+    }
+    let Some(nr_cpus) = nr_cpus else {
+        return warnings;
+    };
+    for &cpu in offline.iter().filter(|&&cpu| cpu >= nr_cpus) {
+        warnings.push(format!(
+            "--cpus-offline: CPU {} doesn't exist (CPUs: 0-{})",
+            cpu,
+            nr_cpus - 1
+        ));
+    }
+    if let Some(cpu) = opts.kernel_cpu.filter(|&cpu| cpu >= nr_cpus) {
+        warnings.push(format!(
+            "--kernel-cpu {} doesn't exist (CPUs: 0-{}), it is ignored",
+            cpu,
+            nr_cpus - 1
+        ));
+    }
+    let is_excluded = |cpu| offline.contains(&cpu) || opts.kernel_cpu == Some(cpu);
+    if (0..nr_cpus).all(is_excluded) {
+        warnings.push(
+            "--cpus-offline and --kernel-cpu exclude all the CPUs, they are ignored".to_string(),
+        );
+    }
+
+    warnings
+}
+
+/// Return all the problems of the configuration, errors first.
+pub fn problems(opts: &Opts, nr_cpus: Option<usize>) -> Vec<String> {
+    let errors = errors(opts)
+        .into_iter()
+        .map(|err| format!("error: {}", err));
+    let warnings = warnings(opts, nr_cpus)
+        .into_iter()
+        .map(|warning| format!("warning: {}", warning));
+
+    errors.chain(warnings).collect()
+}
+
+// Return the amount of CPUs that can ever be online, None if unknown.
+fn possible_cpus() -> Option<usize> {
+    let text = fs::read_to_string(POSSIBLE_CPUS).ok()?;
+    let CpuList(cpus) = cpulist::parse(text.trim()).ok()?;
+
+    cpus.last().map(|&cpu| cpu + 1)
+}
+
+/// Return all the problems of the configuration file `text`, errors first: the keys that can't
+/// be loaded, or the problems of the options that they define.
+pub fn config_problems(text: &str, nr_cpus: Option<usize>) -> Vec<String> {
+    match config::load(text) {
+        Ok(opts) => problems(&opts, nr_cpus),
+        Err(errors) => errors
+            .into_iter()
+            .map(|err| format!("error: {}", err))
+            .collect(),
+    }
+}
+
+/// Validate the configuration (given on the command line, or in the configuration file of the
+/// validate subcommand) and print "OK" or all the problems found.
+pub fn run(opts: &Opts, validate: &ValidateOpts) -> Result<()> {
+    let problems = match &validate.config {
+        Some(path) => {
+            let text =
+                fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+            config_problems(&text, possible_cpus())
+        }
+        None => problems(opts, possible_cpus()),
+    };
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        bail!("{} problems found in the configuration", problems.len());
+    }
+    println!("OK");
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+
+// Time after which the CPU where a task last ran isn't considered warm anymore: the data shared
+// with the woken tasks is likely to have been evicted from its cache.
+pub const WAKE_AFFINE_WINDOW_NS: u64 = 10_000_000;
+
+/// Waker-wakee locality (see --wake-affine).
+///
+/// A task woken up by another task likely consumes the data produced by its waker (e.g., a
+/// pipe, a futex, a work queue), so the idle CPU search of the woken task starts from the CPU
+/// where its waker recently ran, instead of its previously used CPU: the task runs on the
+/// waker's CPU, if idle, or close to it (same LLC domain), sharing the cache of the waker.
+///
+/// When the waker of a task is unknown (see SchedBackend::waker()), it is approximated by the
+/// last thread of the same process dispatched recently (tgid co-location), since the threads
+/// of a process usually wake up each other. The associations older than WAKE_AFFINE_WINDOW_NS
+/// are ignored.
+pub struct WakeAffinity {
+    task_cpus: HashMap<i32, (i32, u64)>, // Last CPU of each task and time
+    processes: HashMap<i32, i32>,        // Last thread dispatched of each process
+    targets: HashMap<i32, i32>,          // CPU preferred for the pending dispatches
+    nr_waker: u64,                       // Wakeups placed near the waker
+    nr_process: u64,                     // Wakeups placed near the process
+    nr_unknown: u64,                     // Wakeups without a recent association
+    nr_hits: u64,                        // Wakeups dispatched to the preferred CPU
+}
+
+impl WakeAffinity {
+    pub fn new() -> Self {
+        Self {
+            task_cpus: HashMap::new(),
+            processes: HashMap::new(),
+            targets: HashMap::new(),
+            nr_waker: 0,
+            nr_process: 0,
+            nr_unknown: 0,
+            nr_hits: 0,
+        }
+    }
+
+    /// Return the CPU where the idle CPU search of the task `pid` needs to start, instead of
+    /// `cpu`, given its waker (if known) and its process `tgid`, at time `now`: the CPU recently
+    /// used by the waker, otherwise by another thread of the process, if `usable`.
+    pub fn pick_cpu(
+        &mut self,
+        pid: i32,
+        waker: Option<i32>,
+        tgid: Option<i32>,
+        cpu: i32,
+        now: u64,
+        usable: impl Fn(usize) -> bool,
+    ) -> i32 {
+        let recent_cpu = |task: i32| {
+            self.task_cpus
+                .get(&task)
+                .filter(|&&(target, ts)| {
+                    now.saturating_sub(ts) < WAKE_AFFINE_WINDOW_NS && usable(target as usize)
+                })
+                .map(|&(target, _)| target)
+        };
+        let waker_cpu = waker.filter(|&waker| waker != pid).and_then(recent_cpu);
+        let process_cpu = tgid
+            .and_then(|tgid| self.processes.get(&tgid).copied())
+            .filter(|&thread| thread != pid)
+            .and_then(recent_cpu);
+
+        let target = match (waker_cpu, process_cpu) {
+            (Some(target), _) => {
+                self.nr_waker += 1;
+                target
+            }
+            (None, Some(target)) => {
+                self.nr_process += 1;
+                target
+            }
+            (None, None) => {
+                self.nr_unknown += 1;
+                self.targets.remove(&pid);
+                return cpu;
+            }
+        };
+        self.targets.insert(pid, target);
+
+        target
+    }
+
+    /// Account the task `pid` of the process `tgid` dispatched to the CPU `cpu` (negative for
+    /// RL_CPU_ANY) at time `now`.
+    pub fn record_dispatch(&mut self, pid: i32, tgid: Option<i32>, cpu: i32, now: u64) {
+        if self.targets.remove(&pid) == Some(cpu) {
+            self.nr_hits += 1;
+        }
+        if cpu < 0 {
+            return;
+        }
+        self.task_cpus.insert(pid, (cpu, now));
+        if let Some(tgid) = tgid {
+            self.processes.insert(tgid, pid);
+        }
+    }
+
+    /// Return the wakeups placed near the waker, near the process and without a recent
+    /// association, and the wakeups dispatched to the preferred CPU, since the last report.
+    pub fn wakeups(&self) -> (u64, u64, u64, u64) {
+        (
+            self.nr_waker,
+            self.nr_process,
+            self.nr_unknown,
+            self.nr_hits,
+        )
+    }
+
+    /// Drop the associations that expired at time `now` and the state of the tasks that are not
+    /// tracked anymore.
+    pub fn retain(&mut self, now: u64, alive: impl Fn(i32) -> bool) {
+        self.task_cpus.retain(|&pid, &mut (_, ts)| {
+            alive(pid) && now.saturating_sub(ts) < WAKE_AFFINE_WINDOW_NS
+        });
+        self.processes
+            .retain(|_, thread| self.task_cpus.contains_key(thread));
+        self.targets.retain(|&pid, _| alive(pid));
+    }
+
+    /// Print the waker-wakee locality hit rate and reset the counters.
+    pub fn report(&mut self) {
+        let nr_affine = self.nr_waker + self.nr_process;
+        let pct = (self.nr_hits * 100).checked_div(nr_affine).unwrap_or(0);
+
+        println!(
+            "wake affinity: hit rate: {}% of {} | near waker: {} | near process: {} | unknown: {}",
+            pct, nr_affine, self.nr_waker, self.nr_process, self.nr_unknown
+        );
+        self.nr_waker = 0;
+        self.nr_process = 0;
+        self.nr_unknown = 0;
+        self.nr_hits = 0;
+    }
+}
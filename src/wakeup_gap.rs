@@ -0,0 +1,104 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use crate::hysteresis::Hysteresis;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+// Minimum amount of gaps measured in an interval to evaluate it (prevents reacting to a handful
+// of isolated delays).
+const WAKEUP_GAP_MIN_SAMPLES: u64 = 10;
+
+// Maximum factor applied to the time slices when the wakeup gaps are high.
+const WAKEUP_GAP_MAX_SLICE_SCALE: u64 = 8;
+
+/// Controller of the time slices driven by the turnaround of the scheduler itself (see
+/// --wakeup-gap-high-us).
+///
+/// The gap between two consecutive wakeups of the scheduler is measured only when the previous
+/// round left tasks waiting to be dispatched (otherwise the gap is just idle time): if the
+/// scheduler is preempted or overloaded, the gaps grow and the tasks wait for longer to be
+/// dispatched, making the scheduler even more likely to lag behind.
+///
+/// To break this cycle, when the average gap of a one-second interval exceeds `high_ns` the time
+/// slices are doubled (up to WAKEUP_GAP_MAX_SLICE_SCALE times), so that the scheduler needs to
+/// wake up and dispatch less often, trading latency for a lower scheduling overhead; they are
+/// halved again when the average gap drops below `low_ns`. Scale changes are filtered by
+/// `hysteresis`, driven by the average gap (in microseconds).
+pub struct WakeupGap {
+    high_ns: u64,           // Average gap above which the time slices are scaled up
+    low_ns: u64,            // Average gap below which the time slices are scaled down
+    hysteresis: Hysteresis, // Anti-flapping filter of the time slice scale
+    interval_ts: u64,       // Beginning of the current interval
+    last_wakeup: u64,       // Last wakeup of the scheduler
+    nr_gaps: u64,           // Gaps measured in the current interval
+    sum_gaps: u64,          // Sum of the gaps measured in the current interval
+    slice_scale: u64,       // Factor applied to the time slices
+}
+
+impl WakeupGap {
+    pub fn new(high_ns: u64, low_ns: u64, hysteresis: Hysteresis, now: u64) -> Self {
+        Self {
+            high_ns,
+            low_ns: low_ns.min(high_ns),
+            hysteresis,
+            interval_ts: now,
+            last_wakeup: now,
+            nr_gaps: 0,
+            sum_gaps: 0,
+            slice_scale: 1,
+        }
+    }
+
+    /// Account a wakeup of the scheduler at time `now`, `backlog` is true if tasks were still
+    /// waiting to be dispatched since the previous wakeup.
+    pub fn record_wakeup(&mut self, now: u64, backlog: bool) {
+        if backlog {
+            self.nr_gaps += 1;
+            self.sum_gaps += now.saturating_sub(self.last_wakeup);
+        }
+        self.last_wakeup = now;
+    }
+
+    /// Return the factor that needs to be applied to the time slices.
+    pub fn slice_scale(&self) -> u64 {
+        self.slice_scale
+    }
+
+    /// Evaluate the current interval (once per second) and update the time slice scale.
+    pub fn evaluate(&mut self, now: u64) {
+        if now.saturating_sub(self.interval_ts) < NSEC_PER_SEC {
+            return;
+        }
+        if self.nr_gaps >= WAKEUP_GAP_MIN_SAMPLES {
+            let avg_ns = self.sum_gaps / self.nr_gaps;
+            let avg_us = avg_ns / 1000;
+
+            if avg_ns > self.high_ns
+                && self.slice_scale < WAKEUP_GAP_MAX_SLICE_SCALE
+                && self.hysteresis.allow(now, avg_us)
+            {
+                self.slice_scale *= 2;
+                println!(
+                    "wakeup gap: average {}us, time slices scaled by {}x",
+                    avg_us, self.slice_scale
+                );
+            } else if avg_ns < self.low_ns
+                && self.slice_scale > 1
+                && self.hysteresis.allow(now, avg_us)
+            {
+                self.slice_scale /= 2;
+                println!(
+                    "wakeup gap: average {}us, time slices scaled by {}x",
+                    avg_us, self.slice_scale
+                );
+            }
+        }
+
+        self.interval_ts = now;
+        self.nr_gaps = 0;
+        self.sum_gaps = 0;
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! Service received by the tasks of each weight class (see --weight-classes).
+//!
+//! The tasks are split in three classes by their weight, according to two bounds provided on
+//! the command line (LOW,HIGH): the low class (weights below LOW), the normal class (weights in
+//! [LOW, HIGH]) and the high class (weights above HIGH), e.g.:
+//!
+//!   $ scx_rust_scheduler --weight-classes 100,100
+//!
+//! puts the tasks with the default weight (nice 0) in the normal class, the niced ones in the
+//! low class and the prioritized ones in the high class. The dispatches and the time slices
+//! assigned to each class show whether the weights make an actual difference in the service
+//! received by the tasks.
+
+// Names of the weight classes, in the order of WeightClasses::class().
+const CLASS_NAMES: [&str; NR_CLASSES] = ["low", "normal", "high"];
+const NR_CLASSES: usize = 3;
+
+/// Bounds of the weight classes provided on the command line (LOW,HIGH).
+#[derive(Debug, Clone, Copy)]
+pub struct WeightBounds {
+    pub low: u64,  // Lowest weight of the normal class
+    pub high: u64, // Highest weight of the normal class
+}
+
+/// Parse the bounds of the weight classes in the format LOW,HIGH (LOW <= HIGH).
+pub fn parse(text: &str) -> Result<WeightBounds, String> {
+    let Some((low, high)) = text.split_once(',') else {
+        return Err(format!("expected LOW,HIGH, found '{}'", text));
+    };
+    let parse_weight = |weight: &str| {
+        weight
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("invalid weight '{}'", weight))
+    };
+    let (low, high) = (parse_weight(low)?, parse_weight(high)?);
+    if low > high {
+        return Err(format!(
+            "the low bound {} is above the high bound {}",
+            low, high
+        ));
+    }
+
+    Ok(WeightBounds { low, high })
+}
+
+// Dispatches of a weight class in an interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct ClassSummary {
+    nr_dispatches: u64, // Tasks dispatched
+    sum_slice_ns: u64,  // Sum of the time slices assigned
+}
+
+impl ClassSummary {
+    fn avg_slice_us(&self) -> u64 {
+        self.sum_slice_ns
+            .checked_div(self.nr_dispatches)
+            .unwrap_or(0)
+            / 1000
+    }
+}
+
+/// Dispatches and time slices of the tasks of each weight class.
+///
+/// As with the weight distribution (see WeightStats), the dispatches are accounted over
+/// one-second intervals (the stats interval) and the summary of the last completed interval is
+/// kept around for the JSON output.
+pub struct WeightClasses {
+    bounds: WeightBounds,                // Bounds of the classes
+    current: [ClassSummary; NR_CLASSES], // Current interval
+    last: [ClassSummary; NR_CLASSES],    // Last completed interval
+}
+
+impl WeightClasses {
+    pub fn new(bounds: WeightBounds) -> Self {
+        Self {
+            bounds,
+            current: [ClassSummary::default(); NR_CLASSES],
+            last: [ClassSummary::default(); NR_CLASSES],
+        }
+    }
+
+    /// Return the class of a task with weight `weight` (0 = low, 1 = normal, 2 = high).
+    pub fn class(&self, weight: u64) -> usize {
+        if weight < self.bounds.low {
+            0
+        } else if weight <= self.bounds.high {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Account a task with weight `weight` dispatched with a time slice of `slice_ns`.
+    pub fn record(&mut self, weight: u64, slice_ns: u64) {
+        let s = &mut self.current[self.class(weight)];
+
+        s.nr_dispatches += 1;
+        s.sum_slice_ns = s.sum_slice_ns.saturating_add(slice_ns);
+    }
+
+    /// Return the tasks dispatched in each class in the current interval.
+    pub fn nr_dispatches(&self) -> [u64; NR_CLASSES] {
+        self.current.map(|s| s.nr_dispatches)
+    }
+
+    // Return the range of weights of each class, as a label.
+    fn labels(&self) -> [String; NR_CLASSES] {
+        let WeightBounds { low, high } = self.bounds;
+
+        [
+            format!("<{}", low),
+            format!("{}-{}", low, high),
+            format!(">{}", high),
+        ]
+    }
+
+    /// Print the summary of the current interval and start a new interval.
+    pub fn report(&mut self) {
+        let classes: Vec<String> = CLASS_NAMES
+            .iter()
+            .zip(self.labels())
+            .zip(self.current.iter())
+            .map(|((name, label), s)| {
+                format!(
+                    "{} ({}): {}/s, avg slice {}us",
+                    name,
+                    label,
+                    s.nr_dispatches,
+                    s.avg_slice_us()
+                )
+            })
+            .collect();
+
+        println!("weight classes: {}", classes.join(" | "));
+        self.last = self.current;
+        self.current = [ClassSummary::default(); NR_CLASSES];
+    }
+
+    /// Format the summary of the last completed interval as a JSON object.
+    pub fn json(&self) -> String {
+        let classes: Vec<String> = CLASS_NAMES
+            .iter()
+            .zip(self.labels())
+            .zip(self.last.iter())
+            .map(|((name, label), s)| {
+                format!(
+                    "\"{}\":{{\"weights\":\"{}\",\"dispatches\":{},\"avg_slice_us\":{}}}",
+                    name,
+                    label,
+                    s.nr_dispatches,
+                    s.avg_slice_us()
+                )
+            })
+            .collect();
+
+        format!("{{{}}}", classes.join(","))
+    }
+}
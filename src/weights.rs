@@ -0,0 +1,102 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// Upper bounds (inclusive) of the weight histogram buckets (the default weight has its own
+// bucket), weights above the last bound are accounted in an additional overflow bucket.
+const WEIGHT_BUCKETS: [u64; 4] = [9, 99, 100, 999];
+const WEIGHT_BUCKETS_LABEL: &str = "1-9/10-99/100/101-999/1000+";
+const NR_WEIGHT_BUCKETS: usize = WEIGHT_BUCKETS.len() + 1;
+
+// Summary of the weights observed in an interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct WeightSummary {
+    count: u64,                     // Tasks received
+    min: u64,                       // Minimum weight
+    max: u64,                       // Maximum weight
+    sum: u64,                       // Sum of the weights
+    hist: [u64; NR_WEIGHT_BUCKETS], // Histogram of the weights
+}
+
+impl WeightSummary {
+    fn mean(&self) -> u64 {
+        self.sum.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Distribution of the weights of the tasks received from the BPF component (see
+/// --weight-stats).
+///
+/// The statistics are collected over one-second intervals: the summary of the last completed
+/// interval is kept around for the JSON output.
+pub struct WeightStats {
+    current: WeightSummary, // Current interval
+    last: WeightSummary,    // Last completed interval
+}
+
+impl WeightStats {
+    pub fn new() -> Self {
+        Self {
+            current: WeightSummary::default(),
+            last: WeightSummary::default(),
+        }
+    }
+
+    /// Account a task with weight `weight`.
+    pub fn record(&mut self, weight: u64) {
+        let s = &mut self.current;
+
+        if s.count == 0 {
+            s.min = weight;
+            s.max = weight;
+        } else {
+            s.min = s.min.min(weight);
+            s.max = s.max.max(weight);
+        }
+        s.count += 1;
+        s.sum += weight;
+
+        let bucket = WEIGHT_BUCKETS
+            .iter()
+            .position(|&bound| weight <= bound)
+            .unwrap_or(NR_WEIGHT_BUCKETS - 1);
+        s.hist[bucket] += 1;
+    }
+
+    /// Print the summary of the current interval and start a new interval.
+    pub fn report(&mut self) {
+        let s = &self.current;
+        let hist: Vec<String> = s.hist.iter().map(|n| n.to_string()).collect();
+
+        println!(
+            "task weights: min {} | max {} | mean {} | {}: {}",
+            s.min,
+            s.max,
+            s.mean(),
+            WEIGHT_BUCKETS_LABEL,
+            hist.join("/")
+        );
+        self.last = self.current;
+        self.current = WeightSummary::default();
+    }
+
+    /// Format the summary of the last completed interval as a JSON object.
+    pub fn json(&self) -> String {
+        let s = &self.last;
+        let hist: Vec<String> = WEIGHT_BUCKETS_LABEL
+            .split('/')
+            .zip(s.hist.iter())
+            .map(|(label, count)| format!("\"{}\":{}", label, count))
+            .collect();
+
+        format!(
+            "{{\"count\":{},\"min\":{},\"max\":{},\"mean\":{},\"hist\":{{{}}}}}",
+            s.count,
+            s.min,
+            s.max,
+            s.mean(),
+            hist.join(",")
+        )
+    }
+}
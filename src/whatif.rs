@@ -0,0 +1,465 @@
+// Copyright (c) Andrea Righi <andrea.righi@linux.dev>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! What-if replay of a recorded trace with modified parameters (see `replay --with`).
+//!
+//! Unlike the regular replay, the policy is not expected to take the recorded decisions: the
+//! trace is used as a workload, i.e., the tasks are received by the policy at the same time
+//! and in the same order of the recorded session (the recorded rounds are preserved), while
+//! all the outputs of the policy are accepted, so that the same incident can be reproduced
+//! under a different configuration. The answers of the other queries are taken from the trace
+//! when they are available:
+//!
+//!   - select_cpu() returns the CPU recorded for the next selection of the same task, or the
+//!     previously used CPU if the recorded session didn't select a CPU for the task anymore
+//!   - the attributes of the tasks (tgid, comm, allowed CPUs, ...) are the first ones recorded
+//!   - every dispatch succeeds
+//!
+//! The metrics of the recorded dispatches are then compared side by side with the metrics of
+//! the dispatches of the policy with the modified parameters:
+//!
+//!   - latency: time between the arrival of a task and its dispatch
+//!   - fairness: Jain's index of the time slices assigned to the tasks, normalized by their
+//!     weight (1.0 = assigned exactly in proportion to the weights)
+//!   - migrations: dispatches to a different CPU than the last one assigned to the task
+//!
+//! NOTE: only the successful dispatches are accounted: the recorded dispatches that failed (e.g.,
+//! with the BPF component busy) are retried by the recorded session, while they always succeed
+//! in the replay, so the latency of a congested recording is higher even with the same
+//! parameters.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use crate::backend::Dispatch;
+use crate::backend::DispatchError;
+use crate::backend::SchedBackend;
+use crate::backend::Task;
+use crate::sweep;
+use crate::trace::Counter;
+use crate::trace::Event;
+use crate::trace::Outcome;
+use crate::Opts;
+use crate::Scheduler;
+
+/// Metrics of the dispatches of a session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunMetrics {
+    pub nr_tasks: usize,      // Tasks received by the policy
+    pub nr_dispatches: usize, // Tasks dispatched
+    pub avg_latency_ns: u64,  // Average time between arrival and dispatch
+    pub p99_latency_ns: u64,  // 99th percentile of the time between arrival and dispatch
+    pub avg_slice_ns: u64,    // Average time slice assigned
+    pub fairness: f64,        // Jain's index of the time slices assigned (weighted)
+    pub nr_migrations: usize, // Dispatches to a different CPU than the previous one
+}
+
+/// Compute the metrics of a session from its task arrivals (Dequeue events) and successful
+/// dispatches (Dispatch events), with the time of each event.
+pub fn metrics<'e>(events: impl IntoIterator<Item = (u64, &'e Event)>) -> RunMetrics {
+    let mut arrivals: HashMap<i32, VecDeque<u64>> = HashMap::new();
+    let mut weights: HashMap<i32, u64> = HashMap::new();
+    let mut slices: BTreeMap<i32, u64> = BTreeMap::new();
+    let mut last_cpus: HashMap<i32, i32> = HashMap::new();
+    let mut latencies = Vec::new();
+    let mut m = RunMetrics::default();
+    let mut total_slice_ns = 0;
+
+    for (now, event) in events {
+        match event {
+            Event::Dequeue(Ok(Some(task))) => {
+                arrivals.entry(task.pid).or_default().push_back(now);
+                weights.insert(task.pid, task.weight);
+                m.nr_tasks += 1;
+            }
+            Event::Dispatch(d, Outcome::Ok) => {
+                if let Some(ts) = arrivals.get_mut(&d.pid).and_then(|ts| ts.pop_front()) {
+                    latencies.push(now.saturating_sub(ts));
+                }
+                *slices.entry(d.pid).or_default() += d.slice_ns;
+                total_slice_ns += d.slice_ns;
+                let last_cpu = (d.cpu >= 0)
+                    .then(|| last_cpus.insert(d.pid, d.cpu))
+                    .flatten();
+                if last_cpu.is_some_and(|cpu| cpu != d.cpu) {
+                    m.nr_migrations += 1;
+                }
+                m.nr_dispatches += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let nr_latencies = latencies.len() as u64;
+    m.avg_latency_ns = latencies
+        .iter()
+        .sum::<u64>()
+        .checked_div(nr_latencies)
+        .unwrap_or(0);
+    m.p99_latency_ns = sweep::percentile(&mut latencies, 99);
+    m.avg_slice_ns = total_slice_ns
+        .checked_div(m.nr_dispatches as u64)
+        .unwrap_or(0);
+    let shares: Vec<(u64, u64)> = slices
+        .iter()
+        .map(|(pid, &slice_ns)| (weights.get(pid).copied().unwrap_or(100), slice_ns))
+        .collect();
+    m.fairness = sweep::fairness(&shares);
+
+    m
+}
+
+// Return the key of the recorded answer of a query about a task (or the system), None for the
+// other events.
+fn lookup_key(event: &Event) -> Option<String> {
+    let key = match event {
+        Event::Exists(pid, _) => format!("exists {}", pid),
+        Event::Tgid(pid, _) => format!("tgid {}", pid),
+        Event::AllowedCpus(pid, _) => format!("allowed {}", pid),
+        Event::ParentCpu(pid, _) => format!("parent {}", pid),
+        Event::Kthread(pid, _) => format!("kthread {}", pid),
+        Event::Waker(pid, _) => format!("waker {}", pid),
+        Event::EnvHint(pid, name, _) => format!("hint {} {}", pid, name),
+        Event::Comm(pid, _) => format!("comm {}", pid),
+        Event::OnlineCpus(_) => "online".to_string(),
+        Event::IsolatedCpus(_) => "isolated".to_string(),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Backend that feeds the tasks of a recorded trace to the policy, accepting all its decisions.
+pub struct WhatIfBackend {
+    events: Vec<Event>,                     // Recorded events
+    pos: usize,                             // Next event
+    now_ns: u64,                            // Last recorded timestamp
+    self_cpu_ns: u64,                       // Last recorded CPU time of the scheduler
+    minute: u32,                            // Last recorded time of the day
+    counters: [u64; Counter::ALL.len()],    // Last recorded statistics
+    selects: HashMap<i32, VecDeque<usize>>, // Recorded CPU selections of each task (events)
+    lookups: HashMap<String, Event>,        // First recorded answer of each query
+    log: Vec<(u64, Event)>,                 // Arrivals and dispatches, with their time
+}
+
+impl WhatIfBackend {
+    pub fn new(events: Vec<Event>) -> Self {
+        let mut selects: HashMap<i32, VecDeque<usize>> = HashMap::new();
+        let mut lookups = HashMap::new();
+        let (mut now_ns, mut self_cpu_ns, mut minute) = (None, None, None);
+        let mut counters = [None; Counter::ALL.len()];
+        for (i, event) in events.iter().enumerate() {
+            // The queries issued before the first recorded ones (e.g., the amount of CPUs at
+            // initialization) are answered with the first recorded values.
+            match event {
+                Event::Now(ns) => now_ns = now_ns.or(Some(*ns)),
+                Event::SelfCpu(ns) => self_cpu_ns = self_cpu_ns.or(Some(*ns)),
+                Event::LocalMinute(m) => minute = minute.or(Some(*m)),
+                Event::Counter(counter, value) => {
+                    let first = &mut counters[*counter as usize];
+                    *first = first.or(Some(*value));
+                }
+                Event::SelectCpu(pid, ..) => selects.entry(*pid).or_default().push_back(i),
+                _ => {}
+            }
+            if let Some(key) = lookup_key(event) {
+                lookups.entry(key).or_insert_with(|| event.clone());
+            }
+        }
+
+        Self {
+            events,
+            pos: 0,
+            now_ns: now_ns.unwrap_or(0),
+            self_cpu_ns: self_cpu_ns.unwrap_or(0),
+            minute: minute.unwrap_or(0),
+            counters: counters.map(|value| value.unwrap_or(0)),
+            selects,
+            lookups,
+            log: Vec::new(),
+        }
+    }
+
+    // Consume the next event, updating the last recorded values of the queries.
+    fn consume(&mut self) {
+        match self.events.get(self.pos) {
+            Some(Event::Now(ns)) => self.now_ns = *ns,
+            Some(Event::SelfCpu(ns)) => self.self_cpu_ns = *ns,
+            Some(Event::LocalMinute(minute)) => self.minute = *minute,
+            Some(Event::Counter(counter, value)) => self.counters[*counter as usize] = *value,
+            Some(_) => {}
+            None => return,
+        }
+        self.pos += 1;
+    }
+
+    fn lookup(&self, key: String) -> Option<&Event> {
+        self.lookups.get(&key)
+    }
+}
+
+impl SchedBackend for WhatIfBackend {
+    // Return the next task received in the current recorded round (the round ends with the
+    // recorded notify_complete()).
+    fn dequeue_task(&mut self) -> Result<Option<Task>, i32> {
+        while let Some(event) = self.events.get(self.pos) {
+            match event {
+                Event::Notify(_) => break,
+                Event::Dequeue(result) => {
+                    let result = result.clone();
+                    self.consume();
+                    if let Ok(Some(task)) = &result {
+                        let event = Event::Dequeue(Ok(Some(task.clone())));
+                        self.log.push((self.now_ns, event));
+                    }
+                    return result;
+                }
+                _ => self.consume(),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn select_cpu(&mut self, pid: i32, prev_cpu: i32, _flags: u64) -> i32 {
+        let Some(selects) = self.selects.get_mut(&pid) else {
+            return prev_cpu;
+        };
+        while selects.front().is_some_and(|&i| i < self.pos) {
+            selects.pop_front();
+        }
+
+        match selects.pop_front().map(|i| &self.events[i]) {
+            Some(Event::SelectCpu(.., cpu)) => *cpu,
+            _ => prev_cpu,
+        }
+    }
+
+    fn dispatch_task(&mut self, task: &Dispatch) -> Result<(), DispatchError> {
+        let event = Event::Dispatch(task.clone(), Outcome::Ok);
+        self.log.push((self.now_ns, event));
+
+        Ok(())
+    }
+
+    // Move to the next recorded round.
+    fn notify_complete(&mut self, _nr_pending: u64) {
+        while let Some(event) = self.events.get(self.pos) {
+            let notify = matches!(event, Event::Notify(_));
+            self.consume();
+            if notify {
+                break;
+            }
+        }
+    }
+
+    // Follow the recorded session, that ends with the trace.
+    fn exited(&mut self) -> bool {
+        while let Some(event) = self.events.get(self.pos) {
+            match event {
+                Event::Exited(exited) => {
+                    let exited = *exited;
+                    self.consume();
+                    return exited;
+                }
+                event if event.is_query() => self.consume(),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    fn exists(&mut self, pid: i32) -> bool {
+        match self.lookup(format!("exists {}", pid)) {
+            Some(Event::Exists(_, exists)) => *exists,
+            _ => false,
+        }
+    }
+
+    fn tgid(&mut self, pid: i32) -> Option<i32> {
+        match self.lookup(format!("tgid {}", pid)) {
+            Some(Event::Tgid(_, tgid)) => *tgid,
+            _ => None,
+        }
+    }
+
+    fn allowed_cpus(&mut self, pid: i32) -> Option<Vec<usize>> {
+        match self.lookup(format!("allowed {}", pid)) {
+            Some(Event::AllowedCpus(_, cpus)) => cpus.clone(),
+            _ => None,
+        }
+    }
+
+    fn parent_cpu(&mut self, pid: i32) -> Option<i32> {
+        match self.lookup(format!("parent {}", pid)) {
+            Some(Event::ParentCpu(_, cpu)) => *cpu,
+            _ => None,
+        }
+    }
+
+    fn is_kthread(&mut self, pid: i32) -> Option<bool> {
+        match self.lookup(format!("kthread {}", pid)) {
+            Some(Event::Kthread(_, kthread)) => *kthread,
+            _ => None,
+        }
+    }
+
+    fn waker(&mut self, pid: i32) -> Option<i32> {
+        match self.lookup(format!("waker {}", pid)) {
+            Some(Event::Waker(_, waker)) => *waker,
+            _ => None,
+        }
+    }
+
+    fn env_hint(&mut self, pid: i32, name: &str) -> Option<u64> {
+        match self.lookup(format!("hint {} {}", pid, name)) {
+            Some(Event::EnvHint(_, _, value)) => *value,
+            _ => None,
+        }
+    }
+
+    fn comm(&mut self, pid: i32) -> Option<String> {
+        match self.lookup(format!("comm {}", pid)) {
+            Some(Event::Comm(_, comm)) => comm.clone(),
+            _ => None,
+        }
+    }
+
+    fn online_cpus(&mut self) -> Option<Vec<usize>> {
+        match self.lookup("online".to_string()) {
+            Some(Event::OnlineCpus(cpus)) => cpus.clone(),
+            _ => None,
+        }
+    }
+
+    fn isolated_cpus(&mut self) -> Option<Vec<usize>> {
+        match self.lookup("isolated".to_string()) {
+            Some(Event::IsolatedCpus(cpus)) => cpus.clone(),
+            _ => None,
+        }
+    }
+
+    fn local_minute(&mut self) -> u32 {
+        self.minute
+    }
+
+    fn nr_online_cpus_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::OnlineCpus as usize]
+    }
+
+    fn nr_queued_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::Queued as usize]
+    }
+
+    fn nr_user_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::UserDispatches as usize]
+    }
+
+    fn nr_kernel_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::KernelDispatches as usize]
+    }
+
+    fn nr_bounce_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::BounceDispatches as usize]
+    }
+
+    fn nr_cancel_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::CancelDispatches as usize]
+    }
+
+    fn nr_failed_dispatches_mut(&mut self) -> &mut u64 {
+        &mut self.counters[Counter::FailedDispatches as usize]
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.now_ns
+    }
+
+    fn self_cpu_ns(&self) -> u64 {
+        self.self_cpu_ns
+    }
+}
+
+/// Return the metrics of the dispatches of the recorded session.
+pub fn recorded_metrics(events: &[Event]) -> RunMetrics {
+    let mut now_ns = 0;
+    let timed = events.iter().map(|event| {
+        if let Event::Now(ns) = event {
+            now_ns = *ns;
+        }
+        (now_ns, event)
+    });
+
+    metrics(timed)
+}
+
+/// Feed the tasks of the recorded `events` to the policy configured by `opts` (confining the
+/// processes to `llc_domains`, if specified) and return the metrics of its dispatches, or the
+/// error returned by the policy.
+pub fn simulate(
+    opts: &Opts,
+    events: Vec<Event>,
+    llc_domains: Option<Vec<Vec<usize>>>,
+) -> Result<RunMetrics, String> {
+    let mut sched = Scheduler::new(WhatIfBackend::new(events), opts, None, None);
+    if let Some(domains) = llc_domains {
+        sched.set_llc_domains(domains);
+    }
+    sched.run_loop().map_err(|err| err.to_string())?;
+
+    Ok(metrics(
+        sched.bpf.log.iter().map(|(now, event)| (*now, event)),
+    ))
+}
+
+/// Print the metrics of the recorded session and of the replay with modified parameters side
+/// by side.
+pub fn compare(recorded: &RunMetrics, modified: &RunMetrics) {
+    let us = |ns: u64| format!("{:.1}", ns as f64 / 1000.0);
+    let rows = [
+        (
+            "tasks",
+            recorded.nr_tasks.to_string(),
+            modified.nr_tasks.to_string(),
+        ),
+        (
+            "dispatches",
+            recorded.nr_dispatches.to_string(),
+            modified.nr_dispatches.to_string(),
+        ),
+        (
+            "avg latency (us)",
+            us(recorded.avg_latency_ns),
+            us(modified.avg_latency_ns),
+        ),
+        (
+            "p99 latency (us)",
+            us(recorded.p99_latency_ns),
+            us(modified.p99_latency_ns),
+        ),
+        (
+            "avg slice (us)",
+            us(recorded.avg_slice_ns),
+            us(modified.avg_slice_ns),
+        ),
+        (
+            "fairness",
+            format!("{:.3}", recorded.fairness),
+            format!("{:.3}", modified.fairness),
+        ),
+        (
+            "migrations",
+            recorded.nr_migrations.to_string(),
+            modified.nr_migrations.to_string(),
+        ),
+    ];
+
+    println!("{:<18} {:>12} {:>12}", "metric", "recorded", "modified");
+    for (name, recorded, modified) in rows {
+        println!("{:<18} {:>12} {:>12}", name, recorded, modified);
+    }
+}
@@ -16,7 +16,7 @@ Output the source code directly, never ever print any additional comment around
 This is the original scheduler's source code:
 %s"""
 
-SCX_SRC_FILE = "src/main.rs"
+SCX_SRC_FILE = "src/lib.rs"
 
 def clean_file_content(file_path, output_path=None):
     try: